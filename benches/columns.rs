@@ -0,0 +1,62 @@
+// Compares scanning a large list of keys stored as a Vec<OwnedKey> against KeyColumns.
+
+use borrow_complex_key_example::columns::KeyColumns;
+use borrow_complex_key_example::{BorrowedKey, Key, OwnedKey};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+fn keys() -> Vec<OwnedKey> {
+    (0..100_000u32)
+        .map(|i| OwnedKey {
+            s: format!("key-{}", i),
+            bytes: i.to_le_bytes().to_vec(),
+        })
+        .collect()
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let vec = keys();
+    let columns: KeyColumns = vec.iter().map(OwnedKey::key).collect();
+
+    let mut group = c.benchmark_group("scan_strings");
+    group.bench_function("Vec<OwnedKey>", |b| {
+        b.iter(|| vec.iter().filter(|key| key.s.ends_with('7')).count())
+    });
+    group.bench_function("KeyColumns", |b| {
+        b.iter(|| columns.strings().filter(|s| s.ends_with('7')).count())
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("filter_keys");
+    group.bench_function("Vec<OwnedKey>", |b| {
+        b.iter(|| {
+            vec.iter()
+                .filter(|key| key.s.ends_with('7') && key.bytes[0] == 7)
+                .count()
+        })
+    });
+    group.bench_function("KeyColumns", |b| {
+        b.iter(|| {
+            columns
+                .filter(|key| key.s.ends_with('7') && key.bytes[0] == 7)
+                .count()
+        })
+    });
+    group.finish();
+
+    let needle = BorrowedKey {
+        s: "key-99999",
+        bytes: &99_999u32.to_le_bytes(),
+    };
+    let mut group = c.benchmark_group("position");
+    group.bench_function("Vec<OwnedKey>", |b| {
+        b.iter(|| vec.iter().position(|key| key.key() == black_box(needle)))
+    });
+    group.bench_function("KeyColumns", |b| {
+        b.iter(|| columns.position(black_box(&needle)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_scan);
+criterion_main!(benches);
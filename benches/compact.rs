@@ -0,0 +1,51 @@
+// Compares building sets of short keys with OwnedKey against CompactOwnedKey.
+
+use borrow_complex_key_example::inline::CompactOwnedKey;
+use borrow_complex_key_example::{BorrowedKey, OwnedKey};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::collections::HashSet;
+
+fn short_keys() -> Vec<(String, Vec<u8>)> {
+    (0..1000u32)
+        .map(|i| (format!("key-{}", i), i.to_le_bytes().to_vec()))
+        .collect()
+}
+
+fn bench_insert(c: &mut Criterion) {
+    let keys = short_keys();
+    let mut group = c.benchmark_group("insert_short_keys");
+
+    group.bench_function("OwnedKey", |b| {
+        b.iter_batched(
+            || HashSet::with_capacity(keys.len()),
+            |mut set| {
+                for (s, bytes) in &keys {
+                    set.insert(OwnedKey {
+                        s: s.clone(),
+                        bytes: bytes.clone(),
+                    });
+                }
+                set
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("CompactOwnedKey", |b| {
+        b.iter_batched(
+            || HashSet::with_capacity(keys.len()),
+            |mut set| {
+                for (s, bytes) in &keys {
+                    set.insert(CompactOwnedKey::from(BorrowedKey { s, bytes }));
+                }
+                set
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_insert);
+criterion_main!(benches);
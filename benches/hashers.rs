@@ -0,0 +1,87 @@
+// Compares hashers on composite keys, both hashing a key on its own and looking keys up in a
+// KeySet, across key lengths.
+
+use borrow_complex_key_example::hashers::{AHashKeySet, FxKeySet, WyKeySet};
+use borrow_complex_key_example::set::KeySet;
+use borrow_complex_key_example::{BorrowedKey, Key, OwnedKey};
+use criterion::measurement::WallTime;
+use criterion::{criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::hint::black_box;
+
+fn keys(count: usize, len: usize) -> Vec<OwnedKey> {
+    (0..count)
+        .map(|i| OwnedKey {
+            s: format!("{:0>width$}", i, width = len),
+            bytes: format!("{:x>width$}", i, width = len).into_bytes(),
+        })
+        .collect()
+}
+
+fn bench_hash_one<S: BuildHasher>(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    name: &str,
+    build_hasher: S,
+    key: BorrowedKey<'_>,
+    len: usize,
+) {
+    group.bench_with_input(BenchmarkId::new(name, len), &key, |b, key| {
+        b.iter(|| build_hasher.hash_one(black_box(key)))
+    });
+}
+
+fn bench_contains<S: BuildHasher>(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    name: &str,
+    set: KeySet<S>,
+    probes: &[BorrowedKey<'_>],
+    len: usize,
+) {
+    group.bench_with_input(BenchmarkId::new(name, len), probes, |b, probes| {
+        b.iter(|| {
+            for probe in probes {
+                assert!(set.contains(black_box(probe)));
+            }
+        })
+    });
+}
+
+fn bench_hashers(c: &mut Criterion) {
+    let lens = [8, 64, 1024];
+
+    let mut group = c.benchmark_group("hash_one");
+    for &len in &lens {
+        let keys = keys(1, len);
+        let key = keys[0].key();
+        bench_hash_one(&mut group, "SipHash", RandomState::new(), key, len);
+        bench_hash_one(&mut group, "ahash", ahash::RandomState::new(), key, len);
+        bench_hash_one(&mut group, "FxHash", rustc_hash::FxBuildHasher, key, len);
+        bench_hash_one(
+            &mut group,
+            "wyhash",
+            wyhash::WyHasherBuilder::default(),
+            key,
+            len,
+        );
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("contains");
+    for &len in &lens {
+        let keys = keys(1000, len);
+        let probes: Vec<BorrowedKey<'_>> = keys.iter().step_by(10).map(OwnedKey::key).collect();
+        let sip: KeySet = keys.iter().cloned().collect();
+        let ahash: AHashKeySet = keys.iter().cloned().collect();
+        let fx: FxKeySet = keys.iter().cloned().collect();
+        let wy: WyKeySet = keys.iter().cloned().collect();
+        bench_contains(&mut group, "SipHash", sip, &probes, len);
+        bench_contains(&mut group, "ahash", ahash, &probes, len);
+        bench_contains(&mut group, "FxHash", fx, &probes, len);
+        bench_contains(&mut group, "wyhash", wy, &probes, len);
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_hashers);
+criterion_main!(benches);
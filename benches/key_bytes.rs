@@ -0,0 +1,118 @@
+// Compares looking up keys through a dyn Key against looking them up as KeyBytes, the unsized
+// encoded form:
+//
+// - dyn Key, in a HashSet<OwnedKey> and a BTreeSet<OwnedKey>
+// - KeyBytes encoded from each BorrowedKey into a reused buffer, in a HashSet<EncodedOwnedKey> and
+//   a BTreeSet<EncodedOwnedKey>
+// - KeyBytes that were already encoded, as if read from a key-value store
+//
+// BTreeSet lookups are where KeyBytes should pay off: every comparison is a memcmp, instead of two
+// virtual calls and a field-by-field comparison.
+
+use borrow_complex_key_example::key_bytes::{EncodedOwnedKey, KeyBytes};
+use borrow_complex_key_example::{Key, OwnedKey};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::collections::{BTreeSet, HashSet};
+use std::hint::black_box;
+
+fn keys(count: usize, len: usize) -> Vec<OwnedKey> {
+    (0..count)
+        .map(|i| OwnedKey {
+            s: format!("{:0>width$}", i, width = len),
+            bytes: format!("{:x>width$}", i, width = len).into_bytes(),
+        })
+        .collect()
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("key_bytes");
+    for &len in &[8, 64] {
+        let keys = keys(10_000, len);
+        let probes: Vec<&OwnedKey> = keys.iter().step_by(100).collect();
+        let encoded_probes: Vec<EncodedOwnedKey> = probes
+            .iter()
+            .map(|key| EncodedOwnedKey::new(*key))
+            .collect();
+        let hash_set: HashSet<OwnedKey> = keys.iter().cloned().collect();
+        let btree_set: BTreeSet<OwnedKey> = keys.iter().cloned().collect();
+        let encoded_hash_set: HashSet<EncodedOwnedKey> =
+            keys.iter().map(|key| EncodedOwnedKey::new(key)).collect();
+        let encoded_btree_set: BTreeSet<EncodedOwnedKey> =
+            encoded_hash_set.iter().cloned().collect();
+
+        group.bench_with_input(
+            BenchmarkId::new("HashSet dyn Key", len),
+            &probes,
+            |b, probes| {
+                b.iter(|| {
+                    for probe in probes {
+                        assert!(hash_set.contains(black_box(*probe) as &dyn Key));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("HashSet KeyBytes", len),
+            &probes,
+            |b, probes| {
+                let mut buf = Vec::new();
+                b.iter(|| {
+                    for probe in probes {
+                        assert!(encoded_hash_set
+                            .contains(KeyBytes::encode(black_box(*probe), &mut buf)));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("HashSet pre-encoded KeyBytes", len),
+            &encoded_probes,
+            |b, probes| {
+                b.iter(|| {
+                    for probe in probes {
+                        assert!(encoded_hash_set.contains(black_box(probe.as_key_bytes())));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("BTreeSet dyn Key", len),
+            &probes,
+            |b, probes| {
+                b.iter(|| {
+                    for probe in probes {
+                        assert!(btree_set.contains(black_box(*probe) as &dyn Key));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("BTreeSet KeyBytes", len),
+            &probes,
+            |b, probes| {
+                let mut buf = Vec::new();
+                b.iter(|| {
+                    for probe in probes {
+                        assert!(encoded_btree_set
+                            .contains(KeyBytes::encode(black_box(*probe), &mut buf)));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("BTreeSet pre-encoded KeyBytes", len),
+            &encoded_probes,
+            |b, probes| {
+                b.iter(|| {
+                    for probe in probes {
+                        assert!(encoded_btree_set.contains(black_box(probe.as_key_bytes())));
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lookup);
+criterion_main!(benches);
@@ -0,0 +1,93 @@
+// Compares three ways of looking up an OwnedKey in a hash set, given the borrowed parts of a key:
+//
+// - borrowing as a dyn Key, as in the crate root
+// - allocating a temporary OwnedKey for every lookup
+// - hashbrown's Equivalent trait, which sidesteps Borrow altogether
+//
+// across set sizes and key lengths.
+
+use borrow_complex_key_example::{BorrowedKey, Key, OwnedKey};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use hashbrown::Equivalent;
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::hint::black_box;
+
+// Equivalent can't be implemented for BorrowedKey itself outside the crate that defines it, so wrap
+// it. The hash must match OwnedKey's, which it does because BorrowedKey's does.
+struct EquivalentKey<'a>(BorrowedKey<'a>);
+
+impl<'a> Hash for EquivalentKey<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<'a> Equivalent<OwnedKey> for EquivalentKey<'a> {
+    fn equivalent(&self, key: &OwnedKey) -> bool {
+        self.0 == key.key()
+    }
+}
+
+fn keys(count: usize, len: usize) -> Vec<OwnedKey> {
+    (0..count)
+        .map(|i| OwnedKey {
+            s: format!("{:0>width$}", i, width = len),
+            bytes: format!("{:x>width$}", i, width = len).into_bytes(),
+        })
+        .collect()
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lookup");
+    for &count in &[100, 10_000] {
+        for &len in &[8, 64] {
+            let keys = keys(count, len);
+            let std_set: HashSet<OwnedKey> = keys.iter().cloned().collect();
+            let hashbrown_set: hashbrown::HashSet<OwnedKey, RandomState> =
+                keys.iter().cloned().collect();
+            // Look up 100 keys spread across the set, as borrowed parts.
+            let probes: Vec<BorrowedKey<'_>> = keys
+                .iter()
+                .step_by(count / 100)
+                .map(OwnedKey::key)
+                .collect();
+            let id = format!("{}x{}", count, len);
+
+            group.bench_with_input(BenchmarkId::new("dyn Key", &id), &probes, |b, probes| {
+                b.iter(|| {
+                    for probe in probes {
+                        assert!(std_set.contains(black_box(probe) as &dyn Key));
+                    }
+                })
+            });
+            group.bench_with_input(
+                BenchmarkId::new("temporary OwnedKey", &id),
+                &probes,
+                |b, probes| {
+                    b.iter(|| {
+                        for probe in probes {
+                            let probe = black_box(probe);
+                            assert!(std_set.contains(&OwnedKey {
+                                s: probe.s.to_owned(),
+                                bytes: probe.bytes.to_owned(),
+                            }));
+                        }
+                    })
+                },
+            );
+            group.bench_with_input(BenchmarkId::new("Equivalent", &id), &probes, |b, probes| {
+                b.iter(|| {
+                    for probe in probes {
+                        assert!(hashbrown_set.contains(&EquivalentKey(*black_box(probe))));
+                    }
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lookup);
+criterion_main!(benches);
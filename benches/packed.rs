@@ -0,0 +1,56 @@
+// Compares building sets of keys with OwnedKey (two allocations per key) against CompactKey (one).
+
+use borrow_complex_key_example::packed::CompactKey;
+use borrow_complex_key_example::{BorrowedKey, OwnedKey};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::collections::HashSet;
+
+fn keys(len: usize) -> Vec<(String, Vec<u8>)> {
+    (0..1000u32)
+        .map(|i| {
+            let s = format!("{:0>width$}", i, width = len);
+            (s, vec![i as u8; len])
+        })
+        .collect()
+}
+
+fn bench_insert(c: &mut Criterion) {
+    for len in [8, 64] {
+        let keys = keys(len);
+        let mut group = c.benchmark_group(format!("insert_keys_{}", len));
+
+        group.bench_function("OwnedKey", |b| {
+            b.iter_batched(
+                || HashSet::with_capacity(keys.len()),
+                |mut set| {
+                    for (s, bytes) in &keys {
+                        set.insert(OwnedKey {
+                            s: s.clone(),
+                            bytes: bytes.clone(),
+                        });
+                    }
+                    set
+                },
+                BatchSize::SmallInput,
+            )
+        });
+
+        group.bench_function("CompactKey", |b| {
+            b.iter_batched(
+                || HashSet::with_capacity(keys.len()),
+                |mut set| {
+                    for (s, bytes) in &keys {
+                        set.insert(CompactKey::from(BorrowedKey { s, bytes }));
+                    }
+                    set
+                },
+                BatchSize::SmallInput,
+            )
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_insert);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+// Compares sorting large batches of keys on one core against sorting them on all of them, with
+// rayon, ahead of building a sorted container from them.
+
+use borrow_complex_key_example::sort::{
+    par_sort_keys, par_sort_keys_unstable, sort_keys, sort_keys_unstable,
+};
+use borrow_complex_key_example::OwnedKey;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+type Sort = fn(&mut [OwnedKey]);
+
+fn keys(count: usize) -> Vec<OwnedKey> {
+    // Scrambled, so that there's real sorting to do, and with shared prefixes, so that comparisons
+    // look past the first few bytes.
+    (0..count as u64)
+        .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15) % count as u64)
+        .map(|i| OwnedKey {
+            s: format!("tenant-{:04}", i % 1000),
+            bytes: i.to_be_bytes().to_vec(),
+        })
+        .collect()
+}
+
+fn bench_par_sort(c: &mut Criterion) {
+    let mut group = c.benchmark_group("par_sort");
+    group.sample_size(10);
+    for &count in &[100_000, 1_000_000] {
+        let keys = keys(count);
+        let sorts: [(&str, Sort); 4] = [
+            ("sort_keys", sort_keys),
+            ("sort_keys_unstable", sort_keys_unstable),
+            ("par_sort_keys", par_sort_keys),
+            ("par_sort_keys_unstable", par_sort_keys_unstable),
+        ];
+        for (name, sort) in sorts {
+            group.bench_with_input(BenchmarkId::new(name, count), &keys, |b, keys| {
+                b.iter_batched(
+                    || keys.clone(),
+                    |mut keys| {
+                        sort(&mut keys);
+                        keys
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_par_sort);
+criterion_main!(benches);
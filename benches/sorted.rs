@@ -0,0 +1,117 @@
+// Compares SortedKeyVec against the tree and hash sets it's meant to replace for build-once sets:
+//
+// - point lookups through a dyn Key, in a SortedKeyVec, a BTreeSet<OwnedKey> and a
+//   HashSet<OwnedKey>
+// - range queries covering 100 keys, in a SortedKeyVec and a BTreeSet<OwnedKey>
+// - building each set from the same keys, in arbitrary order
+
+use borrow_complex_key_example::sorted::SortedKeyVec;
+use borrow_complex_key_example::{Key, OwnedKey};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::collections::{BTreeSet, HashSet};
+use std::hint::black_box;
+use std::ops::Bound;
+
+fn keys(count: usize, len: usize) -> Vec<OwnedKey> {
+    // Reversed, so that building the sets has some sorting to do.
+    (0..count)
+        .rev()
+        .map(|i| OwnedKey {
+            s: format!("{:0>width$}", i, width = len),
+            bytes: format!("{:x>width$}", i, width = len).into_bytes(),
+        })
+        .collect()
+}
+
+fn bench_sorted(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sorted");
+    for &count in &[100, 10_000] {
+        let keys = keys(count, 16);
+        let sorted: SortedKeyVec = keys.iter().cloned().collect();
+        let btree_set: BTreeSet<OwnedKey> = keys.iter().cloned().collect();
+        let hash_set: HashSet<OwnedKey> = keys.iter().cloned().collect();
+        let probes: Vec<&OwnedKey> = keys.iter().step_by(count / 100).collect();
+
+        group.bench_with_input(
+            BenchmarkId::new("lookup SortedKeyVec", count),
+            &probes,
+            |b, probes| {
+                b.iter(|| {
+                    for probe in probes {
+                        assert!(sorted.contains(black_box(*probe)));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("lookup BTreeSet", count),
+            &probes,
+            |b, probes| {
+                b.iter(|| {
+                    for probe in probes {
+                        assert!(btree_set.contains(black_box(*probe) as &dyn Key));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("lookup HashSet", count),
+            &probes,
+            |b, probes| {
+                b.iter(|| {
+                    for probe in probes {
+                        assert!(hash_set.contains(black_box(*probe) as &dyn Key));
+                    }
+                })
+            },
+        );
+
+        // The 100 keys in the middle of the set.
+        let in_order = sorted.as_slice();
+        let (start, end) = (&in_order[count / 2 - 50], &in_order[count / 2 + 50]);
+        let bounds = (start as &dyn Key, end as &dyn Key);
+        group.bench_with_input(
+            BenchmarkId::new("range SortedKeyVec", count),
+            &bounds,
+            |b, &(start, end)| {
+                b.iter(|| {
+                    let range = sorted.range(black_box(start)..black_box(end));
+                    assert_eq!(range.len(), 100);
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("range BTreeSet", count),
+            &bounds,
+            |b, &(start, end)| {
+                b.iter(|| {
+                    let range = btree_set.range::<dyn Key, _>((
+                        Bound::Included(black_box(start)),
+                        Bound::Excluded(black_box(end)),
+                    ));
+                    assert_eq!(range.count(), 100);
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("build SortedKeyVec", count),
+            &keys,
+            |b, keys| b.iter(|| black_box(keys.iter().cloned().collect::<SortedKeyVec>())),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("build BTreeSet", count),
+            &keys,
+            |b, keys| b.iter(|| black_box(keys.iter().cloned().collect::<BTreeSet<_>>())),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("build HashSet", count),
+            &keys,
+            |b, keys| b.iter(|| black_box(keys.iter().cloned().collect::<HashSet<_>>())),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_sorted);
+criterion_main!(benches);
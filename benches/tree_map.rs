@@ -0,0 +1,60 @@
+// Compares ways of loading a KeyBTreeMap from keys that are already sorted:
+//
+// - from_sorted_iter, which checks the order and builds the tree bottom up
+// - inserting the keys one at a time
+// - collecting them with FromIterator, which sorts them first
+//
+// Each iteration clones the input, so the clone is included in every measurement.
+
+use borrow_complex_key_example::tree_map::KeyBTreeMap;
+use borrow_complex_key_example::OwnedKey;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+
+fn entries(count: usize) -> Vec<(OwnedKey, usize)> {
+    (0..count)
+        .map(|i| {
+            let key = OwnedKey {
+                s: format!("{:016}", i),
+                bytes: format!("{:x>16}", i).into_bytes(),
+            };
+            (key, i)
+        })
+        .collect()
+}
+
+fn bench_tree_map(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tree_map");
+    group.sample_size(20);
+    for &count in &[1_000, 100_000] {
+        let entries = entries(count);
+
+        group.bench_with_input(
+            BenchmarkId::new("from_sorted_iter", count),
+            &entries,
+            |b, entries| {
+                b.iter(|| black_box(KeyBTreeMap::from_sorted_iter(entries.clone()).unwrap()))
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("insert", count), &entries, |b, entries| {
+            b.iter(|| {
+                let mut map = KeyBTreeMap::new();
+                for (key, value) in entries.clone() {
+                    map.insert(key, value);
+                }
+                black_box(map)
+            })
+        });
+        group.bench_with_input(
+            BenchmarkId::new("collect", count),
+            &entries,
+            |b, entries| {
+                b.iter(|| black_box(entries.clone().into_iter().collect::<KeyBTreeMap<_>>()))
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_tree_map);
+criterion_main!(benches);
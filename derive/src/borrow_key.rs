@@ -0,0 +1,798 @@
+use proc_macro2::{Group, Span, TokenStream, TokenTree};
+use quote::{format_ident, quote, quote_spanned};
+use syn::parse::ParseStream;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{
+    parse_quote, Data, DataEnum, DeriveInput, Error, Field, Fields, GenericArgument, GenericParam,
+    Generics, Ident, LitStr, Path, PathArguments, Token, Type, TypeParamBound, WherePredicate,
+};
+
+pub(crate) fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let opts = ContainerOpts::from_attrs(&input)?;
+
+    let owned = &input.ident;
+    let vis = &input.vis;
+    let borrowed = opts
+        .borrowed
+        .unwrap_or_else(|| default_borrowed_name(owned));
+    let key_trait = opts
+        .key_trait
+        .unwrap_or_else(|| Ident::new("Key", Span::call_site()));
+
+    let Expansion {
+        def,
+        body,
+        hash_body,
+        borrows,
+        skipped,
+        as_ref,
+    } = match &input.data {
+        Data::Enum(data) => expand_enum(&input, data, &borrowed)?,
+        Data::Struct(_) => expand_struct(&input, &borrowed)?,
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                owned,
+                "BorrowKey can only be derived for structs and enums",
+            ))
+        }
+    };
+    if !borrows {
+        return Err(Error::new_spanned(
+            owned,
+            "BorrowKey needs at least one field that borrows (such as a String or a Vec<u8>); \
+             a key made entirely of Copy fields can be used for lookups as-is",
+        ));
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    // The Borrow impl needs one more lifetime, for the trait object, that the owned type has to
+    // outlive.
+    let mut borrow_generics = input.generics.clone();
+    borrow_generics.params.insert(0, parse_quote!('__k));
+    borrow_generics
+        .make_where_clause()
+        .predicates
+        .push(parse_quote!(#owned #ty_generics: '__k));
+    let (borrow_impl_generics, _, borrow_where_clause) = borrow_generics.split_for_impl();
+
+    let borrowed_doc = format!(
+        "The borrowed form of [`{}`], generated by `BorrowKey`.",
+        owned
+    );
+    let trait_doc = format!(
+        "A key that can be viewed as a [`{}`], generated by `BorrowKey`.",
+        borrowed
+    );
+
+    // Eq, Ord and Hash derived on the owned type would look at the skipped fields too, and so
+    // disagree with the borrowed type. The same goes for a field borrowed through AsRef, which
+    // derived impls would compare with its own type's impls rather than its target's. In either
+    // case, they're generated here instead, in terms of the key, which makes them consistent by
+    // construction.
+    let keyed_comparisons = skipped || as_ref;
+    let comparisons = if keyed_comparisons {
+        quote! {
+            impl #impl_generics ::core::cmp::PartialEq for #owned #ty_generics #where_clause {
+                fn eq(&self, other: &Self) -> bool {
+                    #key_trait::key(self).eq(&#key_trait::key(other))
+                }
+            }
+
+            impl #impl_generics ::core::cmp::Eq for #owned #ty_generics #where_clause {}
+
+            impl #impl_generics ::core::cmp::PartialOrd for #owned #ty_generics #where_clause {
+                fn partial_cmp(
+                    &self,
+                    other: &Self,
+                ) -> ::core::option::Option<::core::cmp::Ordering> {
+                    ::core::option::Option::Some(::core::cmp::Ord::cmp(self, other))
+                }
+            }
+
+            impl #impl_generics ::core::cmp::Ord for #owned #ty_generics #where_clause {
+                fn cmp(&self, other: &Self) -> ::core::cmp::Ordering {
+                    #key_trait::key(self).cmp(&#key_trait::key(other))
+                }
+            }
+        }
+    } else {
+        TokenStream::new()
+    };
+
+    // With portable_hash, the borrowed type's Hash is generated too, writing its fields through a
+    // PortableHasher. Hash on the owned type then has to be generated, in terms of the key, just
+    // as it is when fields are skipped, so that the user can't derive one that disagrees.
+    let owned_hash = if keyed_comparisons || opts.portable_hash {
+        quote! {
+            impl #impl_generics ::core::hash::Hash for #owned #ty_generics #where_clause {
+                fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) {
+                    ::core::hash::Hash::hash(&#key_trait::key(self), state)
+                }
+            }
+        }
+    } else {
+        TokenStream::new()
+    };
+    let (borrowed_derives, borrowed_hash) = if opts.portable_hash {
+        let derives = quote!(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd);
+        let hash = quote! {
+            impl<'a> ::core::hash::Hash for #borrowed<'a> {
+                fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) {
+                    let state =
+                        &mut ::borrow_complex_key_example::portable::PortableHasher::new(state);
+                    #hash_body
+                }
+            }
+        };
+        (derives, hash)
+    } else {
+        (
+            quote!(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd),
+            TokenStream::new(),
+        )
+    };
+
+    // The test goes through the main crate's consistency checks, and needs proptest in the crate
+    // that uses the derive. It's an ordinary proptest! block, so failures are persisted next to the
+    // source file, like any other proptest's. Values come from the owned type's Arbitrary impl,
+    // unless a strategy is given. With a strategy, the owned type is inferred from it, so generic
+    // types work too.
+    let consistency_test = if opts.proptest {
+        let strategy =
+            match &opts.strategy {
+                Some(strategy) => quote!(#strategy()),
+                None if input.generics.params.is_empty() => {
+                    quote!(::proptest::arbitrary::any::<#owned>())
+                }
+                None => return Err(Error::new_spanned(
+                    &input.generics,
+                    "the proptest option needs a strategy for a key type with generic parameters, \
+                     as proptest(strategy = path)",
+                )),
+            };
+        let test_name = format_ident!("{}_consistent_borrow", owned);
+        quote! {
+            #[cfg(test)]
+            ::proptest::proptest! {
+                #[test]
+                #[allow(non_snake_case)]
+                fn #test_name(owned1 in #strategy, owned2 in #strategy) {
+                    ::borrow_complex_key_example::consistency::assert_consistent::<
+                        _,
+                        dyn #key_trait,
+                    >(&owned1, &owned2);
+                }
+            }
+        }
+    } else {
+        TokenStream::new()
+    };
+
+    Ok(quote! {
+        #comparisons
+
+        #owned_hash
+
+        #consistency_test
+
+        #[doc = #borrowed_doc]
+        #[derive(#borrowed_derives)]
+        #def
+
+        #borrowed_hash
+
+        #[doc = #trait_doc]
+        #vis trait #key_trait {
+            fn key<'__k>(&'__k self) -> #borrowed<'__k>;
+        }
+
+        impl #impl_generics #key_trait for #owned #ty_generics #where_clause {
+            fn key<'__k>(&'__k self) -> #borrowed<'__k> {
+                #body
+            }
+        }
+
+        impl<'a> #key_trait for #borrowed<'a> {
+            fn key<'__k>(&'__k self) -> #borrowed<'__k> {
+                *self
+            }
+        }
+
+        impl #borrow_impl_generics ::core::borrow::Borrow<dyn #key_trait + '__k>
+            for #owned #ty_generics #borrow_where_clause
+        {
+            fn borrow(&self) -> &(dyn #key_trait + '__k) {
+                self
+            }
+        }
+
+        impl<'a> ::core::cmp::PartialEq for dyn #key_trait + 'a {
+            fn eq(&self, other: &Self) -> bool {
+                self.key().eq(&other.key())
+            }
+        }
+
+        impl<'a> ::core::cmp::Eq for dyn #key_trait + 'a {}
+
+        impl<'a> ::core::cmp::PartialOrd for dyn #key_trait + 'a {
+            fn partial_cmp(&self, other: &Self) -> ::core::option::Option<::core::cmp::Ordering> {
+                ::core::option::Option::Some(self.cmp(other))
+            }
+        }
+
+        impl<'a> ::core::cmp::Ord for dyn #key_trait + 'a {
+            fn cmp(&self, other: &Self) -> ::core::cmp::Ordering {
+                self.key().cmp(&other.key())
+            }
+        }
+
+        impl<'a> ::core::hash::Hash for dyn #key_trait + 'a {
+            fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) {
+                ::core::hash::Hash::hash(&self.key(), state)
+            }
+        }
+    })
+}
+
+#[derive(Default)]
+struct ContainerOpts {
+    borrowed: Option<Ident>,
+    key_trait: Option<Ident>,
+    proptest: bool,
+    strategy: Option<Path>,
+    portable_hash: bool,
+}
+
+impl ContainerOpts {
+    fn from_attrs(input: &DeriveInput) -> syn::Result<Self> {
+        let mut opts = Self::default();
+        for attr in &input.attrs {
+            if !attr.path().is_ident("borrow_key") {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("borrowed") {
+                    opts.borrowed = Some(parse_name(meta.value()?)?);
+                    Ok(())
+                } else if meta.path.is_ident("trait") {
+                    opts.key_trait = Some(parse_name(meta.value()?)?);
+                    Ok(())
+                } else if meta.path.is_ident("proptest") {
+                    opts.proptest = true;
+                    if meta.input.peek(syn::token::Paren) {
+                        meta.parse_nested_meta(|meta| {
+                            if meta.path.is_ident("strategy") {
+                                opts.strategy = Some(meta.value()?.parse()?);
+                                Ok(())
+                            } else {
+                                Err(meta
+                                    .error("unknown borrow_key proptest option; expected strategy"))
+                            }
+                        })?;
+                    }
+                    Ok(())
+                } else if meta.path.is_ident("portable_hash") {
+                    opts.portable_hash = true;
+                    Ok(())
+                } else {
+                    Err(meta.error(
+                        "unknown borrow_key option; expected borrowed, trait, proptest or \
+                         portable_hash",
+                    ))
+                }
+            })?;
+        }
+        Ok(opts)
+    }
+}
+
+// Names can be written bare, or as strings.
+fn parse_name(input: ParseStream<'_>) -> syn::Result<Ident> {
+    if input.peek(LitStr) {
+        input.parse::<LitStr>()?.parse()
+    } else {
+        input.parse()
+    }
+}
+
+#[derive(Default)]
+struct FieldOpts {
+    skip: bool,
+    borrow_with: Option<Path>,
+    ty: Option<Type>,
+}
+
+impl FieldOpts {
+    fn from_attrs(field: &Field) -> syn::Result<Self> {
+        let mut opts = Self::default();
+        for attr in &field.attrs {
+            if !attr.path().is_ident("borrow_key") {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    opts.skip = true;
+                    Ok(())
+                } else if meta.path.is_ident("borrow_with") {
+                    opts.borrow_with = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("ty") {
+                    opts.ty = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta
+                        .error("unknown borrow_key field option; expected skip, borrow_with or ty"))
+                }
+            })?;
+        }
+
+        match (&opts.borrow_with, &opts.ty) {
+            (Some(path), _) if opts.skip => Err(Error::new_spanned(
+                path,
+                "a skipped field isn't borrowed, so it can't have borrow_with",
+            )),
+            (Some(path), None) => Err(Error::new_spanned(
+                path,
+                "borrow_with needs the borrowed type too, as ty = ...",
+            )),
+            (None, Some(ty)) => Err(Error::new_spanned(ty, "ty is only used with borrow_with")),
+            _ => Ok(opts),
+        }
+    }
+}
+
+pub(crate) fn named_fields<'i>(
+    input: &'i DeriveInput,
+    derive: &str,
+) -> syn::Result<&'i Punctuated<Field, Token![,]>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(&fields.named),
+            _ => Err(Error::new_spanned(
+                &input.ident,
+                format!(
+                    "{} can only be derived for structs with named fields",
+                    derive
+                ),
+            )),
+        },
+        _ => Err(Error::new_spanned(
+            &input.ident,
+            format!("{} can only be derived for structs", derive),
+        )),
+    }
+}
+
+// OwnedKey becomes BorrowedKey, following the naming in the crate root. Anything else gets a
+// Borrowed prefix.
+fn default_borrowed_name(owned: &Ident) -> Ident {
+    let name = owned.to_string();
+    match name.strip_prefix("Owned") {
+        Some(rest) if !rest.is_empty() => format_ident!("Borrowed{}", rest),
+        _ => format_ident!("Borrowed{}", name),
+    }
+}
+
+/// The borrowed type generated for a struct or an enum.
+struct Expansion {
+    /// The borrowed type's definition, without its attributes.
+    def: TokenStream,
+    /// The body of the owned type's `key` method.
+    body: TokenStream,
+    /// The body of the borrowed type's `hash` method, for `portable_hash`. It hashes `self` into
+    /// `state`.
+    hash_body: TokenStream,
+    /// Whether any field borrows from the owned value.
+    borrows: bool,
+    /// Whether any field was skipped.
+    skipped: bool,
+    /// Whether any field borrows through an `AsRef` bound on a type parameter.
+    as_ref: bool,
+}
+
+fn expand_struct(input: &DeriveInput, borrowed: &Ident) -> syn::Result<Expansion> {
+    let fields = named_fields(input, "BorrowKey")?;
+    let mut defs = Vec::new();
+    let mut inits = Vec::new();
+    let mut hashes = Vec::new();
+    let mut borrows = false;
+    let mut skipped = false;
+    let mut as_ref = false;
+    for field in fields {
+        let opts = FieldOpts::from_attrs(field)?;
+        if opts.skip {
+            skipped = true;
+            continue;
+        }
+        let name = field.ident.as_ref().expect("named fields have idents");
+        let field_vis = &field.vis;
+        let Projection {
+            ty,
+            expr,
+            borrows: field_borrows,
+            as_ref: field_as_ref,
+        } = Projection::new(field, opts, quote!(self.#name), &input.generics)?;
+        borrows |= field_borrows;
+        as_ref |= field_as_ref;
+        defs.push(quote!(#field_vis #name: #ty));
+        inits.push(quote!(#name: #expr));
+        hashes.push(quote!(::core::hash::Hash::hash(&self.#name, state);));
+    }
+
+    let vis = &input.vis;
+    Ok(Expansion {
+        def: quote! {
+            #vis struct #borrowed<'a> {
+                #( #defs, )*
+            }
+        },
+        body: quote! {
+            #borrowed {
+                #( #inits, )*
+            }
+        },
+        hash_body: quote!(#( #hashes )*),
+        borrows,
+        skipped,
+        as_ref,
+    })
+}
+
+// The borrowed enum has the same variants, in the same order, each with its fields borrowed, so
+// the derived Ord on both compares variants first and then fields, in the same way. An explicit
+// discriminant would change the owned enum's order without changing the borrowed one's, so those
+// are rejected.
+fn expand_enum(input: &DeriveInput, data: &DataEnum, borrowed: &Ident) -> syn::Result<Expansion> {
+    let owned = &input.ident;
+    let mut variants = Vec::new();
+    let mut arms = Vec::new();
+    let mut hash_arms = Vec::new();
+    let mut borrows = false;
+    let mut skipped = false;
+    let mut as_ref = false;
+    for (index, variant) in data.variants.iter().enumerate() {
+        if let Some((_, discriminant)) = &variant.discriminant {
+            return Err(Error::new_spanned(
+                discriminant,
+                "BorrowKey doesn't support explicit discriminants, since they can change the \
+                 order of variants",
+            ));
+        }
+
+        let name = &variant.ident;
+        let mut pats = Vec::new();
+        let mut defs = Vec::new();
+        let mut inits = Vec::new();
+        let mut bindings = Vec::new();
+        for (i, field) in variant.fields.iter().enumerate() {
+            let opts = FieldOpts::from_attrs(field)?;
+            if opts.skip {
+                skipped = true;
+                pats.push(match &field.ident {
+                    Some(ident) => quote!(#ident: _),
+                    None => quote!(_),
+                });
+                continue;
+            }
+
+            // The match binds fields by reference, so the field itself is the binding,
+            // dereferenced.
+            let binding = match &field.ident {
+                Some(ident) => ident.clone(),
+                None => format_ident!("__f{}", i),
+            };
+            let Projection {
+                ty,
+                expr,
+                borrows: field_borrows,
+                as_ref: field_as_ref,
+            } = Projection::new(field, opts, quote!((*#binding)), &input.generics)?;
+            borrows |= field_borrows;
+            as_ref |= field_as_ref;
+            pats.push(quote!(#binding));
+            bindings.push(binding);
+            match &field.ident {
+                Some(ident) => {
+                    defs.push(quote!(#ident: #ty));
+                    inits.push(quote!(#ident: #expr));
+                }
+                None => {
+                    defs.push(ty);
+                    inits.push(expr);
+                }
+            }
+        }
+
+        // The variant's index is hashed first, as a u32, where a derived Hash would write the
+        // discriminant as an isize.
+        let index = index as u32;
+        let hashes = quote! {
+            ::core::hash::Hasher::write_u32(state, #index);
+            #( ::core::hash::Hash::hash(#bindings, state); )*
+        };
+        match &variant.fields {
+            Fields::Named(_) => {
+                hash_arms.push(quote!(#borrowed::#name { #( #bindings, )* } => { #hashes }));
+                variants.push(quote!(#name { #( #defs, )* }));
+                arms.push(
+                    quote!(#owned::#name { #( #pats, )* } => #borrowed::#name { #( #inits, )* }),
+                );
+            }
+            Fields::Unnamed(_) => {
+                hash_arms.push(quote!(#borrowed::#name( #( #bindings, )* ) => { #hashes }));
+                variants.push(quote!(#name( #( #defs, )* )));
+                arms.push(
+                    quote!(#owned::#name( #( #pats, )* ) => #borrowed::#name( #( #inits, )* )),
+                );
+            }
+            Fields::Unit => {
+                hash_arms.push(quote!(#borrowed::#name => { #hashes }));
+                variants.push(quote!(#name));
+                arms.push(quote!(#owned::#name => #borrowed::#name));
+            }
+        }
+    }
+
+    let vis = &input.vis;
+    Ok(Expansion {
+        def: quote! {
+            #vis enum #borrowed<'a> {
+                #( #variants, )*
+            }
+        },
+        body: quote! {
+            match self {
+                #( #arms, )*
+            }
+        },
+        hash_body: quote! {
+            match self {
+                #( #hash_arms )*
+            }
+        },
+        borrows,
+        skipped,
+        as_ref,
+    })
+}
+
+/// How a single owned field turns into a borrowed one.
+struct Projection {
+    /// The field's type in the borrowed struct, in terms of the lifetime `'a`.
+    ty: TokenStream,
+    /// An expression producing the borrowed field.
+    expr: TokenStream,
+    /// Whether `ty` borrows from the owned value (as opposed to being a copy).
+    borrows: bool,
+    /// Whether the field borrows through an `AsRef` bound on a type parameter.
+    as_ref: bool,
+}
+
+const COPY_PRIMITIVES: &[&str] = &[
+    "bool", "char", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128",
+    "isize",
+];
+
+impl Projection {
+    /// Works out how to borrow `field`, found at `place`.
+    fn new(
+        field: &Field,
+        opts: FieldOpts,
+        place: TokenStream,
+        generics: &Generics,
+    ) -> syn::Result<Self> {
+        let as_ref = opts.borrow_with.is_none() && borrows_through_as_ref(&field.ty, generics);
+        let (ty, expr, borrows) = match (opts.borrow_with, opts.ty) {
+            // A user-supplied function is trusted to borrow, like the built-in projections. The
+            // call is spanned to the function's path, and its argument to the field's type, so that
+            // if the function's signature doesn't fit, the type errors point at those rather than
+            // at the derive.
+            (Some(path), Some(ty)) => {
+                let arg = respan(quote!(&#place), field.ty.span());
+                let expr = quote_spanned!(path.span()=> #path(#arg));
+                (quote!(#ty), expr, true)
+            }
+            _ => borrow_field(&field.ty, place, generics).map_err(|err| {
+                Error::new(
+                    err.span(),
+                    format!(
+                        "{}; to borrow it with a function, add \
+                         #[borrow_key(borrow_with = path, ty = BorrowedType)], or to leave it out \
+                         of the key, add #[borrow_key(skip)]",
+                        err
+                    ),
+                )
+            })?,
+        };
+        Ok(Projection {
+            ty,
+            expr,
+            borrows,
+            as_ref,
+        })
+    }
+}
+
+fn respan(tokens: TokenStream, span: Span) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|tt| match tt {
+            TokenTree::Group(group) => {
+                let mut respanned = Group::new(group.delimiter(), respan(group.stream(), span));
+                respanned.set_span(span);
+                TokenTree::Group(respanned)
+            }
+            mut tt => {
+                tt.set_span(span);
+                tt
+            }
+        })
+        .collect()
+}
+
+/// Works out how to borrow `place`, a field (or part of a field) of type `ty`. Returns the
+/// borrowed type, an expression producing it, and whether it borrows.
+pub(crate) fn borrow_field(
+    ty: &Type,
+    place: TokenStream,
+    generics: &Generics,
+) -> syn::Result<(TokenStream, TokenStream, bool)> {
+    let deref = |target: TokenStream| (quote!(&'a #target), quote!(&*#place), true);
+
+    if let Some(target) = generic_as_ref_target(ty, generics) {
+        return Ok((
+            quote!(&'a #target),
+            quote!(::core::convert::AsRef::<#target>::as_ref(&#place)),
+            true,
+        ));
+    }
+
+    if let Type::Path(type_path) = ty {
+        let segment = type_path.path.segments.last().expect("paths are non-empty");
+        let arg = single_type_arg(&segment.arguments);
+        let name = segment.ident.to_string();
+
+        // These borrow as a reference to their contents, which would put the type parameter in the
+        // borrowed type, and that has no type parameters.
+        if let ("Vec" | "Box" | "Rc" | "Arc", Some(inner)) = (name.as_str(), arg) {
+            if let Some(param) = type_param_in(inner, generics) {
+                return Err(Error::new_spanned(
+                    ty,
+                    format!(
+                        "this field would borrow as a type that mentions the type parameter `{}`, \
+                         but the borrowed type can't have type parameters",
+                        param
+                    ),
+                ));
+            }
+        }
+
+        match (name.as_str(), arg) {
+            ("String", None) => return Ok(deref(quote!(str))),
+            ("Vec", Some(inner)) => return Ok(deref(quote!([#inner]))),
+            ("Box", Some(inner)) | ("Rc", Some(inner)) | ("Arc", Some(inner)) => {
+                return Ok(deref(quote!(#inner)))
+            }
+            ("PathBuf", None) => return Ok(deref(quote!(::std::path::Path))),
+            ("OsString", None) => return Ok(deref(quote!(::std::ffi::OsStr))),
+            ("CString", None) => return Ok(deref(quote!(::std::ffi::CStr))),
+            // Reverse<T> borrows as Reverse<T's borrowed form>, which orders the same way.
+            ("Reverse", Some(inner)) => {
+                let (ty, expr, borrows) = borrow_field(inner, quote!(#place.0), generics)?;
+                return Ok((
+                    quote!(::core::cmp::Reverse<#ty>),
+                    quote!(::core::cmp::Reverse(#expr)),
+                    borrows,
+                ));
+            }
+            (primitive, None)
+                if type_path.path.segments.len() == 1 && COPY_PRIMITIVES.contains(&primitive) =>
+            {
+                return Ok((quote!(#ty), place, false))
+            }
+            _ => {}
+        }
+    }
+
+    Err(Error::new_spanned(
+        ty,
+        "don't know how to borrow a field of this type",
+    ))
+}
+
+fn single_type_arg(arguments: &PathArguments) -> Option<&Type> {
+    match arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Whether borrow_field borrows a field of type `ty` through generic_as_ref_target, either directly
+// or inside a Reverse.
+fn borrows_through_as_ref(ty: &Type, generics: &Generics) -> bool {
+    if generic_as_ref_target(ty, generics).is_some() {
+        return true;
+    }
+    match ty {
+        Type::Path(type_path) => {
+            let segment = type_path.path.segments.last().expect("paths are non-empty");
+            match single_type_arg(&segment.arguments) {
+                Some(inner) if segment.ident == "Reverse" => {
+                    borrows_through_as_ref(inner, generics)
+                }
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+// For a field whose type is one of the struct's type parameters, look for an `AsRef<X>` bound on
+// that parameter, either inline or in the where clause. The field then borrows as an `&X`.
+fn generic_as_ref_target(ty: &Type, generics: &Generics) -> Option<Type> {
+    let ident = match ty {
+        Type::Path(type_path) if type_path.qself.is_none() => type_path.path.get_ident()?,
+        _ => return None,
+    };
+
+    let inline = generics.params.iter().filter_map(|param| match param {
+        GenericParam::Type(param) if param.ident == *ident => Some(&param.bounds),
+        _ => None,
+    });
+    let where_clause = generics
+        .where_clause
+        .iter()
+        .flat_map(|w| &w.predicates)
+        .filter_map(|predicate| match predicate {
+            WherePredicate::Type(predicate) if is_ident(&predicate.bounded_ty, ident) => {
+                Some(&predicate.bounds)
+            }
+            _ => None,
+        });
+
+    inline
+        .chain(where_clause)
+        .flatten()
+        .find_map(|bound| match bound {
+            TypeParamBound::Trait(bound) => {
+                let segment = bound.path.segments.last()?;
+                if segment.ident != "AsRef" {
+                    return None;
+                }
+                single_type_arg(&segment.arguments).cloned()
+            }
+            _ => None,
+        })
+}
+
+// Returns the first of the struct's type parameters that appears anywhere in `ty`.
+fn type_param_in<'g>(ty: &Type, generics: &'g Generics) -> Option<&'g Ident> {
+    fn idents(tokens: TokenStream, out: &mut Vec<Ident>) {
+        for tt in tokens {
+            match tt {
+                TokenTree::Ident(ident) => out.push(ident),
+                TokenTree::Group(group) => idents(group.stream(), out),
+                _ => {}
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    idents(quote!(#ty), &mut found);
+    generics
+        .type_params()
+        .map(|param| &param.ident)
+        .find(|param| found.contains(param))
+}
+
+fn is_ident(ty: &Type, ident: &Ident) -> bool {
+    match ty {
+        Type::Path(type_path) => type_path.qself.is_none() && type_path.path.is_ident(ident),
+        _ => false,
+    }
+}
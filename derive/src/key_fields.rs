@@ -0,0 +1,25 @@
+use crate::borrow_key::named_fields;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::DeriveInput;
+
+pub(crate) fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let fields = named_fields(&input, "KeyFields")?;
+    let names = fields.iter().map(|field| {
+        field
+            .ident
+            .as_ref()
+            .expect("named fields have idents")
+            .to_string()
+    });
+
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::borrow_complex_key_example::key_fields::KeyFields
+            for #ty #ty_generics #where_clause
+        {
+            const FIELDS: &'static [&'static str] = &[#( #names ),*];
+        }
+    })
+}
@@ -0,0 +1,56 @@
+// borrow-complex-key-example
+//
+// Written in 2020 by Rain <rain@sunshowers.io>
+//
+// To the extent possible under law, the author(s) have dedicated all copyright and related and
+// neighboring rights to this software to the public domain worldwide. This software is distributed
+// without any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication along with this software. If
+// not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+
+//! Derive macros for `borrow-complex-key-example`.
+//!
+//! Don't depend on this crate directly: use the re-exports from the main crate instead.
+
+extern crate proc_macro;
+
+mod borrow_key;
+mod key_fields;
+mod multi_index;
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+/// Generates the borrowed type, the `Key` trait and all of the trait object impls for an owned key.
+///
+/// See the `derive` module in the main crate for documentation.
+#[proc_macro_derive(BorrowKey, attributes(borrow_key))]
+pub fn derive_borrow_key(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    borrow_key::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Lists a struct's field names, in declaration order, for `assert_key_fields_match!`.
+///
+/// See the `key_fields` module in the main crate for documentation.
+#[proc_macro_derive(KeyFields)]
+pub fn derive_key_fields(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    key_fields::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Generates a container that keeps a collection of records indexed by several keys at once.
+///
+/// See the `multi_index` module in the main crate for documentation.
+#[proc_macro_derive(MultiIndex, attributes(multi_index))]
+pub fn derive_multi_index(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    multi_index::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,351 @@
+use crate::borrow_key::{borrow_field, named_fields};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::punctuated::Punctuated;
+use syn::{DeriveInput, Error, Field, Ident, LitStr, Token, Type};
+
+pub(crate) fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let opts = ContainerOpts::from_attrs(&input)?;
+    let fields = named_fields(&input, "MultiIndex")?;
+
+    let record = &input.ident;
+    let vis = &input.vis;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "MultiIndex can't be derived for generic records",
+        ));
+    }
+    if opts.indexes.is_empty() {
+        return Err(Error::new_spanned(
+            record,
+            "MultiIndex needs at least one index, such as #[multi_index(by_id(id))]",
+        ));
+    }
+    for (i, spec) in opts.indexes.iter().enumerate() {
+        if opts.indexes[..i]
+            .iter()
+            .any(|other| other.name == spec.name)
+        {
+            return Err(Error::new_spanned(&spec.name, "duplicate index name"));
+        }
+    }
+
+    let container = opts
+        .container
+        .unwrap_or_else(|| format_ident!("{}Index", record));
+    let indexes = opts
+        .indexes
+        .iter()
+        .map(|spec| Index::new(spec, &container, fields))
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let family = quote!(::borrow_complex_key_example::family);
+    let names: Vec<_> = indexes.iter().map(|index| index.name).collect();
+    let name_strs: Vec<_> = indexes
+        .iter()
+        .map(|index| LitStr::new(&index.name.to_string(), index.name.span()))
+        .collect();
+    let keys: Vec<_> = indexes.iter().map(|index| &index.key).collect();
+
+    let key_defs = indexes.iter().map(|index| {
+        let key = &index.key;
+        let field_names = &index.field_names;
+        let field_tys = &index.field_tys;
+        let borrowed = tuple_or_single(&index.borrowed_tys);
+        let self_projection = tuple_or_single(&index.self_exprs);
+        let record_projection = tuple_or_single(&index.record_exprs);
+
+        // The owned key derives Eq and Hash field by field, in order, which is exactly what the
+        // borrowed tuple (or single value) does.
+        quote! {
+            #[derive(Eq, Hash, PartialEq)]
+            struct #key {
+                #( #field_names: #field_tys, )*
+            }
+
+            impl #key {
+                fn new(record: &#record) -> Self {
+                    Self {
+                        #( #field_names: ::core::clone::Clone::clone(&record.#field_names), )*
+                    }
+                }
+
+                fn project(record: &#record) -> <Self as #family::KeyFamily>::Borrowed<'_> {
+                    #record_projection
+                }
+            }
+
+            impl #family::KeyFamily for #key {
+                type Borrowed<'a> = #borrowed;
+
+                fn shorten<'long: 'short, 'short>(
+                    borrowed: Self::Borrowed<'long>,
+                ) -> Self::Borrowed<'short> {
+                    borrowed
+                }
+            }
+
+            impl #family::Lookup<#key> for #key {
+                fn lookup_key(&self) -> <Self as #family::KeyFamily>::Borrowed<'_> {
+                    #self_projection
+                }
+            }
+
+            impl<'a> ::core::borrow::Borrow<dyn #family::Lookup<#key> + 'a> for #key {
+                fn borrow(&self) -> &(dyn #family::Lookup<#key> + 'a) {
+                    self
+                }
+            }
+        }
+    });
+
+    let lookup_fns = indexes.iter().map(|index| {
+        let name = index.name;
+        let key = &index.key;
+        let get = format_ident!("get_{}", name);
+        let remove = format_ident!("remove_{}", name);
+        let params = &index.field_names;
+        let param_tys = &index.borrowed_tys;
+        let lifetime = if index.borrows {
+            quote!(<'a>)
+        } else {
+            quote!()
+        };
+        let probe = tuple_or_single(
+            &params
+                .iter()
+                .map(|param| quote!(#param))
+                .collect::<Vec<_>>(),
+        );
+        let get_doc = format!(
+            "Returns the record with these fields in the `{}` index, if there is one.",
+            name
+        );
+        let remove_doc = format!(
+            "Removes and returns the record with these fields in the `{}` index, if there is one.",
+            name
+        );
+
+        quote! {
+            #[doc = #get_doc]
+            pub fn #get #lifetime(&self, #( #params: #param_tys ),*) -> ::core::option::Option<&#record> {
+                let position = *self.#name.get(#family::Probe::<#key>(#probe).as_lookup())?;
+                ::core::option::Option::Some(&self.records[position])
+            }
+
+            #[doc = #remove_doc]
+            pub fn #remove #lifetime(&mut self, #( #params: #param_tys ),*) -> ::core::option::Option<#record> {
+                let position = *self.#name.get(#family::Probe::<#key>(#probe).as_lookup())?;
+                ::core::option::Option::Some(self.remove_at(position))
+            }
+        }
+    });
+
+    let container_doc = format!(
+        "A collection of [`{}`] records, indexed by each of their keys. Generated by `MultiIndex`.",
+        record
+    );
+
+    Ok(quote! {
+        #( #key_defs )*
+
+        #[doc = #container_doc]
+        #vis struct #container {
+            records: ::std::vec::Vec<#record>,
+            #( #names: ::std::collections::HashMap<#keys, usize>, )*
+        }
+
+        impl ::core::default::Default for #container {
+            fn default() -> Self {
+                Self {
+                    records: ::std::vec::Vec::new(),
+                    #( #names: ::std::collections::HashMap::new(), )*
+                }
+            }
+        }
+
+        impl #container {
+            /// Creates a new, empty collection.
+            pub fn new() -> Self {
+                ::core::default::Default::default()
+            }
+
+            /// Inserts `record`, as long as none of its keys are already taken. Otherwise, returns
+            /// it in the error, along with the name of the first index that already had its key.
+            pub fn insert(
+                &mut self,
+                record: #record,
+            ) -> ::core::result::Result<(), ::borrow_complex_key_example::multi_index::DuplicateKey<#record>> {
+                #(
+                    if self.#names.contains_key(#family::Probe::<#keys>(#keys::project(&record)).as_lookup()) {
+                        return ::core::result::Result::Err(
+                            ::borrow_complex_key_example::multi_index::DuplicateKey {
+                                index: #name_strs,
+                                record,
+                            },
+                        );
+                    }
+                )*
+                let position = self.records.len();
+                #( self.#names.insert(#keys::new(&record), position); )*
+                self.records.push(record);
+                ::core::result::Result::Ok(())
+            }
+
+            #( #lookup_fns )*
+
+            /// Returns the number of records.
+            pub fn len(&self) -> usize {
+                self.records.len()
+            }
+
+            /// Returns true if there are no records.
+            pub fn is_empty(&self) -> bool {
+                self.records.is_empty()
+            }
+
+            /// Iterates over the records, in arbitrary order.
+            pub fn iter(&self) -> ::core::slice::Iter<'_, #record> {
+                self.records.iter()
+            }
+
+            // Removes the record at `position` from every index, then moves the last record into
+            // its place, like Vec::swap_remove.
+            fn remove_at(&mut self, position: usize) -> #record {
+                let record = self.records.swap_remove(position);
+                #( self.#names.remove(#family::Probe::<#keys>(#keys::project(&record)).as_lookup()); )*
+                if let ::core::option::Option::Some(moved) = self.records.get(position) {
+                    #(
+                        *self
+                            .#names
+                            .get_mut(#family::Probe::<#keys>(#keys::project(moved)).as_lookup())
+                            .expect("every record is in every index") = position;
+                    )*
+                }
+                record
+            }
+        }
+    })
+}
+
+#[derive(Default)]
+struct ContainerOpts {
+    container: Option<Ident>,
+    indexes: Vec<IndexSpec>,
+}
+
+/// An index as written in the attribute, such as `by_key(s, bytes)`.
+struct IndexSpec {
+    name: Ident,
+    fields: Vec<Ident>,
+}
+
+impl ContainerOpts {
+    fn from_attrs(input: &DeriveInput) -> syn::Result<Self> {
+        let mut opts = Self::default();
+        for attr in &input.attrs {
+            if !attr.path().is_ident("multi_index") {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("container") {
+                    opts.container = Some(meta.value()?.parse()?);
+                    return Ok(());
+                }
+                let name = match meta.path.get_ident() {
+                    Some(name) => name.clone(),
+                    None => return Err(meta.error("expected an index name")),
+                };
+                let mut fields = Vec::new();
+                meta.parse_nested_meta(|field| match field.path.get_ident() {
+                    Some(ident) => {
+                        fields.push(ident.clone());
+                        Ok(())
+                    }
+                    None => Err(field.error("expected a field name")),
+                })?;
+                if fields.is_empty() {
+                    return Err(meta.error("an index needs at least one field"));
+                }
+                opts.indexes.push(IndexSpec { name, fields });
+                Ok(())
+            })?;
+        }
+        Ok(opts)
+    }
+}
+
+/// Everything needed to generate one index.
+struct Index<'s> {
+    name: &'s Ident,
+    /// The owned key type for this index, such as `RecordIndexByKey`.
+    key: Ident,
+    field_names: Vec<&'s Ident>,
+    field_tys: Vec<&'s Type>,
+    /// Each field's borrowed type, in terms of the lifetime `'a`.
+    borrowed_tys: Vec<TokenStream>,
+    /// Each field borrowed from `self`, an owned key.
+    self_exprs: Vec<TokenStream>,
+    /// Each field borrowed from `record`.
+    record_exprs: Vec<TokenStream>,
+    /// Whether any of the fields borrow, so that lookups need the lifetime `'a`.
+    borrows: bool,
+}
+
+impl<'s> Index<'s> {
+    fn new(
+        spec: &'s IndexSpec,
+        container: &Ident,
+        fields: &'s Punctuated<Field, Token![,]>,
+    ) -> syn::Result<Self> {
+        let mut index = Index {
+            name: &spec.name,
+            key: format_ident!("{}{}", container, camel_case(&spec.name)),
+            field_names: Vec::new(),
+            field_tys: Vec::new(),
+            borrowed_tys: Vec::new(),
+            self_exprs: Vec::new(),
+            record_exprs: Vec::new(),
+            borrows: false,
+        };
+        for name in &spec.fields {
+            let field = fields
+                .iter()
+                .find(|field| field.ident.as_ref() == Some(name))
+                .ok_or_else(|| Error::new_spanned(name, "no field with this name"))?;
+            let generics = Default::default();
+            let (ty, self_expr, borrows) = borrow_field(&field.ty, quote!(self.#name), &generics)?;
+            let (_, record_expr, _) = borrow_field(&field.ty, quote!(record.#name), &generics)?;
+            index.field_names.push(name);
+            index.field_tys.push(&field.ty);
+            index.borrowed_tys.push(ty);
+            index.self_exprs.push(self_expr);
+            index.record_exprs.push(record_expr);
+            index.borrows |= borrows;
+        }
+        Ok(index)
+    }
+}
+
+// An index on one field is looked up by that field's borrowed form on its own, and an index on
+// several fields by a tuple.
+fn tuple_or_single(items: &[TokenStream]) -> TokenStream {
+    match items {
+        [item] => item.clone(),
+        items => quote!(( #( #items ),* )),
+    }
+}
+
+fn camel_case(ident: &Ident) -> String {
+    ident
+        .to_string()
+        .split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().expect("words are non-empty");
+            first.to_uppercase().chain(chars).collect::<String>()
+        })
+        .collect()
+}
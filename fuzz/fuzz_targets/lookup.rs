@@ -0,0 +1,51 @@
+// Differential fuzzing of the two lookup paths: probing a set with an owned key, and probing it
+// with a borrowed key through dyn Key. They should never disagree.
+//
+// Run with `cargo fuzz run lookup` from the repository root (requires nightly).
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use borrow_complex_key_example::set::KeySet;
+use borrow_complex_key_example::{BorrowedKey, Key, OwnedKey};
+use libfuzzer_sys::fuzz_target;
+use std::collections::{BTreeSet, HashSet};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    keys: Vec<(String, Vec<u8>)>,
+    probes: Vec<(String, Vec<u8>)>,
+}
+
+fuzz_target!(|input: Input| {
+    let keys: Vec<OwnedKey> = input
+        .keys
+        .into_iter()
+        .map(|(s, bytes)| OwnedKey { s, bytes })
+        .collect();
+    let hash_set: HashSet<OwnedKey> = keys.iter().cloned().collect();
+    let btree_set: BTreeSet<OwnedKey> = keys.iter().cloned().collect();
+    let key_set: KeySet = keys.iter().cloned().collect();
+
+    // Probe with the inserted keys as well as the random ones, so that hits get exercised too.
+    let probes = input
+        .probes
+        .iter()
+        .map(|(s, bytes)| BorrowedKey { s, bytes })
+        .chain(keys.iter().map(OwnedKey::key));
+    for borrowed_probe in probes {
+        let owned_probe = OwnedKey {
+            s: borrowed_probe.s.to_owned(),
+            bytes: borrowed_probe.bytes.to_owned(),
+        };
+        let expected = hash_set.contains(&owned_probe);
+        assert_eq!(expected, hash_set.contains(&borrowed_probe as &dyn Key));
+        assert_eq!(expected, btree_set.contains(&owned_probe));
+        assert_eq!(expected, btree_set.contains(&borrowed_probe as &dyn Key));
+        assert_eq!(expected, key_set.contains(&borrowed_probe));
+        assert_eq!(
+            hash_set.get(&owned_probe),
+            hash_set.get(&borrowed_probe as &dyn Key)
+        );
+    }
+});
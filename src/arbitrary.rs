@@ -0,0 +1,24 @@
+//! quickcheck's Arbitrary for the key types.
+
+use crate::OwnedKey;
+use quickcheck::{Arbitrary, Gen};
+
+// The crate root derives proptest's Arbitrary for OwnedKey. quickcheck has its own trait with the
+// same name, which has no derive, so it's written out here. Shrinking shrinks both fields at once,
+// by way of the tuple impl.
+impl<B: Arbitrary> Arbitrary for OwnedKey<B> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        OwnedKey {
+            s: String::arbitrary(g),
+            bytes: B::arbitrary(g),
+        }
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(
+            (self.s.clone(), self.bytes.clone())
+                .shrink()
+                .map(|(s, bytes)| OwnedKey { s, bytes }),
+        )
+    }
+}
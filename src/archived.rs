@@ -0,0 +1,90 @@
+//! Looking keys up straight out of an rkyv archive.
+
+use crate::{ArchivedOwnedKey, BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use rkyv::rancor;
+use rkyv::Archive;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashSet};
+use std::hash::{Hash, Hasher};
+
+// rkyv lays out data on disk the same way it's laid out in memory, so an archive can be read by
+// casting a pointer to its bytes (say, from a memory-mapped file) instead of deserializing it. With
+// the rkyv feature on, OwnedKey derives rkyv's Archive, which generates an ArchivedOwnedKey: a
+// struct with an ArchivedString and an ArchivedVec<u8> in place of the String and Vec<u8>.
+//
+// Both of those deref to the same str and [u8] that a BorrowedKey holds, so ArchivedOwnedKey can
+// implement Key, and then an archived key can be used to probe a set of live keys directly,
+// without being deserialized into an OwnedKey first.
+impl<B> Key for ArchivedOwnedKey<B>
+where
+    B: Archive,
+    B::Archived: AsRef<[u8]>,
+{
+    fn key(&self) -> BorrowedKey<'_> {
+        BorrowedKey {
+            s: self.s.as_str(),
+            bytes: self.bytes.as_ref(),
+        }
+    }
+}
+
+#[cfg(test)]
+fn hash_of(key: &dyn Key) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn archived_lookups() {
+    let live: HashSet<OwnedKey> = std::iter::once(OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    })
+    .collect();
+
+    // An archive of keys, as it might be read from a file.
+    let keys = vec![
+        OwnedKey {
+            s: "foo".to_string(),
+            bytes: b"abc".to_vec(),
+        },
+        OwnedKey {
+            s: "bar".to_string(),
+            bytes: vec![],
+        },
+    ];
+    let bytes = rkyv::to_bytes::<rancor::Error>(&keys).unwrap();
+    let archived = rkyv::access::<rkyv::Archived<Vec<OwnedKey>>, rancor::Error>(&bytes).unwrap();
+
+    assert!(live.contains(&archived[0] as &dyn Key));
+    assert!(!live.contains(&archived[1] as &dyn Key));
+}
+
+proptest! {
+    #[test]
+    fn archived_consistent(key in any::<OwnedKey>(), other in any::<OwnedKey>()) {
+        let bytes = rkyv::to_bytes::<rancor::Error>(&key).unwrap();
+        let archived = rkyv::access::<ArchivedOwnedKey, rancor::Error>(&bytes).unwrap();
+
+        let live: &dyn Key = &key;
+        let archived: &dyn Key = archived;
+        let other: &dyn Key = &other;
+        prop_assert!(live == archived);
+        prop_assert_eq!(hash_of(live), hash_of(archived));
+        prop_assert_eq!(live.cmp(other), archived.cmp(other));
+
+        let hash_set: HashSet<&dyn Key> = std::iter::once(live).collect();
+        let btree_set: BTreeSet<&dyn Key> = std::iter::once(live).collect();
+        prop_assert!(hash_set.contains(archived));
+        prop_assert!(btree_set.contains(archived));
+
+        // Deserializing gets back the original key.
+        let deserialized: OwnedKey = rkyv::deserialize::<_, rancor::Error>(
+            rkyv::access::<ArchivedOwnedKey, rancor::Error>(&bytes).unwrap(),
+        )
+        .unwrap();
+        prop_assert_eq!(deserialized, key);
+    }
+}
@@ -0,0 +1,148 @@
+//! Keys that live in an arena.
+//!
+//! Requires the `arena` feature.
+//!
+//! Keys allocated in an arena can't outlive it:
+//!
+//! ```compile_fail
+//! use borrow_complex_key_example::arena::alloc_key;
+//! use borrow_complex_key_example::BorrowedKey;
+//! use bumpalo::Bump;
+//!
+//! let key = {
+//!     let arena = Bump::new();
+//!     alloc_key(&arena, BorrowedKey { s: "foo", bytes: b"abc" })
+//! };
+//! println!("{:?}", key);
+//! ```
+
+use crate::consistency::assert_consistent;
+use crate::{BorrowedKey, Key, OwnedKey};
+use bumpalo::Bump;
+use proptest::prelude::*;
+use std::collections::HashSet;
+
+// A server that builds up a set of keys while handling a request, and throws the whole set away at
+// the end of it, doesn't need each key to have its own heap allocation. If the keys' data is
+// copied into a bumpalo arena instead, each key costs a pointer bump, and the whole lot is freed at
+// once when the arena goes away.
+//
+// An arena-allocated key is just a BorrowedKey<'arena>: there's no need for an owned type at all.
+// The set below stores those directly.
+/// Copies `key` into `arena`, returning a key that borrows from the arena instead.
+pub fn alloc_key<'arena>(arena: &'arena Bump, key: BorrowedKey<'_>) -> BorrowedKey<'arena> {
+    BorrowedKey {
+        s: arena.alloc_str(key.s),
+        bytes: arena.alloc_slice_copy(key.bytes),
+    }
+}
+
+// Storing BorrowedKeys in a set needs them to implement Borrow<dyn Key>, which the crate root
+// does for any shorter trait object lifetime.
+/// A set of keys whose data lives in an arena.
+///
+/// Looking up a key never allocates, and neither does interning a key that's already in the set.
+/// Interning a new key copies it into the arena. (The set's own table is on the heap, as usual.)
+#[derive(Debug)]
+pub struct ArenaKeySet<'arena> {
+    arena: &'arena Bump,
+    keys: HashSet<BorrowedKey<'arena>>,
+}
+
+impl<'arena> ArenaKeySet<'arena> {
+    /// Creates a new, empty set, allocating keys in `arena`.
+    pub fn new(arena: &'arena Bump) -> Self {
+        Self {
+            arena,
+            keys: HashSet::new(),
+        }
+    }
+
+    /// Returns the arena copy of `key`, copying it into the arena first if it isn't in the set.
+    pub fn intern(&mut self, key: BorrowedKey<'_>) -> BorrowedKey<'arena> {
+        if let Some(interned) = self.get(key) {
+            return interned;
+        }
+        let interned = alloc_key(self.arena, key);
+        self.keys.insert(interned);
+        interned
+    }
+
+    /// Returns the arena copy of `key`, if it's in the set.
+    pub fn get(&self, key: BorrowedKey<'_>) -> Option<BorrowedKey<'arena>> {
+        self.keys.get(&key as &dyn Key).copied()
+    }
+
+    /// Returns true if `key` is in the set.
+    pub fn contains(&self, key: BorrowedKey<'_>) -> bool {
+        self.keys.contains(&key as &dyn Key)
+    }
+
+    /// Returns the number of keys in the set.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Iterates over the keys in the set, in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = BorrowedKey<'arena>> + '_ {
+        self.keys.iter().copied()
+    }
+}
+
+#[test]
+fn arena_lookups() {
+    let arena = Bump::new();
+    let mut set = ArenaKeySet::new(&arena);
+
+    let interned = {
+        // Pretend this is a request body, which goes away once the request is handled.
+        let request = String::from("foo");
+        let key = set.intern(BorrowedKey {
+            s: &request,
+            bytes: b"abc",
+        });
+        assert_ne!(key.s.as_ptr(), request.as_ptr());
+        key
+    };
+    // The interned key outlives the request it came from.
+    assert_eq!(
+        interned,
+        BorrowedKey {
+            s: "foo",
+            bytes: b"abc"
+        }
+    );
+
+    // Interning it again, or looking it up, returns the same copy and doesn't touch the arena.
+    let allocated = arena.allocated_bytes();
+    let request = String::from("foo");
+    let probe = BorrowedKey {
+        s: &request,
+        bytes: b"abc",
+    };
+    assert_eq!(set.intern(probe).s.as_ptr(), interned.s.as_ptr());
+    assert_eq!(set.get(probe).unwrap().s.as_ptr(), interned.s.as_ptr());
+    assert!(set.contains(probe));
+    assert_eq!(arena.allocated_bytes(), allocated);
+    assert_eq!(set.len(), 1);
+
+    assert!(!set.contains(BorrowedKey {
+        s: "foo",
+        bytes: b"abd"
+    }));
+}
+
+proptest! {
+    #[test]
+    fn arena_consistent_borrow(owned1 in any::<OwnedKey>(), owned2 in any::<OwnedKey>()) {
+        let arena = Bump::new();
+        let key1 = alloc_key(&arena, owned1.key());
+        let key2 = alloc_key(&arena, owned2.key());
+        assert_consistent::<_, dyn Key>(&key1, &key2);
+    }
+}
@@ -0,0 +1,216 @@
+//! A key set that's read without locking, and replaced as a whole when it changes.
+//!
+//! Requires the `arc-swap` feature.
+
+use crate::collections::RandomState;
+#[cfg(test)]
+use crate::numbered_key;
+#[cfg(test)]
+use crate::owned;
+use crate::set::KeySet;
+use crate::{BorrowedKey, Key, OwnedKey};
+use arc_swap::ArcSwap;
+use proptest::prelude::*;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+
+// Allowlists, ACLs and feature flag sets are checked on every request and changed a few times a
+// day. Behind an RwLock, every check takes the lock, and the lock's reference count bounces between
+// the cores doing the checking, even though nobody is writing.
+//
+// AtomicKeySet keeps an immutable KeySet behind an Arc, and uses arc-swap to replace the Arc
+// atomically. A reader loads the current snapshot without taking a lock, and does an ordinary
+// lookup in it, with the same &dyn Key signature as KeySet's, so borrowed keys work without
+// allocating. A writer builds a whole new set and publishes it. Readers that loaded the old
+// snapshot finish with it, and the next ones see the new one.
+//
+// The cost is that every change copies the set. That's the right trade for sets that change
+// rarely, and the wrong one for sets that change all the time: those are better off behind a lock.
+/// A set of owned keys that readers probe without locking, and writers replace atomically.
+pub struct AtomicKeySet<S = RandomState> {
+    current: ArcSwap<KeySet<S>>,
+}
+
+impl AtomicKeySet {
+    /// Creates a new, empty set.
+    pub fn new() -> Self {
+        Self::from_set(KeySet::new())
+    }
+}
+
+impl Default for AtomicKeySet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: BuildHasher> AtomicKeySet<S> {
+    /// Creates a set whose first snapshot is `set`.
+    pub fn from_set(set: KeySet<S>) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(set),
+        }
+    }
+
+    /// Returns true if the current snapshot contains `key`. This never locks or allocates.
+    pub fn contains(&self, key: &dyn Key) -> bool {
+        self.current.load().contains(key)
+    }
+
+    /// Returns the current snapshot.
+    ///
+    /// The snapshot doesn't change, so several lookups in it all see the same set, even if a
+    /// writer publishes a new one in the meantime.
+    pub fn snapshot(&self) -> Arc<KeySet<S>> {
+        self.current.load_full()
+    }
+
+    /// Replaces the set with `set`, returning the previous snapshot.
+    pub fn store(&self, set: KeySet<S>) -> Arc<KeySet<S>> {
+        self.current.swap(Arc::new(set))
+    }
+
+    /// Updates the set by copying the current snapshot, passing the copy to `f`, and publishing
+    /// the result. Returns the previous snapshot.
+    ///
+    /// If another writer publishes a snapshot in the meantime, `f` is called again on a copy of
+    /// that one, so no update is lost.
+    pub fn update<F: FnMut(&mut KeySet<S>)>(&self, mut f: F) -> Arc<KeySet<S>>
+    where
+        S: Clone,
+    {
+        self.current.rcu(|current| {
+            let mut next = KeySet::clone(current);
+            f(&mut next);
+            next
+        })
+    }
+
+    /// Adds a key, returning true if it wasn't already present.
+    ///
+    /// This copies the whole set. To make several changes, use [`update`](Self::update).
+    pub fn insert(&self, key: OwnedKey) -> bool
+    where
+        S: Clone,
+    {
+        let mut inserted = false;
+        self.update(|set| inserted = set.insert(key.clone()));
+        inserted
+    }
+
+    /// Removes `key`, returning true if it was present.
+    ///
+    /// This copies the whole set. To make several changes, use [`update`](Self::update).
+    pub fn remove(&self, key: &dyn Key) -> bool
+    where
+        S: Clone,
+    {
+        let mut removed = false;
+        self.update(|set| removed = set.remove(key));
+        removed
+    }
+
+    /// Returns the number of keys in the current snapshot.
+    pub fn len(&self) -> usize {
+        self.current.load().len()
+    }
+
+    /// Returns true if the current snapshot is empty.
+    pub fn is_empty(&self) -> bool {
+        self.current.load().is_empty()
+    }
+}
+
+impl<S: BuildHasher> From<KeySet<S>> for AtomicKeySet<S> {
+    fn from(set: KeySet<S>) -> Self {
+        Self::from_set(set)
+    }
+}
+
+impl<S: BuildHasher> fmt::Debug for AtomicKeySet<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AtomicKeySet")
+            .field("current", &self.current.load().iter().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[test]
+fn atomic_lookups() {
+    let set = AtomicKeySet::new();
+    assert!(set.insert(owned("foo", b"abc")));
+    assert!(!set.insert(owned("foo", b"abc")));
+
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert!(set.contains(&borrowed_key));
+
+    // A snapshot doesn't see later changes.
+    let before = set.snapshot();
+    let previous = set.update(|set| {
+        set.insert(owned("bar", b""));
+        set.insert(owned("baz", b""));
+    });
+    assert!(Arc::ptr_eq(&before, &previous));
+    assert_eq!(before.len(), 1);
+    assert_eq!(set.len(), 3);
+
+    set.store(KeySet::new());
+    assert!(set.is_empty());
+    assert!(!set.remove(&borrowed_key));
+}
+
+// Readers probe the set while a writer publishes new snapshots. Every snapshot a reader sees must be
+// one the writer published, never a set that's partway through an update.
+#[test]
+fn atomic_readers_see_whole_snapshots() {
+    let set = AtomicKeySet::new();
+    let keys: Vec<_> = (0..8).map(numbered_key).collect();
+
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            let set = &set;
+            let keys = &keys;
+            scope.spawn(move || {
+                for _ in 0..1000 {
+                    // The writer only ever publishes a prefix of the keys.
+                    let snapshot = set.snapshot();
+                    let present = keys
+                        .iter()
+                        .take_while(|key| snapshot.contains(*key))
+                        .count();
+                    assert_eq!(present, snapshot.len());
+                }
+            });
+        }
+        for n in 0..=keys.len() {
+            set.store(keys[..n].iter().cloned().collect());
+        }
+    });
+    assert_eq!(set.len(), keys.len());
+}
+
+proptest! {
+    #[test]
+    fn atomic_matches_hash_set(
+        ops in prop::collection::vec((any::<bool>(), any::<OwnedKey>()), 0..16),
+    ) {
+        let set = AtomicKeySet::new();
+        let mut model = HashSet::new();
+        for (insert, key) in ops {
+            if insert {
+                prop_assert_eq!(set.insert(key.clone()), model.insert(key));
+            } else {
+                prop_assert_eq!(set.remove(&key.key()), model.remove(&key));
+            }
+            prop_assert_eq!(set.len(), model.len());
+            for key in &model {
+                prop_assert!(set.contains(key));
+            }
+        }
+    }
+}
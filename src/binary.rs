@@ -0,0 +1,123 @@
+//! Encoding keys with compact binary formats, to send them over the wire.
+
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashSet};
+use std::hash::{Hash, Hasher};
+
+// These are thin wrappers over the Serialize and Deserialize impls in serialize.rs, for two binary
+// formats that can borrow strings and bytes straight out of their input: bincode and postcard.
+// Decoding returns a BorrowedKey pointing into the input, so a key that came in over the network
+// can be looked up in a set of owned keys without being copied at all.
+//
+// That only works if the decoded key is Eq, Ord and Hash-identical to the key that was encoded,
+// which is what the property test at the bottom checks. The tests for serialize.rs check that owned
+// and borrowed keys encode the same way; these check that nothing is lost in between.
+
+/// Encodes `key` with bincode, using its standard configuration.
+#[cfg(feature = "bincode")]
+pub fn to_bincode(key: &dyn Key) -> Vec<u8> {
+    bincode::serde::encode_to_vec(key.key(), bincode::config::standard())
+        .expect("encoding a key to a Vec never fails")
+}
+
+/// Decodes a key encoded by [`to_bincode`], borrowing from `encoded`.
+///
+/// Bytes after the end of the key are ignored.
+#[cfg(feature = "bincode")]
+pub fn from_bincode(encoded: &[u8]) -> Result<BorrowedKey<'_>, bincode::error::DecodeError> {
+    let (key, _) = bincode::serde::borrow_decode_from_slice(encoded, bincode::config::standard())?;
+    Ok(key)
+}
+
+/// Encodes `key` with postcard.
+#[cfg(feature = "postcard")]
+pub fn to_postcard(key: &dyn Key) -> Vec<u8> {
+    postcard::to_allocvec(&key.key()).expect("encoding a key to a Vec never fails")
+}
+
+/// Decodes a key encoded by [`to_postcard`], borrowing from `encoded`.
+///
+/// Bytes after the end of the key are ignored.
+#[cfg(feature = "postcard")]
+pub fn from_postcard(encoded: &[u8]) -> Result<BorrowedKey<'_>, postcard::Error> {
+    postcard::from_bytes(encoded)
+}
+
+#[cfg(test)]
+fn hash_of(key: &dyn Key) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+fn assert_identical(original: &OwnedKey, decoded: BorrowedKey<'_>, other: &OwnedKey) {
+    let original: &dyn Key = original;
+    let decoded: &dyn Key = &decoded;
+    let other: &dyn Key = other;
+    assert!(original == decoded, "decoded key is equal");
+    assert_eq!(
+        hash_of(original),
+        hash_of(decoded),
+        "decoded key hashes the same"
+    );
+    assert_eq!(
+        original.cmp(other),
+        decoded.cmp(other),
+        "decoded key orders the same"
+    );
+}
+
+#[test]
+fn binary_lookups() {
+    let hash_set: HashSet<OwnedKey> = std::iter::once(OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    })
+    .collect();
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+
+    #[cfg(feature = "bincode")]
+    {
+        let encoded = to_bincode(&borrowed_key);
+        assert_eq!(encoded, b"\x03foo\x03abc");
+        assert!(hash_set.contains(&from_bincode(&encoded).unwrap() as &dyn Key));
+        assert!(from_bincode(&encoded[..4]).is_err());
+    }
+    #[cfg(feature = "postcard")]
+    {
+        let encoded = to_postcard(&borrowed_key);
+        assert!(hash_set.contains(&from_postcard(&encoded).unwrap() as &dyn Key));
+        assert!(from_postcard(&encoded[..4]).is_err());
+    }
+}
+
+proptest! {
+    #[test]
+    fn binary_consistent(key in any::<OwnedKey>(), other in any::<OwnedKey>()) {
+        let hash_set: HashSet<&dyn Key> = std::iter::once(&key as &dyn Key).collect();
+        let btree_set: BTreeSet<&dyn Key> = std::iter::once(&key as &dyn Key).collect();
+
+        #[cfg(feature = "bincode")]
+        {
+            let encoded = to_bincode(&key);
+            let decoded = from_bincode(&encoded).unwrap();
+            assert_identical(&key, decoded, &other);
+            prop_assert!(hash_set.contains(&decoded as &dyn Key));
+            prop_assert!(btree_set.contains(&decoded as &dyn Key));
+        }
+        #[cfg(feature = "postcard")]
+        {
+            let encoded = to_postcard(&key);
+            let decoded = from_postcard(&encoded).unwrap();
+            assert_identical(&key, decoded, &other);
+            prop_assert!(hash_set.contains(&decoded as &dyn Key));
+            prop_assert!(btree_set.contains(&decoded as &dyn Key));
+        }
+    }
+}
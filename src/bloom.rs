@@ -0,0 +1,153 @@
+//! A Bloom filter over keys, for ruling out lookups without touching the set itself.
+
+#[cfg(test)]
+use crate::numbered_key;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::collections::hash_map::RandomState;
+use std::f64::consts::LN_2;
+use std::hash::BuildHasher;
+
+// A huge key set might live on disk, or on another machine, so that every lookup is expensive. If
+// most lookups are for keys that aren't there, a Bloom filter in memory can answer "definitely
+// not" for nearly all of them, and only the rest need the real lookup.
+//
+// A Bloom filter is a bit array. Inserting a key sets a handful of bits chosen by hashing it, and a
+// key might be present only if all of its bits are set. So the filter never has false negatives,
+// but has false positives at a rate that depends on how full it is.
+//
+// All that's needed from a key is a hash, and the crate root already guarantees that an OwnedKey
+// and its BorrowedKey hash the same way: that's what makes HashSet lookups by &dyn Key work. So
+// KeyBloom hashes each key once through dyn Key, and a key inserted as an OwnedKey is found when
+// probed with a BorrowedKey, without allocating. It derives all of the bit positions from that one
+// hash by double hashing: the i-th position is h1 + i * h2, where h1 and h2 are the two halves of
+// the hash. That's as good as independent hash functions for a Bloom filter, and much cheaper.
+/// A Bloom filter over keys, with lookups by owned or borrowed key.
+#[derive(Clone, Debug)]
+pub struct KeyBloom<S = RandomState> {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+    hasher: S,
+}
+
+impl KeyBloom {
+    /// Creates a filter sized for `expected_keys` keys, with a false positive rate of about
+    /// `false_positive_rate` once they've all been inserted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `false_positive_rate` isn't strictly between 0 and 1.
+    pub fn new(expected_keys: usize, false_positive_rate: f64) -> Self {
+        Self::with_hasher(expected_keys, false_positive_rate, RandomState::new())
+    }
+}
+
+impl<S: BuildHasher> KeyBloom<S> {
+    /// Creates a filter, like [`new`](KeyBloom::new), that uses `hasher` to hash keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `false_positive_rate` isn't strictly between 0 and 1.
+    pub fn with_hasher(expected_keys: usize, false_positive_rate: f64, hasher: S) -> Self {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be between 0 and 1, was {}",
+            false_positive_rate
+        );
+        // The standard formulas for the optimal number of bits and hash functions.
+        let expected_keys = expected_keys.max(1) as f64;
+        let num_bits = (-expected_keys * false_positive_rate.ln() / (LN_2 * LN_2)).ceil();
+        let num_bits = (num_bits as u64).max(64);
+        let num_hashes = ((num_bits as f64 / expected_keys) * LN_2).round().max(1.0) as u32;
+        Self {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+            hasher,
+        }
+    }
+
+    /// Adds `key` to the filter.
+    pub fn insert(&mut self, key: &dyn Key) {
+        for bit in self.bit_positions(key) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns false if `key` was definitely never inserted, and true if it might have been.
+    pub fn might_contain(&self, key: &dyn Key) -> bool {
+        self.bit_positions(key)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Removes every key from the filter.
+    pub fn clear(&mut self) {
+        self.bits.iter_mut().for_each(|word| *word = 0);
+    }
+
+    /// Returns the number of bits in the filter.
+    pub fn num_bits(&self) -> u64 {
+        self.num_bits
+    }
+
+    /// Returns the number of bits set for each key.
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    fn bit_positions(&self, key: &dyn Key) -> impl Iterator<Item = u64> {
+        let hash = self.hasher.hash_one(key);
+        // An odd step visits distinct positions for as long as possible.
+        let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        let num_bits = self.num_bits;
+        (0..u64::from(self.num_hashes)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+}
+
+#[test]
+fn bloom_false_positives() {
+    let mut bloom = KeyBloom::new(1000, 0.01);
+    assert_eq!(bloom.num_hashes(), 7);
+    for i in 0..1000 {
+        bloom.insert(&numbered_key(i));
+    }
+
+    // No false negatives, through either kind of key.
+    for i in 0..1000 {
+        let owned = numbered_key(i);
+        assert!(bloom.might_contain(&owned));
+        assert!(bloom.might_contain(&BorrowedKey {
+            s: &owned.s,
+            bytes: &owned.bytes,
+        }));
+    }
+
+    // About 1% false positives. Allow plenty of slack, since the hasher is randomly seeded.
+    let false_positives = (1000..11_000)
+        .filter(|&i| bloom.might_contain(&numbered_key(i)))
+        .count();
+    assert!(false_positives < 300, "{} false positives", false_positives);
+
+    bloom.clear();
+    assert!(!bloom.might_contain(&numbered_key(0)));
+}
+
+proptest! {
+    #[test]
+    fn bloom_no_false_negatives(
+        keys in prop::collection::vec(any::<OwnedKey>(), 1..32),
+        capacity in 1..64usize,
+    ) {
+        // Even when the filter is overfull, every inserted key is found, whether it's probed as an
+        // OwnedKey or as a BorrowedKey.
+        let mut bloom = KeyBloom::new(capacity, 0.05);
+        for key in &keys {
+            bloom.insert(key);
+        }
+        for key in &keys {
+            prop_assert!(bloom.might_contain(key));
+            prop_assert!(bloom.might_contain(&key.key()));
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Sets that hold keys of several different types at once.
+
+use crate::consistency::assert_consistent;
+#[cfg(test)]
+use crate::owned;
+use crate::packed::CompactKey;
+use crate::shared::SharedOwnedKey;
+use crate::{BorrowedKey, Key, OwnedKey};
+use dyn_clone::DynClone;
+use proptest::prelude::*;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::thread;
+
+// Everything that implements Key is compared, ordered and hashed by its BorrowedKey alone. So keys
+// of different types can share a set: store each one as a Box<dyn Key>, and an OwnedKey and a
+// CompactKey with the same fields are the same entry.
+//
+// Box<dyn Key> already works. Box<T> compares and hashes however T does, and dyn Key has the impls
+// from the crate root. Box<T> also implements Borrow<T>, so a HashSet<Box<dyn Key>> can be probed
+// with a &dyn Key.
+//
+// But a set that's shared between threads needs Send and Sync on its keys, and anything that
+// copies keys out of a set, like a cache, needs to clone them. A Box<dyn Key> can't be cloned,
+// since Clone isn't object safe. The dyn-clone crate gets around that: CloneKey is Key plus
+// DynClone, which every Key + Clone type gets for free, and clone_trait_object! writes Clone for
+// Box<dyn CloneKey>.
+//
+// dyn CloneKey + Send + Sync is a different type from dyn Key, with none of its impls. So here they
+// are again, each forwarding to key() just like the originals, plus Debug so that sets of them can
+// be printed. The Borrow impl upcasts to a plain dyn Key, so probes don't need to be Clone, Send or
+// Sync themselves.
+/// A key that can be cloned from behind a `Box`.
+///
+/// This is implemented for every key type that implements `Clone`.
+pub trait CloneKey: Key + DynClone {}
+
+impl<T: Key + Clone> CloneKey for T {}
+
+dyn_clone::clone_trait_object!(CloneKey);
+
+/// A boxed key of any type, that can be cloned and shared between threads.
+pub type BoxedKey = Box<dyn CloneKey + Send + Sync>;
+
+impl<'a> PartialEq for dyn CloneKey + Send + Sync + 'a {
+    fn eq(&self, other: &Self) -> bool {
+        self.key().eq(&other.key())
+    }
+}
+
+impl<'a> Eq for dyn CloneKey + Send + Sync + 'a {}
+
+impl<'a> PartialOrd for dyn CloneKey + Send + Sync + 'a {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Ord for dyn CloneKey + Send + Sync + 'a {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl<'a> Hash for dyn CloneKey + Send + Sync + 'a {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl<'a> fmt::Debug for dyn CloneKey + Send + Sync + 'a {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.key().fmt(f)
+    }
+}
+
+impl<'a, 'b: 'a> Borrow<dyn Key + 'a> for Box<dyn CloneKey + Send + Sync + 'b> {
+    fn borrow(&self) -> &(dyn Key + 'a) {
+        // This is a trait upcasting coercion, from dyn CloneKey to its supertrait dyn Key.
+        &**self
+    }
+}
+
+#[test]
+fn boxed_lookups() {
+    let mut hash_set: HashSet<BoxedKey> = HashSet::new();
+    assert!(hash_set.insert(Box::new(owned("foo", b"abc"))));
+    assert!(hash_set.insert(Box::new(CompactKey::new("bar", b""))));
+    assert!(hash_set.insert(Box::new(SharedOwnedKey::from(owned("baz", b"x")))));
+    // The same fields in a different type are the same entry.
+    assert!(!hash_set.insert(Box::new(CompactKey::new("foo", b"abc"))));
+    assert_eq!(hash_set.len(), 3);
+
+    let btree_set: BTreeSet<BoxedKey> = vec![
+        Box::new(owned("foo", b"abc")) as BoxedKey,
+        Box::new(CompactKey::new("bar", b"")),
+    ]
+    .into_iter()
+    .collect();
+
+    // Lookups work with any key type, whatever type the entry was stored as.
+    let probes: [&dyn Key; 3] = [
+        &owned("bar", b""),
+        &CompactKey::new("foo", b"abc"),
+        &BorrowedKey {
+            s: "foo",
+            bytes: b"abc",
+        },
+    ];
+    for probe in probes {
+        assert!(hash_set.contains(probe));
+        assert!(btree_set.contains(probe));
+    }
+    assert!(!hash_set.contains(&owned("foo", b"") as &dyn Key));
+
+    // Cloning the set clones each boxed key, whatever its type.
+    let cloned = hash_set.clone();
+    assert_eq!(cloned, hash_set);
+    let compact = cloned
+        .get(&CompactKey::new("bar", b"") as &dyn Key)
+        .and_then(|key| key.as_any()?.downcast_ref::<CompactKey>());
+    assert_eq!(compact, Some(&CompactKey::new("bar", b"")));
+
+    // And the set can be shared between threads.
+    thread::scope(|scope| {
+        scope.spawn(|| assert!(hash_set.contains(&CompactKey::new("bar", b"") as &dyn Key)));
+    });
+}
+
+#[cfg(test)]
+fn boxed_keys() -> impl Strategy<Value = (OwnedKey, BoxedKey)> {
+    (any::<OwnedKey>(), 0..3u8).prop_map(|(owned, kind)| {
+        let boxed: BoxedKey = match kind {
+            0 => Box::new(owned.clone()),
+            1 => Box::new(CompactKey::from(&owned)),
+            _ => Box::new(SharedOwnedKey::from(owned.clone())),
+        };
+        (owned, boxed)
+    })
+}
+
+proptest! {
+    #[test]
+    fn boxed_consistent_borrow((owned1, boxed1) in boxed_keys(), (owned2, boxed2) in boxed_keys()) {
+        assert_consistent::<_, dyn Key>(&boxed1, &boxed2);
+        prop_assert_eq!(boxed1.cmp(&boxed2), owned1.cmp(&owned2));
+        prop_assert_eq!(&boxed1.clone(), &boxed1);
+    }
+}
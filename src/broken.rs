@@ -0,0 +1,186 @@
+//! Key types with broken Borrow impls, to make sure the consistency checks catch them.
+
+use crate::consistency::{assert_consistent, assert_consistent_hash};
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use proptest::test_runner::{Config, TestRunner};
+use std::borrow::Borrow;
+use std::hash::{Hash, Hasher};
+
+// A property test that passes no matter what isn't worth much. Each type here gets the Borrow impl
+// subtly wrong, in a way that's easy to do by accident, and each test runs the consistency checks
+// against it and expects them to fail. If one of these tests starts passing, the checks have lost
+// their teeth.
+
+// Mistake 1: the fields are declared in a different order from BorrowedKey, so the derived Ord
+// compares bytes first. (This is experiment (2) from the crate root.)
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+struct SwappedOwnedKey {
+    bytes: Vec<u8>,
+    s: String,
+}
+
+impl Key for SwappedOwnedKey {
+    fn key(&self) -> BorrowedKey<'_> {
+        BorrowedKey {
+            s: &self.s,
+            bytes: &self.bytes,
+        }
+    }
+}
+
+impl<'a> Borrow<dyn Key + 'a> for SwappedOwnedKey {
+    fn borrow(&self) -> &(dyn Key + 'a) {
+        self
+    }
+}
+
+// Mistake 2: Hash is written by hand, and leaves out a field. Keys that differ only in their bytes
+// still hash differently from their borrowed forms.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct PartialHashOwnedKey {
+    s: String,
+    bytes: Vec<u8>,
+}
+
+impl Hash for PartialHashOwnedKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.s.hash(state);
+    }
+}
+
+impl Key for PartialHashOwnedKey {
+    fn key(&self) -> BorrowedKey<'_> {
+        BorrowedKey {
+            s: &self.s,
+            bytes: &self.bytes,
+        }
+    }
+}
+
+impl<'a> Borrow<dyn Key + 'a> for PartialHashOwnedKey {
+    fn borrow(&self) -> &(dyn Key + 'a) {
+        self
+    }
+}
+
+// Mistake 3: Eq ignores ASCII case, but Hash (derived) doesn't. Borrowed keys still compare case
+// sensitively. (Clippy catches this one, which is why it's allowed here.)
+#[allow(clippy::derived_hash_with_manual_eq)]
+#[derive(Clone, Debug, Hash)]
+struct CaseInsensitiveOwnedKey {
+    s: String,
+    bytes: Vec<u8>,
+}
+
+impl PartialEq for CaseInsensitiveOwnedKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.s.eq_ignore_ascii_case(&other.s) && self.bytes == other.bytes
+    }
+}
+
+impl Eq for CaseInsensitiveOwnedKey {}
+
+impl Key for CaseInsensitiveOwnedKey {
+    fn key(&self) -> BorrowedKey<'_> {
+        BorrowedKey {
+            s: &self.s,
+            bytes: &self.bytes,
+        }
+    }
+}
+
+impl<'a> Borrow<dyn Key + 'a> for CaseInsensitiveOwnedKey {
+    fn borrow(&self) -> &(dyn Key + 'a) {
+        self
+    }
+}
+
+// Small alphabets, so that pairs of keys are often equal, or differ in just one field.
+fn parts() -> impl Strategy<Value = (String, Vec<u8>)> {
+    ("[aA]{0,2}", prop::collection::vec(0..2u8, 0..2))
+}
+
+/// Runs `check` against pairs of keys built from random parts, returning true if it ever panics.
+fn check_fails<K: std::fmt::Debug>(make: fn(String, Vec<u8>) -> K, check: fn(&K, &K)) -> bool {
+    let mut runner = TestRunner::new(Config {
+        failure_persistence: None,
+        ..Config::default()
+    });
+    let result = runner.run(&(parts(), parts()), |((s1, bytes1), (s2, bytes2))| {
+        check(&make(s1, bytes1), &make(s2, bytes2));
+        Ok(())
+    });
+    result.is_err()
+}
+
+#[test]
+fn broken_control() {
+    // The checks pass for a correct key, with the same strategy.
+    assert!(!check_fails(
+        |s, bytes| OwnedKey { s, bytes },
+        assert_consistent::<_, dyn Key>,
+    ));
+}
+
+#[test]
+fn broken_swapped_fields() {
+    assert!(check_fails(
+        |s, bytes| SwappedOwnedKey { bytes, s },
+        assert_consistent::<_, dyn Key>,
+    ));
+}
+
+#[test]
+fn broken_partial_hash() {
+    assert!(check_fails(
+        |s, bytes| PartialHashOwnedKey { s, bytes },
+        assert_consistent::<_, dyn Key>,
+    ));
+}
+
+#[test]
+fn broken_case_insensitive_eq() {
+    assert!(check_fails(
+        |s, bytes| CaseInsensitiveOwnedKey { s, bytes },
+        assert_consistent_hash::<_, dyn Key>,
+    ));
+}
+
+#[cfg(feature = "quickcheck")]
+impl quickcheck::Arbitrary for SwappedOwnedKey {
+    fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+        let OwnedKey { s, bytes } = <OwnedKey as quickcheck::Arbitrary>::arbitrary(g);
+        SwappedOwnedKey { bytes, s }
+    }
+}
+
+#[cfg(feature = "quickcheck")]
+#[test]
+fn broken_quickcheck() {
+    // The quickcheck runner shares its assertions with proptest, so it catches the same mistakes.
+    let result = std::panic::catch_unwind(
+        crate::consistency::quickcheck_consistent::<SwappedOwnedKey, dyn Key>,
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn broken_macro() {
+    // So does the macro, given either pairs of values or a strategy.
+    let strategy = parts().prop_map(|(s, bytes)| SwappedOwnedKey { bytes, s });
+    let result = std::panic::catch_unwind(|| {
+        crate::assert_borrow_consistent!(strategy => dyn Key);
+    });
+    assert!(result.is_err());
+
+    let result = std::panic::catch_unwind(|| {
+        let owned1 = PartialHashOwnedKey {
+            s: "a".to_string(),
+            bytes: vec![0],
+        };
+        let owned2 = owned1.clone();
+        crate::assert_borrow_consistent!(hash: owned1, owned2 => dyn Key);
+    });
+    assert!(result.is_err());
+}
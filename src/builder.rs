@@ -0,0 +1,247 @@
+//! Building an `OwnedKey` field by field, with validation.
+
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::error::Error;
+use std::fmt;
+
+// An OwnedKey is two public fields, so nothing stops application code from building one with a
+// string that's too long for the column it's headed for, or with a NUL in it that a C library will
+// silently cut the string at. Those mistakes show up far from where the key was made.
+//
+// KeyBuilder moves the checks to construction. Each limit is opt-in, since the crate itself works
+// with any key; an application sets up the builder with its own rules, and gets a typed error
+// saying which one a key broke. Both fields must be given, so that forgetting one is an error
+// rather than an empty string or byte string.
+//
+// The builder checks the key's borrowed view, so the same rules can also be applied to a
+// BorrowedKey, or to any other key, with validate.
+/// A builder for an [`OwnedKey`] that checks it against configurable rules.
+///
+/// Returned by [`OwnedKey::builder`].
+#[derive(Clone, Debug, Default)]
+pub struct KeyBuilder {
+    s: Option<String>,
+    bytes: Option<Vec<u8>>,
+    rules: KeyRules,
+}
+
+/// The rules a [`KeyBuilder`] checks keys against. By default, there are none.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct KeyRules {
+    /// The longest the string can be, in bytes.
+    pub max_s_len: Option<usize>,
+    /// The longest the byte string can be.
+    pub max_bytes_len: Option<usize>,
+    /// Whether the string must be free of NUL characters, so that it can be passed on as a C
+    /// string.
+    pub nul_free: bool,
+}
+
+impl OwnedKey {
+    /// Returns a builder for a key, with no rules set.
+    pub fn builder() -> KeyBuilder {
+        KeyBuilder::default()
+    }
+}
+
+impl KeyBuilder {
+    /// Returns a builder for a key that checks it against `rules`.
+    pub fn with_rules(rules: KeyRules) -> Self {
+        Self {
+            rules,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the key's string.
+    pub fn s(mut self, s: impl Into<String>) -> Self {
+        self.s = Some(s.into());
+        self
+    }
+
+    /// Sets the key's bytes.
+    pub fn bytes(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.bytes = Some(bytes.into());
+        self
+    }
+
+    /// Limits the string to `max` bytes.
+    pub fn max_s_len(mut self, max: usize) -> Self {
+        self.rules.max_s_len = Some(max);
+        self
+    }
+
+    /// Limits the byte string to `max` bytes.
+    pub fn max_bytes_len(mut self, max: usize) -> Self {
+        self.rules.max_bytes_len = Some(max);
+        self
+    }
+
+    /// Requires the string to be free of NUL characters.
+    pub fn nul_free(mut self) -> Self {
+        self.rules.nul_free = true;
+        self
+    }
+
+    /// Builds the key, checking it against the builder's rules.
+    pub fn build(self) -> Result<OwnedKey, KeyBuildError> {
+        let s = self.s.ok_or(KeyBuildError::MissingField("s"))?;
+        let bytes = self.bytes.ok_or(KeyBuildError::MissingField("bytes"))?;
+        let key = OwnedKey { s, bytes };
+        self.rules.validate(&key)?;
+        Ok(key)
+    }
+}
+
+impl KeyRules {
+    /// Checks `key` against the rules.
+    pub fn validate(&self, key: &dyn Key) -> Result<(), KeyBuildError> {
+        let BorrowedKey { s, bytes } = key.key();
+        if let Some(max) = self.max_s_len {
+            if s.len() > max {
+                return Err(KeyBuildError::StringTooLong { len: s.len(), max });
+            }
+        }
+        if let Some(max) = self.max_bytes_len {
+            if bytes.len() > max {
+                return Err(KeyBuildError::BytesTooLong {
+                    len: bytes.len(),
+                    max,
+                });
+            }
+        }
+        if self.nul_free {
+            if let Some(position) = s.find('\0') {
+                return Err(KeyBuildError::NulInString { position });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An error returned by [`KeyBuilder::build`] and [`KeyRules::validate`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum KeyBuildError {
+    /// A field wasn't set.
+    MissingField(&'static str),
+    /// The string is longer than the limit.
+    StringTooLong {
+        /// The string's length, in bytes.
+        len: usize,
+        /// The limit.
+        max: usize,
+    },
+    /// The byte string is longer than the limit.
+    BytesTooLong {
+        /// The byte string's length.
+        len: usize,
+        /// The limit.
+        max: usize,
+    },
+    /// The string contains a NUL character, and the rules don't allow one.
+    NulInString {
+        /// The byte offset of the first NUL.
+        position: usize,
+    },
+}
+
+impl fmt::Display for KeyBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyBuildError::MissingField(field) => write!(f, "key field {} wasn't set", field),
+            KeyBuildError::StringTooLong { len, max } => write!(
+                f,
+                "key string is {} bytes long, more than the limit of {}",
+                len, max
+            ),
+            KeyBuildError::BytesTooLong { len, max } => write!(
+                f,
+                "key bytes are {} bytes long, more than the limit of {}",
+                len, max
+            ),
+            KeyBuildError::NulInString { position } => {
+                write!(f, "key string has a NUL at byte {}", position)
+            }
+        }
+    }
+}
+
+impl Error for KeyBuildError {}
+
+#[test]
+fn builder_validation() {
+    let key = OwnedKey::builder().s("foo").bytes(*b"abc").build().unwrap();
+    assert_eq!(
+        key,
+        OwnedKey {
+            s: "foo".to_string(),
+            bytes: b"abc".to_vec(),
+        }
+    );
+
+    assert_eq!(
+        OwnedKey::builder().s("foo").build(),
+        Err(KeyBuildError::MissingField("bytes"))
+    );
+    assert_eq!(
+        OwnedKey::builder()
+            .s("foo")
+            .bytes(vec![])
+            .max_s_len(2)
+            .build(),
+        Err(KeyBuildError::StringTooLong { len: 3, max: 2 })
+    );
+    assert_eq!(
+        OwnedKey::builder()
+            .s("")
+            .bytes(&b"abc"[..])
+            .max_bytes_len(2)
+            .build(),
+        Err(KeyBuildError::BytesTooLong { len: 3, max: 2 })
+    );
+
+    // The same rules can be shared between builders, and used to check borrowed keys.
+    let rules = KeyRules {
+        nul_free: true,
+        ..KeyRules::default()
+    };
+    let err = KeyBuilder::with_rules(rules)
+        .s("foo\0bar")
+        .bytes(vec![0])
+        .build()
+        .unwrap_err();
+    assert_eq!(err, KeyBuildError::NulInString { position: 3 });
+    assert_eq!(err.to_string(), "key string has a NUL at byte 3");
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"\0",
+    };
+    assert_eq!(rules.validate(&borrowed_key), Ok(()));
+}
+
+proptest! {
+    #[test]
+    fn builder_matches_rules(
+        key in any::<OwnedKey>(),
+        max_s_len in prop::option::of(0..8usize),
+        max_bytes_len in prop::option::of(0..8usize),
+        nul_free in any::<bool>(),
+    ) {
+        let rules = KeyRules { max_s_len, max_bytes_len, nul_free };
+        let built = KeyBuilder::with_rules(rules)
+            .s(key.s.clone())
+            .bytes(key.bytes.clone())
+            .build();
+
+        // A key is built exactly when it breaks none of the rules, and is then the same key.
+        let ok = max_s_len.is_none_or(|max| key.s.len() <= max)
+            && max_bytes_len.is_none_or(|max| key.bytes.len() <= max)
+            && !(nul_free && key.s.contains('\0'));
+        prop_assert_eq!(built.is_ok(), ok);
+        prop_assert_eq!(rules.validate(&key).is_ok(), ok);
+        if let Ok(built) = built {
+            prop_assert_eq!(built, key);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! An owned key whose bytes are a reference-counted slice of a larger buffer.
+//!
+//! Requires the `bytes` feature.
+
+use crate::consistency::assert_consistent;
+use crate::storage::owned_keys;
+use crate::{BorrowedKey, Key, OwnedKey};
+use bytes::Bytes;
+use proptest::prelude::*;
+use std::collections::HashSet;
+
+// Networking stacks tend to read a whole frame into a single buffer and then hand out Bytes handles
+// into it. Bytes::slice doesn't copy anything, so a key built this way can be inserted into a set
+// while still pointing into the original frame.
+//
+// Bytes hashes and compares exactly like the [u8] it derefs to, so it's a valid storage type for
+// OwnedKey.
+/// An owned key whose bytes are a `Bytes`, so that they can be sliced out of a buffer for free.
+pub type BytesOwnedKey = OwnedKey<Bytes>;
+
+#[test]
+fn bytes_storage() {
+    crate::storage::check_storage::<Bytes>();
+}
+
+#[test]
+fn bytes_lookups() {
+    // Pretend this came off the wire.
+    let frame = Bytes::from_static(b"HDRabcTRAILER");
+    let key = BytesOwnedKey {
+        s: "foo".to_string(),
+        bytes: frame.slice(3..6),
+    };
+    // No copy was made: the key's bytes still live inside the frame.
+    assert_eq!(key.bytes.as_ptr(), frame[3..].as_ptr());
+
+    let mut hash_set = HashSet::new();
+    hash_set.insert(key);
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert!(hash_set.contains(&borrowed_key as &dyn Key));
+}
+
+proptest! {
+    #[test]
+    fn bytes_consistent_borrow(owned1 in owned_keys::<Bytes>(), owned2 in owned_keys::<Bytes>()) {
+        assert_consistent::<_, dyn Key>(&owned1, &owned2);
+    }
+}
@@ -0,0 +1,187 @@
+//! A key whose string compares case-insensitively.
+
+use crate::consistency::assert_consistent;
+use crate::family::{KeyFamily, Lookup, Probe};
+use proptest::prelude::*;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashSet};
+use std::hash::{Hash, Hasher};
+
+// This is one of the trickier cases mentioned in the crate root. "Alice" and "ALICE" have to be
+// equal, so they also have to hash the same, and the derived Hash (which hashes the raw string)
+// won't do that. Eq, Ord and Hash all have to be written by hand.
+//
+// The trick to keeping them consistent is to write them exactly once, on the borrowed type, in
+// terms of the case-folded characters. The owned type then forwards all three to its borrowed
+// form, so it can't disagree with it.
+/// An owned key whose string field is compared without regard to case.
+#[derive(Clone, Debug)]
+pub struct CaseFoldKey {
+    pub s: String,
+    pub bytes: Vec<u8>,
+}
+
+/// The borrowed form of a [`CaseFoldKey`].
+#[derive(Copy, Clone, Debug)]
+pub struct BorrowedCaseFoldKey<'a> {
+    pub s: &'a str,
+    pub bytes: &'a [u8],
+}
+
+impl<'a> BorrowedCaseFoldKey<'a> {
+    // Going lowercase, uppercase, then lowercase again maps every case variant of a character to
+    // the same thing: "ẞ", "ß" and "SS" all become "ss", and "ς", "σ" and "Σ" all become "σ". This
+    // isn't full Unicode case folding, but it works a character at a time without allocating, and
+    // (most importantly) it's a function of the string, which is all that consistency needs.
+    fn folded(&self) -> impl Iterator<Item = char> + 'a {
+        self.s
+            .chars()
+            .flat_map(char::to_lowercase)
+            .flat_map(char::to_uppercase)
+            .flat_map(char::to_lowercase)
+    }
+}
+
+impl<'a> PartialEq for BorrowedCaseFoldKey<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.folded().eq(other.folded()) && self.bytes == other.bytes
+    }
+}
+
+impl<'a> Eq for BorrowedCaseFoldKey<'a> {}
+
+impl<'a> PartialOrd for BorrowedCaseFoldKey<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Ord for BorrowedCaseFoldKey<'a> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.folded()
+            .cmp(other.folded())
+            .then_with(|| self.bytes.cmp(other.bytes))
+    }
+}
+
+impl<'a> Hash for BorrowedCaseFoldKey<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for c in self.folded() {
+            c.hash(state);
+        }
+        // Mark the end of the string, like str's own Hash impl does, so that ("ab", [..]) and
+        // ("a", [b'b', ..]) don't run together.
+        state.write_u8(0xff);
+        self.bytes.hash(state);
+    }
+}
+
+impl KeyFamily for CaseFoldKey {
+    type Borrowed<'a> = BorrowedCaseFoldKey<'a>;
+
+    fn shorten<'long: 'short, 'short>(
+        borrowed: BorrowedCaseFoldKey<'long>,
+    ) -> BorrowedCaseFoldKey<'short> {
+        borrowed
+    }
+}
+
+impl Lookup<CaseFoldKey> for CaseFoldKey {
+    fn lookup_key(&self) -> BorrowedCaseFoldKey<'_> {
+        BorrowedCaseFoldKey {
+            s: &self.s,
+            bytes: &self.bytes,
+        }
+    }
+}
+
+impl<'a> Borrow<dyn Lookup<CaseFoldKey> + 'a> for CaseFoldKey {
+    fn borrow(&self) -> &(dyn Lookup<CaseFoldKey> + 'a) {
+        self
+    }
+}
+
+impl CaseFoldKey {
+    /// Returns a probe for looking up this key's borrowed form.
+    pub fn probe(key: BorrowedCaseFoldKey<'_>) -> Probe<'_, Self> {
+        Probe(key)
+    }
+}
+
+impl PartialEq for CaseFoldKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.lookup_key() == other.lookup_key()
+    }
+}
+
+impl Eq for CaseFoldKey {}
+
+impl PartialOrd for CaseFoldKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CaseFoldKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.lookup_key().cmp(&other.lookup_key())
+    }
+}
+
+impl Hash for CaseFoldKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.lookup_key().hash(state)
+    }
+}
+
+#[test]
+fn casefold_lookups() {
+    let mut hash_set = HashSet::new();
+    let mut btree_set = BTreeSet::new();
+    let key = CaseFoldKey {
+        s: "Straße".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+    hash_set.insert(key.clone());
+    btree_set.insert(key);
+
+    for s in ["straße", "STRASSE", "strasse", "StRaßE"] {
+        let probe = CaseFoldKey::probe(BorrowedCaseFoldKey { s, bytes: b"abc" });
+        assert!(hash_set.contains(probe.as_lookup()), "{} found", s);
+        assert!(btree_set.contains(probe.as_lookup()), "{} found", s);
+    }
+
+    // The bytes are still compared exactly.
+    let probe = CaseFoldKey::probe(BorrowedCaseFoldKey {
+        s: "strasse",
+        bytes: b"ABC",
+    });
+    assert!(!hash_set.contains(probe.as_lookup()));
+}
+
+// Random strings almost never differ only by case, so draw from a small alphabet of characters
+// with interesting case mappings to make equal keys common.
+#[cfg(test)]
+fn mixed_case_keys() -> impl Strategy<Value = CaseFoldKey> {
+    (
+        "[aAbBẞßSsσςΣİiıIǅǄǆ]{0,6}",
+        prop::collection::vec(0..2u8, 0..2),
+    )
+        .prop_map(|(s, bytes)| CaseFoldKey { s, bytes })
+}
+
+proptest! {
+    #[test]
+    fn casefold_consistent_borrow(owned1 in mixed_case_keys(), owned2 in mixed_case_keys()) {
+        assert_consistent::<_, dyn Lookup<CaseFoldKey>>(&owned1, &owned2);
+    }
+
+    #[test]
+    fn casefold_ignores_case(s in any::<String>(), bytes in any::<Vec<u8>>()) {
+        let upper = CaseFoldKey { s: s.to_uppercase(), bytes: bytes.clone() };
+        let lower = CaseFoldKey { s: s.to_lowercase(), bytes };
+        prop_assert_eq!(&upper, &lower);
+        assert_consistent::<_, dyn Lookup<CaseFoldKey>>(&upper, &lower);
+    }
+}
@@ -0,0 +1,12 @@
+//! The hash map and set that KeySet and KeyMap are built on.
+
+// With std, KeySet and KeyMap wrap std's HashSet and HashMap, and hash with SipHash by default.
+// Without it, hashbrown provides the same types, hashing with foldhash by default. std's are
+// hashbrown underneath anyway, so lookups by &dyn Key work the same way with either.
+#[cfg(feature = "std")]
+pub(crate) use std::collections::{hash_map, hash_map::RandomState, hash_set, HashMap, HashSet};
+
+#[cfg(not(feature = "std"))]
+pub(crate) use hashbrown::{
+    hash_map, hash_set, DefaultHashBuilder as RandomState, HashMap, HashSet,
+};
@@ -0,0 +1,209 @@
+//! Storing many keys as columns, for fast scans.
+
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::iter::FromIterator;
+use std::ops::Range;
+
+// A Vec<OwnedKey> is a Vec of pointers: scanning it means following two pointers per key, to
+// allocations that could be anywhere on the heap. KeyColumns instead appends every string to one
+// big String, and all the bytes to one big Vec<u8>, and keeps track of where each key's fields end.
+// A scan then walks through memory in order, and a scan that only looks at strings never touches
+// the bytes at all.
+//
+// Nothing in here is ever owned per key, so there's no Borrow impl to write: every key comes out
+// as a BorrowedKey, which is the same type a HashSet<OwnedKey> gets probed with anyway.
+/// A list of keys, stored column by column.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct KeyColumns {
+    strings: String,
+    bytes: Vec<u8>,
+    // The end of each key's string in strings, and of its bytes in bytes. The start is the previous
+    // key's end, or 0.
+    string_ends: Vec<usize>,
+    bytes_ends: Vec<usize>,
+}
+
+impl KeyColumns {
+    /// Creates a new, empty list of keys.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a key, returning its index.
+    pub fn push(&mut self, key: &dyn Key) -> usize {
+        let key = key.key();
+        self.strings.push_str(key.s);
+        self.bytes.extend_from_slice(key.bytes);
+        self.string_ends.push(self.strings.len());
+        self.bytes_ends.push(self.bytes.len());
+        self.string_ends.len() - 1
+    }
+
+    /// Returns the key at `index`, if there is one.
+    pub fn get(&self, index: usize) -> Option<BorrowedKey<'_>> {
+        if index >= self.len() {
+            return None;
+        }
+        Some(BorrowedKey {
+            s: &self.strings[Self::range(&self.string_ends, index)],
+            bytes: &self.bytes[Self::range(&self.bytes_ends, index)],
+        })
+    }
+
+    /// Returns the number of keys.
+    pub fn len(&self) -> usize {
+        self.string_ends.len()
+    }
+
+    /// Returns true if there are no keys.
+    pub fn is_empty(&self) -> bool {
+        self.string_ends.is_empty()
+    }
+
+    /// Iterates over the keys, in order.
+    pub fn iter(&self) -> impl Iterator<Item = BorrowedKey<'_>> + '_ {
+        self.strings()
+            .zip(self.bytes())
+            .map(|(s, bytes)| BorrowedKey { s, bytes })
+    }
+
+    /// Iterates over just the string column, in order.
+    pub fn strings(&self) -> impl Iterator<Item = &str> + '_ {
+        Self::ranges(&self.string_ends).map(move |range| &self.strings[range])
+    }
+
+    /// Iterates over just the bytes column, in order.
+    pub fn bytes(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        Self::ranges(&self.bytes_ends).map(move |range| &self.bytes[range])
+    }
+
+    /// Iterates over the keys for which `predicate` returns true, along with their indices.
+    pub fn filter<'a, P>(
+        &'a self,
+        mut predicate: P,
+    ) -> impl Iterator<Item = (usize, BorrowedKey<'a>)>
+    where
+        P: FnMut(BorrowedKey<'a>) -> bool + 'a,
+    {
+        self.iter()
+            .enumerate()
+            .filter(move |(_, key)| predicate(*key))
+    }
+
+    /// Returns the index of the first key equal to `key`, if any.
+    ///
+    /// This is a linear scan. It only compares bytes for keys whose strings match.
+    pub fn position(&self, key: &dyn Key) -> Option<usize> {
+        let key = key.key();
+        // Comparing the strings as bytes skips checking for char boundaries when slicing.
+        let strings = self.strings.as_bytes();
+        let (mut string_start, mut bytes_start) = (0, 0);
+        for (index, (&string_end, &bytes_end)) in
+            self.string_ends.iter().zip(&self.bytes_ends).enumerate()
+        {
+            if &strings[string_start..string_end] == key.s.as_bytes()
+                && &self.bytes[bytes_start..bytes_end] == key.bytes
+            {
+                return Some(index);
+            }
+            string_start = string_end;
+            bytes_start = bytes_end;
+        }
+        None
+    }
+
+    fn range(ends: &[usize], index: usize) -> Range<usize> {
+        let start = if index == 0 { 0 } else { ends[index - 1] };
+        start..ends[index]
+    }
+
+    fn ranges(ends: &[usize]) -> impl Iterator<Item = Range<usize>> + '_ {
+        let starts = std::iter::once(0).chain(ends.iter().copied());
+        starts
+            .zip(ends.iter().copied())
+            .map(|(start, end)| start..end)
+    }
+}
+
+impl<'a> FromIterator<BorrowedKey<'a>> for KeyColumns {
+    fn from_iter<I: IntoIterator<Item = BorrowedKey<'a>>>(iter: I) -> Self {
+        let mut columns = Self::new();
+        columns.extend(iter);
+        columns
+    }
+}
+
+impl<'a> Extend<BorrowedKey<'a>> for KeyColumns {
+    fn extend<I: IntoIterator<Item = BorrowedKey<'a>>>(&mut self, iter: I) {
+        for key in iter {
+            self.push(&key);
+        }
+    }
+}
+
+#[test]
+fn columns_scans() {
+    let mut columns = KeyColumns::new();
+    assert_eq!(
+        columns.push(&BorrowedKey {
+            s: "foo",
+            bytes: b"abc"
+        }),
+        0
+    );
+    columns.push(&OwnedKey {
+        s: "bar".to_string(),
+        bytes: vec![],
+    });
+    columns.push(&BorrowedKey {
+        s: "",
+        bytes: b"xyz",
+    });
+
+    assert_eq!(columns.len(), 3);
+    assert_eq!(
+        columns.get(1),
+        Some(BorrowedKey {
+            s: "bar",
+            bytes: b""
+        })
+    );
+    assert_eq!(columns.get(3), None);
+    assert_eq!(columns.strings().collect::<Vec<_>>(), ["foo", "bar", ""]);
+
+    let with_bytes: Vec<_> = columns
+        .filter(|key| !key.bytes.is_empty())
+        .map(|(index, _)| index)
+        .collect();
+    assert_eq!(with_bytes, [0, 2]);
+    assert_eq!(
+        columns.position(&BorrowedKey {
+            s: "",
+            bytes: b"xyz"
+        }),
+        Some(2)
+    );
+    assert_eq!(
+        columns.position(&BorrowedKey {
+            s: "foo",
+            bytes: b""
+        }),
+        None
+    );
+}
+
+proptest! {
+    #[test]
+    fn columns_match_vec(keys in prop::collection::vec(any::<OwnedKey>(), 0..16)) {
+        let columns: KeyColumns = keys.iter().map(OwnedKey::key).collect();
+
+        prop_assert_eq!(columns.len(), keys.len());
+        prop_assert!(columns.iter().eq(keys.iter().map(OwnedKey::key)));
+        for (index, key) in keys.iter().enumerate() {
+            prop_assert_eq!(columns.get(index), Some(key.key()));
+            let first = keys.iter().position(|other| other == key);
+            prop_assert_eq!(columns.position(key), first);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Single components of a composite key, and their borrowed forms.
+
+use std::cmp::Reverse;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/// A type that can be one field of a composite key.
+///
+/// `Borrowed` is what the field looks like in the borrowed form of the key: `&str` for `String`,
+/// `&[u8]` for `Vec<u8>`, and the value itself for `Copy` types.
+///
+/// Just like for `Borrow`, the borrowed form's Eq, Ord and Hash must be consistent with the
+/// component's own.
+pub trait KeyComponent {
+    /// The borrowed form of this component.
+    type Borrowed<'a>: Copy + Debug + Eq + Ord + Hash;
+
+    /// Returns the borrowed form of this component.
+    fn as_borrowed(&self) -> Self::Borrowed<'_>;
+
+    /// Shortens the lifetime of a borrowed component. This should always be implemented as just
+    /// `borrowed`; see [`KeyFamily::shorten`](crate::family::KeyFamily::shorten) for why it's
+    /// needed.
+    fn shorten<'long: 'short, 'short>(borrowed: Self::Borrowed<'long>) -> Self::Borrowed<'short>;
+}
+
+impl KeyComponent for String {
+    type Borrowed<'a> = &'a str;
+
+    fn as_borrowed(&self) -> &str {
+        self
+    }
+
+    fn shorten<'long: 'short, 'short>(borrowed: &'long str) -> &'short str {
+        borrowed
+    }
+}
+
+impl KeyComponent for Box<str> {
+    type Borrowed<'a> = &'a str;
+
+    fn as_borrowed(&self) -> &str {
+        self
+    }
+
+    fn shorten<'long: 'short, 'short>(borrowed: &'long str) -> &'short str {
+        borrowed
+    }
+}
+
+impl KeyComponent for Vec<u8> {
+    type Borrowed<'a> = &'a [u8];
+
+    fn as_borrowed(&self) -> &[u8] {
+        self
+    }
+
+    fn shorten<'long: 'short, 'short>(borrowed: &'long [u8]) -> &'short [u8] {
+        borrowed
+    }
+}
+
+impl KeyComponent for Box<[u8]> {
+    type Borrowed<'a> = &'a [u8];
+
+    fn as_borrowed(&self) -> &[u8] {
+        self
+    }
+
+    fn shorten<'long: 'short, 'short>(borrowed: &'long [u8]) -> &'short [u8] {
+        borrowed
+    }
+}
+
+// Reverse flips the order of whatever it wraps, and leaves Eq and Hash alone. Wrapping the borrowed
+// form in Reverse too flips its order in exactly the same way, so consistency carries over from T.
+impl<T: KeyComponent> KeyComponent for Reverse<T> {
+    type Borrowed<'a> = Reverse<T::Borrowed<'a>>;
+
+    fn as_borrowed(&self) -> Reverse<T::Borrowed<'_>> {
+        Reverse(self.0.as_borrowed())
+    }
+
+    fn shorten<'long: 'short, 'short>(
+        borrowed: Reverse<T::Borrowed<'long>>,
+    ) -> Reverse<T::Borrowed<'short>> {
+        Reverse(T::shorten(borrowed.0))
+    }
+}
+
+macro_rules! copy_components {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl KeyComponent for $ty {
+                type Borrowed<'a> = $ty;
+
+                fn as_borrowed(&self) -> $ty {
+                    *self
+                }
+
+                fn shorten<'long: 'short, 'short>(borrowed: $ty) -> $ty {
+                    borrowed
+                }
+            }
+        )*
+    };
+}
+
+copy_components!(bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
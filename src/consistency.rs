@@ -0,0 +1,399 @@
+//! Reusable checks that an owned type and its borrowed form agree on Eq, Ord and Hash.
+//!
+//! This is the same set of assertions as the `consistent_borrow` property test in the crate root,
+//! packaged up so that every other key type in this crate (and in yours) can run them too. Hash is
+//! checked with several hashers rather than just one.
+//!
+//! [`assert_borrow_consistent!`](crate::assert_borrow_consistent) wraps them up as a one-line
+//! test.
+
+use proptest::prelude::*;
+use proptest::test_runner::{Config, TestRunner};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+
+/// Asserts that `O`'s Eq, Ord and Hash implementations are consistent with those of `Q`, for this
+/// particular pair of owned values.
+///
+/// Call this from a property test with randomly generated `owned1` and `owned2`.
+///
+/// # Panics
+///
+/// Panics if any of the consistency properties fail to hold.
+pub fn assert_consistent<O, Q>(owned1: &O, owned2: &O)
+where
+    O: Borrow<Q> + Eq + Ord + Hash,
+    Q: ?Sized + Eq + Ord + Hash,
+{
+    assert_consistent_hash::<O, Q>(owned1, owned2);
+
+    let borrowed1: &Q = owned1.borrow();
+    let borrowed2: &Q = owned2.borrow();
+    assert_eq!(
+        owned1.partial_cmp(owned2),
+        borrowed1.partial_cmp(borrowed2),
+        "consistent PartialOrd"
+    );
+    assert_eq!(
+        owned1.cmp(owned2),
+        borrowed1.cmp(borrowed2),
+        "consistent Ord"
+    );
+}
+
+/// Asserts that `O`'s Eq and Hash implementations are consistent with those of `Q`, for this
+/// particular pair of owned values.
+///
+/// This is [`assert_consistent`] for keys that only go into hash-based collections, and so don't
+/// implement Ord.
+///
+/// # Panics
+///
+/// Panics if any of the consistency properties fail to hold.
+pub fn assert_consistent_hash<O, Q>(owned1: &O, owned2: &O)
+where
+    O: Borrow<Q> + Eq + Hash,
+    Q: ?Sized + Eq + Hash,
+{
+    let borrowed1: &Q = owned1.borrow();
+    let borrowed2: &Q = owned2.borrow();
+
+    assert_eq!(owned1 == owned2, borrowed1 == borrowed2, "consistent Eq");
+
+    // "For all hashers" is a strong requirement. An owned type and its borrowed form that write
+    // different things to the hasher can still happen to agree under one hasher, so check with a
+    // few different ones. The last one doesn't hash at all: it records exactly what was written,
+    // and how, so any difference at all is caught.
+    let random_state = RandomState::new();
+    for (owned, borrowed) in [(owned1, borrowed1), (owned2, borrowed2)] {
+        assert_eq!(
+            random_state.hash_one(owned),
+            random_state.hash_one(borrowed),
+            "consistent Hash (SipHash, random keys)"
+        );
+        assert_eq!(
+            hash_with::<FnvHasher, _>(owned).finish(),
+            hash_with::<FnvHasher, _>(borrowed).finish(),
+            "consistent Hash (FNV-1a)"
+        );
+        assert_eq!(
+            hash_with::<RecordingHasher, _>(owned).writes,
+            hash_with::<RecordingHasher, _>(borrowed).writes,
+            "consistent Hash (exact writes)"
+        );
+    }
+
+    // Equal keys also have to hash the same, or a HashMap won't find one when given the other.
+    if borrowed1 == borrowed2 {
+        assert_eq!(
+            hash_with::<RecordingHasher, _>(borrowed1).writes,
+            hash_with::<RecordingHasher, _>(borrowed2).writes,
+            "equal keys hash the same"
+        );
+    }
+}
+
+/// Asserts that `T`'s Ord implementation is a total order that agrees with its PartialEq and
+/// PartialOrd implementations, for this particular triple of values.
+///
+/// Consistency between an owned type and its borrowed form isn't enough on its own: if the borrowed
+/// form's comparison isn't a total order, neither is the owned type's, and a BTreeMap using either
+/// will silently misplace keys. Call this from a property test with randomly generated `a`, `b`
+/// and `c`. Transitivity only gets tested if some of them are in order, so generate them from a
+/// small set of values.
+///
+/// # Panics
+///
+/// Panics if any of the laws fail to hold.
+pub fn assert_ord_laws<T: ?Sized + Ord>(a: &T, b: &T, c: &T) {
+    for (x, y) in [(a, b), (b, c), (a, c)] {
+        // Reflexivity.
+        assert_eq!(x.cmp(x), Ordering::Equal, "reflexive Ord");
+        // Antisymmetry.
+        assert_eq!(x.cmp(y), y.cmp(x).reverse(), "antisymmetric Ord");
+        // Totality, and agreement with PartialOrd and PartialEq.
+        assert_eq!(
+            x.partial_cmp(y),
+            Some(x.cmp(y)),
+            "Ord consistent with PartialOrd"
+        );
+        assert_eq!(
+            x == y,
+            x.cmp(y) == Ordering::Equal,
+            "Ord consistent with Eq"
+        );
+    }
+
+    // Transitivity, for every ordering of the three.
+    for (x, y, z) in [
+        (a, b, c),
+        (a, c, b),
+        (b, a, c),
+        (b, c, a),
+        (c, a, b),
+        (c, b, a),
+    ] {
+        if x <= y && y <= z {
+            assert!(x <= z, "transitive Ord");
+        }
+        if x == y && y == z {
+            assert!(x == z, "transitive Eq");
+        }
+    }
+}
+
+/// Runs [`assert_consistent`] against pairs of values generated by `strategy`.
+///
+/// This is the same check as calling [`assert_consistent`] from a `proptest!` block, for test
+/// suites that would rather not write one. Call this from a test.
+///
+/// # Panics
+///
+/// Panics, after shrinking, if any of the consistency properties fail to hold.
+pub fn proptest_consistent<O, Q, S>(strategy: S)
+where
+    O: fmt::Debug + Borrow<Q> + Eq + Ord + Hash,
+    Q: ?Sized + Eq + Ord + Hash,
+    S: Strategy<Value = O>,
+{
+    run_pairs(strategy, assert_consistent::<O, Q>);
+}
+
+/// Runs [`assert_consistent_hash`] against pairs of values generated by `strategy`.
+///
+/// This is [`proptest_consistent`] for keys that don't implement Ord.
+///
+/// # Panics
+///
+/// Panics, after shrinking, if any of the consistency properties fail to hold.
+pub fn proptest_consistent_hash<O, Q, S>(strategy: S)
+where
+    O: fmt::Debug + Borrow<Q> + Eq + Hash,
+    Q: ?Sized + Eq + Hash,
+    S: Strategy<Value = O>,
+{
+    run_pairs(strategy, assert_consistent_hash::<O, Q>);
+}
+
+fn run_pairs<O: fmt::Debug, S: Strategy<Value = O>>(strategy: S, check: fn(&O, &O)) {
+    // The runner isn't attached to a source file, so there's nowhere to persist failures to.
+    let mut runner = TestRunner::new(Config {
+        failure_persistence: None,
+        ..Config::default()
+    });
+    let result = runner.run(&(&strategy, &strategy), |(owned1, owned2)| {
+        check(&owned1, &owned2);
+        Ok(())
+    });
+    if let Err(err) = result {
+        panic!("{}", err);
+    }
+}
+
+/// Asserts that an owned type's Eq, Ord and Hash implementations are consistent with those of its
+/// borrowed form.
+///
+/// This is a one-line consistency test for crates that use the pattern for their own keys. It
+/// takes either two owned values, to drop into an existing `proptest!` block, or a proptest
+/// strategy, to run on its own. Either way, the borrowed form comes after `=>`. Start with `hash:`
+/// for keys that don't implement Ord.
+///
+/// ```
+/// use borrow_complex_key_example::{assert_borrow_consistent, Key, OwnedKey};
+/// use proptest::prelude::*;
+///
+/// proptest! {
+///     // (In a test suite, with #[test] on it.)
+///     fn consistent(owned1 in any::<OwnedKey>(), owned2 in any::<OwnedKey>()) {
+///         assert_borrow_consistent!(owned1, owned2 => dyn Key);
+///     }
+/// }
+/// # consistent();
+///
+/// // Or, on its own, generating the pairs itself:
+/// assert_borrow_consistent!(any::<OwnedKey>() => dyn Key);
+/// assert_borrow_consistent!(hash: any::<OwnedKey>() => dyn Key);
+/// ```
+///
+/// # Panics
+///
+/// Panics if any of the consistency properties fail to hold. With a strategy, the panic is after
+/// shrinking, and its message has the smallest failing pair.
+#[macro_export]
+macro_rules! assert_borrow_consistent {
+    (hash: $owned1:expr, $owned2:expr => $borrowed:ty) => {
+        $crate::consistency::assert_consistent_hash::<_, $borrowed>(&$owned1, &$owned2)
+    };
+    (hash: $strategy:expr => $borrowed:ty) => {
+        $crate::consistency::proptest_consistent_hash::<_, $borrowed, _>($strategy)
+    };
+    ($owned1:expr, $owned2:expr => $borrowed:ty) => {
+        $crate::consistency::assert_consistent::<_, $borrowed>(&$owned1, &$owned2)
+    };
+    ($strategy:expr => $borrowed:ty) => {
+        $crate::consistency::proptest_consistent::<_, $borrowed, _>($strategy)
+    };
+}
+
+/// Runs [`assert_consistent`] against pairs of values generated by quickcheck.
+///
+/// This is for projects that use quickcheck rather than proptest. The assertions are exactly the
+/// same; only the way values are generated differs. Call this from a test.
+///
+/// # Panics
+///
+/// Panics, after shrinking, if any of the consistency properties fail to hold.
+#[cfg(feature = "quickcheck")]
+pub fn quickcheck_consistent<O, Q>()
+where
+    O: quickcheck::Arbitrary + fmt::Debug + Borrow<Q> + Eq + Ord + Hash,
+    Q: ?Sized + Eq + Ord + Hash,
+{
+    fn prop<O, Q>(owned1: O, owned2: O) -> bool
+    where
+        O: Borrow<Q> + Eq + Ord + Hash,
+        Q: ?Sized + Eq + Ord + Hash,
+    {
+        assert_consistent::<O, Q>(&owned1, &owned2);
+        true
+    }
+    quickcheck::QuickCheck::new().quickcheck(prop::<O, Q> as fn(O, O) -> bool);
+}
+
+/// Runs [`assert_consistent_hash`] against pairs of values generated by quickcheck.
+///
+/// This is [`quickcheck_consistent`] for keys that don't implement Ord.
+///
+/// # Panics
+///
+/// Panics, after shrinking, if any of the consistency properties fail to hold.
+#[cfg(feature = "quickcheck")]
+pub fn quickcheck_consistent_hash<O, Q>()
+where
+    O: quickcheck::Arbitrary + fmt::Debug + Borrow<Q> + Eq + Hash,
+    Q: ?Sized + Eq + Hash,
+{
+    fn prop<O, Q>(owned1: O, owned2: O) -> bool
+    where
+        O: Borrow<Q> + Eq + Hash,
+        Q: ?Sized + Eq + Hash,
+    {
+        assert_consistent_hash::<O, Q>(&owned1, &owned2);
+        true
+    }
+    quickcheck::QuickCheck::new().quickcheck(prop::<O, Q> as fn(O, O) -> bool);
+}
+
+#[cfg(feature = "quickcheck")]
+#[test]
+fn consistency_quickcheck() {
+    use crate::{Key, OwnedKey};
+
+    quickcheck_consistent::<OwnedKey, dyn Key>();
+    quickcheck_consistent_hash::<OwnedKey, dyn Key>();
+}
+
+fn hash_with<H: Default + Hasher, T: ?Sized + Hash>(x: &T) -> H {
+    let mut hasher = H::default();
+    x.hash(&mut hasher);
+    hasher
+}
+
+// FNV-1a, 64-bit. It's simple and unkeyed, and mixes bytes in a different way from SipHash.
+struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= u64::from(b);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+// A hasher that records every write, along with the method that was used to make it. Two values
+// only record the same writes if they feed a hasher exactly the same way, which is what consistency
+// really means. (Writing a u32 as write_u32 and as four calls to write_u8 hashes the same under
+// FNV, but not under every hasher.)
+#[derive(Default)]
+pub(crate) struct RecordingHasher {
+    pub(crate) writes: Vec<(&'static str, Vec<u8>)>,
+}
+
+impl RecordingHasher {
+    fn record(&mut self, method: &'static str, bytes: &[u8]) {
+        self.writes.push((method, bytes.to_vec()));
+    }
+}
+
+impl Hasher for RecordingHasher {
+    fn finish(&self) -> u64 {
+        // The result is the writes themselves, not this.
+        0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.record("write", bytes);
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.record("write_u8", &i.to_le_bytes());
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.record("write_u16", &i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.record("write_u32", &i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.record("write_u64", &i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.record("write_u128", &i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.record("write_usize", &i.to_le_bytes());
+    }
+
+    fn write_i8(&mut self, i: i8) {
+        self.record("write_i8", &i.to_le_bytes());
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.record("write_i16", &i.to_le_bytes());
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.record("write_i32", &i.to_le_bytes());
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.record("write_i64", &i.to_le_bytes());
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.record("write_i128", &i.to_le_bytes());
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.record("write_isize", &i.to_le_bytes());
+    }
+}
@@ -0,0 +1,138 @@
+//! Loading and saving key sets as CSV.
+
+use crate::set::KeySet;
+use crate::text::{decode_hex, Hex};
+use crate::{Key, OwnedKey};
+use proptest::prelude::*;
+use std::error::Error;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::io;
+
+// Test fixtures and data dumps are easiest to read and edit as CSV. A key is a row with two columns,
+// under a header row:
+//
+//     s,bytes
+//     foo,616263
+//     bar,
+//
+// The string is written as-is (the csv crate quotes it if it needs to be), and the bytes are
+// written in lowercase hex, as in the canonical string form from text.rs. Rows are written in key
+// order, so that saving the same set twice gives the same file.
+impl<S: BuildHasher + Default> KeySet<S> {
+    /// Reads a set of keys from CSV with `s` and `bytes` columns, as written by
+    /// [`to_csv_writer`](Self::to_csv_writer).
+    pub fn from_csv_reader<R: io::Read>(reader: R) -> Result<Self, CsvError> {
+        let mut reader = csv::Reader::from_reader(reader);
+        let mut set = KeySet::default();
+        for record in reader.records() {
+            let record = record.map_err(CsvError::Csv)?;
+            let line = record.position().map_or(0, |position| position.line());
+            let (s, hex) = match (record.get(0), record.get(1), record.len()) {
+                (Some(s), Some(hex), 2) => (s, hex),
+                _ => return Err(CsvError::InvalidRecord { line }),
+            };
+            let bytes = decode_hex(hex).map_err(|_| CsvError::InvalidBytes { line })?;
+            set.insert(OwnedKey {
+                s: s.to_string(),
+                bytes,
+            });
+        }
+        Ok(set)
+    }
+}
+
+impl<S: BuildHasher> KeySet<S> {
+    /// Writes the set as CSV with `s` and `bytes` columns, in key order.
+    pub fn to_csv_writer<W: io::Write>(&self, writer: W) -> Result<(), csv::Error> {
+        let mut keys: Vec<_> = self.iter().collect();
+        keys.sort();
+
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["s", "bytes"])?;
+        for key in keys {
+            writer.write_record([key.s.as_str(), &Hex(&key.bytes).to_string()])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// An error returned by [`KeySet::from_csv_reader`].
+#[derive(Debug)]
+pub enum CsvError {
+    /// The input couldn't be read, or isn't valid CSV.
+    Csv(csv::Error),
+    /// The record on `line` doesn't have exactly two fields.
+    InvalidRecord {
+        /// The line the record starts on, counting from 1.
+        line: u64,
+    },
+    /// The bytes on `line` aren't valid lowercase hex.
+    InvalidBytes {
+        /// The line the record starts on, counting from 1.
+        line: u64,
+    },
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvError::Csv(_) => write!(f, "error reading CSV"),
+            CsvError::InvalidRecord { line } => {
+                write!(f, "line {}: expected 2 fields, s and bytes", line)
+            }
+            CsvError::InvalidBytes { line } => {
+                write!(f, "line {}: bytes aren't valid lowercase hex", line)
+            }
+        }
+    }
+}
+
+impl Error for CsvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CsvError::Csv(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn csv_fixtures() {
+    let input = "s,bytes\nfoo,616263\n\"a,b\",\nfoo,616263\n";
+    let set: KeySet = KeySet::from_csv_reader(input.as_bytes()).unwrap();
+    assert_eq!(set.len(), 2);
+    assert!(set.contains(&crate::BorrowedKey {
+        s: "a,b",
+        bytes: b""
+    }));
+
+    let mut output = Vec::new();
+    set.to_csv_writer(&mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "s,bytes\n\"a,b\",\nfoo,616263\n"
+    );
+
+    let err = KeySet::<std::collections::hash_map::RandomState>::from_csv_reader(
+        "s,bytes\nfoo,abc\n".as_bytes(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, CsvError::InvalidBytes { line: 2 }));
+    let err =
+        KeySet::<std::collections::hash_map::RandomState>::from_csv_reader("s\nfoo\n".as_bytes())
+            .unwrap_err();
+    assert!(matches!(err, CsvError::InvalidRecord { line: 2 }));
+}
+
+proptest! {
+    #[test]
+    fn csv_round_trips(keys in prop::collection::vec(any::<OwnedKey>(), 0..8)) {
+        let set: KeySet = keys.into_iter().collect();
+        let mut output = Vec::new();
+        set.to_csv_writer(&mut output).unwrap();
+        let read: KeySet = KeySet::from_csv_reader(&output[..]).unwrap();
+        prop_assert_eq!(read, set);
+    }
+}
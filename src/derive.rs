@@ -0,0 +1,166 @@
+//! Generating the whole pattern with `#[derive(BorrowKey)]`.
+//!
+//! Writing the borrowed type, the trait and five trait object impls by hand gets old quickly.
+//! `BorrowKey` generates all of them from the owned type:
+//!
+//! ```
+//! use borrow_complex_key_example::BorrowKey;
+//! use std::collections::HashSet;
+//!
+//! #[derive(BorrowKey, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+//! struct OwnedKey {
+//!     s: String,
+//!     bytes: Vec<u8>,
+//! }
+//!
+//! // The derive wrote BorrowedKey<'a> and the Key trait for us.
+//! let mut hash_set = HashSet::new();
+//! hash_set.insert(OwnedKey { s: "foo".to_string(), bytes: b"abc".to_vec() });
+//! assert!(hash_set.contains(&BorrowedKey { s: "foo", bytes: b"abc" } as &dyn Key));
+//! ```
+//!
+//! The generated trait is called `Key`, and the borrowed type is named by replacing an `Owned`
+//! prefix with `Borrowed` (or adding `Borrowed` in front). Use `#[borrow_key(borrowed = Name)]` to
+//! pick a different name for the borrowed type, and `#[borrow_key(trait = Name)]` for the trait.
+//! Each key type needs a trait of its own, so several key types in one module need different trait
+//! names. Either name can also be written as a string, such as `trait = "UserKeyLookup"`.
+//!
+//! Each field is borrowed according to its type:
+//!
+//! * `String`, `Vec<T>`, `Box<T>`, `Rc<T>`, `Arc<T>`, `PathBuf`, `OsString` and `CString` borrow as
+//!   a reference to their deref target.
+//! * Integers, `bool` and `char` are copied.
+//! * A type parameter with an `AsRef<X>` bound, inline or in a where clause, borrows as `&X`.
+//! * `Reverse<T>` borrows as `Reverse` of however `T` borrows, for fields sorted in descending order.
+//!
+//! A type parameter that borrows through `AsRef<X>` can be instantiated with a type that compares
+//! differently from `X`, like a case-insensitive string that implements `AsRef<str>`. Derived
+//! comparisons on the owned type would use that type's impls, and disagree with the borrowed type.
+//! So if any field borrows through `AsRef`, the derive generates PartialEq, Eq, PartialOrd, Ord and
+//! Hash for the owned type, comparing only the key, as it does for skipped fields (see below).
+//! Don't derive those yourself in that case:
+//!
+//! ```
+//! use borrow_complex_key_example::BorrowKey;
+//! use std::collections::HashSet;
+//!
+//! #[derive(BorrowKey, Clone, Debug)]
+//! struct OwnedName<T: AsRef<str>> {
+//!     name: T,
+//! }
+//!
+//! let mut hash_set = HashSet::new();
+//! hash_set.insert(OwnedName { name: Box::<str>::from("foo") });
+//! assert!(hash_set.contains(&BorrowedName { name: "foo" } as &dyn Key));
+//! ```
+//!
+//! For any other field, supply the function that borrows it, and the borrowed type it returns, with
+//! `#[borrow_key(borrow_with = path, ty = Type)]`. The type is written in terms of the borrowed
+//! struct's lifetime `'a`, and the function is called with a reference to the field. It's up to you
+//! to make sure its result compares and hashes the same way as the field does:
+//!
+//! ```
+//! use borrow_complex_key_example::BorrowKey;
+//!
+//! #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+//! struct Url(String);
+//!
+//! impl Url {
+//!     fn as_str(&self) -> &str {
+//!         &self.0
+//!     }
+//! }
+//!
+//! #[derive(BorrowKey, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+//! struct OwnedLink {
+//!     #[borrow_key(borrow_with = Url::as_str, ty = &'a str)]
+//!     url: Url,
+//!     #[borrow_key(borrow_with = Option::as_deref, ty = Option<&'a str>)]
+//!     title: Option<String>,
+//! }
+//!
+//! let link = OwnedLink { url: Url("https://example.com".to_string()), title: None };
+//! assert_eq!(link.key(), BorrowedLink { url: "https://example.com", title: None });
+//! ```
+//!
+//! Enums work too. The borrowed type is an enum with the same variants, in the same order, whose
+//! fields are borrowed in the same way as a struct's, so unit, tuple and struct variants can all be
+//! mixed in one key. Variants can't have explicit discriminants, since those can change the order
+//! of the owned enum's variants without changing the borrowed one's:
+//!
+//! ```
+//! use borrow_complex_key_example::BorrowKey;
+//! use std::collections::BTreeSet;
+//!
+//! #[derive(BorrowKey, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+//! enum OwnedName {
+//!     Anonymous,
+//!     Id(u64, String),
+//!     Qualified { namespace: String, name: Vec<u8> },
+//! }
+//!
+//! let mut btree_set = BTreeSet::new();
+//! btree_set.insert(OwnedName::Id(7, "seven".to_string()));
+//! assert!(btree_set.contains(&BorrowedName::Id(7, "seven") as &dyn Key));
+//! assert!(!btree_set.contains(&BorrowedName::Anonymous as &dyn Key));
+//! ```
+//!
+//! The derive only covers the Borrow side. You still derive Eq, Ord and Hash on the owned type
+//! yourself, and those still need to be consistent with the borrowed type: run the owned type
+//! through [`assert_consistent`](crate::consistency::assert_consistent) in a property test.
+//! `#[borrow_key(proptest)]` writes that test for you, as a `#[cfg(test)]` `proptest!` block
+//! called `<Owned>_consistent_borrow`, that runs `assert_consistent` on pairs of values from the
+//! owned type's `Arbitrary` impl. The crate using it needs proptest as a (dev-)dependency. For a
+//! type without an `Arbitrary` impl, or with generic parameters, name a function that returns a
+//! strategy instead, with `#[borrow_key(proptest(strategy = path))]`.
+//!
+//! Fields that aren't part of the key, such as metadata or caches, can be left out with
+//! `#[borrow_key(skip)]`. They don't appear in the borrowed type, and can be of any type. Derived
+//! comparisons on the owned type would still look at them, so if any field is skipped, the derive
+//! also generates PartialEq, Eq, PartialOrd, Ord and Hash for the owned type, comparing only the
+//! key. Don't derive those yourself in that case:
+//!
+//! ```
+//! use borrow_complex_key_example::BorrowKey;
+//! use std::collections::HashSet;
+//!
+//! #[derive(BorrowKey, Clone, Debug)]
+//! struct OwnedEntry {
+//!     name: String,
+//!     version: u32,
+//!     #[borrow_key(skip)]
+//!     hits: u64,
+//! }
+//!
+//! let mut hash_set = HashSet::new();
+//! hash_set.insert(OwnedEntry { name: "foo".to_string(), version: 1, hits: 10 });
+//! // Entries that only differ in skipped fields are the same key.
+//! assert!(!hash_set.insert(OwnedEntry { name: "foo".to_string(), version: 1, hits: 20 }));
+//! assert!(hash_set.contains(&BorrowedEntry { name: "foo", version: 1 } as &dyn Key));
+//! ```
+//!
+//! A key with a `usize` in it, or a byte string, hashes differently on 32-bit and 64-bit targets.
+//! With `#[borrow_key(portable_hash)]`, the borrowed type hashes its fields through a
+//! [`PortableHasher`](crate::portable::PortableHasher), which writes integers at fixed widths and
+//! in a fixed byte order, so that a key hashes the same way on every target. Enum variants are
+//! hashed by their index, as a `u32`. The derive also generates Hash for the owned type, in terms
+//! of the key, so don't derive that one yourself:
+//!
+//! ```
+//! use borrow_complex_key_example::BorrowKey;
+//! use std::collections::HashSet;
+//!
+//! #[derive(BorrowKey, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+//! #[borrow_key(portable_hash)]
+//! struct OwnedSpan {
+//!     file: String,
+//!     start: usize,
+//!     len: usize,
+//! }
+//!
+//! let mut hash_set = HashSet::new();
+//! hash_set.insert(OwnedSpan { file: "lib.rs".to_string(), start: 10, len: 4 });
+//! assert!(hash_set.contains(&BorrowedSpan { file: "lib.rs", start: 10, len: 4 } as &dyn Key));
+//! ```
+
+pub use borrow_complex_key_example_derive::BorrowKey;
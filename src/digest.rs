@@ -0,0 +1,149 @@
+//! Stable SHA-256 digests of keys and key sets.
+
+use crate::encoding::encode_into;
+use crate::set::KeySet;
+use crate::text::Hex;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+// Hash is for hash tables. What it writes is up to the type, what the hasher does with that is up
+// to the hasher, and RandomState picks new keys in every process, so a hash computed today can't
+// be compared with one stored yesterday, or with one computed on a replica. Comparing two copies
+// of an index, or checking that a persisted one hasn't changed, needs a digest that depends only on
+// the keys.
+//
+// A key's digest is SHA-256 over its memcomparable encoding from encoding.rs. That encoding is
+// already fixed, and it's injective: different keys encode differently, so they only share a
+// digest if SHA-256 collides. A set's digest has to be independent of the order the set happens to
+// iterate in, so it's SHA-256 over the digests of its keys, sorted. Digests are all 32 bytes long,
+// so concatenating them is unambiguous.
+/// A SHA-256 digest of a key, or of a set of keys.
+///
+/// Digests are stable: the same keys give the same digest in every process, on every target.
+/// Returned by [`OwnedKey::stable_digest`] and [`KeySet::content_hash`]. Displays as lowercase hex.
+#[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct KeyDigest([u8; 32]);
+
+impl KeyDigest {
+    /// Returns the digest's bytes.
+    pub const fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Returns the digest's bytes.
+    pub const fn into_bytes(self) -> [u8; 32] {
+        self.0
+    }
+}
+
+impl From<[u8; 32]> for KeyDigest {
+    fn from(bytes: [u8; 32]) -> Self {
+        KeyDigest(bytes)
+    }
+}
+
+impl fmt::Display for KeyDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Hex(&self.0).fmt(f)
+    }
+}
+
+impl fmt::Debug for KeyDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "KeyDigest({})", self)
+    }
+}
+
+/// Returns the stable digest of `key`.
+pub fn stable_digest(key: &dyn Key) -> KeyDigest {
+    let mut encoded = Vec::new();
+    encode_into(key, &mut encoded);
+    KeyDigest(Sha256::digest(&encoded).into())
+}
+
+impl OwnedKey {
+    /// Returns the key's stable digest: SHA-256 over its
+    /// [memcomparable encoding](crate::encoding::encode).
+    pub fn stable_digest(&self) -> KeyDigest {
+        stable_digest(self)
+    }
+}
+
+impl<'a> BorrowedKey<'a> {
+    /// Returns the key's stable digest. It's the same as the digest of the equivalent
+    /// [`OwnedKey`].
+    pub fn stable_digest(&self) -> KeyDigest {
+        stable_digest(self)
+    }
+}
+
+impl<S> KeySet<S> {
+    /// Returns a stable digest of the keys in the set: SHA-256 over the sorted digests of its keys.
+    ///
+    /// The digest doesn't depend on the set's hasher, or on the order its keys were inserted in, so
+    /// two sets have the same digest exactly when they have the same keys.
+    pub fn content_hash(&self) -> KeyDigest {
+        let mut digests: Vec<_> = self.into_iter().map(OwnedKey::stable_digest).collect();
+        digests.sort_unstable();
+        let mut hasher = Sha256::new();
+        for digest in &digests {
+            hasher.update(digest.as_bytes());
+        }
+        KeyDigest(hasher.finalize().into())
+    }
+}
+
+#[test]
+fn digest_stable() {
+    // Pinned, so that a change to the encoding, which would invalidate every stored digest, fails
+    // this test.
+    let key = OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+    assert_eq!(
+        key.stable_digest().to_string(),
+        "01fd4ef827681b2fbbcbf10041ab86248c9912b6a18f7f704c359937bf671a46"
+    );
+    assert_eq!(key.key().stable_digest(), key.stable_digest());
+
+    let empty = OwnedKey {
+        s: String::new(),
+        bytes: Vec::new(),
+    };
+    let set: KeySet = vec![key.clone(), empty.clone()].into_iter().collect();
+    assert_eq!(
+        set.content_hash().to_string(),
+        "40262cc0dd563e16456b23d2567286a700287c346ecd6cefcc9530a114777dec"
+    );
+    assert_eq!(
+        KeySet::new().content_hash().to_string(),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        "an empty set's digest is SHA-256 of nothing"
+    );
+}
+
+proptest! {
+    #[test]
+    fn digest_matches_equality(a in any::<OwnedKey>(), b in any::<OwnedKey>()) {
+        prop_assert_eq!(a.stable_digest() == b.stable_digest(), a == b);
+    }
+
+    #[test]
+    fn digest_content_hash(
+        keys in prop::collection::vec(any::<OwnedKey>(), 0..16),
+        extra in any::<OwnedKey>(),
+    ) {
+        // Two sets with independently keyed hashers, filled in opposite orders, iterate in
+        // different orders but have the same digest.
+        let forward: KeySet = keys.iter().cloned().collect();
+        let backward: KeySet = keys.iter().rev().cloned().collect();
+        prop_assert_eq!(forward.content_hash(), backward.content_hash());
+
+        let mut more = forward.clone();
+        let added = more.insert(extra);
+        prop_assert_eq!(more.content_hash() == forward.content_hash(), !added);
+    }
+}
@@ -0,0 +1,75 @@
+//! Recovering the concrete type behind a `&dyn Key`.
+
+use crate::boxed::BoxedKey;
+use crate::packed::CompactKey;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::any::Any;
+
+// A &dyn Key throws away what type it came from, which is the whole point: a lookup doesn't care.
+// But now and then the code holding one does care. A log line might want to say which kind of key
+// it saw, or a hot path might have a faster way to handle the one type it sees most.
+//
+// Key::as_any hands back the key as a &dyn Any, which std knows how to downcast. It returns an
+// Option, and the default implementation returns None, for two reasons:
+//
+// - Any only works for 'static types. A BorrowedKey<'a> can't be one. (It doesn't need to be,
+//   though: key() already returns a BorrowedKey.)
+// - Existing implementations of Key, which don't know about as_any, keep compiling.
+//
+// Every owned key type in this crate overrides it. A key type outside the crate can opt in the same
+// way, with a one-line `Some(self)`.
+impl<'a> dyn Key + 'a {
+    /// Returns true if this key is a `T`.
+    ///
+    /// Always false if the key's type doesn't implement [`Key::as_any`].
+    pub fn is<T: Any>(&self) -> bool {
+        self.as_any().is_some_and(|any| any.is::<T>())
+    }
+
+    /// Returns this key as a `T`, if it is one.
+    ///
+    /// Always `None` if the key's type doesn't implement [`Key::as_any`].
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.as_any()?.downcast_ref()
+    }
+}
+
+#[test]
+fn downcast_types() {
+    let owned = OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+    let key: &dyn Key = &owned;
+    assert_eq!(key.downcast_ref::<OwnedKey>(), Some(&owned));
+    assert!(key.is::<OwnedKey>());
+    assert!(!key.is::<CompactKey>());
+    // OwnedKey<Box<[u8]>> is a different type from the default OwnedKey<Vec<u8>>.
+    assert!(!key.is::<OwnedKey<Box<[u8]>>>());
+
+    let compact = CompactKey::new("foo", b"abc");
+    let key: &dyn Key = &compact;
+    assert!(key.is::<CompactKey>());
+    assert!(key.downcast_ref::<OwnedKey>().is_none());
+
+    // Borrowed keys aren't 'static, so they never downcast.
+    let borrowed = owned.key();
+    let key: &dyn Key = &borrowed;
+    assert!(key.as_any().is_none());
+    assert!(!key.is::<BorrowedKey<'static>>());
+
+    // A boxed key of any type downcasts to whatever it was boxed from.
+    let boxed: BoxedKey = Box::new(compact.clone());
+    let key: &dyn Key = &*boxed;
+    assert_eq!(key.downcast_ref::<CompactKey>(), Some(&compact));
+}
+
+proptest! {
+    #[test]
+    fn downcast_agrees_with_key(owned in any::<OwnedKey>()) {
+        let key: &dyn Key = &owned;
+        let downcast = key.downcast_ref::<OwnedKey>().expect("an OwnedKey downcasts to itself");
+        prop_assert_eq!(downcast.key(), key.key());
+    }
+}
@@ -0,0 +1,249 @@
+//! Encoding keys as byte strings that sort the same way the keys do.
+
+use crate::{Key, OwnedKey};
+use proptest::prelude::*;
+use std::error::Error;
+use std::fmt;
+use std::str::{self, Utf8Error};
+
+// Key-value stores like RocksDB, LMDB and sled only know about byte strings, and keep them sorted
+// byte by byte. A key can be stored in one of them and still be range-scanned in key order if it's
+// encoded as a byte string whose order matches Ord on the key. (This is sometimes called a
+// "memcomparable" encoding.)
+//
+// Ord on a key compares the string first, then the bytes. Strings compare byte by byte, so it's
+// tempting to just concatenate the two fields, but then ("a", b"b") and ("ab", b"") both encode to
+// "ab". The string needs a terminator that sorts before anything that could follow it:
+//
+// - The string is terminated with 0x00 0x01.
+// - A 0x00 inside the string is escaped as 0x00 0xff, so it sorts after the terminator. This makes
+//   "a" sort before "a\0", as it should.
+//
+// The bytes are the last field, so they don't need a terminator or a length: they're everything
+// after the string. That also means a key's encoded string is a prefix of the encodings of every
+// key with that string, which is what string_prefix returns.
+const ESCAPE: u8 = 0x00;
+const TERMINATOR: u8 = 0x01;
+const ESCAPED_ZERO: u8 = 0xff;
+
+/// Encodes `key` as a byte string. Encoded keys sort in the same order as the keys themselves.
+pub fn encode(key: &dyn Key) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_into(key, &mut out);
+    out
+}
+
+/// Appends the encoding of `key` to `out`.
+pub fn encode_into(key: &dyn Key, out: &mut Vec<u8>) {
+    let key = key.key();
+    out.reserve(key.s.len() + 2 + key.bytes.len());
+    encode_extend(&key, out);
+}
+
+/// Appends the encoding of `key` to any byte buffer, such as a `SmallVec` on the stack.
+pub fn encode_extend(key: &dyn Key, out: &mut impl Extend<u8>) {
+    let key = key.key();
+    encode_string(key.s, out);
+    out.extend(key.bytes.iter().copied());
+}
+
+/// Returns the prefix shared by the encodings of all keys whose string is `s`.
+///
+/// Scanning a sorted store from this prefix finds all of those keys, in order of their bytes.
+pub fn string_prefix(s: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len() + 2);
+    encode_string(s, &mut out);
+    out
+}
+
+/// Returns the prefix shared by the encodings of all keys whose string starts with `prefix`.
+///
+/// Unlike [`string_prefix`], this leaves off the terminator, so it also matches longer strings.
+pub fn string_starts_with_prefix(prefix: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(prefix.len());
+    escape_string(prefix, &mut out);
+    out
+}
+
+fn encode_string(s: &str, out: &mut impl Extend<u8>) {
+    escape_string(s, out);
+    out.extend([ESCAPE, TERMINATOR]);
+}
+
+// Each byte of the string becomes one or two bytes on its own, with no terminator in between, so
+// escaping a prefix of a string gives a prefix of the escaped string. The runs of bytes between
+// 0x00s are copied over whole.
+fn escape_string(s: &str, out: &mut impl Extend<u8>) {
+    for (i, run) in s.as_bytes().split(|&b| b == ESCAPE).enumerate() {
+        if i > 0 {
+            out.extend([ESCAPE, ESCAPED_ZERO]);
+        }
+        out.extend(run.iter().copied());
+    }
+}
+
+/// Decodes a key produced by [`encode`].
+pub fn decode(encoded: &[u8]) -> Result<OwnedKey, DecodeError> {
+    let mut s = Vec::new();
+    let mut iter = encoded.iter().enumerate();
+    let bytes_start = loop {
+        match iter.next() {
+            Some((_, &ESCAPE)) => match iter.next() {
+                Some((index, &TERMINATOR)) => break index + 1,
+                Some((_, &ESCAPED_ZERO)) => s.push(0),
+                Some((index, _)) => return Err(DecodeError::InvalidEscape { index }),
+                None => return Err(DecodeError::Unterminated),
+            },
+            Some((_, &b)) => s.push(b),
+            None => return Err(DecodeError::Unterminated),
+        }
+    };
+    let s = String::from_utf8(s).map_err(|err| DecodeError::InvalidUtf8(err.utf8_error()))?;
+    Ok(OwnedKey {
+        s,
+        bytes: encoded[bytes_start..].to_vec(),
+    })
+}
+
+/// Checks that `encoded` could have been produced by [`encode`], without decoding it. Returns the
+/// index at which the key's bytes start.
+pub(crate) fn validate(encoded: &[u8]) -> Result<usize, DecodeError> {
+    // First find the terminator, checking the escapes along the way.
+    let mut index = 0;
+    let terminator = loop {
+        match encoded[index..].iter().position(|&b| b == ESCAPE) {
+            Some(offset) => index += offset,
+            None => return Err(DecodeError::Unterminated),
+        }
+        match encoded.get(index + 1) {
+            Some(&TERMINATOR) => break index,
+            Some(&ESCAPED_ZERO) => index += 2,
+            Some(_) => return Err(DecodeError::InvalidEscape { index: index + 1 }),
+            None => return Err(DecodeError::Unterminated),
+        }
+    };
+
+    // Then check the string. A 0x00 is a whole char on its own, so the string is valid UTF-8
+    // exactly when each run of bytes between the escapes is. Every run after the first starts with
+    // the second byte of the escape before it.
+    for (run, bytes) in encoded[..terminator].split(|&b| b == ESCAPE).enumerate() {
+        let bytes = if run == 0 { bytes } else { &bytes[1..] };
+        str::from_utf8(bytes).map_err(DecodeError::InvalidUtf8)?;
+    }
+    Ok(terminator + 2)
+}
+
+/// An error returned by [`decode`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// The input ended before the end of the string.
+    Unterminated,
+    /// The byte at `index` followed a 0x00 in the string, but isn't a valid escape.
+    InvalidEscape {
+        /// The position of the invalid byte in the input.
+        index: usize,
+    },
+    /// The string isn't valid UTF-8.
+    InvalidUtf8(Utf8Error),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Unterminated => write!(f, "encoded key ended before its string did"),
+            DecodeError::InvalidEscape { index } => {
+                write!(f, "invalid escape byte at index {}", index)
+            }
+            DecodeError::InvalidUtf8(_) => write!(f, "encoded string isn't valid UTF-8"),
+        }
+    }
+}
+
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DecodeError::InvalidUtf8(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn encoding_order() {
+    let keys = [
+        ("", &b""[..]),
+        ("", b"\x00"),
+        ("", b"b"),
+        ("a", b""),
+        ("a", b"\xff"),
+        ("a\0", b""),
+        ("a\0b", b""),
+        ("ab", b""),
+    ];
+    let encoded: Vec<_> = keys
+        .iter()
+        .map(|&(s, bytes)| encode(&crate::BorrowedKey { s, bytes }))
+        .collect();
+    assert!(encoded.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(encoded[5], b"a\x00\xff\x00\x01");
+
+    let prefix = string_prefix("a");
+    assert!(encoded[3].starts_with(&prefix) && encoded[4].starts_with(&prefix));
+    assert!(!encoded[5].starts_with(&prefix));
+
+    // "a" is a prefix of "a", "a\0", "a\0b" and "ab", but not of "".
+    let starts_with = string_starts_with_prefix("a");
+    assert!(encoded[3..].iter().all(|e| e.starts_with(&starts_with)));
+    assert!(!encoded[2].starts_with(&starts_with));
+    let starts_with = string_starts_with_prefix("a\0");
+    let matches: Vec<_> = encoded
+        .iter()
+        .filter(|e| e.starts_with(&starts_with))
+        .collect();
+    assert_eq!(matches, [&encoded[5], &encoded[6]]);
+
+    assert_eq!(decode(b"abc"), Err(DecodeError::Unterminated));
+    assert_eq!(
+        decode(b"a\x00\x02"),
+        Err(DecodeError::InvalidEscape { index: 2 })
+    );
+    assert!(matches!(
+        decode(b"\xc3\x00\x01"),
+        Err(DecodeError::InvalidUtf8(_))
+    ));
+}
+
+#[cfg(test)]
+fn keys_with_zeros() -> impl Strategy<Value = OwnedKey> {
+    // Arbitrary strings rarely contain a 0x00, so pick from a tiny alphabet that includes it.
+    (
+        "[\0ab]{0,4}",
+        prop::collection::vec(prop_oneof![Just(0u8), Just(1), Just(0xff)], 0..4),
+    )
+        .prop_map(|(s, bytes)| OwnedKey { s, bytes })
+}
+
+proptest! {
+    #[test]
+    fn encoding_preserves_order(
+        a in prop_oneof![any::<OwnedKey>(), keys_with_zeros()],
+        b in prop_oneof![any::<OwnedKey>(), keys_with_zeros()],
+    ) {
+        prop_assert_eq!(encode(&a).cmp(&encode(&b)), a.cmp(&b));
+        prop_assert_eq!(
+            encode(&b).starts_with(&string_starts_with_prefix(&a.s)),
+            b.s.starts_with(&a.s)
+        );
+        prop_assert_eq!(decode(&encode(&a)), Ok(a));
+    }
+
+    #[test]
+    fn encoding_validate_matches_decode(encoded in prop::collection::vec(prop_oneof![
+        Just(ESCAPE),
+        Just(TERMINATOR),
+        Just(ESCAPED_ZERO),
+        any::<u8>(),
+    ], 0..8)) {
+        prop_assert_eq!(validate(&encoded).is_ok(), decode(&encoded).is_ok());
+    }
+}
@@ -0,0 +1,379 @@
+//! A key set whose entries expire.
+
+#[cfg(test)]
+use crate::owned;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+use std::time::{Duration, Instant};
+
+// Caches of recently seen keys, like a deduplication window for incoming requests or a set of
+// sessions that time out, need each key to go away on its own some time after it was last seen.
+//
+// ExpiringKeySet stores a deadline next to each key. Lookups compare it against the clock, so an
+// entry past its deadline is absent as far as contains is concerned, even before anything has
+// removed it. Expired entries are only actually removed by purge and drain_expired, or when their
+// key is inserted again, so the caller decides when to pay for the scan.
+//
+// A time to live can be too long to add to the current time: Instant can't represent the result,
+// and adding would panic. Duration::MAX is the natural way to ask for entries that never expire, so
+// a deadline that can't be represented is taken to mean exactly that, and stored as None.
+//
+// The common operation on a hit is touch, which pushes the deadline back. It takes &dyn Key like
+// any other lookup, and updates the deadline in place, so refreshing a key never clones it.
+//
+// The time comes from a Clock. SystemClock reads Instant::now; ManualClock only moves when told
+// to, so tests can check expiry without sleeping.
+/// A source of the current time for an [`ExpiringKeySet`].
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+/// The system's monotonic clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when [`advance`](Self::advance) is called, for tests.
+///
+/// Share it with a set by reference, as `ExpiringKeySet<&ManualClock>`.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Cell<Instant>,
+}
+
+impl ManualClock {
+    /// Creates a clock that starts at the current time.
+    pub fn new() -> Self {
+        Self {
+            now: Cell::new(Instant::now()),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+}
+
+/// A set of keys that each expire a fixed time after they were inserted or last touched.
+///
+/// An entry is live until its deadline, and expired from then on. An entry whose time to live is
+/// too long to represent, such as [`Duration::MAX`], never expires.
+#[derive(Clone, Debug)]
+pub struct ExpiringKeySet<C = SystemClock, S = RandomState> {
+    deadlines: HashMap<OwnedKey, Option<Instant>, S>,
+    ttl: Duration,
+    clock: C,
+}
+
+impl ExpiringKeySet {
+    /// Creates a new, empty set whose entries expire `ttl` after they're inserted or touched.
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_clock(ttl, SystemClock)
+    }
+}
+
+impl<C: Clock> ExpiringKeySet<C> {
+    /// Creates a new, empty set that reads the time from `clock`.
+    pub fn with_clock(ttl: Duration, clock: C) -> Self {
+        Self::with_clock_and_hasher(ttl, clock, RandomState::new())
+    }
+}
+
+impl<C: Clock, S: BuildHasher> ExpiringKeySet<C, S> {
+    /// Creates a new, empty set that reads the time from `clock` and uses `hasher` to hash keys.
+    pub fn with_clock_and_hasher(ttl: Duration, clock: C, hasher: S) -> Self {
+        Self {
+            deadlines: HashMap::with_hasher(hasher),
+            ttl,
+            clock,
+        }
+    }
+
+    /// Returns the time entries live for after they're inserted or touched.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Inserts `key`, expiring after the set's time to live. Returns true if the key wasn't live
+    /// already. If it was, its deadline is pushed back, as with [`touch`](Self::touch).
+    pub fn insert(&mut self, key: OwnedKey) -> bool {
+        self.insert_with_ttl(key, self.ttl)
+    }
+
+    /// Inserts `key`, expiring after `ttl` rather than the set's time to live. Returns true if the
+    /// key wasn't live already.
+    pub fn insert_with_ttl(&mut self, key: OwnedKey, ttl: Duration) -> bool {
+        let now = self.clock.now();
+        let old = self.deadlines.insert(key, now.checked_add(ttl));
+        !old.is_some_and(|deadline| is_live(deadline, now))
+    }
+
+    /// Returns true if `key` is in the set and hasn't expired.
+    pub fn contains(&self, key: &dyn Key) -> bool {
+        self.deadlines
+            .get(key)
+            .is_some_and(|&deadline| is_live(deadline, self.clock.now()))
+    }
+
+    /// Returns the deadline of `key`, if it's in the set, hasn't expired, and has a deadline at
+    /// all. An entry that never expires has none.
+    pub fn deadline(&self, key: &dyn Key) -> Option<Instant> {
+        let deadline = (*self.deadlines.get(key)?)?;
+        (self.clock.now() < deadline).then_some(deadline)
+    }
+
+    /// Pushes the deadline of `key` back to the set's time to live from now. Returns false, and
+    /// does nothing, if the key isn't in the set or has already expired.
+    pub fn touch(&mut self, key: &dyn Key) -> bool {
+        let now = self.clock.now();
+        match self.deadlines.get_mut(key) {
+            Some(deadline) if is_live(*deadline, now) => {
+                *deadline = now.checked_add(self.ttl);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes `key`. Returns true if it was in the set and hadn't expired.
+    pub fn remove(&mut self, key: &dyn Key) -> bool {
+        let now = self.clock.now();
+        self.deadlines
+            .remove(key)
+            .is_some_and(|deadline| is_live(deadline, now))
+    }
+
+    /// Removes every expired entry, and returns how many there were.
+    pub fn purge(&mut self) -> usize {
+        let now = self.clock.now();
+        let before = self.deadlines.len();
+        self.deadlines.retain(|_, deadline| is_live(*deadline, now));
+        before - self.deadlines.len()
+    }
+
+    /// Removes every expired entry, returning their keys in arbitrary order.
+    ///
+    /// The entries are all removed before this returns, whatever is done with the keys.
+    pub fn drain_expired(&mut self) -> Vec<OwnedKey> {
+        let now = self.clock.now();
+        self.deadlines
+            .extract_if(|_, deadline| !is_live(*deadline, now))
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// Returns an iterator over the live keys and their deadlines, in arbitrary order. Keys that
+    /// never expire have no deadline.
+    pub fn iter(&self) -> impl Iterator<Item = (BorrowedKey<'_>, Option<Instant>)> {
+        let now = self.clock.now();
+        self.deadlines
+            .iter()
+            .filter(move |(_, deadline)| is_live(**deadline, now))
+            .map(|(key, deadline)| (key.key(), *deadline))
+    }
+
+    /// Returns the number of entries, including expired ones that haven't been purged yet.
+    pub fn len(&self) -> usize {
+        self.deadlines.len()
+    }
+
+    /// Returns true if the set has no entries, expired or not.
+    pub fn is_empty(&self) -> bool {
+        self.deadlines.is_empty()
+    }
+}
+
+// None is a deadline too far away to represent, which is never reached.
+fn is_live(deadline: Option<Instant>, now: Instant) -> bool {
+    deadline.is_none_or(|deadline| now < deadline)
+}
+
+#[test]
+fn expiring_lookups() {
+    let clock = ManualClock::new();
+    let mut set = ExpiringKeySet::with_clock(Duration::from_secs(10), &clock);
+    let key = BorrowedKey::new("foo", b"abc");
+    assert!(set.insert(owned("foo", b"abc")));
+    assert!(set.insert_with_ttl(owned("bar", b""), Duration::from_secs(30)));
+    assert!(set.contains(&key));
+
+    // Touching a live key pushes its deadline back.
+    clock.advance(Duration::from_secs(6));
+    assert!(set.touch(&key));
+    assert_eq!(
+        set.deadline(&key),
+        Some(clock.now() + Duration::from_secs(10))
+    );
+    clock.advance(Duration::from_secs(6));
+    assert!(set.contains(&key));
+
+    // Past its deadline, a key is absent, and can't be touched back to life.
+    clock.advance(Duration::from_secs(4));
+    assert!(!set.contains(&key));
+    assert!(!set.touch(&key));
+    assert_eq!(set.len(), 2, "expired entries stay until they're purged");
+    let live: Vec<_> = set.iter().map(|(key, _)| key).collect();
+    assert_eq!(live, [BorrowedKey::new("bar", b"")]);
+
+    assert_eq!(set.purge(), 1);
+    assert_eq!(set.len(), 1);
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(set.drain_expired(), [owned("bar", b"")]);
+    assert!(set.is_empty());
+
+    // Inserting an expired key again counts as a new entry.
+    assert!(set.insert(owned("foo", b"abc")));
+    assert!(!set.insert(owned("foo", b"abc")));
+    clock.advance(Duration::from_secs(10));
+    assert!(set.insert(owned("foo", b"abc")));
+    assert!(set.remove(&key));
+    assert!(!set.remove(&key));
+}
+
+#[test]
+fn expiring_drain_partly_used() {
+    let clock = ManualClock::new();
+    let mut set = ExpiringKeySet::with_clock(Duration::from_secs(10), &clock);
+    for s in ["a", "b", "c"] {
+        set.insert(owned(s, b""));
+    }
+    clock.advance(Duration::from_secs(10));
+    set.insert(owned("d", b""));
+
+    // Only looking at the first key still removes all of them.
+    let first = set.drain_expired().into_iter().next();
+    assert!(first.is_some());
+    assert_eq!(set.len(), 1);
+    assert!(set.drain_expired().is_empty());
+}
+
+#[test]
+fn expiring_never() {
+    // A time to live too long to add to the current time means the entry never expires.
+    let clock = ManualClock::new();
+    let mut set = ExpiringKeySet::with_clock(Duration::MAX, &clock);
+    let key = BorrowedKey::new("foo", b"abc");
+    assert!(set.insert(owned("foo", b"abc")));
+    assert!(set.insert_with_ttl(owned("bar", b""), Duration::from_secs(10)));
+    clock.advance(Duration::from_secs(1000 * 365 * 24 * 60 * 60));
+    assert!(set.contains(&key));
+    assert_eq!(set.deadline(&key), None);
+    assert!(set.touch(&key));
+    assert!(!set.insert(owned("foo", b"abc")));
+    assert_eq!(set.purge(), 1);
+    let live: Vec<_> = set.iter().collect();
+    assert_eq!(live, [(key, None)]);
+
+    // It works the other way around too.
+    let mut set = ExpiringKeySet::with_clock(Duration::from_secs(10), &clock);
+    assert!(set.insert_with_ttl(owned("foo", b"abc"), Duration::MAX));
+    clock.advance(Duration::from_secs(20));
+    assert!(set.contains(&key));
+    assert!(set.remove(&key));
+}
+
+#[cfg(test)]
+#[derive(Clone, Debug)]
+enum Op {
+    Insert(OwnedKey),
+    Touch(OwnedKey),
+    Remove(OwnedKey),
+    Advance(u8),
+    Purge,
+}
+
+#[cfg(test)]
+fn op() -> impl Strategy<Value = Op> {
+    // A small set of keys, so that operations often hit keys that are already there.
+    let key =
+        ("[ab]", prop::collection::vec(0..2u8, 0..2)).prop_map(|(s, bytes)| OwnedKey { s, bytes });
+    prop_oneof![
+        key.clone().prop_map(Op::Insert),
+        key.clone().prop_map(Op::Touch),
+        key.prop_map(Op::Remove),
+        (0..8u8).prop_map(Op::Advance),
+        Just(Op::Purge),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn expiring_matches_model(ops in prop::collection::vec(op(), 0..64)) {
+        // The model keeps each key's deadline in whole seconds since the start.
+        const TTL: u64 = 5;
+        let clock = ManualClock::new();
+        let mut set = ExpiringKeySet::with_clock(Duration::from_secs(TTL), &clock);
+        let mut model: HashMap<OwnedKey, u64> = HashMap::new();
+        let mut now = 0;
+        for op in ops {
+            match op {
+                Op::Insert(key) => {
+                    let live = model.get(&key).is_some_and(|&deadline| now < deadline);
+                    model.insert(key.clone(), now + TTL);
+                    prop_assert_eq!(set.insert(key), !live);
+                }
+                Op::Touch(key) => {
+                    let touched = match model.get_mut(&key) {
+                        Some(deadline) if now < *deadline => {
+                            *deadline = now + TTL;
+                            true
+                        }
+                        _ => false,
+                    };
+                    prop_assert_eq!(set.touch(&key), touched);
+                }
+                Op::Remove(key) => {
+                    let live = model.remove(&key).is_some_and(|deadline| now < deadline);
+                    prop_assert_eq!(set.remove(&key), live);
+                }
+                Op::Advance(secs) => {
+                    clock.advance(Duration::from_secs(secs.into()));
+                    now += u64::from(secs);
+                }
+                Op::Purge => {
+                    let before = model.len();
+                    model.retain(|_, deadline| now < *deadline);
+                    prop_assert_eq!(set.purge(), before - model.len());
+                }
+            }
+
+            let live: HashSet<_> = model
+                .iter()
+                .filter(|(_, &deadline)| now < deadline)
+                .map(|(key, _)| key.key())
+                .collect();
+            let set_live: HashSet<_> = set.iter().map(|(key, _)| key).collect();
+            prop_assert_eq!(set_live, live);
+            prop_assert_eq!(set.len(), model.len());
+        }
+    }
+}
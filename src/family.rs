@@ -0,0 +1,116 @@
+//! The trait object pattern from the crate root, written once for any borrowed form.
+//!
+//! In the crate root, the `Key` trait is tied to one particular borrowed type, `BorrowedKey`. If you
+//! have many key types, writing a new trait (and five trait object impls) for each one gets
+//! repetitive. Instead, a [`KeyFamily`] names a borrowed form, and `dyn Lookup<F>` plays the role
+//! that `dyn Key` does in the crate root.
+
+use crate::{BorrowedKey, Key, OwnedKey};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+
+/// Names the borrowed form shared by a family of key types.
+///
+/// This is usually implemented by the owned key type itself.
+pub trait KeyFamily {
+    /// The borrowed form of keys in this family.
+    type Borrowed<'a>: Copy + Debug + Eq + Ord + Hash;
+
+    /// Shortens the lifetime of a borrowed key.
+    ///
+    /// The compiler treats generic associated types as invariant over their lifetime parameters,
+    /// even when the actual type (such as `&'a str`) is covariant. The implementation of this
+    /// method should always be just `borrowed`: that compiles exactly when `Borrowed` is covariant.
+    fn shorten<'long: 'short, 'short>(borrowed: Self::Borrowed<'long>) -> Self::Borrowed<'short>;
+}
+
+/// A key that can be viewed in the borrowed form of the family `F`.
+///
+/// This is the generic version of the `Key` trait in the crate root.
+pub trait Lookup<F: KeyFamily> {
+    /// Returns the borrowed form of this key.
+    fn lookup_key(&self) -> F::Borrowed<'_>;
+}
+
+impl<'a, F: KeyFamily> PartialEq for dyn Lookup<F> + 'a {
+    fn eq(&self, other: &Self) -> bool {
+        self.lookup_key().eq(&other.lookup_key())
+    }
+}
+
+impl<'a, F: KeyFamily> Eq for dyn Lookup<F> + 'a {}
+
+impl<'a, F: KeyFamily> PartialOrd for dyn Lookup<F> + 'a {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a, F: KeyFamily> Ord for dyn Lookup<F> + 'a {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.lookup_key().cmp(&other.lookup_key())
+    }
+}
+
+impl<'a, F: KeyFamily> Hash for dyn Lookup<F> + 'a {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.lookup_key().hash(state)
+    }
+}
+
+/// A borrowed key in the family `F`, ready to be used for lookups.
+///
+/// This wraps `F::Borrowed` so that it can implement `Lookup<F>`.
+pub struct Probe<'a, F: KeyFamily>(pub F::Borrowed<'a>);
+
+// These are written out by hand because the derives would require F itself to be Copy, Debug, etc.
+impl<'a, F: KeyFamily> Clone for Probe<'a, F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, F: KeyFamily> Copy for Probe<'a, F> {}
+
+impl<'a, F: KeyFamily> Debug for Probe<'a, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Probe").field(&self.0).finish()
+    }
+}
+
+impl<'a, F: KeyFamily> Probe<'a, F> {
+    /// Returns this probe as a trait object, for passing into `contains`, `get` and friends.
+    pub fn as_lookup(&self) -> &(dyn Lookup<F> + '_) {
+        self
+    }
+}
+
+impl<'a, F: KeyFamily> Lookup<F> for Probe<'a, F> {
+    fn lookup_key(&self) -> F::Borrowed<'_> {
+        F::shorten(self.0)
+    }
+}
+
+// The crate root's OwnedKey, as a family. Lookups in a map of OwnedKeys can go through dyn Key as
+// usual; this is for code that's generic over families, such as migrations between key versions.
+impl KeyFamily for OwnedKey {
+    type Borrowed<'a> = BorrowedKey<'a>;
+
+    fn shorten<'long: 'short, 'short>(borrowed: BorrowedKey<'long>) -> BorrowedKey<'short> {
+        borrowed
+    }
+}
+
+impl Lookup<OwnedKey> for OwnedKey {
+    fn lookup_key(&self) -> BorrowedKey<'_> {
+        self.key()
+    }
+}
+
+impl<'a> Borrow<dyn Lookup<OwnedKey> + 'a> for OwnedKey {
+    fn borrow(&self) -> &(dyn Lookup<OwnedKey> + 'a) {
+        self
+    }
+}
@@ -0,0 +1,208 @@
+//! Grouping keys by a projection of their borrowed view, without cloning them.
+
+#[cfg(test)]
+use crate::OwnedKey;
+use crate::{BorrowedKey, Key};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::iter::Peekable;
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+use std::collections::BTreeSet;
+
+// Reports over keyed data tend to start by bucketing records by part of the key: every key with a
+// given string, say. The obvious way to write it clones the string into a map's key, once per
+// record, which adds up over a large dataset.
+//
+// No clone is needed. Key::key borrows the view from the key for as long as the key itself is
+// borrowed, so with keys given as &'a K, the view is a BorrowedKey<'a>, and so is anything
+// projected out of it: the &'a str for the string, the &'a [u8] for the bytes. Those can go
+// straight into the map as its keys.
+//
+// group_by collects into a BTreeMap, so it works on keys in any order, and returns the groups
+// sorted by their projection. Within a group, keys stay in the order they came in. When the keys
+// are already sorted in a way that keeps each group together, like iterating over a BTreeSet of
+// OwnedKeys grouped by string, runs_by does the same job without collecting anything, a group at a
+// time.
+/// Groups `keys` by `project`, applied to each key's borrowed view.
+///
+/// The groups come out sorted by their projection, and the keys in each group in the order they
+/// came in.
+pub fn group_by<'a, K, P, I>(
+    keys: I,
+    mut project: impl FnMut(BorrowedKey<'a>) -> P,
+) -> impl Iterator<Item = (P, Vec<&'a K>)>
+where
+    K: Key + ?Sized + 'a,
+    P: Ord,
+    I: IntoIterator<Item = &'a K>,
+{
+    let mut groups: BTreeMap<P, Vec<&'a K>> = BTreeMap::new();
+    for key in keys {
+        groups.entry(project(key.key())).or_default().push(key);
+    }
+    groups.into_iter()
+}
+
+/// Groups `keys` by their string. See [`group_by`].
+pub fn group_by_s<'a, K, I>(keys: I) -> impl Iterator<Item = (&'a str, Vec<&'a K>)>
+where
+    K: Key + ?Sized + 'a,
+    I: IntoIterator<Item = &'a K>,
+{
+    group_by(keys, |key| key.s)
+}
+
+/// Groups `keys` by their bytes. See [`group_by`].
+pub fn group_by_bytes<'a, K, I>(keys: I) -> impl Iterator<Item = (&'a [u8], Vec<&'a K>)>
+where
+    K: Key + ?Sized + 'a,
+    I: IntoIterator<Item = &'a K>,
+{
+    group_by(keys, |key| key.bytes)
+}
+
+/// Splits `keys` into runs of consecutive keys with the same projection.
+///
+/// Nothing is collected apart from each run as it's returned. If keys with the same projection
+/// aren't next to each other, each stretch of them is a separate run: sort them first, or use
+/// [`group_by`].
+pub fn runs_by<'a, K, P, I, F>(keys: I, project: F) -> Runs<I::IntoIter, F>
+where
+    K: Key + ?Sized + 'a,
+    P: PartialEq,
+    I: IntoIterator<Item = &'a K>,
+    F: FnMut(BorrowedKey<'a>) -> P,
+{
+    Runs {
+        keys: keys.into_iter().peekable(),
+        project,
+    }
+}
+
+/// Splits sorted `keys` into runs with the same string. See [`runs_by`].
+///
+/// Iterating over a `BTreeSet` or `BTreeMap` of keys gives them in the right order for this.
+pub fn runs_by_s<'a, K, I>(keys: I) -> Runs<I::IntoIter, impl FnMut(BorrowedKey<'a>) -> &'a str>
+where
+    K: Key + ?Sized + 'a,
+    I: IntoIterator<Item = &'a K>,
+{
+    runs_by(keys, |key| key.s)
+}
+
+/// An iterator over runs of keys with the same projection. Returned by [`runs_by`].
+pub struct Runs<I: Iterator, F> {
+    keys: Peekable<I>,
+    project: F,
+}
+
+impl<'a, K, P, I, F> Iterator for Runs<I, F>
+where
+    K: Key + ?Sized + 'a,
+    P: PartialEq,
+    I: Iterator<Item = &'a K>,
+    F: FnMut(BorrowedKey<'a>) -> P,
+{
+    type Item = (P, Vec<&'a K>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.keys.next()?;
+        let projection = (self.project)(first.key());
+        let mut run = Vec::new();
+        run.push(first);
+        while let Some(&key) = self.keys.peek() {
+            if (self.project)(key.key()) != projection {
+                break;
+            }
+            run.push(key);
+            self.keys.next();
+        }
+        Some((projection, run))
+    }
+}
+
+#[test]
+fn group_reports() {
+    let keys: Vec<OwnedKey> = vec![
+        ("b", &b"1"[..]),
+        ("a", b"2"),
+        ("b", b"3"),
+        ("c", b"1"),
+        ("a", b"1"),
+    ]
+    .into_iter()
+    .map(|(s, bytes)| OwnedKey {
+        s: s.to_string(),
+        bytes: bytes.to_vec(),
+    })
+    .collect();
+
+    let groups: Vec<_> = group_by_s(&keys)
+        .map(|(s, group)| (s, group.len()))
+        .collect();
+    assert_eq!(groups, [("a", 2), ("b", 2), ("c", 1)]);
+    // The groups borrow from the keys: no strings were copied.
+    let (s, group) = group_by_s(&keys).next().unwrap();
+    assert_eq!(s.as_ptr(), keys[1].s.as_ptr());
+    assert!(std::ptr::eq(group[0], &keys[1]));
+    assert!(std::ptr::eq(group[1], &keys[4]));
+
+    let by_bytes: Vec<_> = group_by_bytes(&keys)
+        .map(|(bytes, group)| (bytes, group.len()))
+        .collect();
+    assert_eq!(by_bytes, [(&b"1"[..], 3), (b"2", 1), (b"3", 1)]);
+
+    // Any projection of the view works, and any key type, including trait objects.
+    let dyn_keys: Vec<&dyn Key> = keys.iter().map(|key| key as &dyn Key).collect();
+    let by_len: Vec<_> = group_by(dyn_keys.iter().copied(), |key| {
+        key.s.len() + key.bytes.len()
+    })
+    .map(|(len, group)| (len, group.len()))
+    .collect();
+    assert_eq!(by_len, [(2, 5)]);
+
+    // Sorted input can be grouped without collecting into a map.
+    let sorted: BTreeSet<_> = keys.iter().cloned().collect();
+    let runs: Vec<_> = runs_by_s(&sorted)
+        .map(|(s, run)| {
+            (
+                s,
+                run.into_iter()
+                    .map(|key| &key.bytes[..])
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        runs,
+        [
+            ("a", vec![&b"1"[..], b"2"]),
+            ("b", vec![&b"1"[..], b"3"]),
+            ("c", vec![&b"1"[..]]),
+        ]
+    );
+    // Unsorted, each stretch is its own run.
+    assert_eq!(runs_by_s(&keys).count(), 5);
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn group_matches_runs(keys in prop::collection::vec(any::<OwnedKey>(), 0..16)) {
+        // Grouping unsorted keys is the same as sorting them stably by the projection, then
+        // splitting them into runs.
+        let mut sorted: Vec<&OwnedKey> = keys.iter().collect();
+        sorted.sort_by(|a, b| a.s.cmp(&b.s));
+        let grouped: Vec<_> = group_by_s(&keys).collect();
+        let runs: Vec<_> = runs_by_s(sorted.iter().copied()).collect();
+        prop_assert_eq!(&grouped, &runs);
+
+        let total: usize = grouped.iter().map(|(_, group)| group.len()).sum();
+        prop_assert_eq!(total, keys.len());
+        for (s, group) in &grouped {
+            prop_assert!(group.iter().all(|key| key.s == *s));
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Key containers that use faster hashers than the default.
+
+use crate::index_map::KeyIndexMap;
+use crate::interner::{ConcurrentKeyInterner, KeyInterner};
+use crate::map::KeyMap;
+use crate::set::KeySet;
+use crate::{BorrowedKey, Key, OwnedKey};
+
+// Every lookup hashes the whole key: the string, a terminator byte, the length of the bytes, and
+// then the bytes themselves. That's four writes to the hasher for one lookup, which makes the
+// hasher a bigger share of the cost than it would be for a single integer.
+//
+// The containers in this crate default to std's RandomState, which is SipHash-1-3 with random keys.
+// SipHash resists HashDoS, where an attacker picks keys that all land in the same bucket, and
+// that's the right default for keys that come from untrusted input. For keys that don't, a faster
+// hasher can be swapped in, and these aliases cover the popular ones:
+//
+// - ahash, which uses AES instructions where they're available, and is still keyed randomly.
+// - FxHash, the hasher used inside rustc. It's very fast on short keys, but isn't keyed at all.
+// - wyhash, which is fast on long keys, and is keyed with a fixed seed by default.
+//
+// `cargo bench --bench hashers --features ahash,fxhash,wyhash` compares them on keys of a few
+// different lengths.
+
+/// A [`KeySet`] that uses ahash.
+#[cfg(feature = "ahash")]
+pub type AHashKeySet = KeySet<ahash::RandomState>;
+/// A [`KeyMap`] that uses ahash.
+#[cfg(feature = "ahash")]
+pub type AHashKeyMap<V> = KeyMap<V, ahash::RandomState>;
+/// A [`KeyInterner`] that uses ahash.
+#[cfg(feature = "ahash")]
+pub type AHashKeyInterner = KeyInterner<ahash::RandomState>;
+/// A [`ConcurrentKeyInterner`] that uses ahash.
+#[cfg(feature = "ahash")]
+pub type AHashConcurrentKeyInterner = ConcurrentKeyInterner<ahash::RandomState>;
+/// A [`KeyIndexMap`] that uses ahash.
+#[cfg(feature = "ahash")]
+pub type AHashKeyIndexMap<K> = KeyIndexMap<K, ahash::RandomState>;
+
+/// A [`KeySet`] that uses FxHash.
+#[cfg(feature = "fxhash")]
+pub type FxKeySet = KeySet<rustc_hash::FxBuildHasher>;
+/// A [`KeyMap`] that uses FxHash.
+#[cfg(feature = "fxhash")]
+pub type FxKeyMap<V> = KeyMap<V, rustc_hash::FxBuildHasher>;
+/// A [`KeyInterner`] that uses FxHash.
+#[cfg(feature = "fxhash")]
+pub type FxKeyInterner = KeyInterner<rustc_hash::FxBuildHasher>;
+/// A [`ConcurrentKeyInterner`] that uses FxHash.
+#[cfg(feature = "fxhash")]
+pub type FxConcurrentKeyInterner = ConcurrentKeyInterner<rustc_hash::FxBuildHasher>;
+/// A [`KeyIndexMap`] that uses FxHash.
+#[cfg(feature = "fxhash")]
+pub type FxKeyIndexMap<K> = KeyIndexMap<K, rustc_hash::FxBuildHasher>;
+
+/// A [`KeySet`] that uses wyhash.
+#[cfg(feature = "wyhash")]
+pub type WyKeySet = KeySet<wyhash::WyHasherBuilder>;
+/// A [`KeyMap`] that uses wyhash.
+#[cfg(feature = "wyhash")]
+pub type WyKeyMap<V> = KeyMap<V, wyhash::WyHasherBuilder>;
+/// A [`KeyInterner`] that uses wyhash.
+#[cfg(feature = "wyhash")]
+pub type WyKeyInterner = KeyInterner<wyhash::WyHasherBuilder>;
+/// A [`ConcurrentKeyInterner`] that uses wyhash.
+#[cfg(feature = "wyhash")]
+pub type WyConcurrentKeyInterner = ConcurrentKeyInterner<wyhash::WyHasherBuilder>;
+/// A [`KeyIndexMap`] that uses wyhash.
+#[cfg(feature = "wyhash")]
+pub type WyKeyIndexMap<K> = KeyIndexMap<K, wyhash::WyHasherBuilder>;
+
+#[cfg(test)]
+fn check_hasher<S: std::hash::BuildHasher + Clone + Default + 'static>() {
+    use crate::pair::KeyPair;
+
+    let key = OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+
+    let set: KeySet<S> = std::iter::once(key.clone()).collect();
+    assert!(set.contains(&borrowed_key));
+
+    let map: KeyMap<u32, S> = std::iter::once((key.clone(), 1)).collect();
+    assert_eq!(map.get(&borrowed_key), Some(&1));
+
+    let mut interner = KeyInterner::with_hasher(S::default());
+    let id = interner.intern(&key);
+    assert_eq!(interner.get(&borrowed_key), Some(id));
+
+    // With cfg(shuttle), the concurrent interner's locks are shuttle's, so it has to be used inside
+    // a shuttle test.
+    crate::sync::check(
+        || {
+            let interner: ConcurrentKeyInterner<S> =
+                ConcurrentKeyInterner::with_shards_and_hasher(4, S::default());
+            let key = BorrowedKey {
+                s: "foo",
+                bytes: b"abc",
+            };
+            let id = interner.intern(&key);
+            assert_eq!(interner.get(&key), Some(id));
+            assert_eq!(interner.resolve(id).key(), key);
+        },
+        1,
+    );
+
+    let mut index_map: KeyIndexMap<KeyPair<String, u32>, S> = KeyIndexMap::default();
+    index_map.insert(KeyPair {
+        a: "foo".to_string(),
+        b: 7,
+    });
+    assert_eq!(index_map.get_index_of(("foo", 7)), Some(0));
+}
+
+#[test]
+fn hashers_lookups() {
+    check_hasher::<std::collections::hash_map::RandomState>();
+    #[cfg(feature = "ahash")]
+    check_hasher::<ahash::RandomState>();
+    #[cfg(feature = "fxhash")]
+    check_hasher::<rustc_hash::FxBuildHasher>();
+    #[cfg(feature = "wyhash")]
+    check_hasher::<wyhash::WyHasherBuilder>();
+}
@@ -0,0 +1,118 @@
+//! Probes with an owned byte string and a borrowed string.
+
+#[cfg(test)]
+use crate::consistency::assert_consistent;
+use crate::key_fields::KeyFields;
+use crate::{BorrowedKey, Key, OwnedKey};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+use std::collections::{BTreeSet, HashSet};
+
+// Call sites don't always have both halves of a key in the same state. A decoder might produce the
+// bytes as a fresh Vec<u8>, while the string is a slice of some longer-lived input. Probing with a
+// BorrowedKey works, but needs the Vec to be kept alive separately, and building an OwnedKey means
+// copying the string for nothing.
+//
+// HybridKey holds the two as they are: the string borrowed, the bytes owned. It's a key like any
+// other, so it probes collections of OwnedKeys through &dyn Key. It can also be stored, with
+// Borrow<dyn Key> like OwnedKey, for sets that live no longer than the strings they borrow.
+//
+// The fields are in the same order as OwnedKey's and BorrowedKey's, and the derives compare a
+// &str like a String and a Vec<u8> like a &[u8], so the derived Eq, Ord and Hash agree with the
+// other two.
+/// A key with a borrowed string and owned bytes.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, KeyFields)]
+pub struct HybridKey<'a> {
+    pub s: &'a str,
+    pub bytes: Vec<u8>,
+}
+
+impl<'a> HybridKey<'a> {
+    /// Creates a key from a borrowed string and owned bytes.
+    pub fn new(s: &'a str, bytes: impl Into<Vec<u8>>) -> Self {
+        Self {
+            s,
+            bytes: bytes.into(),
+        }
+    }
+
+    /// Copies the string, turning this into an [`OwnedKey`].
+    pub fn into_owned(self) -> OwnedKey {
+        OwnedKey {
+            s: self.s.into(),
+            bytes: self.bytes,
+        }
+    }
+}
+
+impl<'a> Key for HybridKey<'a> {
+    fn key<'k>(&'k self) -> BorrowedKey<'k> {
+        BorrowedKey {
+            s: self.s,
+            bytes: &self.bytes,
+        }
+    }
+}
+
+impl<'a, 'b: 'a> Borrow<dyn Key + 'a> for HybridKey<'b> {
+    fn borrow(&self) -> &(dyn Key + 'a) {
+        self
+    }
+}
+
+crate::assert_key_fields_match!(OwnedKey, HybridKey);
+
+#[test]
+fn hybrid_lookups() {
+    let mut hash_set: HashSet<OwnedKey> = HashSet::new();
+    hash_set.insert(OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    });
+
+    let input = String::from("foo=616263");
+    let (s, _) = input.split_once('=').unwrap();
+    // Pretend these were decoded from the hex.
+    let decoded = b"abc".to_vec();
+    let probe = HybridKey::new(s, decoded);
+    assert!(hash_set.contains(&probe as &dyn Key));
+
+    // Hybrid keys can be stored too, and probed with the other two.
+    let btree_set: BTreeSet<HybridKey<'_>> = vec![probe.clone(), HybridKey::new("bar", [0])]
+        .into_iter()
+        .collect();
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert!(btree_set.contains(&borrowed_key as &dyn Key));
+    let owned_key = probe.into_owned();
+    assert!(btree_set.contains(&owned_key as &dyn Key));
+    assert!(!btree_set.contains(&BorrowedKey::new("bar", b"") as &dyn Key));
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn hybrid_consistent_borrow(owned1 in any::<OwnedKey>(), owned2 in any::<OwnedKey>()) {
+        let hybrid1 = HybridKey::new(&owned1.s, owned1.bytes.clone());
+        let hybrid2 = HybridKey::new(&owned2.s, owned2.bytes.clone());
+        assert_consistent::<_, dyn Key>(&owned1, &owned2);
+        assert_consistent::<_, dyn Key>(&hybrid1, &hybrid2);
+
+        // Across representations: all three compare the same way.
+        let (borrowed1, borrowed2) = (owned1.key(), owned2.key());
+        prop_assert!(&hybrid1 as &dyn Key == &owned1 as &dyn Key);
+        prop_assert!(&hybrid1 as &dyn Key == &borrowed1 as &dyn Key);
+        prop_assert_eq!(hybrid1.cmp(&hybrid2), owned1.cmp(&owned2));
+        prop_assert_eq!(hybrid1.cmp(&hybrid2), borrowed1.cmp(&borrowed2));
+        prop_assert_eq!(
+            (&hybrid1 as &dyn Key).cmp(&borrowed2),
+            (&owned1 as &dyn Key).cmp(&owned2),
+        );
+        prop_assert_eq!(hybrid1.into_owned(), owned1);
+    }
+}
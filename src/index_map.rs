@@ -0,0 +1,185 @@
+//! A two-way map between keys and dense indices.
+
+use crate::family::{KeyFamily, Lookup, Probe};
+use crate::pair::KeyPair;
+use proptest::prelude::*;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+// Columnar data structures keep one Vec per column, and a key's row is its index into each of them.
+// Finding the row for a key means a map from keys to indices, and going from a row back to its key
+// means a Vec of keys. KeyIndexMap keeps the two in sync.
+//
+// Unlike KeyInterner, KeyIndexMap works with any key family, and supports removal. Removing a key
+// moves the last key into its slot, just like Vec::swap_remove, so indices stay dense: callers
+// should swap_remove the same row from each of their columns.
+//
+// Each key is stored twice, once in each direction, so keys are cloned on insert.
+/// A map from keys in the family `K` to dense indices, and back.
+#[derive(Clone, Debug)]
+pub struct KeyIndexMap<K, S = RandomState> {
+    indices: HashMap<K, usize, S>,
+    keys: Vec<K>,
+}
+
+impl<K, S: Default> Default for KeyIndexMap<K, S> {
+    fn default() -> Self {
+        Self {
+            indices: HashMap::default(),
+            keys: Vec::new(),
+        }
+    }
+}
+
+impl<K> KeyIndexMap<K>
+where
+    K: KeyFamily + Lookup<K> + Clone + Eq + Hash + for<'a> Borrow<dyn Lookup<K> + 'a>,
+{
+    /// Creates a new, empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<K, S> KeyIndexMap<K, S>
+where
+    K: KeyFamily + Lookup<K> + Clone + Eq + Hash + for<'a> Borrow<dyn Lookup<K> + 'a>,
+    S: BuildHasher,
+{
+    /// Creates a new, empty map that uses `hasher` to hash keys.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            indices: HashMap::with_hasher(hasher),
+            keys: Vec::new(),
+        }
+    }
+
+    /// Inserts `key` if it isn't already present. Returns its index, and whether it was inserted.
+    pub fn insert(&mut self, key: K) -> (usize, bool) {
+        if let Some(index) = self.get_index_of(key.lookup_key()) {
+            return (index, false);
+        }
+        let index = self.keys.len();
+        self.keys.push(key.clone());
+        self.indices.insert(key, index);
+        (index, true)
+    }
+
+    /// Returns the index of `key`, if it's present.
+    pub fn get_index_of(&self, key: K::Borrowed<'_>) -> Option<usize> {
+        self.indices.get(Probe::<K>(key).as_lookup()).copied()
+    }
+
+    /// Returns the key at `index`, if there is one.
+    pub fn get_key(&self, index: usize) -> Option<&K> {
+        self.keys.get(index)
+    }
+
+    /// Removes `key`, returning its index and the stored key.
+    ///
+    /// The last key is moved into the removed key's index.
+    pub fn swap_remove(&mut self, key: K::Borrowed<'_>) -> Option<(usize, K)> {
+        let index = self.indices.remove(Probe::<K>(key).as_lookup())?;
+        let removed = self.keys.swap_remove(index);
+        if let Some(moved) = self.keys.get(index) {
+            *self
+                .indices
+                .get_mut(moved)
+                .expect("every key in keys is in indices") = index;
+        }
+        Some((index, removed))
+    }
+
+    /// Returns the number of keys.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true if the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns all keys, in index order.
+    pub fn keys(&self) -> &[K] {
+        &self.keys
+    }
+}
+
+#[cfg(test)]
+type TestKey = KeyPair<String, u32>;
+
+#[test]
+fn index_map_lookups() {
+    // A tiny column store: one column of scores, indexed by (name, id).
+    let mut index_map: KeyIndexMap<TestKey> = KeyIndexMap::new();
+    let mut scores = Vec::new();
+    for (name, id, score) in [("alice", 1, 0.5), ("bob", 2, 0.25), ("carol", 3, 0.75)] {
+        let (index, inserted) = index_map.insert(KeyPair {
+            a: name.to_string(),
+            b: id,
+        });
+        assert!(inserted);
+        assert_eq!(index, scores.len());
+        scores.push(score);
+    }
+
+    let bob = index_map.get_index_of(("bob", 2)).unwrap();
+    assert_eq!(scores[bob], 0.25);
+    assert_eq!(index_map.get_key(bob).unwrap().a, "bob");
+    assert_eq!(index_map.get_index_of(("bob", 3)), None);
+
+    // Removing alice moves carol into her row.
+    let (alice, _) = index_map.swap_remove(("alice", 1)).unwrap();
+    scores.swap_remove(alice);
+    assert_eq!(index_map.get_index_of(("carol", 3)), Some(alice));
+    assert_eq!(scores[alice], 0.75);
+    assert_eq!(index_map.len(), 2);
+}
+
+#[cfg(test)]
+#[derive(Clone, Debug)]
+enum Op {
+    Insert(TestKey),
+    Remove(TestKey),
+}
+
+#[cfg(test)]
+fn ops() -> impl Strategy<Value = Vec<Op>> {
+    // Few enough distinct keys that inserts and removes hit existing keys often.
+    let key = ("[ab]", 0..3u32).prop_map(|(a, b)| KeyPair { a, b });
+    prop::collection::vec(
+        prop_oneof![key.clone().prop_map(Op::Insert), key.prop_map(Op::Remove)],
+        0..32,
+    )
+}
+
+proptest! {
+    #[test]
+    fn index_map_stays_in_sync(ops in ops()) {
+        let mut index_map = KeyIndexMap::new();
+        for op in ops {
+            match op {
+                Op::Insert(key) => {
+                    let before = index_map.get_index_of(key.lookup_key());
+                    let (index, inserted) = index_map.insert(key);
+                    prop_assert_eq!(inserted, before.is_none());
+                    prop_assert_eq!(before.unwrap_or(index), index);
+                }
+                Op::Remove(key) => {
+                    let before = index_map.get_index_of(key.lookup_key());
+                    let removed = index_map.swap_remove(key.lookup_key());
+                    prop_assert_eq!(removed.map(|(index, _)| index), before);
+                }
+            }
+
+            // Both directions agree.
+            prop_assert_eq!(index_map.indices.len(), index_map.keys.len());
+            for (index, key) in index_map.keys().iter().enumerate() {
+                prop_assert_eq!(index_map.get_index_of(key.lookup_key()), Some(index));
+            }
+        }
+    }
+}
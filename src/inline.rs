@@ -0,0 +1,96 @@
+//! An owned key that stores short strings and byte strings inline.
+//!
+//! Requires the `compact` feature.
+
+use crate::consistency::assert_consistent;
+use crate::{BorrowedKey, Key, OwnedKey};
+use compact_str::CompactString;
+use proptest::prelude::*;
+use smallvec::SmallVec;
+use std::any::Any;
+use std::borrow::Borrow;
+use std::collections::HashSet;
+
+// Most keys in practice are short. CompactString keeps strings of up to 24 bytes inline, and a
+// SmallVec<[u8; 16]> does the same for up to 16 bytes, so building one of these keys usually
+// doesn't touch the heap at all.
+//
+// Both types forward Eq, Ord and Hash to the underlying str and [u8], so deriving is still enough
+// for consistency with BorrowedKey.
+/// An owned key that avoids heap allocations for short fields.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct CompactOwnedKey {
+    pub s: CompactString,
+    pub bytes: SmallVec<[u8; 16]>,
+}
+
+impl Key for CompactOwnedKey {
+    fn key<'k>(&'k self) -> BorrowedKey<'k> {
+        BorrowedKey {
+            s: self.s.as_str(),
+            bytes: self.bytes.as_slice(),
+        }
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+impl<'a> Borrow<dyn Key + 'a> for CompactOwnedKey {
+    fn borrow(&self) -> &(dyn Key + 'a) {
+        self
+    }
+}
+
+impl<'a> From<BorrowedKey<'a>> for CompactOwnedKey {
+    fn from(key: BorrowedKey<'a>) -> Self {
+        Self {
+            s: key.s.into(),
+            bytes: key.bytes.into(),
+        }
+    }
+}
+
+impl From<OwnedKey> for CompactOwnedKey {
+    fn from(key: OwnedKey) -> Self {
+        Self {
+            s: key.s.into(),
+            bytes: key.bytes.into(),
+        }
+    }
+}
+
+impl Arbitrary for CompactOwnedKey {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        any::<OwnedKey>().prop_map(CompactOwnedKey::from).boxed()
+    }
+}
+
+#[test]
+fn compact_lookups() {
+    let key = CompactOwnedKey::from(BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    });
+    assert!(!key.s.is_heap_allocated());
+    assert!(!key.bytes.spilled());
+
+    let mut hash_set = HashSet::new();
+    hash_set.insert(key);
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert!(hash_set.contains(&borrowed_key as &dyn Key));
+}
+
+proptest! {
+    #[test]
+    fn compact_consistent_borrow(owned1 in any::<CompactOwnedKey>(), owned2 in any::<CompactOwnedKey>()) {
+        assert_consistent::<_, dyn Key>(&owned1, &owned2);
+    }
+}
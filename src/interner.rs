@@ -0,0 +1,406 @@
+//! Interning keys as small integer ids.
+
+use crate::shared::SharedOwnedKey;
+use crate::sync::RwLock;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::PoisonError;
+
+// Programs that see the same keys over and over (compilers, query engines, anything that builds a
+// graph out of named things) often swap each key for a small integer id the first time they see it,
+// and use the id from then on. Ids are Copy, four bytes, and compare and hash in a single
+// instruction.
+//
+// The interner needs to go both ways: from a key to its id, which is a map lookup, and from an id
+// back to its key, which is an index into a Vec. Both sides need the key, so it's stored as a
+// SharedOwnedKey, and each side holds one reference to the same allocation.
+//
+// The key-to-id direction is where the Borrow machinery comes in: SharedOwnedKey borrows as
+// dyn Key, so a key that's already been interned can be found without allocating.
+/// A handle to a key in a [`KeyInterner`].
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct KeyId(u32);
+
+impl KeyId {
+    /// Returns the id as an integer. Ids are handed out in order, starting at 0.
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+/// Maps keys to small [`KeyId`]s and back.
+#[derive(Clone, Debug, Default)]
+pub struct KeyInterner<S = RandomState> {
+    ids: HashMap<SharedOwnedKey, KeyId, S>,
+    keys: Vec<SharedOwnedKey>,
+}
+
+impl KeyInterner {
+    /// Creates a new, empty interner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S: BuildHasher> KeyInterner<S> {
+    /// Creates a new, empty interner that uses `hasher` to hash keys.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            ids: HashMap::with_hasher(hasher),
+            keys: Vec::new(),
+        }
+    }
+
+    /// Returns the id for `key`, interning it if it hasn't been seen before.
+    ///
+    /// Both owned and borrowed keys can be passed in. If the key has already been interned, this
+    /// doesn't allocate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than `u32::MAX` keys are interned.
+    pub fn intern(&mut self, key: &dyn Key) -> KeyId {
+        if let Some(id) = self.get(key) {
+            return id;
+        }
+        let id = KeyId(u32::try_from(self.keys.len()).expect("too many keys interned"));
+        let key = SharedOwnedKey::from(key.key());
+        self.keys.push(key.clone());
+        self.ids.insert(key, id);
+        id
+    }
+
+    /// Returns the id for `key`, if it has been interned.
+    pub fn get(&self, key: &dyn Key) -> Option<KeyId> {
+        self.ids.get(key).copied()
+    }
+
+    /// Returns the key for `id`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` came from a different interner.
+    pub fn resolve(&self, id: KeyId) -> BorrowedKey<'_> {
+        self.keys[id.0 as usize].key()
+    }
+
+    /// Returns the number of interned keys.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true if no keys have been interned.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Iterates over the interned keys and their ids, in the order they were interned.
+    pub fn iter(&self) -> impl Iterator<Item = (KeyId, BorrowedKey<'_>)> {
+        self.keys
+            .iter()
+            .enumerate()
+            .map(|(index, key)| (KeyId(index as u32), key.key()))
+    }
+}
+
+// Sharing a KeyInterner between threads means putting it behind a lock, and then every thread waits
+// on every other. ConcurrentKeyInterner splits the key-to-id map into shards, each behind its own
+// RwLock, with a key's hash picking its shard. Lookups only ever take read locks, so threads that
+// are mostly looking up already-interned keys hardly contend at all.
+//
+// The tricky part is two threads interning the same new key at the same time. Both miss under the
+// read lock, and then both line up for the write lock. Whichever gets there second has to check
+// again, or the key would be interned twice with two different ids.
+//
+// Ids are handed out under the lock for the id-to-key Vec, which is always taken after a shard lock
+// (never before), so there's no way for two threads to deadlock.
+//
+// A Key impl that panics while it's being hashed or compared under a write lock poisons that lock.
+// Both maps only ever have keys added to them, and a key is pushed onto the Vec before it goes into
+// its shard, so a panic can't leave them disagreeing about an id that's been handed out. So, like
+// SyncKeyMap, the interner takes a poisoned lock anyway rather than panicking from then on.
+//
+// Like KeyInterner, it's generic over the hasher. The same hasher picks a key's shard and then
+// places the key within the shard's map, so the shard is picked with the high half of the hash:
+// hashbrown places keys using the low bits, and picking shards with those too would leave every
+// key in a shard agreeing on them, and crowded into a fraction of the map's buckets.
+/// A [`KeyInterner`] that can be shared between threads.
+pub struct ConcurrentKeyInterner<S = RandomState> {
+    build_hasher: S,
+    shards: Box<[RwLock<HashMap<SharedOwnedKey, KeyId, S>>]>,
+    keys: RwLock<Vec<SharedOwnedKey>>,
+}
+
+impl ConcurrentKeyInterner {
+    /// Creates a new, empty interner.
+    pub fn new() -> Self {
+        Self::with_shards(Self::DEFAULT_SHARDS)
+    }
+
+    /// Creates a new, empty interner with the given number of shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0.
+    pub fn with_shards(shards: usize) -> Self {
+        Self::with_shards_and_hasher(shards, RandomState::new())
+    }
+}
+
+impl<S> ConcurrentKeyInterner<S> {
+    const DEFAULT_SHARDS: usize = 16;
+}
+
+impl<S: BuildHasher + Clone> ConcurrentKeyInterner<S> {
+    /// Creates a new, empty interner that uses `hasher` to hash keys.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::with_shards_and_hasher(Self::DEFAULT_SHARDS, hasher)
+    }
+
+    /// Creates a new, empty interner with the given number of shards, that uses `hasher` to hash
+    /// keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0.
+    pub fn with_shards_and_hasher(shards: usize, hasher: S) -> Self {
+        assert!(shards > 0, "at least one shard is required");
+        Self {
+            shards: (0..shards)
+                .map(|_| RwLock::new(HashMap::with_hasher(hasher.clone())))
+                .collect(),
+            build_hasher: hasher,
+            keys: RwLock::default(),
+        }
+    }
+
+    /// Returns the id for `key`, interning it if it hasn't been seen before.
+    ///
+    /// If the key has already been interned, this doesn't allocate, and only takes a read lock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than `u32::MAX` keys are interned.
+    pub fn intern(&self, key: &dyn Key) -> KeyId {
+        let shard = self.shard(key);
+        if let Some(id) = shard
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(key)
+        {
+            return *id;
+        }
+
+        let mut shard = shard.write().unwrap_or_else(PoisonError::into_inner);
+        // Another thread may have interned this key between the read lock being released and the
+        // write lock being acquired.
+        if let Some(id) = shard.get(key) {
+            return *id;
+        }
+        let key = SharedOwnedKey::from(key.key());
+        let id = {
+            let mut keys = self.keys.write().unwrap_or_else(PoisonError::into_inner);
+            let id = KeyId(u32::try_from(keys.len()).expect("too many keys interned"));
+            keys.push(key.clone());
+            id
+        };
+        shard.insert(key, id);
+        id
+    }
+
+    /// Returns the id for `key`, if it has been interned.
+    pub fn get(&self, key: &dyn Key) -> Option<KeyId> {
+        self.shard(key)
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(key)
+            .copied()
+    }
+
+    /// Returns the key for `id`.
+    ///
+    /// The key can't be borrowed from the interner past the lock protecting it, so this returns a
+    /// clone, which only bumps two reference counts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` came from a different interner.
+    pub fn resolve(&self, id: KeyId) -> SharedOwnedKey {
+        self.keys.read().unwrap_or_else(PoisonError::into_inner)[id.0 as usize].clone()
+    }
+
+    /// Returns the number of interned keys.
+    pub fn len(&self) -> usize {
+        self.keys
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Returns true if no keys have been interned.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn shard(&self, key: &dyn Key) -> &RwLock<HashMap<SharedOwnedKey, KeyId, S>> {
+        let hash = self.build_hasher.hash_one(key);
+        &self.shards[(hash >> 32) as usize % self.shards.len()]
+    }
+}
+
+impl<S: BuildHasher + Clone + Default> Default for ConcurrentKeyInterner<S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<S> fmt::Debug for ConcurrentKeyInterner<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConcurrentKeyInterner")
+            .field("shards", &self.shards.len())
+            .field(
+                "len",
+                &self
+                    .keys
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .len(),
+            )
+            .finish()
+    }
+}
+
+#[test]
+fn interner_lookups() {
+    let mut interner = KeyInterner::new();
+    let foo = OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+    let foo_id = interner.intern(&foo);
+    let bar_id = interner.intern(&BorrowedKey {
+        s: "bar",
+        bytes: b"abc",
+    });
+    assert_ne!(foo_id, bar_id);
+    assert_eq!((foo_id.as_u32(), bar_id.as_u32()), (0, 1));
+
+    // Interning an equal key, owned or borrowed, returns the same id.
+    let borrowed_foo = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert_eq!(interner.intern(&borrowed_foo), foo_id);
+    assert_eq!(interner.get(&borrowed_foo), Some(foo_id));
+    assert_eq!(interner.len(), 2);
+
+    assert_eq!(interner.resolve(foo_id), borrowed_foo);
+    assert_eq!(
+        interner.get(&BorrowedKey {
+            s: "baz",
+            bytes: b""
+        }),
+        None
+    );
+}
+
+proptest! {
+    #[test]
+    fn interner_round_trip(keys in prop::collection::vec(any::<OwnedKey>(), 0..32)) {
+        let mut interner = KeyInterner::new();
+        let ids: Vec<_> = keys.iter().map(|key| interner.intern(key)).collect();
+
+        for (key, id) in keys.iter().zip(&ids) {
+            // Every key resolves back to itself, and equal keys share an id.
+            prop_assert_eq!(interner.resolve(*id), key.key());
+            prop_assert_eq!(interner.get(key), Some(*id));
+        }
+        for (key1, id1) in keys.iter().zip(&ids) {
+            for (key2, id2) in keys.iter().zip(&ids) {
+                prop_assert_eq!(key1 == key2, id1 == id2);
+            }
+        }
+        prop_assert_eq!(interner.iter().count(), interner.len());
+    }
+}
+
+// Several threads intern an overlapping set of keys at once. Whatever order they run in, every key
+// must end up with exactly one id, and the ids must be dense.
+#[test]
+fn concurrent_interner_race() {
+    use std::sync::Arc;
+
+    crate::sync::check(
+        || {
+            // A single shard makes every thread contend for the same lock.
+            let interner = Arc::new(ConcurrentKeyInterner::with_shards(1));
+            let threads: Vec<_> = (0..3)
+                .map(|i| {
+                    let interner = interner.clone();
+                    crate::sync::thread::spawn(move || {
+                        let names = ["foo", "bar"];
+                        [names[i % 2], names[(i + 1) % 2]].map(|s| {
+                            let key = BorrowedKey { s, bytes: b"abc" };
+                            (s, interner.intern(&key))
+                        })
+                    })
+                })
+                .collect();
+            let results: Vec<_> = threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect();
+
+            assert_eq!(interner.len(), 2);
+            for (s, id) in results {
+                assert!(id.as_u32() < 2);
+                let key = BorrowedKey { s, bytes: b"abc" };
+                assert_eq!(interner.resolve(id).key(), key);
+                assert_eq!(interner.get(&key), Some(id));
+            }
+        },
+        1000,
+    );
+}
+
+#[test]
+// shuttle fails a test on any panic, even one that's caught.
+#[cfg_attr(shuttle, ignore)]
+fn concurrent_interner_poisoned() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let interner = ConcurrentKeyInterner::with_shards(1);
+    let foo = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    let foo_id = interner.intern(&foo);
+
+    // Poison both the shard's lock and the id-to-key lock.
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let _shard = interner.shards[0].write().unwrap();
+        let _keys = interner.keys.write().unwrap();
+        panic!("panicked while holding the write locks");
+    }));
+    assert!(result.is_err());
+    assert!(interner.shards[0].read().is_err());
+    assert!(interner.keys.read().is_err());
+
+    // Every method still works, and sees the keys interned before the panic.
+    assert_eq!(interner.intern(&foo), foo_id);
+    assert_eq!(interner.get(&foo), Some(foo_id));
+    let bar = BorrowedKey {
+        s: "bar",
+        bytes: b"abc",
+    };
+    let bar_id = interner.intern(&bar);
+    assert_ne!(bar_id, foo_id);
+    assert_eq!(interner.resolve(bar_id).key(), bar);
+    assert_eq!(interner.len(), 2);
+    assert!(format!("{:?}", interner).contains("len: 2"));
+}
@@ -0,0 +1,163 @@
+//! Keys stored in their encoded form, with an unsized borrowed type.
+
+use crate::consistency::assert_consistent;
+use crate::encoding::{self, DecodeError};
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::borrow::Borrow;
+use std::collections::{BTreeSet, HashSet};
+use std::ops::Deref;
+
+// name.rs borrows a Name as an unsized NameRef, but that only works for a key with one field. A
+// key with two fields can be turned into one field, though: encode it. The encoding in
+// encoding.rs is a single byte string that sorts the same way the key does, so:
+//
+// - EncodedOwnedKey holds an encoded key in a Box<[u8]>.
+// - KeyBytes is a #[repr(transparent)] wrapper around [u8], so a &[u8] can be reinterpreted as a
+//   &KeyBytes.
+// - Both derive Eq, Ord and Hash from their bytes. Box<[u8]> and [u8] agree on all three, so the
+//   impls are consistent for the same reason String and str's are.
+//
+// There's no trait object, and so no vtable: comparing two keys is a memcmp. And a key that's
+// already encoded, such as one read out of a sorted key-value store, can be looked up without
+// copying it at all. The cost is that a BorrowedKey has to be encoded before it can be looked up,
+// which KeyBytes::encode does into a buffer that can be reused across lookups.
+//
+// The cast from &[u8] to &KeyBytes is the one piece of unsafe code. Unlike name.rs, this doesn't
+// use ref-cast, so that the cast is right here to read. tests/miri.rs runs it under Miri.
+/// A borrowed key, in its encoded form.
+#[derive(Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[repr(transparent)]
+pub struct KeyBytes([u8]);
+
+/// An owned key, stored in its encoded form.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct EncodedOwnedKey(Box<[u8]>);
+
+impl KeyBytes {
+    /// Checks that `encoded` is a key encoded by [`encoding::encode`], and returns it as a
+    /// `KeyBytes` without copying it.
+    pub fn from_encoded(encoded: &[u8]) -> Result<&Self, DecodeError> {
+        encoding::validate(encoded)?;
+        Ok(Self::from_encoded_unchecked(encoded))
+    }
+
+    /// Encodes `key` into `buf`, replacing its contents, and returns the result as a `KeyBytes`.
+    ///
+    /// Reusing `buf` for many lookups avoids allocating for each one.
+    pub fn encode<'buf>(key: &dyn Key, buf: &'buf mut Vec<u8>) -> &'buf Self {
+        buf.clear();
+        encoding::encode_into(key, buf);
+        Self::from_encoded_unchecked(buf)
+    }
+
+    /// Returns the encoded bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Decodes the key.
+    pub fn decode(&self) -> OwnedKey {
+        encoding::decode(&self.0).expect("KeyBytes is always a valid encoding")
+    }
+
+    /// Returns the key's bytes field, which comes last in the encoding.
+    pub fn key_bytes(&self) -> &[u8] {
+        let start = encoding::validate(&self.0).expect("KeyBytes is always a valid encoding");
+        &self.0[start..]
+    }
+
+    // Not unsafe to call, since an invalid encoding can't cause undefined behavior. But every
+    // KeyBytes is meant to be a valid encoding, so this is private.
+    fn from_encoded_unchecked(encoded: &[u8]) -> &Self {
+        // SAFETY: KeyBytes is repr(transparent) over [u8], so a *const [u8] and a *const KeyBytes
+        // have the same layout and the same metadata (the length). The returned reference borrows
+        // from encoded, so it can't outlive the bytes it points to.
+        unsafe { &*(encoded as *const [u8] as *const KeyBytes) }
+    }
+}
+
+impl EncodedOwnedKey {
+    /// Encodes `key`.
+    pub fn new(key: &dyn Key) -> Self {
+        Self(encoding::encode(key).into_boxed_slice())
+    }
+
+    /// Returns this key as a `KeyBytes`.
+    pub fn as_key_bytes(&self) -> &KeyBytes {
+        KeyBytes::from_encoded_unchecked(&self.0)
+    }
+}
+
+impl Deref for EncodedOwnedKey {
+    type Target = KeyBytes;
+
+    fn deref(&self) -> &KeyBytes {
+        self.as_key_bytes()
+    }
+}
+
+impl Borrow<KeyBytes> for EncodedOwnedKey {
+    fn borrow(&self) -> &KeyBytes {
+        self.as_key_bytes()
+    }
+}
+
+impl ToOwned for KeyBytes {
+    type Owned = EncodedOwnedKey;
+
+    fn to_owned(&self) -> EncodedOwnedKey {
+        EncodedOwnedKey(self.0.into())
+    }
+}
+
+impl AsRef<[u8]> for KeyBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[test]
+fn key_bytes_lookups() {
+    let owned = EncodedOwnedKey::new(&BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    });
+    let mut hash_set = HashSet::new();
+    hash_set.insert(owned.clone());
+    let btree_set: BTreeSet<_> = hash_set.iter().cloned().collect();
+
+    let mut buf = Vec::new();
+    let probe = KeyBytes::encode(
+        &BorrowedKey {
+            s: "foo",
+            bytes: b"abc",
+        },
+        &mut buf,
+    );
+    assert!(hash_set.contains(probe));
+    assert!(btree_set.contains(probe));
+    assert_eq!(probe.key_bytes(), b"abc");
+    assert_eq!(probe.to_owned(), owned);
+
+    // An already-encoded key is looked up without copying it.
+    let stored: &[u8] = b"foo\x00\x01abc";
+    assert!(hash_set.contains(KeyBytes::from_encoded(stored).unwrap()));
+    assert_eq!(
+        KeyBytes::from_encoded(b"foo"),
+        Err(DecodeError::Unterminated)
+    );
+}
+
+proptest! {
+    #[test]
+    fn key_bytes_consistent_borrow(owned1 in any::<OwnedKey>(), owned2 in any::<OwnedKey>()) {
+        let (encoded1, encoded2) = (EncodedOwnedKey::new(&owned1), EncodedOwnedKey::new(&owned2));
+        assert_consistent::<_, KeyBytes>(&encoded1, &encoded2);
+
+        // And the encoded keys sort the same way the keys themselves do.
+        prop_assert_eq!(encoded1.cmp(&encoded2), owned1.cmp(&owned2));
+        prop_assert_eq!(encoded1.decode(), owned1.clone());
+        prop_assert_eq!(encoded1.key_bytes(), &owned1.bytes[..]);
+    }
+}
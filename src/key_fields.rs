@@ -0,0 +1,126 @@
+//! Checking at compile time that a hand-written owned key and borrowed key have the same fields,
+//! in the same order.
+
+use crate::{BorrowedKey, OwnedKey};
+
+// Experiment (2) in the crate root invites you to swap the order of the fields in OwnedKey or
+// BorrowedKey, and watch the property test fail. The derived Ord compares fields in declaration
+// order, so the two types end up ordering keys differently, and a BTreeMap probed with one finds
+// the wrong entries for the other. The property test catches it, but only when it runs, and only
+// if the two sides are both covered by it.
+//
+// The mistake is visible in the source, though, so it can be caught before anything runs.
+// #[derive(KeyFields)] records a struct's field names, in declaration order, as a constant, and
+// assert_key_fields_match! compares the constants of two structs in a const assertion. A missing,
+// extra, renamed or reordered field fails the build, pointing at the assertion.
+//
+// Only names are compared. The field types differ between the two sides by design (String against
+// &str), and the property test is still what checks that they compare the same way.
+/// A struct's field names, in declaration order.
+///
+/// Derive it with [`#[derive(KeyFields)]`](derive@KeyFields), and compare two structs with
+/// [`assert_key_fields_match!`](crate::assert_key_fields_match).
+pub trait KeyFields {
+    /// The names of the struct's fields, in declaration order.
+    const FIELDS: &'static [&'static str];
+}
+
+pub use borrow_complex_key_example_derive::KeyFields;
+
+/// Returns true if `a` and `b` have the same names in the same order. Used by
+/// [`assert_key_fields_match!`](crate::assert_key_fields_match).
+#[doc(hidden)]
+pub const fn fields_match(a: &[&str], b: &[&str]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        let (x, y) = (a[i].as_bytes(), b[i].as_bytes());
+        if x.len() != y.len() {
+            return false;
+        }
+        let mut j = 0;
+        while j < x.len() {
+            if x[j] != y[j] {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Fails to compile unless the two types, both implementing
+/// [`KeyFields`](crate::key_fields::KeyFields), have the same field names in the same order.
+///
+/// ```
+/// use borrow_complex_key_example::assert_key_fields_match;
+/// use borrow_complex_key_example::key_fields::KeyFields;
+///
+/// #[derive(KeyFields)]
+/// struct OwnedKey {
+///     s: String,
+///     bytes: Vec<u8>,
+/// }
+///
+/// #[derive(KeyFields)]
+/// struct BorrowedKey<'a> {
+///     s: &'a str,
+///     bytes: &'a [u8],
+/// }
+///
+/// assert_key_fields_match!(OwnedKey, BorrowedKey);
+/// ```
+///
+/// ```compile_fail
+/// use borrow_complex_key_example::assert_key_fields_match;
+/// use borrow_complex_key_example::key_fields::KeyFields;
+///
+/// #[derive(KeyFields)]
+/// struct OwnedKey {
+///     bytes: Vec<u8>,
+///     s: String,
+/// }
+///
+/// #[derive(KeyFields)]
+/// struct BorrowedKey<'a> {
+///     s: &'a str,
+///     bytes: &'a [u8],
+/// }
+///
+/// assert_key_fields_match!(OwnedKey, BorrowedKey);
+/// ```
+#[macro_export]
+macro_rules! assert_key_fields_match {
+    ($owned:ty, $borrowed:ty $(,)?) => {
+        const _: () = assert!(
+            $crate::key_fields::fields_match(
+                <$owned as $crate::key_fields::KeyFields>::FIELDS,
+                <$borrowed as $crate::key_fields::KeyFields>::FIELDS,
+            ),
+            concat!(
+                stringify!($owned),
+                " and ",
+                stringify!($borrowed),
+                " have different fields, or the same fields in a different order",
+            ),
+        );
+    };
+}
+
+// The crate's own pair.
+assert_key_fields_match!(OwnedKey, BorrowedKey);
+
+#[test]
+fn key_fields_match() {
+    assert_eq!(OwnedKey::<Vec<u8>>::FIELDS, ["s", "bytes"]);
+    assert_eq!(BorrowedKey::FIELDS, ["s", "bytes"]);
+
+    assert!(fields_match(&[], &[]));
+    assert!(fields_match(&["s", "bytes"], &["s", "bytes"]));
+    assert!(!fields_match(&["s", "bytes"], &["bytes", "s"]));
+    assert!(!fields_match(&["s", "bytes"], &["s"]));
+    assert!(!fields_match(&["s", "bytes"], &["s", "byte"]));
+}
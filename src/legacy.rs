@@ -0,0 +1,158 @@
+//! The [`family`](crate::family) module, written without generic associated types.
+//!
+//! `KeyFamily` in the family module names its borrowed form with a generic associated type,
+//! `type Borrowed<'a>`. Those need Rust 1.65 or later. Before that, the usual workaround was to
+//! move the lifetime from the associated type to the trait: [`KeyLt<'a>`] is implemented once for
+//! every lifetime, and `<F as KeyLt<'a>>::Borrowed` stands in for `F::Borrowed<'a>`.
+//!
+//! Nothing in this module uses GATs, so it shows how code written against it can be carried to an
+//! older toolchain, such as a crate of your own that can't require 1.65 yet. This crate itself
+//! needs Rust 1.88, its `rust-version`, since other modules use GATs and newer library APIs. The
+//! family module is the one to reach for on a current compiler: the signatures here are noisier,
+//! and every bound on a family has to be spelled `for<'a> KeyLt<'a>`.
+
+use crate::consistency::assert_consistent;
+use crate::{BorrowedKey, OwnedKey};
+use proptest::prelude::*;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+
+/// Names the borrowed form of a family of key types, at one particular lifetime.
+///
+/// Implement this for every lifetime, with `impl<'a> KeyLt<'a> for ...`.
+pub trait KeyLt<'a> {
+    /// The borrowed form of keys in this family, borrowed for `'a`.
+    type Borrowed: Copy + Debug + Eq + Ord + Hash;
+}
+
+/// Names the borrowed form shared by a family of key types, at every lifetime.
+///
+/// This is the counterpart to [`family::KeyFamily`](crate::family::KeyFamily).
+pub trait KeyFamily: for<'a> KeyLt<'a> {
+    /// Shortens the lifetime of a borrowed key.
+    ///
+    /// As with `family::KeyFamily::shorten`, the implementation should always be just `borrowed`.
+    fn shorten<'long: 'short, 'short>(
+        borrowed: <Self as KeyLt<'long>>::Borrowed,
+    ) -> <Self as KeyLt<'short>>::Borrowed;
+}
+
+/// A key that can be viewed in the borrowed form of the family `F`.
+///
+/// This is the counterpart to [`family::Lookup`](crate::family::Lookup).
+pub trait Lookup<F: KeyFamily> {
+    /// Returns the borrowed form of this key.
+    fn lookup_key<'k>(&'k self) -> <F as KeyLt<'k>>::Borrowed;
+}
+
+impl<'a, F: KeyFamily> PartialEq for dyn Lookup<F> + 'a {
+    fn eq(&self, other: &Self) -> bool {
+        self.lookup_key().eq(&other.lookup_key())
+    }
+}
+
+impl<'a, F: KeyFamily> Eq for dyn Lookup<F> + 'a {}
+
+impl<'a, F: KeyFamily> PartialOrd for dyn Lookup<F> + 'a {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a, F: KeyFamily> Ord for dyn Lookup<F> + 'a {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.lookup_key().cmp(&other.lookup_key())
+    }
+}
+
+impl<'a, F: KeyFamily> Hash for dyn Lookup<F> + 'a {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.lookup_key().hash(state)
+    }
+}
+
+/// A borrowed key in the family `F`, ready to be used for lookups.
+pub struct Probe<'a, F: KeyFamily>(pub <F as KeyLt<'a>>::Borrowed);
+
+impl<'a, F: KeyFamily> Clone for Probe<'a, F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, F: KeyFamily> Copy for Probe<'a, F> {}
+
+impl<'a, F: KeyFamily> Debug for Probe<'a, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Probe").field(&self.0).finish()
+    }
+}
+
+impl<'a, F: KeyFamily> Probe<'a, F> {
+    /// Returns this probe as a trait object, for passing into `contains`, `get` and friends.
+    pub fn as_lookup(&self) -> &(dyn Lookup<F> + '_) {
+        self
+    }
+}
+
+impl<'a, F: KeyFamily> Lookup<F> for Probe<'a, F> {
+    fn lookup_key<'k>(&'k self) -> <F as KeyLt<'k>>::Borrowed {
+        F::shorten(self.0)
+    }
+}
+
+// The crate root's OwnedKey, as a legacy family.
+impl<'a> KeyLt<'a> for OwnedKey {
+    type Borrowed = BorrowedKey<'a>;
+}
+
+impl KeyFamily for OwnedKey {
+    fn shorten<'long: 'short, 'short>(borrowed: BorrowedKey<'long>) -> BorrowedKey<'short> {
+        borrowed
+    }
+}
+
+impl Lookup<OwnedKey> for OwnedKey {
+    fn lookup_key<'k>(&'k self) -> BorrowedKey<'k> {
+        BorrowedKey {
+            s: &self.s,
+            bytes: &self.bytes,
+        }
+    }
+}
+
+impl<'a> Borrow<dyn Lookup<OwnedKey> + 'a> for OwnedKey {
+    fn borrow(&self) -> &(dyn Lookup<OwnedKey> + 'a) {
+        self
+    }
+}
+
+#[test]
+fn legacy_lookups() {
+    let mut hash_set: HashSet<OwnedKey> = HashSet::new();
+    hash_set.insert(OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    });
+
+    let probe = Probe::<OwnedKey>(BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    });
+    assert!(hash_set.contains(probe.as_lookup()));
+    let probe = Probe::<OwnedKey>(BorrowedKey {
+        s: "foo",
+        bytes: b"",
+    });
+    assert!(!hash_set.contains(probe.as_lookup()));
+}
+
+proptest! {
+    #[test]
+    fn legacy_consistent_borrow(owned1 in any::<OwnedKey>(), owned2 in any::<OwnedKey>()) {
+        assert_consistent::<_, dyn Lookup<OwnedKey>>(&owned1, &owned2);
+    }
+}
@@ -15,14 +15,28 @@
 //! inspiration.
 
 #![allow(unused_imports)]
-
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(all(test, not(feature = "std")))]
+compile_error!("the tests need the std feature");
+
+extern crate alloc;
+// The derives name the crate by path, so that path has to work inside the crate too.
+extern crate self as borrow_complex_key_example;
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use key_fields::KeyFields;
+#[cfg(feature = "std")]
 use proptest::prelude::*;
-use proptest_derive::Arbitrary;
-use std::borrow::Borrow;
-use std::cmp::Ordering;
-use std::collections::HashSet;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+#[cfg(feature = "std")]
+use std::collections::{hash_map::DefaultHasher, HashSet};
 
 #[test]
 fn basic() {
@@ -62,21 +76,80 @@ fn basic() {
 
 // But what about a user-defined type that's more complex than just a String? For example,
 // consider this owned type:
-#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Arbitrary)]
-struct OwnedKey {
-    s: String,
-    bytes: Vec<u8>,
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, KeyFields)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+pub struct OwnedKey<B = Vec<u8>> {
+    pub s: String,
+    pub bytes: B,
 }
 
-// (You might have noticed the "Arbitrary" above. Put a pin in that.)
+// (OwnedKey also implements proptest's Arbitrary, in strategy.rs. Put a pin in that.)
+//
+// (The bytes can live in any KeyStorage, not just a Vec<u8>. Vec<u8> is the default, and for now
+// you can read OwnedKey as if it were the only option. KeyStorage is below, and storage.rs has more
+// on the others.)
+//
+// (The rkyv derives are only there with the rkyv feature on. See archived.rs.)
+//
+// (KeyFields lets key_fields.rs check, while compiling, that the two types below have the same
+// fields in the same order. Ignore it for now too.)
+//
+// (The Yokeable derive on BorrowedKey is only there with the yoke feature on. See yoked.rs.)
 
 // ... and this borrowed type:
-#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-struct BorrowedKey<'a> {
-    s: &'a str,
-    bytes: &'a [u8],
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, KeyFields)]
+#[cfg_attr(feature = "yoke", derive(yoke::Yokeable))]
+pub struct BorrowedKey<'a> {
+    pub s: &'a str,
+    pub bytes: &'a [u8],
 }
 
+impl<'a> BorrowedKey<'a> {
+    /// Creates a borrowed key. This is a `const fn`, so keys can be written in statics and
+    /// constants.
+    pub const fn new(s: &'a str, bytes: &'a [u8]) -> Self {
+        Self { s, bytes }
+    }
+}
+
+// (Static tables of borrowed keys, sorted at compile time, are in static_table.rs.)
+
+// (OwnedKey<B> derives Eq, Ord and Hash, so it's only consistent with BorrowedKey if B compares and
+// hashes exactly like the [u8] it holds. Being able to produce a &[u8] isn't enough: String does,
+// but hashes like a str, with a 0xff on the end instead of a length in front. So the types that
+// can hold a key's bytes are listed, rather than left open.)
+/// A type that can hold an [`OwnedKey`]'s bytes.
+///
+/// Implemented for `Vec<u8>`, `Box<[u8]>`, `Arc<[u8]>`, and, with the `bytes` feature,
+/// `bytes::Bytes`: the types whose Eq, Ord and Hash are those of the `[u8]` they hold. The trait
+/// is sealed, since another type would have to promise the same, and nothing checks that it does.
+///
+/// ```compile_fail
+/// use borrow_complex_key_example::{Key, OwnedKey};
+///
+/// // A String hashes like a str, not like the bytes it holds.
+/// let key: &dyn Key = &OwnedKey { s: "foo".to_string(), bytes: "abc".to_string() };
+/// ```
+pub trait KeyStorage: AsRef<[u8]> + sealed::Sealed + 'static {}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+impl sealed::Sealed for Vec<u8> {}
+impl KeyStorage for Vec<u8> {}
+impl sealed::Sealed for alloc::boxed::Box<[u8]> {}
+impl KeyStorage for alloc::boxed::Box<[u8]> {}
+impl sealed::Sealed for alloc::sync::Arc<[u8]> {}
+impl KeyStorage for alloc::sync::Arc<[u8]> {}
+#[cfg(feature = "bytes")]
+impl sealed::Sealed for bytes::Bytes {}
+#[cfg(feature = "bytes")]
+impl KeyStorage for bytes::Bytes {}
+
 #[test]
 fn complex1() {
     // They're basically the same type, modulo ownership. Can we take a hash set of owned keys...
@@ -106,23 +179,36 @@ impl<'a> Borrow<BorrowedKey<'a>> for OwnedKey {
     }
 }
 
+// (tests/ui/direct_borrow.rs fills in the obvious answer, and shows what the compiler thinks of
+// it. tests/ui has a couple of other dead ends, too.)
+
 // It turns out that we can approach this in a different manner, using the power of trait objects!
 //
 // Here's how:
 // (1) define a trait object that looks like this.
-trait Key {
+pub trait Key {
     // (The lifetimes can be elided here, but are shown for clarity.)
     fn key<'k>(&'k self) -> BorrowedKey<'k>;
+
+    // (This one isn't part of the pattern, and has a default so that implementations can ignore
+    // it. See downcast.rs.)
+    fn as_any(&self) -> Option<&dyn Any> {
+        None
+    }
 }
 
 // (2) Implement it for both the owned and borrowed versions.
-impl Key for OwnedKey {
+impl<B: KeyStorage> Key for OwnedKey<B> {
     fn key<'k>(&'k self) -> BorrowedKey<'k> {
         BorrowedKey {
             s: self.s.as_str(),
-            bytes: self.bytes.as_slice(),
+            bytes: self.bytes.as_ref(),
         }
     }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
 }
 
 impl<'a> Key for BorrowedKey<'a> {
@@ -135,29 +221,80 @@ impl<'a> Key for BorrowedKey<'a> {
     }
 }
 
+// (References to keys are keys too. Probes often end up behind an extra reference, say from
+// iterating over a Vec<&OwnedKey>, and this lets &&OwnedKey coerce to &dyn Key without a manual
+// deref at every call site. It covers &dyn Key itself as well, since dyn Key implements Key.)
+impl<T: Key + ?Sized> Key for &T {
+    fn key<'k>(&'k self) -> BorrowedKey<'k> {
+        (**self).key()
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        (**self).as_any()
+    }
+}
+
+#[test]
+fn key_for_references() {
+    let mut hash_set: HashSet<OwnedKey> = HashSet::new();
+    let owned_key = OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+    hash_set.insert(owned_key.clone());
+
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    let probes = [&borrowed_key];
+    for probe in &probes {
+        // probe is a &&BorrowedKey.
+        assert!(hash_set.contains(probe as &dyn Key));
+    }
+    let owned_refs = [&owned_key];
+    assert!(owned_refs
+        .iter()
+        .all(|key| hash_set.contains(key as &dyn Key)));
+
+    let dyn_key: &dyn Key = &owned_key;
+    assert!(hash_set.contains(&dyn_key as &dyn Key));
+    assert!(hash_set.contains(&&&dyn_key as &dyn Key));
+}
+
 // For the rest of this example, we're going to make trait objects of type &(dyn Key + 'a)
 // central to our strategy.
 //
 // OK, so...
 //
 // (3) Implement Borrow<dyn Key> for OwnedKey.
-impl<'a> Borrow<dyn Key + 'a> for OwnedKey {
+impl<'a, B: KeyStorage> Borrow<dyn Key + 'a> for OwnedKey<B> {
     fn borrow(&self) -> &(dyn Key + 'a) {
         // This is a simple coercion from the concrete type to a trait object.
         self
     }
 }
 
-// Note that while we *could* impl<'a> Borrow<dyn Key + 'a> for BorrowedKey<'a>, we don't have to.
 // https://doc.rust-lang.org/std/collections/struct.HashSet.html#method.contains requires
 // T: Borrow<Q>. This means that Borrow only needs to be implemented for the type stored in the
-// HashSet (or, correspondingly, the key type in a HashMap or BTreeMap).
+// HashSet (or, correspondingly, the key type in a HashMap or BTreeMap). Looking up a borrowed key
+// doesn't need the borrowed type to implement Borrow at all.
+//
+// A set that *stores* BorrowedKeys does need it, though. Probes usually borrow from short-lived
+// request data, and so have a shorter lifetime than the keys in the set, so this impl allows any
+// shorter trait object lifetime 'a. (It lives here rather than next to such a set so that whether
+// it exists doesn't depend on which features are enabled.)
+impl<'a, 'b: 'a> Borrow<dyn Key + 'a> for BorrowedKey<'b> {
+    fn borrow(&self) -> &(dyn Key + 'a) {
+        self
+    }
+}
 
 // Now, remember that for Borrow to be valid, Eq, Hash and Ord need to be consistent. How do
 // we ensure that? Let's see:
 
 // (4) PartialEq and Eq turn out to be easy to do.
-impl<'a> PartialEq for (dyn Key + 'a) {
+impl<'a> PartialEq for dyn Key + 'a {
     fn eq(&self, other: &Self) -> bool {
         // It's easy to see from the definition that the owned and borrowed types have a consistent
         // implementation. (Don't worry, we're actually going to verify this.)
@@ -165,7 +302,7 @@ impl<'a> PartialEq for (dyn Key + 'a) {
     }
 }
 
-impl<'a> Eq for (dyn Key + 'a) {}
+impl<'a> Eq for dyn Key + 'a {}
 
 // (5) PartialOrd and Ord are similar.
 //
@@ -174,13 +311,13 @@ impl<'a> Eq for (dyn Key + 'a) {}
 //   lexicographic ordering on struct member order.
 // - You need to implement this if you're using a btree based data structure, not if you're only
 //   using hash-based data structures.
-impl<'a> PartialOrd for (dyn Key + 'a) {
+impl<'a> PartialOrd for dyn Key + 'a {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.key().partial_cmp(&other.key())
+        Some(self.cmp(other))
     }
 }
 
-impl<'a> Ord for (dyn Key + 'a) {
+impl<'a> Ord for dyn Key + 'a {
     fn cmp(&self, other: &Self) -> Ordering {
         self.key().cmp(&other.key())
     }
@@ -190,7 +327,7 @@ impl<'a> Ord for (dyn Key + 'a) {
 // consistent impl may be trickier and may require implementing Hash by hand for the owned type.
 //
 // Implementing Hash is only necessary if you're using a hash-based data structure.
-impl<'a> Hash for (dyn Key + 'a) {
+impl<'a> Hash for dyn Key + 'a {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.key().hash(state)
     }
@@ -210,14 +347,12 @@ fn complex2() {
         s: "foo",
         bytes: b"abc",
     };
-    
+
     // And here it is!
     //
-    // &borrowed_key should automatically be coerced into a &dyn Key, but in case it doesn't work,
-    // you can write:
-    //
-    //   assert!(hash_set.contains(&borrowed_key as &dyn Key));
-    assert!(hash_set.contains(&borrowed_key));
+    // HashSet::contains is generic over the borrowed type, so the compiler won't coerce
+    // &borrowed_key into a &dyn Key on its own. We have to ask for the trait object explicitly.
+    assert!(hash_set.contains(&borrowed_key as &dyn Key));
 }
 
 // ... not so fast, though! We've attempted to satisfy the constraints required for the Borrow impl.
@@ -230,9 +365,12 @@ fn complex2() {
 // https://blog.jessitron.com/2013/04/25/property-based-testing-what-is-it/ is a good intro.
 //
 // We're going to use the proptest framework to write our property-based tests.
+#[cfg(test)]
 proptest! {
-    // Here's where that Arbitrary above is useful. It's a simple way to generate random values of
-    // your structure.
+    // Here's where that Arbitrary impl is useful. It's a simple way to generate random values of
+    // your structure. For most structures, #[derive(Arbitrary)] is all you need; OwnedKey's is
+    // written by hand so that it's biased towards the edge cases most likely to break a Borrow
+    // impl. strategy.rs explains which ones.
     #[test]
     fn consistent_borrow(owned1 in any::<OwnedKey>(), owned2 in any::<OwnedKey>()) {
         // owned1 and owned2 will be populated with random values of OwnedKey. That's enough for us
@@ -257,8 +395,14 @@ proptest! {
             hasher.finish()
         }
 
-        assert_eq!(hash_output(&owned1), hash_output(&borrowed1), "consistent Hash");
-        assert_eq!(hash_output(&owned2), hash_output(&borrowed2), "consistent Hash");
+        assert_eq!(hash_output(&owned1), hash_output(borrowed1), "consistent Hash");
+        assert_eq!(hash_output(&owned2), hash_output(borrowed2), "consistent Hash");
+
+        // DefaultHasher is only one hasher, though, and the property is "for all hashers". Two
+        // impls could agree under it by coincidence. consistency.rs has a version of this test that
+        // checks a few very different hashers, including one that records exactly what was
+        // written to it.
+        consistency::assert_consistent_hash::<_, dyn Key>(&owned1, &owned2);
 
         // and that's it! Any implementation that satisfies these properties is a valid
         // Borrow implementation. A property-based test guarantees that with high confidence.
@@ -266,6 +410,273 @@ proptest! {
         // Here's some stuff to play around with:
         // (1) does this work for enums as well?
         // (2) try swapping the order of fields in either OwnedKey or BorrowedKey, and see what
-        //     happens to this property test.
+        //     happens to this property test. (You'll have to take out the assertion in
+        //     key_fields.rs first, which catches exactly this mistake while compiling.)
+    }
+}
+
+// There's one more thing to check. The test above makes sure the owned and borrowed comparisons
+// agree with each other, but two comparisons can agree and both be wrong. A BTreeMap assumes its
+// keys are totally ordered: every key is equal to itself, a < b exactly when b > a, a < b and
+// b < c means a < c, and Ord agrees with PartialEq. If any of those don't hold, lookups quietly
+// go down the wrong branch of the tree.
+//
+// Random keys are almost never equal or near each other, so this test picks from a tiny alphabet
+// to get plenty of ties and chains.
+#[cfg(test)]
+fn small_keys() -> impl Strategy<Value = OwnedKey> {
+    ("[ab]{0,2}", prop::collection::vec(0..2u8, 0..2)).prop_map(|(s, bytes)| OwnedKey { s, bytes })
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn total_order(owned1 in small_keys(), owned2 in small_keys(), owned3 in small_keys()) {
+        let (key1, key2, key3): (&dyn Key, &dyn Key, &dyn Key) = (&owned1, &owned2, &owned3);
+        consistency::assert_ord_laws(key1, key2, key3);
+    }
+}
+
+// Tests all over the crate build keys out of literals.
+#[cfg(test)]
+pub(crate) fn owned(s: &str, bytes: &[u8]) -> OwnedKey {
+    OwnedKey {
+        s: s.to_string(),
+        bytes: bytes.to_vec(),
+    }
+}
+
+// ... and ones that need lots of distinct keys number them.
+#[cfg(test)]
+pub(crate) fn numbered_key(i: u32) -> OwnedKey {
+    OwnedKey {
+        s: alloc::format!("key-{}", i),
+        bytes: i.to_le_bytes().to_vec(),
+    }
+}
+
+// Nothing in the pattern itself needs the standard library: Borrow, Eq, Ord and Hash are all in
+// core, and String and Vec are in alloc. So with the std feature turned off, the crate root still
+// works as is, and a BTreeMap from alloc takes &dyn Key lookups the same way.
+#[test]
+fn btree_map() {
+    let mut map = alloc::collections::BTreeMap::new();
+    map.insert(
+        OwnedKey {
+            s: "foo".to_string(),
+            bytes: b"abc".to_vec(),
+        },
+        1,
+    );
+
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert_eq!(map.get(&borrowed_key as &dyn Key), Some(&1));
+}
+
+// Borrow has a counterpart that goes the other way: ToOwned, which turns a borrowed type back into
+// its owned one, the way str::to_owned makes a String. It's also how Cow knows what the owned half
+// of a Cow<'a, B> is. ToOwned requires that the owned type borrows as the borrowed one, which step
+// (3) already provides, so dyn Key can have it too.
+//
+// That gives code holding a &dyn Key a standard way to make an OwnedKey only once it needs one. (An
+// entry API is the classic case: see KeyMap::entry_by_key in map.rs.)
+impl<'a> ToOwned for dyn Key + 'a {
+    type Owned = OwnedKey;
+
+    fn to_owned(&self) -> OwnedKey {
+        let key = self.key();
+        OwnedKey {
+            s: key.s.to_owned(),
+            bytes: key.bytes.to_owned(),
+        }
     }
 }
+
+// dyn Key isn't a type you can derive Debug for, but a key's borrowed view already has it, so the
+// trait object can print that. Owned and borrowed keys then print the same way behind a &dyn Key,
+// which is what panic messages and assertions about lookups want.
+impl<'a> fmt::Debug for dyn Key + 'a {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.key().fmt(f)
+    }
+}
+
+#[test]
+fn debug() {
+    let owned_key = OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+    let key: &dyn Key = &owned_key;
+    assert_eq!(
+        format!("{:?}", key),
+        r#"BorrowedKey { s: "foo", bytes: [97, 98, 99] }"#
+    );
+}
+
+#[test]
+fn cow() {
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    let key: alloc::borrow::Cow<'_, dyn Key> = alloc::borrow::Cow::Borrowed(&borrowed_key);
+    assert_eq!(
+        key.into_owned(),
+        OwnedKey {
+            s: "foo".to_string(),
+            bytes: b"abc".to_vec(),
+        }
+    );
+}
+
+// HashMap and HashSet are another matter, since they're only in std. With the hashbrown feature on
+// instead, KeySet and KeyMap (see set.rs and map.rs) are backed by hashbrown's HashSet and HashMap.
+// Everything else is left out without std.
+
+// The rest of this crate builds on the pattern above. Each module is a variation on the theme, or a
+// tool for using it.
+#[cfg(feature = "quickcheck")]
+mod arbitrary;
+#[cfg(feature = "rkyv")]
+pub mod archived;
+#[cfg(feature = "arena")]
+pub mod arena;
+#[cfg(feature = "arc-swap")]
+pub mod atomic;
+#[cfg(any(feature = "bincode", feature = "postcard"))]
+pub mod binary;
+#[cfg(feature = "std")]
+pub mod bloom;
+#[cfg(feature = "std")]
+pub mod boxed;
+#[cfg(test)]
+mod broken;
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "bytes")]
+pub mod bytes_key;
+#[cfg(feature = "std")]
+pub mod casefold;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+mod collections;
+#[cfg(feature = "std")]
+pub mod columns;
+#[cfg(feature = "std")]
+pub mod component;
+#[cfg(feature = "std")]
+pub mod consistency;
+#[cfg(feature = "csv")]
+pub mod csv_io;
+#[cfg(feature = "std")]
+pub mod derive;
+#[cfg(feature = "digest")]
+pub mod digest;
+#[cfg(feature = "std")]
+pub mod downcast;
+#[cfg(feature = "std")]
+pub mod encoding;
+#[cfg(feature = "std")]
+pub mod expiring;
+#[cfg(feature = "std")]
+pub mod family;
+pub mod group;
+#[cfg(feature = "std")]
+pub mod hashers;
+pub mod hybrid;
+#[cfg(feature = "std")]
+pub mod index_map;
+#[cfg(feature = "compact")]
+pub mod inline;
+#[cfg(feature = "std")]
+pub mod interner;
+#[cfg(feature = "std")]
+pub mod key_bytes;
+pub mod key_fields;
+#[cfg(feature = "std")]
+pub mod legacy;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub mod map;
+#[cfg(feature = "serde")]
+pub mod map_key;
+#[cfg(feature = "std")]
+pub mod message;
+#[cfg(feature = "std")]
+pub mod migration;
+#[cfg(feature = "std")]
+pub mod multi_index;
+#[cfg(feature = "ref-cast")]
+pub mod name;
+#[cfg(feature = "nfc")]
+pub mod nfc;
+#[cfg(feature = "std")]
+pub mod packed;
+#[cfg(feature = "std")]
+pub mod pair;
+#[cfg(feature = "std")]
+pub mod path;
+pub mod perfect;
+#[cfg(feature = "redb")]
+pub mod persistent;
+pub mod portable;
+#[cfg(feature = "std")]
+pub mod prehashed;
+#[cfg(feature = "std")]
+pub mod priority_queue;
+#[cfg(kani)]
+mod proofs;
+pub mod range;
+#[cfg(feature = "std")]
+pub mod scoped;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub mod secondary;
+#[cfg(feature = "secret")]
+pub mod secret;
+#[cfg(feature = "zeroize")]
+pub mod sensitive;
+#[cfg(feature = "serde")]
+pub mod serialize;
+#[cfg(any(feature = "std", feature = "hashbrown"))]
+pub mod set;
+#[cfg(feature = "std")]
+pub mod shared;
+pub mod sort;
+#[cfg(feature = "std")]
+pub mod sorted;
+pub mod split;
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+pub mod sqlite;
+pub mod static_table;
+#[cfg(feature = "std")]
+pub mod storage;
+#[cfg(feature = "std")]
+pub mod strategy;
+#[cfg(feature = "std")]
+mod sync;
+#[cfg(feature = "std")]
+pub mod sync_map;
+#[cfg(feature = "std")]
+pub mod tagged;
+#[cfg(feature = "std")]
+pub mod text;
+#[cfg(feature = "std")]
+pub mod tree_map;
+#[cfg(feature = "std")]
+pub mod trie;
+#[cfg(feature = "std")]
+pub mod tuple;
+#[cfg(feature = "std")]
+pub mod typed_map;
+#[cfg(feature = "left-right")]
+pub mod wait_free;
+pub mod with_key;
+#[cfg(feature = "yoke")]
+pub mod yoked;
+
+#[cfg(feature = "std")]
+pub use derive::BorrowKey;
+#[cfg(feature = "std")]
+pub use multi_index::MultiIndex;
@@ -0,0 +1,715 @@
+//! A map keyed by owned keys that takes borrowed keys directly.
+
+use crate::collections::{hash_map, HashMap, RandomState};
+use crate::{BorrowedKey, Key, OwnedKey};
+use alloc::borrow::ToOwned;
+use core::fmt;
+use core::hash::BuildHasher;
+use core::iter::FromIterator;
+use core::ops::Index;
+#[cfg(test)]
+use proptest::prelude::*;
+
+// KeyMap is to HashMap<OwnedKey, V> what KeySet is to HashSet<OwnedKey>: lookups take &dyn Key, so
+// owned and borrowed keys can both be passed in without spelling out the coercion.
+//
+// The one place HashMap's API doesn't fit is the entry API. HashMap::entry takes the key by value,
+// so calling it with a key that's already present allocates an OwnedKey just to throw it away.
+// get_or_insert_with looks the key up first, and only makes an OwnedKey if it's missing.
+//
+// entry_by_key is the same idea with the whole entry API: it takes a &dyn Key, and only turns it
+// into an OwnedKey, through ToOwned for dyn Key, when a vacant entry is filled in. Without the raw
+// entry API, which std doesn't have on stable, an occupied entry can't hold on to its slot in the
+// table, so each of its methods looks the key up again. That's a few extra hash lookups on the
+// occupied path, in exchange for no allocation on it.
+/// A map from owned keys to values, with lookups by owned or borrowed key.
+#[derive(Clone, Debug, Default)]
+pub struct KeyMap<V, S = RandomState> {
+    map: HashMap<OwnedKey, V, S>,
+}
+
+impl<V> KeyMap<V> {
+    /// Creates a new, empty map.
+    pub fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+        }
+    }
+}
+
+impl<V, S: BuildHasher> KeyMap<V, S> {
+    /// Creates a new, empty map that uses `hasher` to hash keys.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            map: HashMap::with_hasher(hasher),
+        }
+    }
+
+    /// Returns the map's hasher.
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    /// Inserts a value for `key`, returning the old value if there was one.
+    pub fn insert(&mut self, key: OwnedKey, value: V) -> Option<V> {
+        self.map.insert(key, value)
+    }
+
+    /// Returns the value for `key`, if any.
+    pub fn get(&self, key: &dyn Key) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    /// Returns a mutable reference to the value for `key`, if any.
+    pub fn get_mut(&mut self, key: &dyn Key) -> Option<&mut V> {
+        self.map.get_mut(key)
+    }
+
+    /// Returns mutable references to the values for several keys at once, each `None` if there's no
+    /// value for that key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any two of the keys are equal, since that would hand out two mutable references
+    /// to the same value.
+    pub fn get_many_mut<const N: usize>(&mut self, keys: [&dyn Key; N]) -> [Option<&mut V>; N] {
+        // Compare the borrowed views up front, so that a duplicate is reported by position
+        // whether or not it's in the map.
+        for (i, a) in keys.iter().enumerate() {
+            for (j, b) in keys.iter().enumerate().skip(i + 1) {
+                assert!(a.key() != b.key(), "keys {} and {} are equal", i, j);
+            }
+        }
+        self.map.get_disjoint_mut(keys)
+    }
+
+    /// Returns the stored key and value for `key`, if any.
+    pub fn get_key_value(&self, key: &dyn Key) -> Option<(&OwnedKey, &V)> {
+        self.map.get_key_value(key)
+    }
+
+    /// Returns true if the map has a value for `key`.
+    pub fn contains_key(&self, key: &dyn Key) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Removes the value for `key`, returning it if there was one.
+    pub fn remove(&mut self, key: &dyn Key) -> Option<V> {
+        self.map.remove(key)
+    }
+
+    /// Removes every entry, keeping the allocated memory for reuse.
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    /// Keeps only the entries for which `f` returns true, removing the rest.
+    ///
+    /// `f` sees each key as a `BorrowedKey`, like every other key in the API.
+    pub fn retain<F: FnMut(BorrowedKey<'_>, &mut V) -> bool>(&mut self, mut f: F) {
+        self.map.retain(|key, value| f(key.key(), value))
+    }
+
+    /// Removes the entries for which `pred` returns true, handing them back as an iterator.
+    ///
+    /// Entries are removed as the iterator reaches them. If it's dropped early, the entries it
+    /// hasn't reached yet stay in the map.
+    pub fn extract_if<'a, F>(&'a mut self, mut pred: F) -> impl Iterator<Item = (OwnedKey, V)> + 'a
+    where
+        F: FnMut(BorrowedKey<'_>, &mut V) -> bool + 'a,
+    {
+        self.map
+            .extract_if(move |key, value| pred(key.key(), value))
+    }
+
+    /// Returns the entry for `key`, for in-place updates.
+    ///
+    /// This takes an owned key. To avoid allocating one when the key is already present, use
+    /// [`get_or_insert_with`](Self::get_or_insert_with).
+    #[cfg(feature = "std")]
+    pub fn entry(&mut self, key: OwnedKey) -> hash_map::Entry<'_, OwnedKey, V> {
+        self.map.entry(key)
+    }
+
+    /// Returns the entry for `key`, for in-place updates.
+    ///
+    /// This takes an owned key. To avoid allocating one when the key is already present, use
+    /// [`get_or_insert_with`](Self::get_or_insert_with).
+    #[cfg(not(feature = "std"))]
+    pub fn entry(&mut self, key: OwnedKey) -> hash_map::Entry<'_, OwnedKey, V, S> {
+        self.map.entry(key)
+    }
+
+    /// Returns the value for `key`, inserting the result of `f` first if there isn't one.
+    ///
+    /// The key is only copied into an `OwnedKey` if it isn't already present.
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: &dyn Key, f: F) -> &mut V {
+        // This looks the key up twice on a miss. Doing it once would need the unstable raw entry
+        // API, or hashbrown.
+        if !self.map.contains_key(key) {
+            self.map.insert(key.to_owned(), f());
+        }
+        self.map
+            .get_mut(key)
+            .expect("key was just checked or inserted")
+    }
+
+    /// Returns the entry for `key`, for in-place updates, without making an `OwnedKey` unless the
+    /// entry is vacant and gets filled in.
+    pub fn entry_by_key<'a, 'k>(&'a mut self, key: &'k dyn Key) -> Entry<'a, 'k, V, S> {
+        if self.map.contains_key(key) {
+            Entry::Occupied(OccupiedEntry {
+                map: &mut self.map,
+                key,
+            })
+        } else {
+            Entry::Vacant(VacantEntry {
+                map: &mut self.map,
+                key,
+            })
+        }
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Iterates over the entries in the map, in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (&OwnedKey, &V)> {
+        self.map.iter()
+    }
+
+    /// Iterates over the entries in the map, with borrowed views of the keys, in arbitrary order.
+    pub fn iter_borrowed(&self) -> impl Iterator<Item = (BorrowedKey<'_>, &V)> {
+        self.map.iter().map(|(key, value)| (key.key(), value))
+    }
+}
+
+impl<V: PartialEq, S: BuildHasher> PartialEq for KeyMap<V, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl<V: Eq, S: BuildHasher> Eq for KeyMap<V, S> {}
+
+impl<V, S: BuildHasher + Default> FromIterator<(OwnedKey, V)> for KeyMap<V, S> {
+    fn from_iter<I: IntoIterator<Item = (OwnedKey, V)>>(iter: I) -> Self {
+        Self {
+            map: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<'k, V, S: BuildHasher> Index<&'k dyn Key> for KeyMap<V, S> {
+    type Output = V;
+
+    /// Returns the value for `key`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there's no value for `key`. Use [`get`](KeyMap::get) to get an `Option` instead.
+    fn index(&self, key: &'k dyn Key) -> &V {
+        match self.map.get(key) {
+            Some(value) => value,
+            None => panic!("no value in KeyMap for key {:?}", key),
+        }
+    }
+}
+
+impl<V, S: BuildHasher> Extend<(OwnedKey, V)> for KeyMap<V, S> {
+    fn extend<I: IntoIterator<Item = (OwnedKey, V)>>(&mut self, iter: I) {
+        self.map.extend(iter)
+    }
+}
+
+// Extending from borrowed keys has to copy them, but only the ones that aren't there already: a key
+// that is just has its value replaced.
+impl<'a, V, S: BuildHasher> Extend<(BorrowedKey<'a>, V)> for KeyMap<V, S> {
+    fn extend<I: IntoIterator<Item = (BorrowedKey<'a>, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            match self.map.get_mut(&key as &dyn Key) {
+                Some(old) => *old = value,
+                None => {
+                    self.map.insert((&key as &dyn Key).to_owned(), value);
+                }
+            }
+        }
+    }
+}
+
+impl<V, S> IntoIterator for KeyMap<V, S> {
+    type Item = (OwnedKey, V);
+    type IntoIter = hash_map::IntoIter<OwnedKey, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter()
+    }
+}
+
+impl<'a, V, S> IntoIterator for &'a KeyMap<V, S> {
+    type Item = (&'a OwnedKey, &'a V);
+    type IntoIter = hash_map::Iter<'a, OwnedKey, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter()
+    }
+}
+
+impl<'a, V, S> IntoIterator for &'a mut KeyMap<V, S> {
+    type Item = (&'a OwnedKey, &'a mut V);
+    type IntoIter = hash_map::IterMut<'a, OwnedKey, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter_mut()
+    }
+}
+
+/// An entry in a [`KeyMap`], returned by [`KeyMap::entry_by_key`].
+#[derive(Debug)]
+pub enum Entry<'a, 'k, V, S = RandomState> {
+    /// There's a value for the key.
+    Occupied(OccupiedEntry<'a, 'k, V, S>),
+    /// There's no value for the key.
+    Vacant(VacantEntry<'a, 'k, V, S>),
+}
+
+/// An entry in a [`KeyMap`] that has a value.
+pub struct OccupiedEntry<'a, 'k, V, S = RandomState> {
+    map: &'a mut HashMap<OwnedKey, V, S>,
+    key: &'k dyn Key,
+}
+
+/// An entry in a [`KeyMap`] that has no value. Filling it in copies the key into an `OwnedKey`.
+pub struct VacantEntry<'a, 'k, V, S = RandomState> {
+    map: &'a mut HashMap<OwnedKey, V, S>,
+    key: &'k dyn Key,
+}
+
+impl<'a, 'k, V, S: BuildHasher> Entry<'a, 'k, V, S> {
+    /// Returns the entry's key.
+    pub fn key(&self) -> BorrowedKey<'k> {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Returns the value, inserting `default` first if the entry is vacant.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns the value, inserting the result of `default` first if the entry is vacant.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Returns the value, inserting `V::default()` first if the entry is vacant.
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` with the value if the entry is occupied, and returns the entry.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, 'k, V, S: BuildHasher> OccupiedEntry<'a, 'k, V, S> {
+    /// Returns the key the entry was looked up with.
+    pub fn key(&self) -> BorrowedKey<'k> {
+        self.key.key()
+    }
+
+    /// Returns the value.
+    pub fn get(&self) -> &V {
+        self.map.get(self.key).expect("entry is occupied")
+    }
+
+    /// Returns a mutable reference to the value.
+    pub fn get_mut(&mut self) -> &mut V {
+        self.map.get_mut(self.key).expect("entry is occupied")
+    }
+
+    /// Returns a mutable reference to the value that lives as long as the map's borrow.
+    pub fn into_mut(self) -> &'a mut V {
+        self.map.get_mut(self.key).expect("entry is occupied")
+    }
+
+    /// Replaces the value, returning the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry, returning its value.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Removes the entry, returning the stored key and its value.
+    pub fn remove_entry(self) -> (OwnedKey, V) {
+        self.map.remove_entry(self.key).expect("entry is occupied")
+    }
+}
+
+impl<'a, 'k, V, S: BuildHasher> VacantEntry<'a, 'k, V, S> {
+    /// Returns the key the entry was looked up with.
+    pub fn key(&self) -> BorrowedKey<'k> {
+        self.key.key()
+    }
+
+    /// Copies the key into an `OwnedKey`, and inserts `value` for it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.map.entry(self.key.to_owned()).or_insert(value)
+    }
+}
+
+impl<V, S> fmt::Debug for OccupiedEntry<'_, '_, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedEntry")
+            .field("key", &self.key.key())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<V, S> fmt::Debug for VacantEntry<'_, '_, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VacantEntry")
+            .field("key", &self.key.key())
+            .finish()
+    }
+}
+
+#[test]
+fn map_lookups() {
+    let mut map = KeyMap::new();
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    *map.get_or_insert_with(&borrowed_key, || 0) += 1;
+    *map.get_or_insert_with(&borrowed_key, || 0) += 1;
+    assert_eq!(map.get(&borrowed_key), Some(&2));
+
+    let owned_key = OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+    assert_eq!(map.insert(owned_key.clone(), 5), Some(2));
+    assert_eq!(map.get_key_value(&borrowed_key), Some((&owned_key, &5)));
+    assert_eq!(map.remove(&borrowed_key), Some(5));
+    assert!(map.is_empty());
+}
+
+#[test]
+fn map_conversions() {
+    let mut map: KeyMap<u32> = KeyMap::new();
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    map.extend([
+        (borrowed_key, 1),
+        (
+            BorrowedKey {
+                s: "bar",
+                bytes: b"",
+            },
+            2,
+        ),
+    ]);
+    map.extend([(borrowed_key, 3)]);
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get(&borrowed_key), Some(&3));
+
+    for (_, value) in &mut map {
+        *value += 1;
+    }
+    let mut borrowed: Vec<_> = map.iter_borrowed().collect();
+    borrowed.sort();
+    assert_eq!(
+        borrowed,
+        [
+            (
+                BorrowedKey {
+                    s: "bar",
+                    bytes: b""
+                },
+                &3
+            ),
+            (borrowed_key, &4)
+        ]
+    );
+    assert_eq!((&map).into_iter().count(), 2);
+    let mut owned: Vec<_> = map.into_iter().map(|(key, value)| (key.s, value)).collect();
+    owned.sort();
+    assert_eq!(owned, [("bar".to_string(), 3), ("foo".to_string(), 4)]);
+}
+
+#[test]
+fn map_index() {
+    let mut map = KeyMap::new();
+    let owned_key = OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+    map.insert(owned_key.clone(), 1);
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert_eq!(map[&borrowed_key], 1);
+    assert_eq!(map[&owned_key], 1);
+}
+
+#[test]
+#[should_panic(expected = "no value in KeyMap for key BorrowedKey { s: \"bar\", bytes: [0] }")]
+fn map_index_missing() {
+    let map: KeyMap<u32> = KeyMap::new();
+    let _ = map[&OwnedKey {
+        s: "bar".to_string(),
+        bytes: vec![0],
+    }];
+}
+
+#[test]
+fn map_get_many_mut() {
+    let mut map: KeyMap<u32> = KeyMap::new();
+    let a = OwnedKey {
+        s: "a".to_string(),
+        bytes: vec![],
+    };
+    map.insert(a.clone(), 1);
+    map.insert(
+        OwnedKey {
+            s: "b".to_string(),
+            bytes: vec![],
+        },
+        2,
+    );
+
+    let b = BorrowedKey { s: "b", bytes: b"" };
+    let missing = BorrowedKey { s: "c", bytes: b"" };
+    let [x, y, z] = map.get_many_mut([&a, &b, &missing]);
+    core::mem::swap(x.unwrap(), y.unwrap());
+    assert_eq!(z, None);
+    assert_eq!(map.get(&a), Some(&2));
+    assert_eq!(map.get(&b), Some(&1));
+}
+
+#[test]
+#[should_panic(expected = "keys 0 and 2 are equal")]
+fn map_get_many_mut_duplicates() {
+    let mut map: KeyMap<u32> = KeyMap::new();
+    let owned = OwnedKey {
+        s: "a".to_string(),
+        bytes: vec![],
+    };
+    // The keys are equal even though only one of them is owned, and neither is in the map.
+    let borrowed = BorrowedKey { s: "a", bytes: b"" };
+    let other = BorrowedKey { s: "b", bytes: b"" };
+    map.get_many_mut([&owned, &other, &borrowed]);
+}
+
+#[test]
+fn map_entry_by_key() {
+    let mut map: KeyMap<u32> = KeyMap::new();
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    match map.entry_by_key(&borrowed_key) {
+        Entry::Vacant(entry) => {
+            assert_eq!(entry.key(), borrowed_key);
+            *entry.insert(1) += 1;
+        }
+        Entry::Occupied(_) => panic!("map is empty"),
+    }
+    map.entry_by_key(&borrowed_key)
+        .and_modify(|value| *value *= 10)
+        .or_insert(0);
+    assert_eq!(map.get(&borrowed_key), Some(&20));
+
+    match map.entry_by_key(&borrowed_key) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.insert(5), 20);
+            assert_eq!(entry.get(), &5);
+            let (key, value) = entry.remove_entry();
+            assert_eq!(key.key(), borrowed_key);
+            assert_eq!(value, 5);
+        }
+        Entry::Vacant(_) => panic!("key was inserted"),
+    }
+    assert!(map.is_empty());
+    assert_eq!(*map.entry_by_key(&borrowed_key).or_default(), 0);
+}
+
+#[test]
+fn map_retain_and_extract_if() {
+    let mut map: KeyMap<u32> = (0..10)
+        .map(|i| {
+            let key = OwnedKey {
+                s: if i % 2 == 0 { "even" } else { "odd" }.to_string(),
+                bytes: vec![i as u8],
+            };
+            (key, i)
+        })
+        .collect();
+
+    map.retain(|key, value| {
+        *value *= 10;
+        key.bytes[0] < 8
+    });
+    assert_eq!(map.len(), 8);
+
+    let mut odd: Vec<_> = map.extract_if(|key, _| key.s == "odd").collect();
+    odd.sort_unstable_by_key(|(_, value)| *value);
+    let odd_values: Vec<_> = odd.iter().map(|(_, value)| *value).collect();
+    assert_eq!(odd_values, [10, 30, 50, 70]);
+    assert_eq!(odd[0].0.s, "odd");
+    assert_eq!(map.len(), 4);
+    assert!(map.iter().all(|(key, _)| key.s == "even"));
+}
+
+// The model-based test below runs random sequences of operations against both a KeyMap and a plain
+// HashMap keyed by (String, Vec<u8>) tuples, and checks that they agree after every step.
+#[cfg(test)]
+#[derive(Clone, Debug)]
+enum Op {
+    Insert(OwnedKey, u8),
+    Remove(OwnedKey),
+    Get(OwnedKey),
+    GetOrInsert(OwnedKey, u8),
+    EntryAdd(OwnedKey, u8),
+    GetManyAdd(OwnedKey, OwnedKey, u8),
+    EntryByKeyAdd(OwnedKey, u8),
+    EntryByKeyRemove(OwnedKey),
+    RetainBelow(u8),
+    ExtractString(String),
+}
+
+#[cfg(test)]
+fn ops() -> impl Strategy<Value = Vec<Op>> {
+    // Few enough distinct keys that operations hit existing keys often.
+    let key = ("[ab]{0,1}", prop::collection::vec(0..2u8, 0..2))
+        .prop_map(|(s, bytes)| OwnedKey { s, bytes });
+    let op = prop_oneof![
+        (key.clone(), any::<u8>()).prop_map(|(key, value)| Op::Insert(key, value)),
+        key.clone().prop_map(Op::Remove),
+        key.clone().prop_map(Op::Get),
+        (key.clone(), any::<u8>()).prop_map(|(key, value)| Op::GetOrInsert(key, value)),
+        (key.clone(), any::<u8>()).prop_map(|(key, value)| Op::EntryAdd(key, value)),
+        (key.clone(), key.clone(), any::<u8>())
+            .prop_map(|(a, b, value)| Op::GetManyAdd(a, b, value)),
+        (key.clone(), any::<u8>()).prop_map(|(key, value)| Op::EntryByKeyAdd(key, value)),
+        key.prop_map(Op::EntryByKeyRemove),
+        any::<u8>().prop_map(Op::RetainBelow),
+        "[ab]{0,1}".prop_map(Op::ExtractString),
+    ];
+    prop::collection::vec(op, 0..32)
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn map_matches_model(ops in ops()) {
+        let mut map = KeyMap::new();
+        let mut model: HashMap<(String, Vec<u8>), u8> = HashMap::new();
+        for op in ops {
+            match op {
+                Op::Insert(key, value) => {
+                    let expected = model.insert((key.s.clone(), key.bytes.clone()), value);
+                    prop_assert_eq!(map.insert(key, value), expected);
+                }
+                Op::Remove(key) => {
+                    let expected = model.remove(&(key.s.clone(), key.bytes.clone()));
+                    prop_assert_eq!(map.remove(&key.key()), expected);
+                }
+                Op::Get(key) => {
+                    let expected = model.get(&(key.s.clone(), key.bytes.clone()));
+                    prop_assert_eq!(map.get(&key.key()), expected);
+                    prop_assert_eq!(map.contains_key(&key.key()), expected.is_some());
+                }
+                Op::GetOrInsert(key, value) => {
+                    let expected = *model.entry((key.s.clone(), key.bytes.clone())).or_insert(value);
+                    prop_assert_eq!(*map.get_or_insert_with(&key.key(), || value), expected);
+                }
+                Op::EntryAdd(key, value) => {
+                    let expected = model.entry((key.s.clone(), key.bytes.clone())).or_insert(0);
+                    *expected = expected.wrapping_add(value);
+                    let actual = map.entry(key).or_insert(0);
+                    *actual = actual.wrapping_add(value);
+                }
+                Op::GetManyAdd(a, b, value) => {
+                    // Equal keys panic, which map_get_many_mut_duplicates covers.
+                    if a == b {
+                        continue;
+                    }
+                    for key in [&a, &b] {
+                        if let Some(expected) = model.get_mut(&(key.s.clone(), key.bytes.clone())) {
+                            *expected = expected.wrapping_add(value);
+                        }
+                    }
+                    let [x, y] = map.get_many_mut([&a.key(), &b.key()]);
+                    for actual in x.into_iter().chain(y) {
+                        *actual = actual.wrapping_add(value);
+                    }
+                }
+                Op::EntryByKeyAdd(key, value) => {
+                    let expected = model.entry((key.s.clone(), key.bytes.clone())).or_insert(0);
+                    *expected = expected.wrapping_add(value);
+                    let actual = map.entry_by_key(&key.key()).or_insert(0);
+                    *actual = actual.wrapping_add(value);
+                }
+                Op::EntryByKeyRemove(key) => {
+                    let expected = model.remove(&(key.s.clone(), key.bytes.clone()));
+                    let actual = match map.entry_by_key(&key.key()) {
+                        Entry::Occupied(entry) => Some(entry.remove()),
+                        Entry::Vacant(_) => None,
+                    };
+                    prop_assert_eq!(actual, expected);
+                }
+                Op::RetainBelow(limit) => {
+                    model.retain(|_, value| *value < limit);
+                    map.retain(|_, value| *value < limit);
+                }
+                Op::ExtractString(s) => {
+                    let mut expected: Vec<_> = model
+                        .iter()
+                        .filter(|((key_s, _), _)| *key_s == s)
+                        .map(|((key_s, bytes), value)| (key_s.clone(), bytes.clone(), *value))
+                        .collect();
+                    model.retain(|(key_s, _), _| *key_s != s);
+                    let mut actual: Vec<_> = map
+                        .extract_if(|key, _| key.s == s)
+                        .map(|(key, value)| (key.s, key.bytes, value))
+                        .collect();
+                    expected.sort();
+                    actual.sort();
+                    prop_assert_eq!(actual, expected);
+                }
+            }
+
+            prop_assert_eq!(map.len(), model.len());
+            for (key, value) in map.iter() {
+                prop_assert_eq!(model.get(&(key.s.clone(), key.bytes.clone())), Some(value));
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! A serde adapter for maps keyed by [`OwnedKey`], including in JSON.
+
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use serde::de::{self, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+
+// A key serializes as a struct (see serialize.rs), which is fine for a HashSet<OwnedKey>. But a
+// HashMap<OwnedKey, V> serializes as a map with keys as *map keys*, and JSON object keys can only
+// be strings: serde_json gives up with "key must be a string".
+//
+// This module is meant to be used with serde's `with` attribute, on a field holding a map:
+//
+//     #[derive(Serialize, Deserialize)]
+//     struct Scores {
+//         #[serde(with = "borrow_complex_key_example::map_key")]
+//         scores: HashMap<OwnedKey, u32>,
+//     }
+//
+// For human-readable formats like JSON, it writes each key in its canonical string form, as in
+// text.rs. Binary formats have no trouble with structured map keys, and parsing a string back
+// would only slow them down, so for them each key is written as an (s, bytes) tuple instead.
+
+/// Serializes a map keyed by `OwnedKey`. Use with `#[serde(with = "...")]`.
+pub fn serialize<'a, M, V, S>(map: &'a M, serializer: S) -> Result<S::Ok, S::Error>
+where
+    &'a M: IntoIterator<Item = (&'a OwnedKey, &'a V)>,
+    V: Serialize + 'a,
+    S: Serializer,
+{
+    let iter = map.into_iter();
+    let mut out = serializer.serialize_map(iter.size_hint().1)?;
+    for (key, value) in iter {
+        out.serialize_entry(&MapKeyRef(key.key()), value)?;
+    }
+    out.end()
+}
+
+/// Deserializes a map keyed by `OwnedKey`. Use with `#[serde(with = "...")]`.
+pub fn deserialize<'de, M, V, D>(deserializer: D) -> Result<M, D::Error>
+where
+    M: FromIterator<(OwnedKey, V)>,
+    V: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    struct MapVisitor<M, V>(PhantomData<fn() -> (M, V)>);
+
+    impl<'de, M, V> Visitor<'de> for MapVisitor<M, V>
+    where
+        M: FromIterator<(OwnedKey, V)>,
+        V: Deserialize<'de>,
+    {
+        type Value = M;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a map keyed by OwnedKey")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<M, A::Error> {
+            let mut entries = Vec::with_capacity(access.size_hint().unwrap_or(0));
+            while let Some((MapKey(key), value)) = access.next_entry()? {
+                entries.push((key, value));
+            }
+            Ok(entries.into_iter().collect())
+        }
+    }
+
+    deserializer.deserialize_map(MapVisitor(PhantomData))
+}
+
+struct MapKeyRef<'a>(BorrowedKey<'a>);
+
+impl<'a> Serialize for MapKeyRef<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(&self.0)
+        } else {
+            (self.0.s, serde_bytes::Bytes::new(self.0.bytes)).serialize(serializer)
+        }
+    }
+}
+
+struct MapKey(OwnedKey);
+
+impl<'de> Deserialize<'de> for MapKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            s.parse().map(MapKey).map_err(de::Error::custom)
+        } else {
+            let (s, bytes) = <(String, serde_bytes::ByteBuf)>::deserialize(deserializer)?;
+            Ok(MapKey(OwnedKey {
+                s,
+                bytes: bytes.into_vec(),
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Scores {
+    #[serde(with = "crate::map_key")]
+    by_key: BTreeMap<OwnedKey, u32>,
+}
+
+#[test]
+fn map_key_formats() {
+    let mut by_key = BTreeMap::new();
+    by_key.insert(
+        OwnedKey {
+            s: "foo".to_string(),
+            bytes: b"abc".to_vec(),
+        },
+        1,
+    );
+    by_key.insert(
+        OwnedKey {
+            s: "bar:baz".to_string(),
+            bytes: vec![],
+        },
+        2,
+    );
+    let scores = Scores { by_key };
+
+    // The plain impls can't be used as JSON object keys.
+    assert!(serde_json::to_string(&scores.by_key).is_err());
+
+    let json = serde_json::to_string(&scores).unwrap();
+    assert_eq!(json, r#"{"by_key":{"bar:baz:":2,"foo:616263":1}}"#);
+    assert_eq!(serde_json::from_str::<Scores>(&json).unwrap(), scores);
+    assert!(serde_json::from_str::<Scores>(r#"{"by_key":{"foo":1}}"#).is_err());
+
+    let encoded = postcard::to_allocvec(&scores).unwrap();
+    assert_eq!(postcard::from_bytes::<Scores>(&encoded).unwrap(), scores);
+}
+
+proptest! {
+    #[test]
+    fn map_key_round_trips(entries in prop::collection::hash_map(any::<OwnedKey>(), any::<u32>(), 0..8)) {
+        #[derive(Debug, Deserialize, PartialEq, Serialize)]
+        struct Map {
+            #[serde(with = "crate::map_key")]
+            entries: HashMap<OwnedKey, u32>,
+        }
+
+        let map = Map { entries };
+        let json = serde_json::to_string(&map).unwrap();
+        prop_assert_eq!(&serde_json::from_str::<Map>(&json).unwrap(), &map);
+        let encoded = postcard::to_allocvec(&map).unwrap();
+        prop_assert_eq!(&postcard::from_bytes::<Map>(&encoded).unwrap(), &map);
+    }
+}
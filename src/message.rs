@@ -0,0 +1,183 @@
+//! Reading borrowed keys straight out of RPC messages.
+
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::collections::HashSet;
+use std::convert::Infallible;
+
+// Zero-copy message formats like FlatBuffers and Cap'n Proto hand out readers: small Copy handles
+// into the received buffer, whose string and data accessors return slices of that buffer. An RPC
+// server that keys its state by OwnedKey can build a BorrowedKey out of those slices and look it up
+// directly, without copying the payload into an OwnedKey first.
+//
+// Why not just implement Key for the reader? Key::key borrows from &self, so the BorrowedKey it
+// returns can't outlive the reader. But a reader is only a handle, and the data it points to lives
+// as long as the buffer does. KeyReader<'a> returns a BorrowedKey<'a> tied to the buffer instead, so
+// the key can be kept around after the reader is gone.
+//
+// Reading can fail: Cap'n Proto checks each pointer as it's followed, for example. FlatBuffers
+// instead checks the whole message up front, so its reader can't fail, and uses Infallible.
+/// A reader for a message that contains a key.
+pub trait KeyReader<'a> {
+    /// The error returned if the key can't be read.
+    type Error;
+
+    /// Reads the key out of the message, borrowing from the message's buffer.
+    fn read_key(&self) -> Result<BorrowedKey<'a>, Self::Error>;
+}
+
+// What follows is what flatc would generate for this schema, trimmed down to what's needed:
+//
+//     table KeyMessage {
+//         s: string (required);
+//         bytes: [ubyte] (required);
+//     }
+//     root_type KeyMessage;
+//
+// Both fields are required, so the verifier rejects messages without them, and the accessors below
+// can't come up empty.
+/// A FlatBuffers table holding a key.
+#[cfg(feature = "flatbuffers")]
+#[derive(Copy, Clone, Debug)]
+pub struct KeyMessage<'a> {
+    table: flatbuffers::Table<'a>,
+}
+
+#[cfg(feature = "flatbuffers")]
+impl<'a> KeyMessage<'a> {
+    const VT_S: flatbuffers::VOffsetT = 4;
+    const VT_BYTES: flatbuffers::VOffsetT = 6;
+
+    /// Verifies `buf`, and returns the KeyMessage at its root.
+    pub fn root(buf: &'a [u8]) -> Result<Self, flatbuffers::InvalidFlatbuffer> {
+        flatbuffers::root::<KeyMessage<'a>>(buf)
+    }
+
+    /// Writes `key` as a KeyMessage, and returns its offset in `builder`.
+    pub fn create<'fbb>(
+        builder: &mut flatbuffers::FlatBufferBuilder<'fbb>,
+        key: &dyn Key,
+    ) -> flatbuffers::WIPOffset<KeyMessage<'fbb>> {
+        let key = key.key();
+        let s = builder.create_string(key.s);
+        let bytes = builder.create_vector(key.bytes);
+        let start = builder.start_table();
+        builder.push_slot_always(Self::VT_S, s);
+        builder.push_slot_always(Self::VT_BYTES, bytes);
+        let end = builder.end_table(start);
+        builder.required(end, Self::VT_S, "s");
+        builder.required(end, Self::VT_BYTES, "bytes");
+        flatbuffers::WIPOffset::new(end.value())
+    }
+
+    /// Returns the key's string.
+    pub fn s(&self) -> &'a str {
+        // SAFETY: the verifier checked that this field is present and is a string.
+        unsafe {
+            self.table
+                .get::<flatbuffers::ForwardsUOffset<&str>>(Self::VT_S, None)
+                .expect("required field verified")
+        }
+    }
+
+    /// Returns the key's bytes.
+    pub fn bytes(&self) -> &'a [u8] {
+        // SAFETY: the verifier checked that this field is present and is a vector of bytes.
+        unsafe {
+            self.table
+                .get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(
+                    Self::VT_BYTES,
+                    None,
+                )
+                .expect("required field verified")
+                .bytes()
+        }
+    }
+}
+
+#[cfg(feature = "flatbuffers")]
+impl<'a> flatbuffers::Follow<'a> for KeyMessage<'a> {
+    type Inner = KeyMessage<'a>;
+
+    unsafe fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+        KeyMessage {
+            table: flatbuffers::Table::new(buf, loc),
+        }
+    }
+}
+
+#[cfg(feature = "flatbuffers")]
+impl flatbuffers::Verifiable for KeyMessage<'_> {
+    fn run_verifier(
+        verifier: &mut flatbuffers::Verifier<'_, '_>,
+        pos: usize,
+    ) -> Result<(), flatbuffers::InvalidFlatbuffer> {
+        verifier
+            .visit_table(pos)?
+            .visit_field::<flatbuffers::ForwardsUOffset<&str>>("s", Self::VT_S, true)?
+            .visit_field::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'_, u8>>>(
+                "bytes",
+                Self::VT_BYTES,
+                true,
+            )?
+            .finish();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "flatbuffers")]
+impl<'a> KeyReader<'a> for KeyMessage<'a> {
+    type Error = Infallible;
+
+    fn read_key(&self) -> Result<BorrowedKey<'a>, Infallible> {
+        Ok(BorrowedKey {
+            s: self.s(),
+            bytes: self.bytes(),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "flatbuffers"))]
+fn key_message(key: &dyn Key) -> Vec<u8> {
+    let mut builder = flatbuffers::FlatBufferBuilder::new();
+    let message = KeyMessage::create(&mut builder, key);
+    builder.finish(message, None);
+    builder.finished_data().to_vec()
+}
+
+#[cfg(feature = "flatbuffers")]
+#[test]
+fn message_lookups() {
+    let state: HashSet<OwnedKey> = std::iter::once(OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    })
+    .collect();
+
+    // A request, as it would arrive over the wire.
+    let buf = key_message(&BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    });
+    let key = KeyMessage::root(&buf).unwrap().read_key().unwrap();
+    assert!(state.contains(&key as &dyn Key));
+    // The key points into the buffer: nothing was copied.
+    assert!(buf.as_ptr_range().contains(&key.s.as_ptr()));
+
+    // Messages missing a field are rejected up front.
+    let mut builder = flatbuffers::FlatBufferBuilder::new();
+    let start = builder.start_table();
+    let end = builder.end_table(start);
+    builder.finish(end, None);
+    assert!(KeyMessage::root(builder.finished_data()).is_err());
+}
+
+#[cfg(feature = "flatbuffers")]
+proptest! {
+    #[test]
+    fn message_round_trips(key in any::<OwnedKey>()) {
+        let buf = key_message(&key);
+        let read = KeyMessage::root(&buf).unwrap().read_key().unwrap();
+        prop_assert_eq!(read, key.key());
+    }
+}
@@ -0,0 +1,305 @@
+//! Looking up keys across a change to the key's schema.
+
+use crate::consistency::assert_consistent;
+use crate::family::{KeyFamily, Lookup, Probe};
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use proptest_derive::Arbitrary;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+
+// Key types change. A service that keyed its data by OwnedKey might add a namespace to the key in
+// its next version. During a rolling upgrade, though, the new code still has to read snapshots
+// written by the old code, and those are maps keyed by the old type. Converting every snapshot up
+// front defeats the point of a rolling upgrade, and building an old owned key for every lookup
+// allocates.
+//
+// The lookup doesn't need an old owned key, only the old borrowed form, and a new borrowed key can
+// usually be projected onto that without copying anything: the old key's fields are still there,
+// borrowed from the same place. KeyMigration is that projection, from one family's borrowed form
+// to the previous family's, and back again. Either direction can fail. A key that uses something
+// only the new version has, like a namespace other than the default, has no old form, and can't be
+// in an old snapshot at all. An old key always has a new form here, but a migration that drops a
+// field wouldn't be able to go back.
+//
+// The projection has to be faithful for lookups to be right. Going there and back must give the
+// same key, so that a probe finds the entry that was written for it, and not some other one. And
+// keys with old forms must compare the same way as their old forms do, so that a range scan over an
+// old BTreeMap returns entries in the order the new code expects. assert_migration_consistent
+// checks both, in the same way that the consistency module checks an owned key against its
+// borrowed form.
+/// A key family that replaced `Previous`, and whose borrowed keys can be projected onto
+/// `Previous`'s borrowed keys and back.
+pub trait KeyMigration<Previous: KeyFamily>: KeyFamily {
+    /// Returns `key` in the previous version's borrowed form, or `None` if it has none.
+    ///
+    /// A key with no previous form can't be in a map keyed by the previous version.
+    fn to_previous<'a>(key: Self::Borrowed<'a>) -> Option<Previous::Borrowed<'a>>;
+
+    /// Returns a previous version's borrowed key in this version's form, or `None` if the
+    /// migration lost information that's needed to build it.
+    fn from_previous<'a>(key: Previous::Borrowed<'a>) -> Option<Self::Borrowed<'a>>;
+
+    /// Returns a probe for looking up `key` in a map keyed by the previous version, or `None` if
+    /// it can't be in one.
+    fn previous_probe<'a>(key: Self::Borrowed<'a>) -> Option<Probe<'a, Previous>> {
+        Self::to_previous(key).map(Probe)
+    }
+}
+
+/// Asserts that `N`'s migration from `P` is consistent, for this particular pair of borrowed keys.
+///
+/// Call this from a property test with randomly generated keys, including ones that have
+/// previous forms.
+///
+/// # Panics
+///
+/// Panics if:
+///
+/// * a key's previous form doesn't migrate back to the same key,
+/// * two keys with previous forms don't compare the same way as their previous forms.
+pub fn assert_migration_consistent<'a, P, N>(key1: N::Borrowed<'a>, key2: N::Borrowed<'a>)
+where
+    P: KeyFamily,
+    N: KeyMigration<P>,
+{
+    let previous1 = N::to_previous(key1);
+    let previous2 = N::to_previous(key2);
+    for (key, previous) in [(key1, previous1), (key2, previous2)] {
+        if let Some(previous) = previous {
+            assert_eq!(
+                N::from_previous(previous),
+                Some(key),
+                "round trip through previous"
+            );
+        }
+    }
+
+    if let (Some(previous1), Some(previous2)) = (previous1, previous2) {
+        assert_eq!(
+            key1.cmp(&key2),
+            previous1.cmp(&previous2),
+            "consistent Ord across versions"
+        );
+        assert_eq!(
+            key1 == key2,
+            previous1 == previous2,
+            "consistent Eq across versions"
+        );
+    }
+}
+
+/// Asserts that a previous version's borrowed key migrates to a key that maps back to it, if it
+/// migrates at all.
+///
+/// # Panics
+///
+/// Panics if the migrated key's previous form isn't `previous`.
+pub fn assert_previous_round_trip<P, N>(previous: P::Borrowed<'_>)
+where
+    P: KeyFamily,
+    N: KeyMigration<P>,
+{
+    if let Some(key) = N::from_previous(previous) {
+        assert_eq!(
+            N::to_previous(key),
+            Some(previous),
+            "round trip from previous"
+        );
+    }
+}
+
+/// The second version of [`OwnedKey`], which adds a namespace.
+///
+/// Keys without a namespace are the ones that existed before it was added, and have an
+/// [`OwnedKey`] form. They sort first, in the same order as the `OwnedKey`s they came from.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Arbitrary)]
+pub struct OwnedKeyV2 {
+    pub namespace: Option<String>,
+    pub s: String,
+    pub bytes: Vec<u8>,
+}
+
+/// The borrowed form of an [`OwnedKeyV2`].
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct BorrowedKeyV2<'a> {
+    pub namespace: Option<&'a str>,
+    pub s: &'a str,
+    pub bytes: &'a [u8],
+}
+
+impl OwnedKeyV2 {
+    /// Returns the key's borrowed form.
+    pub fn key(&self) -> BorrowedKeyV2<'_> {
+        BorrowedKeyV2 {
+            namespace: self.namespace.as_deref(),
+            s: &self.s,
+            bytes: &self.bytes,
+        }
+    }
+}
+
+impl KeyFamily for OwnedKeyV2 {
+    type Borrowed<'a> = BorrowedKeyV2<'a>;
+
+    fn shorten<'long: 'short, 'short>(borrowed: BorrowedKeyV2<'long>) -> BorrowedKeyV2<'short> {
+        borrowed
+    }
+}
+
+impl Lookup<OwnedKeyV2> for OwnedKeyV2 {
+    fn lookup_key(&self) -> BorrowedKeyV2<'_> {
+        self.key()
+    }
+}
+
+impl<'a> Borrow<dyn Lookup<OwnedKeyV2> + 'a> for OwnedKeyV2 {
+    fn borrow(&self) -> &(dyn Lookup<OwnedKeyV2> + 'a) {
+        self
+    }
+}
+
+// The signatures name the borrowed forms through KeyFamily, as the trait does. Written out as
+// BorrowedKeyV2<'a> and BorrowedKey<'a>, the lifetime would be late-bound, and wouldn't match the
+// trait's.
+impl KeyMigration<OwnedKey> for OwnedKeyV2 {
+    fn to_previous<'a>(key: Self::Borrowed<'a>) -> Option<<OwnedKey as KeyFamily>::Borrowed<'a>> {
+        match key.namespace {
+            None => Some(BorrowedKey {
+                s: key.s,
+                bytes: key.bytes,
+            }),
+            Some(_) => None,
+        }
+    }
+
+    fn from_previous<'a>(key: <OwnedKey as KeyFamily>::Borrowed<'a>) -> Option<Self::Borrowed<'a>> {
+        Some(BorrowedKeyV2 {
+            namespace: None,
+            s: key.s,
+            bytes: key.bytes,
+        })
+    }
+}
+
+#[test]
+fn migration_lookups() {
+    // A snapshot written by the previous version.
+    let mut snapshot = HashMap::new();
+    snapshot.insert(
+        OwnedKey {
+            s: "foo".to_string(),
+            bytes: b"abc".to_vec(),
+        },
+        1,
+    );
+    let ordered: BTreeMap<_, _> = snapshot.clone().into_iter().collect();
+
+    let key = OwnedKeyV2 {
+        namespace: None,
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+    let probe = OwnedKeyV2::previous_probe(key.key()).unwrap();
+    assert_eq!(snapshot.get(probe.as_lookup()), Some(&1));
+    assert_eq!(ordered.get(probe.as_lookup()), Some(&1));
+
+    // A key in a namespace can't be in the old snapshot, so there's nothing to probe with.
+    let namespaced = BorrowedKeyV2 {
+        namespace: Some("tenant"),
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert!(OwnedKeyV2::previous_probe(namespaced).is_none());
+
+    // Keys read out of the old snapshot migrate forward, into the new version's maps.
+    let mut current = HashMap::new();
+    for (previous, value) in &snapshot {
+        let key = OwnedKeyV2::from_previous(previous.key()).unwrap();
+        current.insert(
+            OwnedKeyV2 {
+                namespace: key.namespace.map(str::to_string),
+                s: key.s.to_string(),
+                bytes: key.bytes.to_vec(),
+            },
+            *value,
+        );
+    }
+    assert_eq!(current.get(&key as &dyn Lookup<OwnedKeyV2>), Some(&1));
+}
+
+#[test]
+fn migration_ordering() {
+    let keys = [
+        OwnedKeyV2 {
+            namespace: None,
+            s: "b".to_string(),
+            bytes: vec![],
+        },
+        OwnedKeyV2 {
+            namespace: Some(String::new()),
+            s: "a".to_string(),
+            bytes: vec![],
+        },
+        OwnedKeyV2 {
+            namespace: None,
+            s: "a".to_string(),
+            bytes: vec![1],
+        },
+    ];
+    // Keys from the previous version sort first, and in their previous order.
+    let mut sorted = keys.to_vec();
+    sorted.sort();
+    let previous: Vec<_> = sorted
+        .iter()
+        .map_while(|key| OwnedKeyV2::to_previous(key.key()))
+        .collect();
+    assert_eq!(
+        previous,
+        [BorrowedKey::new("a", &[1]), BorrowedKey::new("b", &[])]
+    );
+    assert_eq!(sorted[2].key(), keys[1].key());
+}
+
+// Mostly keys without a namespace, so that pairs with previous forms come up often.
+#[cfg(test)]
+fn key_v2() -> impl Strategy<Value = OwnedKeyV2> {
+    (
+        prop_oneof![3 => Just(None), 1 => any::<Option<String>>()],
+        any::<String>(),
+        any::<Vec<u8>>(),
+    )
+        .prop_map(|(namespace, s, bytes)| OwnedKeyV2 {
+            namespace,
+            s,
+            bytes,
+        })
+}
+
+proptest! {
+    #[test]
+    fn migration_consistent_borrow(owned1 in any::<OwnedKeyV2>(), owned2 in any::<OwnedKeyV2>()) {
+        assert_consistent::<_, dyn Lookup<OwnedKeyV2>>(&owned1, &owned2);
+    }
+
+    #[test]
+    fn migration_consistent(key1 in key_v2(), key2 in key_v2(), previous in any::<OwnedKey>()) {
+        assert_migration_consistent::<OwnedKey, OwnedKeyV2>(key1.key(), key2.key());
+        assert_previous_round_trip::<OwnedKey, OwnedKeyV2>(previous.key());
+    }
+
+    #[test]
+    fn migration_probes_snapshot(
+        previous in prop::collection::vec(any::<OwnedKey>(), 0..8),
+        key in key_v2(),
+    ) {
+        // Probing an old snapshot with a new key finds exactly the entry written for that key's
+        // previous form.
+        let snapshot: HashMap<_, _> = previous.iter().cloned().map(|key| (key, ())).collect();
+        let expected = key.namespace.is_none()
+            && previous.iter().any(|previous| previous.s == key.s && previous.bytes == key.bytes);
+        let found = OwnedKeyV2::previous_probe(key.key())
+            .is_some_and(|probe| snapshot.contains_key(probe.as_lookup()));
+        prop_assert_eq!(found, expected);
+    }
+}
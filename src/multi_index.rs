@@ -0,0 +1,74 @@
+//! Looking up one record by any of several keys, with `#[derive(MultiIndex)]`.
+//!
+//! A record often has more than one key. A user has a numeric id, but is also unique by
+//! `(tenant, name)`. Keeping one map per key and updating all of them on every insert and remove
+//! is easy to get wrong. `MultiIndex` generates a container that does it for you:
+//!
+//! ```
+//! use borrow_complex_key_example::MultiIndex;
+//!
+//! #[derive(Debug, MultiIndex)]
+//! #[multi_index(by_key(s, bytes), by_id(id))]
+//! struct Record {
+//!     s: String,
+//!     bytes: Vec<u8>,
+//!     id: u64,
+//!     payload: f64,
+//! }
+//!
+//! // The derive wrote RecordIndex, with a lookup and a remove method for each index.
+//! let mut records = RecordIndex::new();
+//! records
+//!     .insert(Record { s: "foo".to_string(), bytes: b"abc".to_vec(), id: 7, payload: 0.5 })
+//!     .unwrap();
+//!
+//! // Lookups take borrowed fields, so nothing is allocated.
+//! assert_eq!(records.get_by_key("foo", b"abc").map(|r| r.id), Some(7));
+//! assert_eq!(records.get_by_id(7).map(|r| r.payload), Some(0.5));
+//!
+//! // A record whose key is already taken in any index is handed back.
+//! let err = records
+//!     .insert(Record { s: "bar".to_string(), bytes: vec![], id: 7, payload: 1.0 })
+//!     .unwrap_err();
+//! assert_eq!(err.index, "by_id");
+//! ```
+//!
+//! Each index is written `name(field, ...)`, and gets `get_name` and `remove_name` methods that take
+//! the borrowed form of each field as a separate argument. Fields are borrowed the same way
+//! [`BorrowKey`](crate::derive) borrows them. The container is called `RecordIndex` by default; use
+//! `#[multi_index(container = Name)]` to pick a different name. Every index is unique.
+//!
+//! Under the hood, each index is a `HashMap` from a generated owned key type to the record's
+//! position in a `Vec`. The key types implement [`KeyFamily`](crate::family::KeyFamily) with the
+//! fields' borrowed forms as their borrowed type, so lookups go through
+//! [`Probe`](crate::family::Probe) just like [`KeyIndexMap`](crate::index_map::KeyIndexMap) does.
+//! The indexed fields are cloned into each index on insert, so they need to implement `Clone`,
+//! `Eq` and `Hash`. Removing a record moves the last record into its position, as
+//! `Vec::swap_remove` does, so iteration order isn't insertion order.
+
+use std::error::Error;
+use std::fmt;
+
+pub use borrow_complex_key_example_derive::MultiIndex;
+
+/// The error returned when a record can't be inserted into a `MultiIndex` container, because one of
+/// its keys is already taken.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DuplicateKey<R> {
+    /// The name of the first index that already had the record's key.
+    pub index: &'static str,
+    /// The record that wasn't inserted.
+    pub record: R,
+}
+
+impl<R> fmt::Display for DuplicateKey<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a record with the same {} key is already present",
+            self.index
+        )
+    }
+}
+
+impl<R: fmt::Debug> Error for DuplicateKey<R> {}
@@ -0,0 +1,133 @@
+//! Single-field keys that borrow as an unsized newtype, the way `PathBuf` borrows as `Path`.
+//!
+//! Requires the `ref-cast` feature.
+
+use crate::consistency::assert_consistent;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use ref_cast::RefCast;
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::mem;
+use std::ops::Deref;
+
+// The trait object in the crate root is needed because BorrowedKey is a new struct made of two
+// references, and Borrow has to return a reference to something that already exists. A key with a
+// single field doesn't have that problem. If the owned key wraps a String, the borrowed key can
+// wrap a str:
+//
+//     struct Name(String);
+//     #[repr(transparent)]
+//     struct NameRef(str);
+//
+// repr(transparent) guarantees that NameRef is laid out exactly like str, so a &str can be
+// reinterpreted as a &NameRef. Writing that cast by hand takes unsafe code; the RefCast derive
+// writes it for us, after checking that the struct really is a transparent wrapper. Then
+// Borrow<NameRef> for Name is a one-liner, just like Borrow<Path> for PathBuf.
+//
+// Consistency is the same argument as for String and str: Name and NameRef derive Eq, Ord and Hash
+// from their one field, and String and str agree on all three.
+//
+// How does this compare to the trait object?
+//
+// - A &NameRef is a pointer and a length, like a &str. A &dyn Key is a pointer and a vtable, and
+//   every comparison goes through a virtual call to key().
+// - NameRef is a real type, so it can have its own methods, and implement ToOwned.
+// - But it only works for one field. Two fields can't be reinterpreted as a single unsized type
+//   without first being encoded into one buffer.
+/// An owned name.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Name(String);
+
+/// A borrowed name.
+#[derive(Debug, Eq, Hash, Ord, PartialEq, PartialOrd, RefCast)]
+#[repr(transparent)]
+pub struct NameRef(str);
+
+impl Name {
+    /// Creates a new name.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    /// Returns this name as a `NameRef`.
+    pub fn as_name_ref(&self) -> &NameRef {
+        NameRef::new(&self.0)
+    }
+}
+
+impl NameRef {
+    /// Reinterprets a string as a `NameRef`, without copying it.
+    pub fn new(name: &str) -> &Self {
+        Self::ref_cast(name)
+    }
+
+    /// Returns the name as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for Name {
+    type Target = NameRef;
+
+    fn deref(&self) -> &NameRef {
+        self.as_name_ref()
+    }
+}
+
+impl Borrow<NameRef> for Name {
+    fn borrow(&self) -> &NameRef {
+        self.as_name_ref()
+    }
+}
+
+impl ToOwned for NameRef {
+    type Owned = Name;
+
+    fn to_owned(&self) -> Name {
+        Name::new(&self.0)
+    }
+}
+
+#[test]
+fn name_lookups() {
+    let mut hash_set: HashSet<Name> = HashSet::new();
+    hash_set.insert(Name::new("foo"));
+
+    assert!(hash_set.contains(NameRef::new("foo")));
+    assert!(!hash_set.contains(NameRef::new("bar")));
+    assert_eq!(NameRef::new("foo").to_owned(), Name::new("foo"));
+    assert_eq!(Name::new("foo").as_str(), "foo");
+
+    // A &NameRef is as big as a &str, as is a &dyn Key: the difference is that the second word of
+    // a &dyn Key is a vtable pointer.
+    assert_eq!(mem::size_of::<&NameRef>(), mem::size_of::<&str>());
+    assert_eq!(mem::size_of::<&NameRef>(), mem::size_of::<&dyn Key>());
+}
+
+proptest! {
+    #[test]
+    fn name_consistent_borrow(name1 in any::<String>(), name2 in any::<String>()) {
+        assert_consistent::<_, NameRef>(&Name::new(name1), &Name::new(name2));
+    }
+
+    #[test]
+    fn name_matches_trait_object(
+        names in prop::collection::vec("[ab]{0,2}", 0..8),
+        probe in "[ab]{0,2}",
+    ) {
+        // The same set, stored once as Names and once as OwnedKeys with no bytes, should answer
+        // every lookup the same way.
+        let by_name: HashSet<Name> = names.iter().map(Name::new).collect();
+        let by_key: HashSet<OwnedKey> = names
+            .iter()
+            .map(|s| OwnedKey { s: s.clone(), bytes: vec![] })
+            .collect();
+        let key = BorrowedKey { s: &probe, bytes: b"" };
+        prop_assert_eq!(
+            by_name.contains(NameRef::new(&probe)),
+            by_key.contains(&key as &dyn Key)
+        );
+    }
+}
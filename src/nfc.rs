@@ -0,0 +1,197 @@
+//! A key whose string is compared after Unicode normalization.
+//!
+//! Requires the `nfc` feature.
+
+use crate::consistency::assert_consistent;
+use crate::family::{KeyFamily, Lookup, Probe};
+use proptest::prelude::*;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashSet};
+use std::hash::{Hash, Hasher};
+use unicode_normalization::UnicodeNormalization;
+
+// "é" can be written as one code point (U+00E9), or as "e" followed by a combining acute accent
+// (U+0301). The two are canonically equivalent and look identical, but as strs they're different,
+// so a user who types one can't find a key stored as the other. NFC normalization picks the
+// composed form for both.
+//
+// The owned key normalizes once, when it's built. After that its string is already in NFC, so
+// plain string comparisons on it are correct, and the derived Eq and Ord can stay. The borrowed key
+// comes straight from user input and can't be normalized ahead of time without allocating, so it
+// normalizes on the fly while comparing. Since a char-by-char comparison orders strings the same
+// way a byte-by-byte one does, the two sides agree.
+//
+// Hash is the one impl that can't be derived. str's Hash hashes the raw bytes, and the borrowed
+// side has no normalized bytes to hand over, only normalized chars. So both sides hash chars.
+/// An owned key whose string is stored in NFC.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct NfcKey {
+    // Private, so that the string can't be replaced with one that isn't normalized.
+    s: String,
+    pub bytes: Vec<u8>,
+}
+
+/// The borrowed form of an [`NfcKey`]. The string doesn't need to be normalized.
+#[derive(Copy, Clone, Debug)]
+pub struct BorrowedNfcKey<'a> {
+    pub s: &'a str,
+    pub bytes: &'a [u8],
+}
+
+impl NfcKey {
+    /// Creates a new key, normalizing `s` to NFC.
+    pub fn new(s: &str, bytes: Vec<u8>) -> Self {
+        Self {
+            s: s.nfc().collect(),
+            bytes,
+        }
+    }
+
+    /// Returns the key's string, in NFC.
+    pub fn s(&self) -> &str {
+        &self.s
+    }
+
+    /// Returns a probe for looking up this key's borrowed form.
+    pub fn probe(key: BorrowedNfcKey<'_>) -> Probe<'_, Self> {
+        Probe(key)
+    }
+}
+
+impl Hash for NfcKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_key(self.s.chars(), &self.bytes, state)
+    }
+}
+
+impl<'a> PartialEq for BorrowedNfcKey<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.s.nfc().eq(other.s.nfc()) && self.bytes == other.bytes
+    }
+}
+
+impl<'a> Eq for BorrowedNfcKey<'a> {}
+
+impl<'a> PartialOrd for BorrowedNfcKey<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Ord for BorrowedNfcKey<'a> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.s
+            .nfc()
+            .cmp(other.s.nfc())
+            .then_with(|| self.bytes.cmp(other.bytes))
+    }
+}
+
+impl<'a> Hash for BorrowedNfcKey<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_key(self.s.nfc(), self.bytes, state)
+    }
+}
+
+fn hash_key<H: Hasher>(chars: impl Iterator<Item = char>, bytes: &[u8], state: &mut H) {
+    for c in chars {
+        c.hash(state);
+    }
+    // Mark the end of the string, like str's own Hash impl does.
+    state.write_u8(0xff);
+    bytes.hash(state);
+}
+
+impl KeyFamily for NfcKey {
+    type Borrowed<'a> = BorrowedNfcKey<'a>;
+
+    fn shorten<'long: 'short, 'short>(borrowed: BorrowedNfcKey<'long>) -> BorrowedNfcKey<'short> {
+        borrowed
+    }
+}
+
+impl Lookup<NfcKey> for NfcKey {
+    fn lookup_key(&self) -> BorrowedNfcKey<'_> {
+        BorrowedNfcKey {
+            s: &self.s,
+            bytes: &self.bytes,
+        }
+    }
+}
+
+impl<'a> Borrow<dyn Lookup<NfcKey> + 'a> for NfcKey {
+    fn borrow(&self) -> &(dyn Lookup<NfcKey> + 'a) {
+        self
+    }
+}
+
+impl Arbitrary for NfcKey {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        any::<(String, Vec<u8>)>()
+            .prop_map(|(s, bytes)| NfcKey::new(&s, bytes))
+            .boxed()
+    }
+}
+
+#[test]
+fn nfc_lookups() {
+    let mut hash_set = HashSet::new();
+    let mut btree_set = BTreeSet::new();
+    // Stored decomposed...
+    let key = NfcKey::new("cafe\u{301}", b"abc".to_vec());
+    assert_eq!(key.s(), "caf\u{e9}");
+    hash_set.insert(key.clone());
+    btree_set.insert(key);
+
+    // ... and found either way.
+    for s in ["caf\u{e9}", "cafe\u{301}"] {
+        let probe = NfcKey::probe(BorrowedNfcKey { s, bytes: b"abc" });
+        assert!(hash_set.contains(probe.as_lookup()));
+        assert!(btree_set.contains(probe.as_lookup()));
+    }
+}
+
+// Characters with several canonically equivalent spellings: precomposed letters, their
+// decompositions, and the Angstrom sign, which normalizes to "Å".
+#[cfg(test)]
+fn equivalent_strings() -> impl Strategy<Value = String> {
+    prop::collection::vec(
+        prop_oneof![
+            Just("e"),
+            Just("\u{e9}"),
+            Just("e\u{301}"),
+            Just("\u{c5}"),
+            Just("A\u{30a}"),
+            Just("\u{212b}"),
+            Just("\u{301}"),
+        ],
+        0..5,
+    )
+    .prop_map(|parts| parts.concat())
+}
+
+proptest! {
+    #[test]
+    fn nfc_consistent_borrow(owned1 in any::<NfcKey>(), owned2 in any::<NfcKey>()) {
+        assert_consistent::<_, dyn Lookup<NfcKey>>(&owned1, &owned2);
+    }
+
+    #[test]
+    fn nfc_equivalent_consistent_borrow(s1 in equivalent_strings(), s2 in equivalent_strings()) {
+        let owned1 = NfcKey::new(&s1, vec![]);
+        let owned2 = NfcKey::new(&s2, vec![]);
+        assert_consistent::<_, dyn Lookup<NfcKey>>(&owned1, &owned2);
+
+        // Probing with the unnormalized strings gives the same answers as the owned keys.
+        let probe1 = NfcKey::probe(BorrowedNfcKey { s: &s1, bytes: &[] });
+        let probe2 = NfcKey::probe(BorrowedNfcKey { s: &s2, bytes: &[] });
+        prop_assert_eq!(owned1 == owned2, probe1.as_lookup() == probe2.as_lookup());
+        prop_assert_eq!(owned1.cmp(&owned2), probe1.as_lookup().cmp(probe2.as_lookup()));
+        let hash_set: HashSet<_> = [owned1].into();
+        prop_assert!(hash_set.contains(probe1.as_lookup()));
+    }
+}
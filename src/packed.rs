@@ -0,0 +1,163 @@
+//! An owned key that keeps both of its fields in a single allocation.
+
+use crate::consistency::assert_consistent;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::any::Any;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+// Building an OwnedKey allocates twice, once for the String and once for the Vec, and the key
+// itself is six words long. CompactKey copies the string and the bytes into a single boxed buffer,
+// one after the other, and remembers where the string ends. That's one allocation, and three words.
+//
+// The catch is that the derives no longer work. "ab" + [] and "a" + [b'b'] have the same buffer, so
+// the split point matters for Eq, and comparing buffers byte by byte doesn't order keys the way
+// comparing (s, bytes) does either. So Eq, Ord and Hash all go through key(), which splits the
+// buffer back up into a BorrowedKey. That's consistent by construction.
+/// An owned key stored in a single allocation.
+#[derive(Clone)]
+pub struct CompactKey {
+    // The string, followed by the bytes. buf[..split] is always valid UTF-8.
+    buf: Box<[u8]>,
+    split: usize,
+}
+
+impl CompactKey {
+    /// Creates a new key by copying `s` and `bytes` into a single buffer.
+    pub fn new(s: &str, bytes: &[u8]) -> Self {
+        let mut buf = Vec::with_capacity(s.len() + bytes.len());
+        buf.extend_from_slice(s.as_bytes());
+        buf.extend_from_slice(bytes);
+        Self {
+            buf: buf.into_boxed_slice(),
+            split: s.len(),
+        }
+    }
+
+    /// Returns the string field.
+    pub fn s(&self) -> &str {
+        // SAFETY: buf[..split] is a copy of a &str, made in new().
+        unsafe { std::str::from_utf8_unchecked(&self.buf[..self.split]) }
+    }
+
+    /// Returns the bytes field.
+    pub fn bytes(&self) -> &[u8] {
+        &self.buf[self.split..]
+    }
+}
+
+impl Key for CompactKey {
+    fn key<'k>(&'k self) -> BorrowedKey<'k> {
+        BorrowedKey {
+            s: self.s(),
+            bytes: self.bytes(),
+        }
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+impl<'a> Borrow<dyn Key + 'a> for CompactKey {
+    fn borrow(&self) -> &(dyn Key + 'a) {
+        self
+    }
+}
+
+impl PartialEq for CompactKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for CompactKey {}
+
+impl PartialOrd for CompactKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CompactKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Hash for CompactKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl fmt::Debug for CompactKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompactKey")
+            .field("s", &self.s())
+            .field("bytes", &self.bytes())
+            .finish()
+    }
+}
+
+impl<'a> From<BorrowedKey<'a>> for CompactKey {
+    fn from(key: BorrowedKey<'a>) -> Self {
+        Self::new(key.s, key.bytes)
+    }
+}
+
+impl From<&OwnedKey> for CompactKey {
+    fn from(key: &OwnedKey) -> Self {
+        Self::from(key.key())
+    }
+}
+
+impl Arbitrary for CompactKey {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        any::<OwnedKey>()
+            .prop_map(|key| CompactKey::from(&key))
+            .boxed()
+    }
+}
+
+#[test]
+fn packed_lookups() {
+    let key = CompactKey::new("foo", b"abc");
+    assert_eq!(key.s(), "foo");
+    assert_eq!(key.bytes(), b"abc");
+    assert_eq!(
+        std::mem::size_of::<CompactKey>(),
+        3 * std::mem::size_of::<usize>()
+    );
+
+    let mut hash_set = HashSet::new();
+    hash_set.insert(key);
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert!(hash_set.contains(&borrowed_key as &dyn Key));
+
+    // Same buffer, different split.
+    assert_ne!(CompactKey::new("ab", b""), CompactKey::new("a", b"b"));
+}
+
+proptest! {
+    #[test]
+    fn packed_consistent_borrow(owned1 in any::<CompactKey>(), owned2 in any::<CompactKey>()) {
+        assert_consistent::<_, dyn Key>(&owned1, &owned2);
+    }
+
+    #[test]
+    fn packed_round_trip(owned in any::<OwnedKey>()) {
+        let compact = CompactKey::from(&owned);
+        prop_assert_eq!(compact.key(), owned.key());
+    }
+}
@@ -0,0 +1,125 @@
+//! A reusable two-field composite key.
+
+use crate::component::KeyComponent;
+use crate::consistency::assert_consistent;
+use crate::family::{KeyFamily, Lookup, Probe};
+use proptest::prelude::*;
+use proptest_derive::Arbitrary;
+use std::borrow::Borrow;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashSet};
+use std::ops::Bound;
+
+// Most composite keys are just two fields glued together. With KeyComponent describing how each
+// field borrows, and KeyFamily + Lookup providing the trait object impls, a two-field key needs no
+// custom code at all.
+//
+// The borrowed form is a tuple of the components' borrowed forms. Tuples compare lexicographically
+// and hash their elements in order, just like the derives on KeyPair do, so consistency comes down
+// to each component being consistent on its own.
+/// A composite key made of two components.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Arbitrary)]
+pub struct KeyPair<A, B> {
+    pub a: A,
+    pub b: B,
+}
+
+impl<A: KeyComponent, B: KeyComponent> KeyPair<A, B> {
+    /// Returns a probe for looking up the pair `(a, b)` without building a `KeyPair`.
+    pub fn probe<'a>(a: A::Borrowed<'a>, b: B::Borrowed<'a>) -> Probe<'a, Self> {
+        Probe((a, b))
+    }
+}
+
+impl<A: KeyComponent, B: KeyComponent> KeyFamily for KeyPair<A, B> {
+    type Borrowed<'a> = (A::Borrowed<'a>, B::Borrowed<'a>);
+
+    fn shorten<'long: 'short, 'short>((a, b): Self::Borrowed<'long>) -> Self::Borrowed<'short> {
+        (A::shorten(a), B::shorten(b))
+    }
+}
+
+impl<A: KeyComponent, B: KeyComponent> Lookup<KeyPair<A, B>> for KeyPair<A, B> {
+    fn lookup_key(&self) -> (A::Borrowed<'_>, B::Borrowed<'_>) {
+        (self.a.as_borrowed(), self.b.as_borrowed())
+    }
+}
+
+impl<'a, A, B> Borrow<dyn Lookup<KeyPair<A, B>> + 'a> for KeyPair<A, B>
+where
+    A: KeyComponent + 'a,
+    B: KeyComponent + 'a,
+{
+    fn borrow(&self) -> &(dyn Lookup<KeyPair<A, B>> + 'a) {
+        self
+    }
+}
+
+#[test]
+fn pair_lookups() {
+    let mut hash_set: HashSet<KeyPair<String, Vec<u8>>> = HashSet::new();
+    hash_set.insert(KeyPair {
+        a: "foo".to_string(),
+        b: b"abc".to_vec(),
+    });
+    let mut btree_set: BTreeSet<KeyPair<String, u32>> = BTreeSet::new();
+    btree_set.insert(KeyPair {
+        a: "foo".to_string(),
+        b: 42,
+    });
+
+    let probe = KeyPair::<String, Vec<u8>>::probe("foo", b"abc");
+    assert!(hash_set.contains(probe.as_lookup()));
+    assert!(btree_set.contains(KeyPair::<String, u32>::probe("foo", 42).as_lookup()));
+    assert!(!btree_set.contains(KeyPair::<String, u32>::probe("foo", 43).as_lookup()));
+}
+
+#[test]
+fn pair_reverse_lookups() {
+    // A leaderboard: highest score first, ties broken by name.
+    let mut leaderboard: BTreeSet<KeyPair<Reverse<u64>, String>> = BTreeSet::new();
+    for (score, name) in [(10, "carol"), (30, "alice"), (20, "bob"), (30, "dave")] {
+        leaderboard.insert(KeyPair {
+            a: Reverse(score),
+            b: name.to_string(),
+        });
+    }
+
+    let names: Vec<_> = leaderboard.iter().map(|key| key.b.as_str()).collect();
+    assert_eq!(names, ["alice", "dave", "bob", "carol"]);
+
+    let probe = KeyPair::<Reverse<u64>, String>::probe(Reverse(20), "bob");
+    assert!(leaderboard.contains(probe.as_lookup()));
+    // Range queries work with probes too: everyone from bob down.
+    let rest: Vec<_> = leaderboard
+        .range::<dyn Lookup<_>, _>((Bound::Included(probe.as_lookup()), Bound::Unbounded))
+        .map(|key| key.b.as_str())
+        .collect();
+    assert_eq!(rest, ["bob", "carol"]);
+}
+
+proptest! {
+    #[test]
+    fn pair_consistent_borrow(
+        owned1 in any::<KeyPair<String, Vec<u8>>>(),
+        owned2 in any::<KeyPair<String, Vec<u8>>>(),
+    ) {
+        assert_consistent::<_, dyn Lookup<KeyPair<String, Vec<u8>>>>(&owned1, &owned2);
+    }
+
+    #[test]
+    fn pair_copy_consistent_borrow(
+        owned1 in any::<KeyPair<Box<str>, i64>>(),
+        owned2 in any::<KeyPair<Box<str>, i64>>(),
+    ) {
+        assert_consistent::<_, dyn Lookup<KeyPair<Box<str>, i64>>>(&owned1, &owned2);
+    }
+
+    #[test]
+    fn pair_reverse_consistent_borrow(
+        owned1 in any::<KeyPair<Reverse<String>, Reverse<u32>>>(),
+        owned2 in any::<KeyPair<Reverse<String>, Reverse<u32>>>(),
+    ) {
+        assert_consistent::<_, dyn Lookup<KeyPair<Reverse<String>, Reverse<u32>>>>(&owned1, &owned2);
+    }
+}
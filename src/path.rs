@@ -0,0 +1,212 @@
+//! A key for paths that ignores insignificant differences in how they're written.
+
+use crate::consistency::assert_consistent;
+use crate::family::{KeyFamily, Lookup, Probe};
+use proptest::prelude::*;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+
+// A router wants "/a/b", "/a/b/", "/a//b" and "/a/./b" to all find the same route. What these have
+// in common is their list of segments, ["a", "b"], once empty and "." segments are dropped. So
+// that's what Eq, Ord and Hash look at, plus whether the path starts with a slash. (".." segments
+// are left alone: collapsing them changes which path is meant when symlinks are involved, and
+// routers usually reject them outright anyway.)
+//
+// It might be tempting to normalize the owned path once, and then compare owned paths as plain
+// strings. That works for Eq and Hash, but not for Ord: "/a-c" sorts before "/a/b" as a string
+// (since '-' < '/'), but after it segment by segment (since "a" < "a-c"). So, as with CaseFoldKey,
+// every impl is written once on the borrowed type, and the owned type forwards to it.
+/// An owned path key, compared segment by segment.
+#[derive(Clone, Debug)]
+pub struct PathKey {
+    pub path: String,
+}
+
+/// The borrowed form of a [`PathKey`].
+#[derive(Copy, Clone, Debug)]
+pub struct BorrowedPathKey<'a> {
+    pub path: &'a str,
+}
+
+impl<'a> BorrowedPathKey<'a> {
+    fn is_absolute(&self) -> bool {
+        self.path.starts_with('/')
+    }
+
+    fn segments(&self) -> impl Iterator<Item = &'a str> {
+        self.path
+            .split('/')
+            .filter(|segment| !segment.is_empty() && *segment != ".")
+    }
+}
+
+impl<'a> PartialEq for BorrowedPathKey<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_absolute() == other.is_absolute() && self.segments().eq(other.segments())
+    }
+}
+
+impl<'a> Eq for BorrowedPathKey<'a> {}
+
+impl<'a> PartialOrd for BorrowedPathKey<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Ord for BorrowedPathKey<'a> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.is_absolute()
+            .cmp(&other.is_absolute())
+            .then_with(|| self.segments().cmp(other.segments()))
+    }
+}
+
+impl<'a> Hash for BorrowedPathKey<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.is_absolute().hash(state);
+        // Each str hash ends with a marker byte, so ["ab"] and ["a", "b"] hash differently.
+        for segment in self.segments() {
+            segment.hash(state);
+        }
+    }
+}
+
+impl PathKey {
+    /// Returns a probe for looking up a path.
+    pub fn probe(path: &str) -> Probe<'_, Self> {
+        Probe(BorrowedPathKey { path })
+    }
+
+    /// Returns the path in its normalized form: no empty or `.` segments, and no trailing slash.
+    pub fn normalized(&self) -> String {
+        let key = self.lookup_key();
+        let mut out = String::with_capacity(self.path.len());
+        for (i, segment) in key.segments().enumerate() {
+            if i > 0 || key.is_absolute() {
+                out.push('/');
+            }
+            out.push_str(segment);
+        }
+        if out.is_empty() && key.is_absolute() {
+            out.push('/');
+        }
+        out
+    }
+}
+
+impl KeyFamily for PathKey {
+    type Borrowed<'a> = BorrowedPathKey<'a>;
+
+    fn shorten<'long: 'short, 'short>(borrowed: BorrowedPathKey<'long>) -> BorrowedPathKey<'short> {
+        borrowed
+    }
+}
+
+impl Lookup<PathKey> for PathKey {
+    fn lookup_key(&self) -> BorrowedPathKey<'_> {
+        BorrowedPathKey { path: &self.path }
+    }
+}
+
+impl<'a> Borrow<dyn Lookup<PathKey> + 'a> for PathKey {
+    fn borrow(&self) -> &(dyn Lookup<PathKey> + 'a) {
+        self
+    }
+}
+
+impl PartialEq for PathKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.lookup_key() == other.lookup_key()
+    }
+}
+
+impl Eq for PathKey {}
+
+impl PartialOrd for PathKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PathKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.lookup_key().cmp(&other.lookup_key())
+    }
+}
+
+impl Hash for PathKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.lookup_key().hash(state)
+    }
+}
+
+#[test]
+fn path_lookups() {
+    let mut routes = HashMap::new();
+    routes.insert(
+        PathKey {
+            path: "/users/./list/".to_string(),
+        },
+        "list users",
+    );
+
+    for path in [
+        "/users/list",
+        "/users/list/",
+        "//users//list",
+        "/./users/list/.",
+    ] {
+        assert_eq!(
+            routes.get(PathKey::probe(path).as_lookup()),
+            Some(&"list users"),
+            "{} found",
+            path
+        );
+    }
+    for path in [
+        "users/list",
+        "/users",
+        "/users/list/x",
+        "/users/../users/list",
+    ] {
+        assert_eq!(routes.get(PathKey::probe(path).as_lookup()), None);
+    }
+
+    let key = routes.keys().next().unwrap();
+    assert_eq!(key.normalized(), "/users/list");
+
+    // Paths sort by segment, not by raw string.
+    let mut sorted = BTreeSet::new();
+    for path in ["/a-c", "/a/b", "/a/"] {
+        sorted.insert(PathKey {
+            path: path.to_string(),
+        });
+    }
+    let sorted: Vec<_> = sorted.iter().map(|key| key.path.as_str()).collect();
+    assert_eq!(sorted, ["/a/", "/a/b", "/a-c"]);
+}
+
+// Paths over a tiny alphabet, with plenty of stray slashes and "." segments, so that different
+// spellings of the same path come up often.
+#[cfg(test)]
+fn messy_paths() -> impl Strategy<Value = PathKey> {
+    "/?((a|b|a-c|\\.|\\.\\.|)/){0,4}(a|b|\\.)?/?".prop_map(|path| PathKey { path })
+}
+
+proptest! {
+    #[test]
+    fn path_consistent_borrow(owned1 in messy_paths(), owned2 in messy_paths()) {
+        assert_consistent::<_, dyn Lookup<PathKey>>(&owned1, &owned2);
+    }
+
+    #[test]
+    fn path_normalized_equal(owned in messy_paths()) {
+        let normalized = PathKey { path: owned.normalized() };
+        prop_assert_eq!(&normalized, &owned);
+        prop_assert_eq!(normalized.normalized(), owned.normalized());
+        assert_consistent::<_, dyn Lookup<PathKey>>(&normalized, &owned);
+    }
+}
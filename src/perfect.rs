@@ -0,0 +1,354 @@
+//! Key sets with a perfect hash function, computed at compile time.
+
+use crate::static_table::cmp_keys;
+use crate::{BorrowedKey, Key};
+use core::cmp::Ordering;
+#[cfg(test)]
+use core::convert::TryInto;
+use core::fmt;
+use core::slice;
+#[cfg(test)]
+use proptest::prelude::*;
+
+// Keyword and registry tables are fixed when the program is compiled, and are then only ever asked
+// one question: is this key in the table? KeyTable answers it with a binary search. A perfect hash
+// function answers it with one hash and one comparison: it sends each of the table's keys to a slot
+// of its own, so a probe hashes to exactly one slot, and is in the set if and only if the key in
+// that slot is equal to it.
+//
+// The usual way to get one is a build script that runs a generator, like phf_codegen, and writes
+// out Rust source. Composite keys aren't among the key types those generators know about, and
+// const fn can now do all the work, so PerfectKeySet::new computes the function itself. In a static
+// or a const, that happens while compiling, and the binary only holds the result.
+//
+// The construction is "hash and displace", the one phf uses. Each key's hash is split three ways:
+// a bucket, and two numbers f1 and f2. A key's slot is (f1 * d1 + f2 + d2) % N, where (d1, d2)
+// is a displacement chosen per bucket. Buckets are placed largest first, and each gets the first
+// displacement that sends all of its keys to free slots. Buckets with one key always fit, since d2
+// alone can reach every slot. If a bigger bucket doesn't fit, the whole thing starts over with a
+// different seed for the hash. There are as many slots as keys, so the table has no empty slots.
+//
+// The hash is FNV-1a over the string's length, the string and the bytes, finished with a splitmix64
+// round. Including the length means ("ab", "c") and ("a", "bc") hash differently. The same const fn
+// hashes keys at compile time and probes at run time, so the two always agree. Two equal keys would
+// need the same slot, so new checks for duplicates first, and panics with a clear message rather
+// than trying seeds forever.
+//
+// Constructing the function takes time roughly quadratic in the number of keys, which is fine for
+// keyword tables of up to a few hundred keys. Beyond that, rustc's long_running_const_eval lint may
+// fire, and a build script or a runtime KeySet is a better fit.
+/// A set of static keys, looked up through a perfect hash function computed when the set is
+/// built.
+///
+/// Build one in a `static` or `const`, with [`perfect_key_set!`](crate::perfect_key_set) or
+/// [`PerfectKeySet::new`], so that the hash function is computed at compile time.
+pub struct PerfectKeySet<const N: usize> {
+    seed: u64,
+    displacements: [(u32, u32); N],
+    slots: [BorrowedKey<'static>; N],
+}
+
+// The number of seeds to try before giving up. Each one fails with a small probability, so running
+// out means something is badly wrong.
+const MAX_SEEDS: u64 = 1024;
+
+impl<const N: usize> PerfectKeySet<N> {
+    /// Builds a set of `keys`, computing its perfect hash function.
+    ///
+    /// # Panics
+    ///
+    /// Panics if two keys are equal. In a `static` or `const`, this is a compile error.
+    pub const fn new(keys: [BorrowedKey<'static>; N]) -> Self {
+        let mut i = 0;
+        while i < N {
+            let mut j = i + 1;
+            while j < N {
+                if matches!(cmp_keys(keys[i], keys[j]), Ordering::Equal) {
+                    panic!("perfect key set has duplicate keys");
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+
+        let mut seed = 0;
+        while seed < MAX_SEEDS {
+            if let Some(set) = Self::try_seed(&keys, seed) {
+                return set;
+            }
+            seed += 1;
+        }
+        panic!("couldn't find a perfect hash function for the keys");
+    }
+
+    const fn try_seed(keys: &[BorrowedKey<'static>; N], seed: u64) -> Option<Self> {
+        let mut set = Self {
+            seed,
+            displacements: [(0, 0); N],
+            slots: [BorrowedKey::new("", b""); N],
+        };
+        let mut hashes = [(0, 0, 0); N];
+        let mut bucket_sizes = [0; N];
+        let mut largest = 0;
+        let mut i = 0;
+        while i < N {
+            hashes[i] = split(hash_key(seed, keys[i]), N);
+            let bucket = hashes[i].0;
+            bucket_sizes[bucket] += 1;
+            if bucket_sizes[bucket] > largest {
+                largest = bucket_sizes[bucket];
+            }
+            i += 1;
+        }
+
+        // Which slots are taken by buckets already placed. Slots taken by the bucket being placed
+        // are marked with the attempt number, so that a bucket's own keys can't share a slot
+        // either.
+        let mut taken = [false; N];
+        let mut attempts = [0; N];
+        let mut attempt = 0;
+
+        let mut size = largest;
+        while size > 0 {
+            let mut bucket = 0;
+            while bucket < N {
+                if bucket_sizes[bucket] == size {
+                    let mut placed = false;
+                    let mut d1 = 0;
+                    while !placed && d1 < N as u32 {
+                        let mut d2 = 0;
+                        while !placed && d2 < N as u32 {
+                            attempt += 1;
+                            let mut fits = true;
+                            let mut i = 0;
+                            while fits && i < N {
+                                let (key_bucket, f1, f2) = hashes[i];
+                                if key_bucket == bucket {
+                                    let slot = slot(f1, f2, (d1, d2), N);
+                                    if taken[slot] || attempts[slot] == attempt {
+                                        fits = false;
+                                    }
+                                    attempts[slot] = attempt;
+                                }
+                                i += 1;
+                            }
+                            if fits {
+                                let mut i = 0;
+                                while i < N {
+                                    let (key_bucket, f1, f2) = hashes[i];
+                                    if key_bucket == bucket {
+                                        let slot = slot(f1, f2, (d1, d2), N);
+                                        taken[slot] = true;
+                                        set.slots[slot] = keys[i];
+                                    }
+                                    i += 1;
+                                }
+                                set.displacements[bucket] = (d1, d2);
+                                placed = true;
+                            }
+                            d2 += 1;
+                        }
+                        d1 += 1;
+                    }
+                    if !placed {
+                        return None;
+                    }
+                }
+                bucket += 1;
+            }
+            size -= 1;
+        }
+        Some(set)
+    }
+
+    /// Returns true if the set contains `key`. This hashes the key once, and compares it with at
+    /// most one stored key.
+    pub fn contains(&self, key: &dyn Key) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the stored key equal to `key`, if any.
+    pub fn get(&self, key: &dyn Key) -> Option<BorrowedKey<'static>> {
+        if N == 0 {
+            return None;
+        }
+        let key = key.key();
+        let (bucket, f1, f2) = split(hash_key(self.seed, key), N);
+        let stored = self.slots[slot(f1, f2, self.displacements[bucket], N)];
+        if stored == key {
+            Some(stored)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the number of keys.
+    pub const fn len(&self) -> usize {
+        N
+    }
+
+    /// Returns true if the set is empty.
+    pub const fn is_empty(&self) -> bool {
+        N == 0
+    }
+
+    /// Returns an iterator over the keys, in the order of their slots.
+    pub fn iter(&self) -> slice::Iter<'_, BorrowedKey<'static>> {
+        self.slots.iter()
+    }
+
+    /// Returns an iterator over copies of the keys, in the order of their slots.
+    pub fn iter_borrowed(&self) -> impl Iterator<Item = BorrowedKey<'static>> + '_ {
+        self.slots.iter().copied()
+    }
+}
+
+impl<const N: usize> fmt::Debug for PerfectKeySet<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.slots.iter()).finish()
+    }
+}
+
+impl<'a, const N: usize> IntoIterator for &'a PerfectKeySet<N> {
+    type Item = &'a BorrowedKey<'static>;
+    type IntoIter = slice::Iter<'a, BorrowedKey<'static>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+const fn hash_key(seed: u64, key: BorrowedKey<'_>) -> u64 {
+    let hash = 0xcbf2_9ce4_8422_2325 ^ seed;
+    let hash = fnv(hash, &(key.s.len() as u64).to_le_bytes());
+    let hash = fnv(hash, key.s.as_bytes());
+    splitmix64(fnv(hash, key.bytes))
+}
+
+const fn fnv(mut hash: u64, bytes: &[u8]) -> u64 {
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+        i += 1;
+    }
+    hash
+}
+
+const fn splitmix64(hash: u64) -> u64 {
+    let hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+// Splits a hash into a bucket, f1 and f2.
+const fn split(hash: u64, n: usize) -> (usize, u32, u32) {
+    let bucket = ((hash >> 32) % n as u64) as usize;
+    let f1 = hash as u32;
+    let f2 = (splitmix64(hash) >> 32) as u32;
+    (bucket, f1, f2)
+}
+
+const fn slot(f1: u32, f2: u32, (d1, d2): (u32, u32), n: usize) -> usize {
+    let slot = f1.wrapping_mul(d1).wrapping_add(f2).wrapping_add(d2);
+    (slot % n as u32) as usize
+}
+
+/// Builds a [`PerfectKeySet`](crate::perfect::PerfectKeySet) from `(s, bytes)` keys, in any
+/// order.
+///
+/// Used in a `static` or `const`, the perfect hash function is computed at compile time, and
+/// duplicate keys are a compile error:
+///
+/// ```
+/// use borrow_complex_key_example::perfect::PerfectKeySet;
+/// use borrow_complex_key_example::perfect_key_set;
+/// use borrow_complex_key_example::BorrowedKey;
+///
+/// static KEYWORDS: PerfectKeySet<3> = perfect_key_set! {
+///     ("fn", b"rust"),
+///     ("def", b"python"),
+///     ("func", b"go"),
+/// };
+///
+/// assert!(KEYWORDS.contains(&BorrowedKey::new("def", b"python")));
+/// assert!(!KEYWORDS.contains(&BorrowedKey::new("def", b"rust")));
+/// ```
+///
+/// ```compile_fail
+/// use borrow_complex_key_example::perfect::PerfectKeySet;
+/// use borrow_complex_key_example::perfect_key_set;
+///
+/// static KEYWORDS: PerfectKeySet<2> = perfect_key_set! {
+///     ("fn", b"rust"),
+///     ("fn", b"rust"),
+/// };
+/// ```
+#[macro_export]
+macro_rules! perfect_key_set {
+    ($(($s:expr, $bytes:expr)),* $(,)?) => {
+        $crate::perfect::PerfectKeySet::new([
+            $($crate::BorrowedKey { s: $s, bytes: $bytes }),*
+        ])
+    };
+}
+
+#[cfg(test)]
+static KEYWORDS: PerfectKeySet<8> = perfect_key_set! {
+    ("", b""),
+    ("ab", b"c"),
+    ("a", b"bc"),
+    ("fn", b"rust"),
+    ("fn", b"rus"),
+    ("def", b"python"),
+    ("func", b"go"),
+    ("\0", b"\0"),
+};
+
+#[test]
+fn perfect_lookups() {
+    for key in &KEYWORDS {
+        assert_eq!(KEYWORDS.get(key), Some(*key));
+    }
+    assert_eq!(KEYWORDS.len(), 8);
+    let owned_key = crate::OwnedKey {
+        s: "fn".to_string(),
+        bytes: b"rust".to_vec(),
+    };
+    assert!(KEYWORDS.contains(&owned_key));
+    assert!(!KEYWORDS.contains(&BorrowedKey::new("fn", b"rus\0")));
+    assert!(!KEYWORDS.contains(&BorrowedKey::new("abc", b"")));
+    assert!(KEYWORDS.iter_borrowed().eq(KEYWORDS.iter().copied()));
+
+    const EMPTY: PerfectKeySet<0> = perfect_key_set! {};
+    assert!(EMPTY.is_empty());
+    assert!(!EMPTY.contains(&owned_key));
+}
+
+#[test]
+#[should_panic(expected = "perfect key set has duplicate keys")]
+fn perfect_duplicates() {
+    // Built at run time, the check panics instead.
+    PerfectKeySet::new([BorrowedKey::new("fn", b""), BorrowedKey::new("fn", b"")]);
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn perfect_matches_hash_set(
+        keys in prop::collection::hash_set(any::<crate::OwnedKey>(), 32),
+        probes in prop::collection::vec(any::<crate::OwnedKey>(), 0..8),
+    ) {
+        let borrowed: Vec<_> = keys
+            .iter()
+            .map(|key| BorrowedKey::new(key.s.clone().leak(), key.bytes.clone().leak()))
+            .collect();
+        let set = PerfectKeySet::<32>::new(borrowed.try_into().unwrap());
+        for key in &keys {
+            prop_assert!(set.contains(key));
+        }
+        for probe in &probes {
+            prop_assert_eq!(set.contains(probe), keys.contains(probe));
+        }
+    }
+}
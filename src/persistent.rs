@@ -0,0 +1,448 @@
+//! A key map stored on disk in [redb](https://docs.rs/redb), keyed by the order-preserving
+//! encoding.
+//!
+//! Requires the `redb` feature.
+
+use crate::encoding::{self, DecodeError};
+#[cfg(test)]
+use crate::owned;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use redb::{
+    CommitError, Database, DatabaseError, OwnedAccessGuard, OwnedRange, ReadableDatabase,
+    ReadableTableMetadata, StorageError, TableDefinition, TableError, TransactionError,
+};
+use smallvec::SmallVec;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::str;
+
+// redb is an embedded key-value store, like a persistent BTreeMap<Vec<u8>, Vec<u8>>. Its keys are
+// byte strings, sorted byte by byte, so PersistentKeyMap stores each key in the encoding from
+// encoding.rs. The encoded keys sort the same way the keys do, so redb's range scans come out in
+// key order.
+//
+// A point lookup has to encode the key first. Most keys are short, so the encoding goes into a
+// SmallVec on the stack, and only spills to the heap for long keys.
+//
+// A range scan goes the other way, from encoded keys back to BorrowedKeys. The bytes field is the
+// tail of the encoding, so it can always be borrowed from redb's page. The string can be borrowed
+// too, unless it contains a 0x00: those are escaped in the encoding, so that string is decoded into
+// a String of its own. Either way, Entry::key returns a BorrowedKey pointing into the entry.
+//
+// Each insert and remove is its own write transaction. That keeps the API close to KeyMap's, at the
+// cost of a commit per write.
+/// A map from keys to byte strings, stored in a redb database.
+pub struct PersistentKeyMap {
+    db: Database,
+}
+
+const TABLE: TableDefinition<'static, &'static [u8], &'static [u8]> = TableDefinition::new("keys");
+
+/// A value in a [`PersistentKeyMap`], borrowed straight from the database.
+///
+/// Call `value()` to get the bytes. The guard keeps its read transaction open until it's dropped.
+pub type ValueGuard = OwnedAccessGuard<&'static [u8]>;
+
+// Most keys fit in this many bytes once encoded.
+type EncodeBuf = SmallVec<[u8; 128]>;
+
+fn encode(key: &dyn Key) -> EncodeBuf {
+    let mut buf = EncodeBuf::new();
+    encoding::encode_extend(key, &mut buf);
+    buf
+}
+
+impl PersistentKeyMap {
+    /// Opens the map stored in the database file at `path`, creating the file if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, PersistentMapError> {
+        Self::from_database(Database::create(path)?)
+    }
+
+    /// Creates a new, empty map that's only stored in memory.
+    pub fn in_memory() -> Result<Self, PersistentMapError> {
+        let db = Database::builder().create_with_backend(redb::backends::InMemoryBackend::new())?;
+        Self::from_database(db)
+    }
+
+    /// Stores the map in an already open database, alongside any tables it already has.
+    pub fn from_database(db: Database) -> Result<Self, PersistentMapError> {
+        // Create the table up front, so that reads don't have to handle it not existing.
+        let txn = db.begin_write()?;
+        txn.open_table(TABLE)?;
+        txn.commit()?;
+        Ok(Self { db })
+    }
+
+    /// Inserts a value for `key`, returning the old value if there was one.
+    pub fn insert(
+        &self,
+        key: &dyn Key,
+        value: &[u8],
+    ) -> Result<Option<Vec<u8>>, PersistentMapError> {
+        let txn = self.db.begin_write()?;
+        let old = {
+            let mut table = txn.open_table(TABLE)?;
+            let old = table.insert(&encode(key)[..], value)?;
+            old.map(|old| old.value().to_vec())
+        };
+        txn.commit()?;
+        Ok(old)
+    }
+
+    /// Removes the value for `key`, returning it if there was one.
+    pub fn remove(&self, key: &dyn Key) -> Result<Option<Vec<u8>>, PersistentMapError> {
+        let txn = self.db.begin_write()?;
+        let old = {
+            let mut table = txn.open_table(TABLE)?;
+            let old = table.remove(&encode(key)[..])?;
+            old.map(|old| old.value().to_vec())
+        };
+        txn.commit()?;
+        Ok(old)
+    }
+
+    /// Returns the value for `key`, if any, without copying it.
+    pub fn get(&self, key: &dyn Key) -> Result<Option<ValueGuard>, PersistentMapError> {
+        let table = self.db.begin_read()?.open_table(TABLE)?;
+        Ok(table.get_owned(&encode(key)[..])?)
+    }
+
+    /// Returns true if the map has a value for `key`.
+    pub fn contains_key(&self, key: &dyn Key) -> Result<bool, PersistentMapError> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    /// Iterates over the entries with keys in `range`, in key order.
+    pub fn range<'k, R: RangeBounds<&'k dyn Key>>(
+        &self,
+        range: R,
+    ) -> Result<Range, PersistentMapError> {
+        fn encode_bound(bound: Bound<&&dyn Key>) -> Bound<EncodeBuf> {
+            match bound {
+                Bound::Included(key) => Bound::Included(encode(*key)),
+                Bound::Excluded(key) => Bound::Excluded(encode(*key)),
+                Bound::Unbounded => Bound::Unbounded,
+            }
+        }
+
+        let start = encode_bound(range.start_bound());
+        let end = encode_bound(range.end_bound());
+        let table = self.db.begin_read()?.open_table(TABLE)?;
+        let inner = table.range_owned::<&[u8]>((
+            start.as_ref().map(|start| &start[..]),
+            end.as_ref().map(|end| &end[..]),
+        ))?;
+        Ok(Range { inner })
+    }
+
+    /// Iterates over every entry, in key order.
+    pub fn iter(&self) -> Result<Range, PersistentMapError> {
+        self.range::<(Bound<&dyn Key>, Bound<&dyn Key>)>((Bound::Unbounded, Bound::Unbounded))
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> Result<u64, PersistentMapError> {
+        let table = self.db.begin_read()?.open_table(TABLE)?;
+        Ok(table.len()?)
+    }
+
+    /// Returns true if the map is empty.
+    pub fn is_empty(&self) -> Result<bool, PersistentMapError> {
+        Ok(self.len()? == 0)
+    }
+}
+
+impl fmt::Debug for PersistentKeyMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PersistentKeyMap").finish_non_exhaustive()
+    }
+}
+
+/// An iterator over the entries of a [`PersistentKeyMap`], in key order.
+///
+/// Returned by [`PersistentKeyMap::range`] and [`PersistentKeyMap::iter`]. It and the entries it
+/// returns keep their read transaction open until they're all dropped.
+pub struct Range {
+    inner: OwnedRange<&'static [u8], &'static [u8]>,
+}
+
+impl Iterator for Range {
+    type Item = Result<Entry, PersistentMapError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = match self.inner.next()? {
+            Ok(entry) => entry,
+            Err(err) => return Some(Err(err.into())),
+        };
+        Some(Entry::new(key, value))
+    }
+}
+
+impl DoubleEndedIterator for Range {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, value) = match self.inner.next_back()? {
+            Ok(entry) => entry,
+            Err(err) => return Some(Err(err.into())),
+        };
+        Some(Entry::new(key, value))
+    }
+}
+
+impl fmt::Debug for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Range").finish_non_exhaustive()
+    }
+}
+
+/// An entry read from a [`PersistentKeyMap`], with its key decoded.
+pub struct Entry {
+    encoded: OwnedAccessGuard<&'static [u8]>,
+    value: ValueGuard,
+    // Where the string ends in the encoding, if it can be borrowed from it.
+    string_end: usize,
+    // The string, if it had to be decoded because it contains a 0x00.
+    decoded: Option<String>,
+}
+
+impl Entry {
+    fn new(
+        encoded: OwnedAccessGuard<&'static [u8]>,
+        value: ValueGuard,
+    ) -> Result<Self, PersistentMapError> {
+        let bytes_start = encoding::validate(encoded.value())?;
+        let string_end = bytes_start - 2;
+        let decoded = if encoded.value()[..string_end].contains(&0) {
+            Some(encoding::decode(encoded.value())?.s)
+        } else {
+            None
+        };
+        Ok(Self {
+            encoded,
+            value,
+            string_end,
+            decoded,
+        })
+    }
+
+    /// Returns the entry's key.
+    pub fn key(&self) -> BorrowedKey<'_> {
+        let encoded = self.encoded.value();
+        let s = match &self.decoded {
+            Some(s) => s,
+            None => str::from_utf8(&encoded[..self.string_end]).expect("validated in Entry::new"),
+        };
+        BorrowedKey {
+            s,
+            bytes: &encoded[self.string_end + 2..],
+        }
+    }
+
+    /// Returns the entry's value.
+    pub fn value(&self) -> &[u8] {
+        self.value.value()
+    }
+}
+
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Entry")
+            .field("key", &self.key())
+            .field("value", &self.value())
+            .finish()
+    }
+}
+
+/// An error returned by [`PersistentKeyMap`].
+#[derive(Debug)]
+pub enum PersistentMapError {
+    /// The database returned an error.
+    Storage(redb::Error),
+    /// A key stored in the database isn't a valid encoding. This means that something other than
+    /// `PersistentKeyMap` wrote to its table.
+    Decode(DecodeError),
+}
+
+impl fmt::Display for PersistentMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistentMapError::Storage(_) => write!(f, "database error"),
+            PersistentMapError::Decode(_) => write!(f, "invalid key in database"),
+        }
+    }
+}
+
+impl Error for PersistentMapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PersistentMapError::Storage(err) => Some(err),
+            PersistentMapError::Decode(err) => Some(err),
+        }
+    }
+}
+
+impl From<DecodeError> for PersistentMapError {
+    fn from(err: DecodeError) -> Self {
+        PersistentMapError::Decode(err)
+    }
+}
+
+// redb has a separate error type for each kind of operation, and each one converts into
+// redb::Error.
+impl From<redb::Error> for PersistentMapError {
+    fn from(err: redb::Error) -> Self {
+        PersistentMapError::Storage(err)
+    }
+}
+
+impl From<DatabaseError> for PersistentMapError {
+    fn from(err: DatabaseError) -> Self {
+        PersistentMapError::Storage(err.into())
+    }
+}
+
+impl From<TransactionError> for PersistentMapError {
+    fn from(err: TransactionError) -> Self {
+        PersistentMapError::Storage(err.into())
+    }
+}
+
+impl From<TableError> for PersistentMapError {
+    fn from(err: TableError) -> Self {
+        PersistentMapError::Storage(err.into())
+    }
+}
+
+impl From<StorageError> for PersistentMapError {
+    fn from(err: StorageError) -> Self {
+        PersistentMapError::Storage(err.into())
+    }
+}
+
+impl From<CommitError> for PersistentMapError {
+    fn from(err: CommitError) -> Self {
+        PersistentMapError::Storage(err.into())
+    }
+}
+
+#[test]
+fn persistent_lookups() {
+    let map = PersistentKeyMap::in_memory().unwrap();
+    assert_eq!(map.insert(&owned("foo", b"abc"), b"1").unwrap(), None);
+    assert_eq!(
+        map.insert(&owned("foo", b"abc"), b"2").unwrap(),
+        Some(b"1".to_vec())
+    );
+    map.insert(&owned("foo", b""), b"3").unwrap();
+    map.insert(&owned("foo\0bar", b"\0"), b"4").unwrap();
+    map.insert(&owned("bar", b"x"), b"5").unwrap();
+    assert_eq!(map.len().unwrap(), 4);
+
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert_eq!(map.get(&borrowed_key).unwrap().unwrap().value(), b"2");
+    assert!(!map.contains_key(&owned("foo", b"ab")).unwrap());
+
+    // Every key with the string "foo", in order, decoded back into borrowed keys.
+    let start = owned("foo", b"");
+    let end = owned("foo\0", b"");
+    let foos: Vec<_> = map
+        .range(&start as &dyn Key..&end)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            (entry.key().bytes.to_vec(), entry.value().to_vec())
+        })
+        .collect();
+    assert_eq!(
+        foos,
+        [
+            (b"".to_vec(), b"3".to_vec()),
+            (b"abc".to_vec(), b"2".to_vec())
+        ]
+    );
+
+    // A string with a 0x00 in it is decoded.
+    let last = map.iter().unwrap().next_back().unwrap().unwrap();
+    assert_eq!(last.key(), owned("foo\0bar", b"\0").key());
+
+    assert_eq!(map.remove(&borrowed_key).unwrap(), Some(b"2".to_vec()));
+    assert_eq!(map.remove(&borrowed_key).unwrap(), None);
+    assert_eq!(map.len().unwrap(), 3);
+}
+
+#[test]
+fn persistent_reopen() {
+    let path = std::env::temp_dir().join(format!(
+        "borrow-complex-key-example-{}.redb",
+        std::process::id()
+    ));
+    {
+        let map = PersistentKeyMap::open(&path).unwrap();
+        map.insert(&owned("foo", b"abc"), b"1").unwrap();
+    }
+    let map = PersistentKeyMap::open(&path).unwrap();
+    let value = map
+        .get(&owned("foo", b"abc"))
+        .unwrap()
+        .map(|v| v.value().to_vec());
+    drop(map);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(value, Some(b"1".to_vec()));
+}
+
+#[cfg(test)]
+fn persistent_keys() -> impl Strategy<Value = OwnedKey> {
+    // Include 0x00s, which are escaped in the encoding, and long keys, which don't fit in the
+    // stack buffer.
+    prop_oneof![
+        (
+            "[\0ab]{0,3}",
+            prop::collection::vec(prop_oneof![Just(0u8), Just(1), Just(0xff)], 0..3),
+        )
+            .prop_map(|(s, bytes)| OwnedKey { s, bytes }),
+        any::<OwnedKey>(),
+    ]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn persistent_matches_btree_map(
+        entries in prop::collection::vec((persistent_keys(), any::<u8>()), 0..16),
+        bounds in (persistent_keys(), persistent_keys()),
+    ) {
+        let map = PersistentKeyMap::in_memory().unwrap();
+        let mut model = BTreeMap::new();
+        for (key, value) in entries {
+            let expected = model.insert(key.clone(), value).map(|old| vec![old]);
+            prop_assert_eq!(map.insert(&key, &[value]).unwrap(), expected);
+        }
+        prop_assert_eq!(map.len().unwrap(), model.len() as u64);
+        for (key, value) in &model {
+            let stored = map.get(&key.key()).unwrap().unwrap();
+            prop_assert_eq!(stored.value(), &[*value][..]);
+        }
+
+        let (start, end) = bounds;
+        if start <= end {
+            let scanned: Vec<_> = map
+                .range(&start as &dyn Key..&end)
+                .unwrap()
+                .map(|entry| {
+                    let entry = entry.unwrap();
+                    let key = entry.key();
+                    (OwnedKey { s: key.s.to_owned(), bytes: key.bytes.to_owned() }, entry.value()[0])
+                })
+                .collect();
+            let expected: Vec<_> = model.range(start..end).map(|(k, &v)| (k.clone(), v)).collect();
+            prop_assert_eq!(scanned, expected);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Hashing keys the same way on 32-bit and 64-bit targets.
+
+use core::hash::{BuildHasher, Hasher};
+#[cfg(test)]
+use proptest::prelude::*;
+
+// A hash is only as portable as the writes that go into it. Hashers get integers through
+// write_usize, write_u32 and friends, and how those turn into bytes is up to the hasher: most
+// hash the integer's native-endian bytes, at its native width. A usize is 4 bytes on a 32-bit
+// target and 8 on a 64-bit one, so a key with a usize in it hashes differently on each, even
+// under a hasher with fixed keys. So does any key with a byte string in it, since a slice's
+// length goes to the hasher as a usize. For a hash table that lives in one process, that doesn't
+// matter. For hashes that get stored, or sent to a wasm plugin, or compared between a server and
+// an embedded device, it does.
+//
+// PortableHasher sits in front of any other hasher and rewrites every integer into a fixed form
+// before passing it on: usize and isize are widened to 64 bits, and everything is written as
+// little-endian bytes through write. What the inner hasher sees then depends only on the values
+// being hashed, never on the target. BuildPortableHasher does the same for a BuildHasher, so it
+// can be used as the hasher of any of the crate's containers.
+//
+// Wrapping the hasher makes a portable hash out of any Hash impl, but only where the wrapper is
+// used. #[borrow_key(portable_hash)] on a derived key builds it into the key instead: the owned
+// type, the borrowed type and the trait object all hash through a PortableHasher, whatever hasher
+// they're given.
+/// A hasher that writes integers to `H` at fixed widths and in a fixed byte order, so that a value
+/// hashes the same way on every target.
+///
+/// `usize` and `isize` are written as 64-bit integers. Every integer is written through
+/// [`Hasher::write`], as little-endian bytes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PortableHasher<H> {
+    inner: H,
+}
+
+impl<H: Hasher> PortableHasher<H> {
+    /// Wraps `inner`.
+    ///
+    /// `inner` can be a `&mut H`, to hash portably into a hasher that's been passed in.
+    pub const fn new(inner: H) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the inner hasher.
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<H: Hasher> Hasher for PortableHasher<H> {
+    fn finish(&self) -> u64 {
+        self.inner.finish()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.inner.write(bytes);
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.inner.write(&[i]);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.inner.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.inner.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.inner.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.inner.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i8(&mut self, i: i8) {
+        self.write_u8(i as u8);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_i64(i as i64);
+    }
+}
+
+/// A [`BuildHasher`] whose hashers are [`PortableHasher`]s, wrapping the hashers built by `S`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BuildPortableHasher<S> {
+    inner: S,
+}
+
+impl<S: BuildHasher> BuildPortableHasher<S> {
+    /// Wraps `inner`.
+    pub const fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the inner `BuildHasher`.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: BuildHasher> BuildHasher for BuildPortableHasher<S> {
+    type Hasher = PortableHasher<S::Hasher>;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        PortableHasher::new(self.inner.build_hasher())
+    }
+}
+
+#[cfg(test)]
+fn portable_bytes<T: core::hash::Hash + ?Sized>(value: &T) -> Vec<u8> {
+    // Every write from a PortableHasher goes through write, so concatenating them is a complete
+    // record of what the inner hasher saw.
+    #[derive(Default)]
+    struct Concat(Vec<u8>);
+
+    impl Hasher for Concat {
+        fn finish(&self) -> u64 {
+            0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            self.0.extend_from_slice(bytes);
+        }
+    }
+
+    let mut hasher = PortableHasher::new(Concat::default());
+    value.hash(&mut hasher);
+    hasher.into_inner().0
+}
+
+#[test]
+fn portable_writes() {
+    use crate::consistency::RecordingHasher;
+    use crate::BorrowedKey;
+    use core::hash::Hash;
+
+    // The inner hasher only ever sees write, whatever the value wrote.
+    let mut hasher = PortableHasher::new(RecordingHasher::default());
+    (1u8, 2u16, 3usize, -4isize, 5u128).hash(&mut hasher);
+    let methods: Vec<_> = hasher
+        .inner
+        .writes
+        .iter()
+        .map(|(method, _)| *method)
+        .collect();
+    assert_eq!(methods, ["write"; 5]);
+
+    // The exact bytes for a key. These are the same on every target: the wasm tests check them
+    // on a 32-bit one.
+    let key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert_eq!(
+        portable_bytes(&key),
+        b"foo\xff\x03\0\0\0\0\0\0\0abc",
+        "str, then its terminator, then the length of the byte string as a u64, then its bytes"
+    );
+}
+
+#[test]
+fn portable_build_hasher() {
+    use std::collections::hash_map::RandomState;
+    use std::collections::HashSet;
+
+    let mut set: HashSet<usize, _> =
+        HashSet::with_hasher(BuildPortableHasher::new(RandomState::new()));
+    set.insert(7);
+    assert!(set.contains(&7));
+
+    // With the same inner hasher, a usize hashes like the u64 it's widened to.
+    let build_hasher = BuildPortableHasher::new(RandomState::new());
+    assert_eq!(build_hasher.hash_one(7usize), build_hasher.hash_one(7u64));
+}
+
+#[cfg(test)]
+proptest! {
+    // On a 32-bit target, a usize holds at most a u32, which a PortableHasher writes as the same
+    // u64 that a 64-bit target would write. The same goes for isize and i32.
+    #[test]
+    fn portable_cross_width(n in any::<u32>(), i in any::<i32>()) {
+        prop_assert_eq!(portable_bytes(&(n as usize)), portable_bytes(&u64::from(n)));
+        prop_assert_eq!(portable_bytes(&(i as isize)), portable_bytes(&i64::from(i)));
+        prop_assert_eq!(portable_bytes(&(n as usize)), u64::from(n).to_le_bytes().to_vec());
+    }
+
+    // Slice lengths are usizes, so a byte string hashes the same way on every target too.
+    #[test]
+    fn portable_slice_lengths(bytes in prop::collection::vec(any::<u8>(), 0..16)) {
+        let mut expected = (bytes.len() as u64).to_le_bytes().to_vec();
+        expected.extend_from_slice(&bytes);
+        prop_assert_eq!(portable_bytes(&bytes[..]), expected);
+    }
+}
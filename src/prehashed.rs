@@ -0,0 +1,239 @@
+//! Keys that carry their hash around with them.
+
+use crate::consistency::assert_consistent;
+use crate::family::{KeyFamily, Lookup, Probe};
+use crate::pair::KeyPair;
+use proptest::prelude::*;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+
+// Hashing a long key on every lookup adds up in a hot map, and rehashing every key whenever the map
+// grows adds up too. If the key hashes once, when it's built, and keeps the result, every later
+// hash is just writing out a u64.
+//
+// The hash has to come from somewhere, so a PrehashedKey is built with a BuildHasher (usually a
+// RandomState that lives next to the map), and probes are built with the same one. The map itself
+// then uses PassThroughHasher, which returns the stored hash as-is. (Any other hasher works too,
+// it just hashes the stored u64 again.)
+//
+// The borrowed form is a Hashed: the probe's key along with its hash, computed on the fly. Both
+// sides hash by writing out their stored hash, and compare their hashes before their keys. That's
+// what makes them consistent: equal keys hashed by the same BuildHasher get equal hashes, and if a
+// probe is accidentally built with a different BuildHasher, it's simply not equal to anything,
+// rather than being equal to a key that it hashes differently from.
+/// A key of the family `K`, with its hash precomputed.
+pub struct PrehashedKey<K> {
+    hash: u64,
+    key: K,
+}
+
+/// A value along with its precomputed hash. This is the borrowed form of a [`PrehashedKey`].
+///
+/// Ordering is by hash first. That's arbitrary, but cheap.
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Hashed<T> {
+    pub hash: u64,
+    pub value: T,
+}
+
+impl<T> Hash for Hashed<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash)
+    }
+}
+
+impl<K: KeyFamily + Lookup<K>> PrehashedKey<K> {
+    /// Hashes `key` with `build_hasher`, and stores the result along with it.
+    pub fn new(key: K, build_hasher: &impl BuildHasher) -> Self {
+        Self {
+            hash: build_hasher.hash_one(key.lookup_key()),
+            key,
+        }
+    }
+
+    /// Returns a probe for looking up `key`. `build_hasher` must be the one that the stored keys
+    /// were built with.
+    pub fn probe<'a>(
+        key: K::Borrowed<'a>,
+        build_hasher: &impl BuildHasher,
+    ) -> Probe<'a, PrehashedKey<K>> {
+        Probe(Hashed {
+            hash: build_hasher.hash_one(key),
+            value: key,
+        })
+    }
+
+    /// Returns the precomputed hash.
+    pub fn hash_value(&self) -> u64 {
+        self.hash
+    }
+
+    /// Returns the key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns the key, discarding the hash.
+    pub fn into_inner(self) -> K {
+        self.key
+    }
+}
+
+impl<K: KeyFamily> KeyFamily for PrehashedKey<K> {
+    type Borrowed<'a> = Hashed<K::Borrowed<'a>>;
+
+    fn shorten<'long: 'short, 'short>(
+        borrowed: Hashed<K::Borrowed<'long>>,
+    ) -> Hashed<K::Borrowed<'short>> {
+        Hashed {
+            hash: borrowed.hash,
+            value: K::shorten(borrowed.value),
+        }
+    }
+}
+
+impl<K: KeyFamily + Lookup<K>> Lookup<PrehashedKey<K>> for PrehashedKey<K> {
+    fn lookup_key(&self) -> Hashed<K::Borrowed<'_>> {
+        Hashed {
+            hash: self.hash,
+            value: self.key.lookup_key(),
+        }
+    }
+}
+
+impl<'a, K: KeyFamily + Lookup<K> + 'a> Borrow<dyn Lookup<PrehashedKey<K>> + 'a>
+    for PrehashedKey<K>
+{
+    fn borrow(&self) -> &(dyn Lookup<PrehashedKey<K>> + 'a) {
+        self
+    }
+}
+
+// As with CaseFoldKey, the owned key forwards everything to its borrowed form.
+impl<K: KeyFamily + Lookup<K>> PartialEq for PrehashedKey<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.lookup_key() == other.lookup_key()
+    }
+}
+
+impl<K: KeyFamily + Lookup<K>> Eq for PrehashedKey<K> {}
+
+impl<K: KeyFamily + Lookup<K>> PartialOrd for PrehashedKey<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: KeyFamily + Lookup<K>> Ord for PrehashedKey<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.lookup_key().cmp(&other.lookup_key())
+    }
+}
+
+impl<K> Hash for PrehashedKey<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash)
+    }
+}
+
+impl<K: Clone> Clone for PrehashedKey<K> {
+    fn clone(&self) -> Self {
+        Self {
+            hash: self.hash,
+            key: self.key.clone(),
+        }
+    }
+}
+
+impl<K: fmt::Debug> fmt::Debug for PrehashedKey<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrehashedKey")
+            .field("hash", &self.hash)
+            .field("key", &self.key)
+            .finish()
+    }
+}
+
+/// A hasher that passes a precomputed hash through unchanged.
+///
+/// # Panics
+///
+/// Panics if asked to hash anything other than a single `u64`, such as a key that isn't a
+/// [`PrehashedKey`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PassThroughHasher(u64);
+
+impl Hasher for PassThroughHasher {
+    fn write(&mut self, _bytes: &[u8]) {
+        panic!("PassThroughHasher can only hash PrehashedKeys");
+    }
+
+    fn write_u64(&mut self, hash: u64) {
+        self.0 = hash;
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A `BuildHasher` for maps keyed by [`PrehashedKey`].
+pub type BuildPassThroughHasher = BuildHasherDefault<PassThroughHasher>;
+
+#[cfg(test)]
+type TestKey = KeyPair<String, Vec<u8>>;
+
+#[test]
+fn prehashed_lookups() {
+    let build_hasher = RandomState::new();
+    let mut map: HashMap<PrehashedKey<TestKey>, u32, BuildPassThroughHasher> = HashMap::default();
+    let key = KeyPair {
+        a: "foo".to_string(),
+        b: b"abc".to_vec(),
+    };
+    map.insert(PrehashedKey::new(key, &build_hasher), 42);
+
+    let probe = PrehashedKey::<TestKey>::probe(("foo", b"abc"), &build_hasher);
+    assert_eq!(map.get(probe.as_lookup()), Some(&42));
+    let probe = PrehashedKey::<TestKey>::probe(("foo", b"abd"), &build_hasher);
+    assert_eq!(map.get(probe.as_lookup()), None);
+
+    // A probe hashed by a different BuildHasher doesn't find anything.
+    let probe = PrehashedKey::<TestKey>::probe(("foo", b"abc"), &RandomState::new());
+    assert_eq!(map.get(probe.as_lookup()), None);
+}
+
+proptest! {
+    #[test]
+    fn prehashed_consistent_borrow(
+        owned1 in any::<TestKey>(),
+        owned2 in any::<TestKey>(),
+    ) {
+        let build_hasher = RandomState::new();
+        let owned1 = PrehashedKey::new(owned1, &build_hasher);
+        let owned2 = PrehashedKey::new(owned2, &build_hasher);
+        assert_consistent::<_, dyn Lookup<PrehashedKey<TestKey>>>(&owned1, &owned2);
+    }
+
+    #[test]
+    fn prehashed_insert_lookup(keys in prop::collection::vec(any::<TestKey>(), 0..16)) {
+        let build_hasher = RandomState::new();
+        let mut prehashed: HashMap<_, _, BuildPassThroughHasher> = HashMap::default();
+        let mut plain = HashMap::new();
+        for (i, key) in keys.iter().enumerate() {
+            prehashed.insert(PrehashedKey::new(key.clone(), &build_hasher), i);
+            plain.insert(key.clone(), i);
+        }
+
+        // Every key finds the same value as it would in an ordinary map.
+        prop_assert_eq!(prehashed.len(), plain.len());
+        for key in &keys {
+            let probe = PrehashedKey::<TestKey>::probe(key.lookup_key(), &build_hasher);
+            prop_assert_eq!(prehashed.get(probe.as_lookup()), plain.get(key));
+        }
+    }
+}
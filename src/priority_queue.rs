@@ -0,0 +1,403 @@
+//! A priority queue whose entries can be found, reprioritized and removed by key.
+
+use crate::family::{KeyFamily, Lookup, Probe};
+#[cfg(test)]
+use crate::owned;
+use crate::pair::KeyPair;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+
+// A scheduler keeps its jobs in priority order, but it also has to find a particular job again:
+// to bump its priority when a user asks for it, or to cancel it. BinaryHeap can do the first but
+// not the second, since nothing in a heap can be found without scanning it.
+//
+// KeyPriorityQueue is a binary min-heap that also keeps a map from each key to its position in the
+// heap, updated whenever an entry moves. Finding an entry by key is a hash lookup, and changing
+// its priority or removing it is then the usual O(log n) sift from wherever it is. Lookups take
+// &dyn Lookup<K>, so a job keyed by a (name, id) pair can be found with a probe, without
+// allocating a name. A queue of the crate root's OwnedKeys can also be addressed by &dyn Key, as
+// every other container of OwnedKeys can.
+//
+// Entries with equal priorities come out in key order, so that the order entries are popped in
+// doesn't depend on the order they were pushed in, or on the hasher.
+//
+// As with KeyIndexMap, each key is stored twice, once in the heap and once in the map, so keys are
+// cloned on push.
+/// A min-priority queue of keys in the family `K`, with priorities of type `P`, in which entries
+/// can be looked up, reprioritized and removed by key.
+#[derive(Clone, Debug)]
+pub struct KeyPriorityQueue<K, P, S = RandomState> {
+    heap: Vec<(K, P)>,
+    positions: HashMap<K, usize, S>,
+}
+
+impl<K, P, S: Default> Default for KeyPriorityQueue<K, P, S> {
+    fn default() -> Self {
+        Self {
+            heap: Vec::new(),
+            positions: HashMap::default(),
+        }
+    }
+}
+
+impl<K, P> KeyPriorityQueue<K, P>
+where
+    K: KeyFamily + Lookup<K> + Clone + Eq + Hash + for<'a> Borrow<dyn Lookup<K> + 'a>,
+    P: Ord,
+{
+    /// Creates a new, empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<K, P, S> KeyPriorityQueue<K, P, S>
+where
+    K: KeyFamily + Lookup<K> + Clone + Eq + Hash + for<'a> Borrow<dyn Lookup<K> + 'a>,
+    P: Ord,
+    S: BuildHasher,
+{
+    /// Creates a new, empty queue that uses `hasher` to hash keys.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            heap: Vec::new(),
+            positions: HashMap::with_hasher(hasher),
+        }
+    }
+
+    /// Adds `key` with `priority`. If `key` is already in the queue, its priority is changed
+    /// instead, and the old priority is returned.
+    pub fn push(&mut self, key: K, priority: P) -> Option<P> {
+        let lookup: &dyn Lookup<K> = key.borrow();
+        if self.positions.contains_key(lookup) {
+            return self.change_priority(lookup, priority);
+        }
+        let position = self.heap.len();
+        self.positions.insert(key.clone(), position);
+        self.heap.push((key, priority));
+        self.sift_up(position);
+        None
+    }
+
+    /// Returns the entry with the lowest priority, without removing it.
+    pub fn peek_min(&self) -> Option<(&K, &P)> {
+        self.heap.first().map(|(key, priority)| (key, priority))
+    }
+
+    /// Removes and returns the entry with the lowest priority.
+    pub fn pop_min(&mut self) -> Option<(K, P)> {
+        self.remove_at(0)
+    }
+
+    /// Returns the priority of `key`, if it's in the queue.
+    pub fn get_priority(&self, key: &dyn Lookup<K>) -> Option<&P> {
+        let position = *self.positions.get(key)?;
+        Some(&self.heap[position].1)
+    }
+
+    /// Returns true if `key` is in the queue.
+    pub fn contains_key(&self, key: &dyn Lookup<K>) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    /// Changes the priority of `key`, returning its old priority, or `None` if it isn't in the
+    /// queue.
+    pub fn change_priority(&mut self, key: &dyn Lookup<K>, priority: P) -> Option<P> {
+        let position = *self.positions.get(key)?;
+        let old = std::mem::replace(&mut self.heap[position].1, priority);
+        self.restore(position);
+        Some(old)
+    }
+
+    /// Removes `key`, returning it and its priority.
+    pub fn remove(&mut self, key: &dyn Lookup<K>) -> Option<(K, P)> {
+        let position = *self.positions.get(key)?;
+        self.remove_at(position)
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// Returns true if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Returns an iterator over the entries, in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &P)> {
+        self.heap.iter().map(|(key, priority)| (key, priority))
+    }
+
+    fn remove_at(&mut self, position: usize) -> Option<(K, P)> {
+        if position >= self.heap.len() {
+            return None;
+        }
+        let removed = self.heap.swap_remove(position);
+        let lookup: &dyn Lookup<K> = removed.0.borrow();
+        self.positions.remove(lookup);
+        if position < self.heap.len() {
+            self.set_position(position);
+            self.restore(position);
+        }
+        Some(removed)
+    }
+
+    // Moves the entry at `position` up or down, whichever its priority calls for.
+    fn restore(&mut self, position: usize) {
+        let position = self.sift_up(position);
+        self.sift_down(position);
+    }
+
+    fn sift_up(&mut self, mut position: usize) -> usize {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if !self.less(position, parent) {
+                break;
+            }
+            self.swap(position, parent);
+            position = parent;
+        }
+        position
+    }
+
+    fn sift_down(&mut self, mut position: usize) {
+        loop {
+            let mut smallest = position;
+            for child in [2 * position + 1, 2 * position + 2] {
+                if child < self.heap.len() && self.less(child, smallest) {
+                    smallest = child;
+                }
+            }
+            if smallest == position {
+                return;
+            }
+            self.swap(position, smallest);
+            position = smallest;
+        }
+    }
+
+    // Ties on priority are broken by key, so that the order is fully determined.
+    fn less(&self, a: usize, b: usize) -> bool {
+        let (a_key, a_priority) = &self.heap[a];
+        let (b_key, b_priority) = &self.heap[b];
+        (a_priority, a_key.lookup_key()) < (b_priority, b_key.lookup_key())
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.set_position(a);
+        self.set_position(b);
+    }
+
+    fn set_position(&mut self, position: usize) {
+        let key: &dyn Lookup<K> = self.heap[position].0.borrow();
+        *self
+            .positions
+            .get_mut(key)
+            .expect("every key in the heap is in positions") = position;
+    }
+}
+
+impl<P, S> KeyPriorityQueue<OwnedKey, P, S>
+where
+    P: Ord,
+    S: BuildHasher,
+{
+    /// Returns the priority of `key`, if it's in the queue.
+    pub fn get_priority_by_key(&self, key: &dyn Key) -> Option<&P> {
+        self.get_priority(Probe::<OwnedKey>(key.key()).as_lookup())
+    }
+
+    /// Returns true if `key` is in the queue.
+    pub fn contains_by_key(&self, key: &dyn Key) -> bool {
+        self.contains_key(Probe::<OwnedKey>(key.key()).as_lookup())
+    }
+
+    /// Changes the priority of `key`, returning its old priority, or `None` if it isn't in the
+    /// queue.
+    pub fn change_priority_by_key(&mut self, key: &dyn Key, priority: P) -> Option<P> {
+        self.change_priority(Probe::<OwnedKey>(key.key()).as_lookup(), priority)
+    }
+
+    /// Removes `key`, returning it and its priority.
+    pub fn remove_by_key(&mut self, key: &dyn Key) -> Option<(OwnedKey, P)> {
+        self.remove(Probe::<OwnedKey>(key.key()).as_lookup())
+    }
+}
+
+#[cfg(test)]
+impl<K, P, S> KeyPriorityQueue<K, P, S>
+where
+    K: KeyFamily + Lookup<K> + Clone + Eq + Hash + for<'a> Borrow<dyn Lookup<K> + 'a>,
+    P: Ord,
+    S: BuildHasher,
+{
+    fn check_invariants(&self) {
+        assert_eq!(self.heap.len(), self.positions.len());
+        for (position, (key, _)) in self.heap.iter().enumerate() {
+            let key: &dyn Lookup<K> = key.borrow();
+            assert_eq!(self.positions.get(key), Some(&position));
+            if position > 0 {
+                assert!(!self.less(position, (position - 1) / 2), "heap order");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+type Job = KeyPair<String, u32>;
+
+#[cfg(test)]
+fn job(name: &str, id: u32) -> Job {
+    KeyPair {
+        a: name.to_string(),
+        b: id,
+    }
+}
+
+#[test]
+fn priority_queue_scheduling() {
+    let mut queue: KeyPriorityQueue<Job, u64> = KeyPriorityQueue::new();
+    queue.push(job("build", 1), 30);
+    queue.push(job("test", 1), 20);
+    queue.push(job("deploy", 1), 50);
+    queue.push(job("build", 2), 20);
+    assert_eq!(queue.len(), 4);
+
+    // Jobs are found by probe, without allocating their names.
+    let probe = Job::probe("deploy", 1);
+    assert_eq!(queue.get_priority(probe.as_lookup()), Some(&50));
+    assert_eq!(queue.change_priority(probe.as_lookup(), 10), Some(50));
+    assert_eq!(
+        queue.remove(Job::probe("test", 1).as_lookup()),
+        Some((job("test", 1), 20))
+    );
+    assert!(!queue.contains_key(Job::probe("test", 1).as_lookup()));
+    assert_eq!(
+        queue.change_priority(Job::probe("test", 1).as_lookup(), 0),
+        None
+    );
+
+    // Pushing a key that's already there changes its priority.
+    assert_eq!(queue.push(job("build", 1), 40), Some(30));
+    queue.check_invariants();
+
+    let order: Vec<_> = std::iter::from_fn(|| queue.pop_min()).collect();
+    assert_eq!(
+        order,
+        [
+            (job("deploy", 1), 10),
+            (job("build", 2), 20),
+            (job("build", 1), 40)
+        ]
+    );
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn priority_queue_owned_keys() {
+    // A queue of OwnedKeys is addressed by &dyn Key, so a BorrowedKey works as it is.
+    let mut queue: KeyPriorityQueue<OwnedKey, u64> = KeyPriorityQueue::new();
+    queue.push(owned("foo", b"abc"), 30);
+    queue.push(owned("bar", b""), 20);
+    queue.push(owned("baz", b"xyz"), 10);
+
+    let foo = BorrowedKey::new("foo", b"abc");
+    assert!(queue.contains_by_key(&foo));
+    assert_eq!(queue.get_priority_by_key(&foo), Some(&30));
+    assert_eq!(queue.change_priority_by_key(&foo, 5), Some(30));
+    assert_eq!(
+        queue.remove_by_key(&BorrowedKey::new("bar", b"")),
+        Some((owned("bar", b""), 20))
+    );
+    assert!(!queue.contains_by_key(&BorrowedKey::new("bar", b"")));
+    assert_eq!(
+        queue.change_priority_by_key(&BorrowedKey::new("bar", b""), 0),
+        None
+    );
+
+    // An OwnedKey is a dyn Key too.
+    let baz = owned("baz", b"xyz");
+    assert_eq!(queue.get_priority_by_key(&baz), Some(&10));
+    queue.check_invariants();
+    assert_eq!(queue.pop_min(), Some((owned("foo", b"abc"), 5)));
+    assert_eq!(queue.pop_min(), Some((baz, 10)));
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn priority_queue_ties() {
+    // Equal priorities come out in key order, whatever order they went in.
+    let mut queue: KeyPriorityQueue<Job, u64> = KeyPriorityQueue::new();
+    for (name, id) in [("b", 2), ("a", 9), ("b", 1), ("a", 1)] {
+        queue.push(job(name, id), 0);
+    }
+    let order: Vec<_> = std::iter::from_fn(|| queue.pop_min())
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(order, [job("a", 1), job("a", 9), job("b", 1), job("b", 2)]);
+}
+
+#[cfg(test)]
+#[derive(Clone, Debug)]
+enum Op {
+    Push(Job, u8),
+    PopMin,
+    ChangePriority(Job, u8),
+    Remove(Job),
+}
+
+#[cfg(test)]
+fn op() -> impl Strategy<Value = Op> {
+    // A small set of keys, so that operations often hit keys that are already there.
+    let key = ("[ab]", 0..3u32).prop_map(|(name, id)| job(&name, id));
+    prop_oneof![
+        (key.clone(), any::<u8>()).prop_map(|(key, priority)| Op::Push(key, priority)),
+        Just(Op::PopMin),
+        (key.clone(), any::<u8>()).prop_map(|(key, priority)| Op::ChangePriority(key, priority)),
+        key.prop_map(Op::Remove),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn priority_queue_matches_model(ops in prop::collection::vec(op(), 0..64)) {
+        // The model is a map from keys to priorities; the minimum is found by scanning it.
+        let mut queue: KeyPriorityQueue<Job, u8> = KeyPriorityQueue::new();
+        let mut model: BTreeMap<Job, u8> = BTreeMap::new();
+        for op in ops {
+            match op {
+                Op::Push(key, priority) => {
+                    prop_assert_eq!(queue.push(key.clone(), priority), model.insert(key, priority));
+                }
+                Op::PopMin => {
+                    let min = model
+                        .iter()
+                        .min_by_key(|(key, priority)| (**priority, key.lookup_key()))
+                        .map(|(key, priority)| (key.clone(), *priority));
+                    if let Some((key, _)) = &min {
+                        model.remove(key);
+                    }
+                    prop_assert_eq!(queue.pop_min(), min);
+                }
+                Op::ChangePriority(key, priority) => {
+                    let old = model.get_mut(&key).map(|old| std::mem::replace(old, priority));
+                    let probe = Probe::<Job>(key.lookup_key());
+                    prop_assert_eq!(queue.change_priority(probe.as_lookup(), priority), old);
+                }
+                Op::Remove(key) => {
+                    let removed = model.remove_entry(&key);
+                    prop_assert_eq!(queue.remove(&key), removed);
+                }
+            }
+            queue.check_invariants();
+            prop_assert_eq!(queue.len(), model.len());
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Kani proof harnesses for Borrow consistency on small keys.
+//!
+//! Run these with `cargo kani`. They're only compiled under Kani, which sets `cfg(kani)`.
+
+use crate::{BorrowedKey, Key, OwnedKey};
+use std::hash::{Hash, Hasher};
+
+// The property tests check consistency on a few hundred random keys. Kani checks it on *every*
+// key up to a size bound, by turning each harness into a SAT problem: kani::any() stands for all
+// possible values at once, and an assertion that could fail for any of them is reported with a
+// counterexample.
+//
+// The bound is small, because the problem gets exponentially harder as keys get longer. Bugs in
+// Eq, Ord and Hash impls almost always show up on short keys anyway: an empty field, a field that's
+// a prefix of the other, a one-byte difference in either field.
+const MAX_LEN: usize = 3;
+
+/// Returns any ASCII string of at most `MAX_LEN` bytes, in `buf`. (Restricting to ASCII keeps the
+/// string valid UTF-8 without Kani having to reason about from_utf8.)
+fn any_str(buf: &mut [u8; MAX_LEN]) -> &str {
+    *buf = kani::any();
+    let len: usize = kani::any();
+    kani::assume(len <= MAX_LEN);
+    for &b in &buf[..len] {
+        kani::assume(b.is_ascii());
+    }
+    std::str::from_utf8(&buf[..len]).unwrap()
+}
+
+/// Returns any byte string of at most `MAX_LEN` bytes, in `buf`.
+fn any_bytes(buf: &mut [u8; MAX_LEN]) -> &[u8] {
+    *buf = kani::any();
+    let len: usize = kani::any();
+    kani::assume(len <= MAX_LEN);
+    &buf[..len]
+}
+
+fn owned(key: BorrowedKey<'_>) -> OwnedKey {
+    OwnedKey {
+        s: key.s.to_string(),
+        bytes: key.bytes.to_vec(),
+    }
+}
+
+// SipHash is far too much work for Kani. Consistency has to hold for all hashers, though, so any
+// hasher will do: this one is FNV-1a, which is simple enough to verify quickly.
+struct FnvHasher(u64);
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= u64::from(b);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+fn hash_output<T: ?Sized + Hash>(x: &T) -> u64 {
+    let mut hasher = FnvHasher(0xcbf2_9ce4_8422_2325);
+    x.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[kani::proof]
+#[kani::unwind(8)]
+fn consistent_eq_ord() {
+    let (mut s1, mut bytes1, mut s2, mut bytes2) =
+        ([0; MAX_LEN], [0; MAX_LEN], [0; MAX_LEN], [0; MAX_LEN]);
+    let owned1 = owned(BorrowedKey {
+        s: any_str(&mut s1),
+        bytes: any_bytes(&mut bytes1),
+    });
+    let owned2 = owned(BorrowedKey {
+        s: any_str(&mut s2),
+        bytes: any_bytes(&mut bytes2),
+    });
+    let borrowed1: &dyn Key = &owned1;
+    let borrowed2: &dyn Key = &owned2;
+
+    assert_eq!(owned1 == owned2, borrowed1 == borrowed2);
+    assert_eq!(owned1.cmp(&owned2), borrowed1.cmp(borrowed2));
+}
+
+#[kani::proof]
+#[kani::unwind(8)]
+fn consistent_hash() {
+    let (mut s, mut bytes) = ([0; MAX_LEN], [0; MAX_LEN]);
+    let owned = owned(BorrowedKey {
+        s: any_str(&mut s),
+        bytes: any_bytes(&mut bytes),
+    });
+    let borrowed: &dyn Key = &owned;
+
+    assert_eq!(hash_output(&owned), hash_output(borrowed));
+}
+
+#[kani::proof]
+#[kani::unwind(8)]
+fn equal_keys_hash_equal() {
+    // The Hash/Eq contract for the borrowed form itself: keys that are equal hash the same. This is
+    // where a missing terminator between the string and the bytes would show up, for example.
+    let (mut s1, mut bytes1, mut s2, mut bytes2) =
+        ([0; MAX_LEN], [0; MAX_LEN], [0; MAX_LEN], [0; MAX_LEN]);
+    let key1 = BorrowedKey {
+        s: any_str(&mut s1),
+        bytes: any_bytes(&mut bytes1),
+    };
+    let key2 = BorrowedKey {
+        s: any_str(&mut s2),
+        bytes: any_bytes(&mut bytes2),
+    };
+    if key1 == key2 {
+        assert_eq!(hash_output(&key1), hash_output(&key2));
+    }
+}
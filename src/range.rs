@@ -0,0 +1,160 @@
+//! Range bounds for scanning every key with a given string out of a sorted collection.
+
+use crate::{BorrowedKey, Key, OwnedKey};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Bound;
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+use std::collections::BTreeSet;
+
+// Keys are ordered by their string first, then by their bytes, so all the keys with a given string
+// sit next to each other in a BTreeSet. Getting at them with range needs a key at each end of that
+// run.
+//
+// The lower end is easy: the empty byte string sorts before every other, so (s, b"") is the
+// smallest key with string s. BorrowedKey::min_for_s returns it, without allocating.
+//
+// The upper end is harder, because there's no largest byte string, and so no largest key with
+// string s. Instead, max_for_s returns the smallest key that sorts after all of them, to be used as
+// an excluded bound. That's (s + "\0", b""): a string with a character after s sorts after s, and
+// NUL is the smallest character there is, so no string falls between the two. It has to own its
+// string, since s + "\0" isn't in memory anywhere.
+//
+// SRange wraps the two up, and hands out bounds as &dyn Key, the same way every other lookup in
+// the crate works. The run of keys is then set.range::<dyn Key, _>(range.bounds()).
+impl<'a> BorrowedKey<'a> {
+    /// Returns the smallest key with string `s`: `s` with empty bytes.
+    pub const fn min_for_s(s: &'a str) -> Self {
+        Self { s, bytes: b"" }
+    }
+}
+
+impl OwnedKey {
+    /// Returns the smallest key that sorts after every key with string `s`, for use as an
+    /// *excluded* upper bound.
+    ///
+    /// There is no largest key with a given string, since there's no largest byte string, so this
+    /// is `s` followed by a NUL, with empty bytes.
+    pub fn max_for_s(s: &str) -> Self {
+        let mut after = String::with_capacity(s.len() + 1);
+        after.push_str(s);
+        after.push('\0');
+        OwnedKey {
+            s: after,
+            bytes: Vec::new(),
+        }
+    }
+}
+
+/// The range of keys whose string is exactly `s`.
+///
+/// ```
+/// use borrow_complex_key_example::range::SRange;
+/// use borrow_complex_key_example::{Key, OwnedKey};
+/// use std::collections::BTreeSet;
+///
+/// let set: BTreeSet<OwnedKey> = vec![("a", b"x"), ("b", b"x"), ("b", b"y"), ("b\0", b"z")]
+///     .into_iter()
+///     .map(|(s, bytes)| OwnedKey { s: s.to_string(), bytes: bytes.to_vec() })
+///     .collect();
+/// let range = SRange::new("b");
+/// let bytes: Vec<_> = set.range::<dyn Key, _>(range.bounds()).map(|key| &key.bytes[..]).collect();
+/// assert_eq!(bytes, [b"x", b"y"]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct SRange<'a> {
+    start: BorrowedKey<'a>,
+    end: OwnedKey,
+}
+
+impl<'a> SRange<'a> {
+    /// Creates the range of keys whose string is `s`.
+    pub fn new(s: &'a str) -> Self {
+        Self {
+            start: BorrowedKey::min_for_s(s),
+            end: OwnedKey::max_for_s(s),
+        }
+    }
+
+    /// Returns the bounds of the range, for `BTreeSet::range` and `BTreeMap::range` with
+    /// `dyn Key` as the borrowed type.
+    pub fn bounds(&self) -> (Bound<&(dyn Key + '_)>, Bound<&(dyn Key + '_)>) {
+        (Bound::Included(&self.start), Bound::Excluded(&self.end))
+    }
+
+    /// Returns true if `key` is in the range.
+    pub fn contains(&self, key: &dyn Key) -> bool {
+        key.key().s == self.start.s
+    }
+}
+
+#[test]
+fn range_scans() {
+    let set: BTreeSet<OwnedKey> = vec![
+        ("", &b"\0"[..]),
+        ("foo", b""),
+        ("foo", b"abc"),
+        ("foo", b"\xff\xff"),
+        ("foo\0", b""),
+        ("foo\x01", b""),
+        ("foobar", b""),
+    ]
+    .into_iter()
+    .map(|(s, bytes)| OwnedKey {
+        s: s.to_string(),
+        bytes: bytes.to_vec(),
+    })
+    .collect();
+
+    let scan = |s| {
+        let range = SRange::new(s);
+        set.range::<dyn Key, _>(range.bounds())
+            .map(|key| key.bytes.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(scan("foo"), [&b""[..], b"abc", b"\xff\xff"]);
+    assert_eq!(scan("foo\0"), [b""]);
+    assert_eq!(scan(""), [b"\0"]);
+    assert!(scan("fo").is_empty());
+
+    assert_eq!(BorrowedKey::min_for_s("foo"), BorrowedKey::new("foo", b""));
+    assert_eq!(
+        OwnedKey::max_for_s("foo").key(),
+        BorrowedKey::new("foo\0", b"")
+    );
+}
+
+#[cfg(test)]
+fn clustered_keys() -> impl Strategy<Value = OwnedKey> {
+    // A small alphabet, so that strings repeat, and are often prefixes of one another.
+    ("[a\0\u{ff}]{0,3}", prop::collection::vec(any::<u8>(), 0..3))
+        .prop_map(|(s, bytes)| OwnedKey { s, bytes })
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn range_sentinels_bracket(key in clustered_keys(), s in "[a\0\u{ff}]{0,3}") {
+        let (min, max) = (BorrowedKey::min_for_s(&s), OwnedKey::max_for_s(&s));
+        let key: &dyn Key = &key;
+        if key.key().s == s {
+            prop_assert!(&min as &dyn Key <= key);
+            prop_assert!(key < &max as &dyn Key);
+        } else {
+            prop_assert!(key < &min as &dyn Key || key >= &max as &dyn Key);
+        }
+    }
+
+    #[test]
+    fn range_matches_filter(
+        keys in prop::collection::btree_set(clustered_keys(), 0..16),
+        s in "[a\0\u{ff}]{0,3}",
+    ) {
+        let range = SRange::new(&s);
+        let scanned: Vec<_> = keys.range::<dyn Key, _>(range.bounds()).collect();
+        let filtered: Vec<_> = keys.iter().filter(|key| range.contains(*key)).collect();
+        prop_assert_eq!(scanned, filtered);
+    }
+}
@@ -0,0 +1,305 @@
+//! Keys prefixed with a tenant or namespace, and maps that look keys up within one scope.
+
+use crate::component::KeyComponent;
+use crate::consistency::assert_consistent;
+use crate::family::{KeyFamily, Lookup, Probe};
+#[cfg(test)]
+use crate::owned;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use proptest_derive::Arbitrary;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+// A multi-tenant service keeps every tenant's keys in one map, each prefixed with the tenant it
+// belongs to. The prefix is just another component of the key: ScopedKey is a KeyPair whose second
+// half is a whole key family rather than a single component, so any key type can be scoped, and
+// its borrowed form is the scope's borrowed form next to the key's. As with KeyPair, tuples compare
+// and hash element by element, the same way the derives on ScopedKey do, so a ScopedKey is
+// consistent with its borrowed form as long as the scope and the key are each consistent with
+// theirs.
+//
+// Code that serves one tenant shouldn't have to pass the tenant to every lookup, though, and
+// certainly shouldn't be able to forget it. ScopedKeyMap::scope returns a view of the map that
+// pairs the tenant with every key it's given, so lookups through it take a plain &dyn Key, exactly
+// as they would in a KeyMap, and can only see that tenant's entries.
+/// A key of the family `K`, prefixed with the scope `ID` it belongs to, such as a tenant.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Arbitrary)]
+pub struct ScopedKey<ID, K> {
+    pub scope: ID,
+    pub key: K,
+}
+
+impl<ID: KeyComponent, K: KeyFamily> ScopedKey<ID, K> {
+    /// Returns a probe for looking up `key` within `scope`, without building a `ScopedKey`.
+    pub fn probe<'a>(scope: ID::Borrowed<'a>, key: K::Borrowed<'a>) -> Probe<'a, Self> {
+        Probe((scope, key))
+    }
+}
+
+impl<ID: KeyComponent, K: KeyFamily> KeyFamily for ScopedKey<ID, K> {
+    type Borrowed<'a> = (ID::Borrowed<'a>, K::Borrowed<'a>);
+
+    fn shorten<'long: 'short, 'short>(
+        (scope, key): Self::Borrowed<'long>,
+    ) -> Self::Borrowed<'short> {
+        (ID::shorten(scope), K::shorten(key))
+    }
+}
+
+impl<ID: KeyComponent, K: KeyFamily + Lookup<K>> Lookup<ScopedKey<ID, K>> for ScopedKey<ID, K> {
+    fn lookup_key(&self) -> (ID::Borrowed<'_>, K::Borrowed<'_>) {
+        (self.scope.as_borrowed(), self.key.lookup_key())
+    }
+}
+
+impl<'a, ID, K> Borrow<dyn Lookup<ScopedKey<ID, K>> + 'a> for ScopedKey<ID, K>
+where
+    ID: KeyComponent + 'a,
+    K: KeyFamily + Lookup<K> + 'a,
+{
+    fn borrow(&self) -> &(dyn Lookup<ScopedKey<ID, K>> + 'a) {
+        self
+    }
+}
+
+/// A map from scoped [`OwnedKey`]s to values, whose lookups can be confined to a single scope.
+#[derive(Clone, Default)]
+pub struct ScopedKeyMap<ID, V, S = RandomState> {
+    map: HashMap<ScopedKey<ID, OwnedKey>, V, S>,
+}
+
+impl<ID, V> ScopedKeyMap<ID, V> {
+    /// Creates a new, empty map.
+    pub fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+        }
+    }
+}
+
+impl<ID: KeyComponent + Eq + Hash, V, S: BuildHasher> ScopedKeyMap<ID, V, S> {
+    /// Creates a new, empty map that uses `hasher` to hash keys.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            map: HashMap::with_hasher(hasher),
+        }
+    }
+
+    /// Inserts a value for `key` within `scope`, returning the old value if there was one.
+    pub fn insert(&mut self, scope: ID, key: OwnedKey, value: V) -> Option<V> {
+        self.map.insert(ScopedKey { scope, key }, value)
+    }
+
+    /// Returns the value for `key` within `scope`, if any.
+    pub fn get(&self, scope: ID::Borrowed<'_>, key: &dyn Key) -> Option<&V> {
+        let probe = ScopedKey::<ID, OwnedKey>::probe(ID::shorten(scope), key.key());
+        self.map.get(probe.as_lookup())
+    }
+
+    /// Removes and returns the value for `key` within `scope`, if any.
+    pub fn remove(&mut self, scope: ID::Borrowed<'_>, key: &dyn Key) -> Option<V> {
+        let probe = ScopedKey::<ID, OwnedKey>::probe(ID::shorten(scope), key.key());
+        self.map.remove(probe.as_lookup())
+    }
+
+    /// Returns a view of the entries within `scope`. Lookups through it take the key alone.
+    pub fn scope<'m>(&'m self, scope: ID::Borrowed<'m>) -> Scope<'m, ID, V, S> {
+        Scope { map: self, scope }
+    }
+
+    /// Returns a mutable view of the entries within `scope`. Keys inserted through it are given a
+    /// clone of `scope`.
+    pub fn scope_mut(&mut self, scope: ID) -> ScopeMut<'_, ID, V, S>
+    where
+        ID: Clone,
+    {
+        ScopeMut { map: self, scope }
+    }
+
+    /// Returns the number of entries, in every scope.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if the map has no entries, in any scope.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns an iterator over the scopes, keys and values, in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (ID::Borrowed<'_>, BorrowedKey<'_>, &V)> {
+        self.map
+            .iter()
+            .map(|(key, value)| (key.scope.as_borrowed(), key.key.key(), value))
+    }
+}
+
+impl<ID: fmt::Debug, V: fmt::Debug, S> fmt::Debug for ScopedKeyMap<ID, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.map.iter()).finish()
+    }
+}
+
+/// A view of the entries of a [`ScopedKeyMap`] within one scope.
+///
+/// Returned by [`ScopedKeyMap::scope`].
+pub struct Scope<'m, ID: KeyComponent, V, S> {
+    map: &'m ScopedKeyMap<ID, V, S>,
+    scope: ID::Borrowed<'m>,
+}
+
+impl<'m, ID: KeyComponent + Eq + Hash, V, S: BuildHasher> Scope<'m, ID, V, S> {
+    /// Returns the scope this view is confined to.
+    pub fn scope(&self) -> ID::Borrowed<'m> {
+        self.scope
+    }
+
+    /// Returns the value for `key` within the scope, if any.
+    pub fn get(&self, key: &dyn Key) -> Option<&'m V> {
+        let map = self.map;
+        let probe = ScopedKey::<ID, OwnedKey>::probe(ID::shorten(self.scope), key.key());
+        map.map.get(probe.as_lookup())
+    }
+
+    /// Returns true if the scope has a value for `key`.
+    pub fn contains_key(&self, key: &dyn Key) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns an iterator over the keys and values within the scope, in arbitrary order.
+    ///
+    /// This goes through every entry in the map, in every scope.
+    pub fn iter(&self) -> impl Iterator<Item = (BorrowedKey<'m>, &'m V)> {
+        let scope = self.scope;
+        self.map
+            .map
+            .iter()
+            .filter(move |(key, _)| key.scope.as_borrowed() == scope)
+            .map(|(key, value)| (key.key.key(), value))
+    }
+}
+
+impl<ID: KeyComponent, V, S> fmt::Debug for Scope<'_, ID, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scope")
+            .field("scope", &self.scope)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A mutable view of the entries of a [`ScopedKeyMap`] within one scope.
+///
+/// Returned by [`ScopedKeyMap::scope_mut`].
+pub struct ScopeMut<'m, ID, V, S> {
+    map: &'m mut ScopedKeyMap<ID, V, S>,
+    scope: ID,
+}
+
+impl<ID: KeyComponent + Clone + Eq + Hash, V, S: BuildHasher> ScopeMut<'_, ID, V, S> {
+    /// Returns the scope this view is confined to.
+    pub fn scope(&self) -> &ID {
+        &self.scope
+    }
+
+    /// Inserts a value for `key` within the scope, returning the old value if there was one.
+    pub fn insert(&mut self, key: OwnedKey, value: V) -> Option<V> {
+        self.map.insert(self.scope.clone(), key, value)
+    }
+
+    /// Returns the value for `key` within the scope, if any.
+    pub fn get(&self, key: &dyn Key) -> Option<&V> {
+        self.map.get(self.scope.as_borrowed(), key)
+    }
+
+    /// Returns a mutable reference to the value for `key` within the scope, if any.
+    pub fn get_mut(&mut self, key: &dyn Key) -> Option<&mut V> {
+        let probe = ScopedKey::<ID, OwnedKey>::probe(self.scope.as_borrowed(), key.key());
+        self.map.map.get_mut(probe.as_lookup())
+    }
+
+    /// Removes and returns the value for `key` within the scope, if any.
+    pub fn remove(&mut self, key: &dyn Key) -> Option<V> {
+        self.map.remove(self.scope.as_borrowed(), key)
+    }
+}
+
+impl<ID: fmt::Debug, V, S> fmt::Debug for ScopeMut<'_, ID, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScopeMut")
+            .field("scope", &self.scope)
+            .finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn scoped_lookups() {
+    let mut map: ScopedKeyMap<String, u32> = ScopedKeyMap::new();
+    map.scope_mut("acme".to_string())
+        .insert(owned("foo", b"abc"), 1);
+    map.insert("globex".to_string(), owned("foo", b"abc"), 2);
+
+    let key = BorrowedKey::new("foo", b"abc");
+    let acme = map.scope("acme");
+    assert_eq!(acme.get(&key), Some(&1));
+    assert!(!acme.contains_key(&BorrowedKey::new("foo", b"")));
+    assert_eq!(map.scope("globex").get(&key), Some(&2));
+    // A tenant only sees its own entries.
+    assert_eq!(map.scope("initech").get(&key), None);
+    assert_eq!(map.scope("globex").iter().count(), 1);
+
+    let mut acme = map.scope_mut("acme".to_string());
+    *acme.get_mut(&key).unwrap() += 10;
+    assert_eq!(acme.remove(&key), Some(11));
+    assert_eq!(map.get("acme", &key), None);
+    assert_eq!(map.len(), 1);
+
+    // ScopedKey works with any key family, through probes.
+    let mut pairs = std::collections::HashSet::new();
+    pairs.insert(ScopedKey {
+        scope: 7u32,
+        key: crate::pair::KeyPair {
+            a: "foo".to_string(),
+            b: 1u64,
+        },
+    });
+    let probe = ScopedKey::<u32, crate::pair::KeyPair<String, u64>>::probe(7, ("foo", 1));
+    assert!(pairs.contains(probe.as_lookup()));
+}
+
+proptest! {
+    #[test]
+    fn scoped_consistent_borrow(
+        owned1 in any::<ScopedKey<String, OwnedKey>>(),
+        owned2 in any::<ScopedKey<String, OwnedKey>>(),
+    ) {
+        assert_consistent::<_, dyn Lookup<ScopedKey<String, OwnedKey>>>(&owned1, &owned2);
+    }
+
+    #[test]
+    fn scoped_matches_hash_map(
+        entries in prop::collection::vec((0..3u8, any::<OwnedKey>(), any::<u32>()), 0..16),
+        scope in 0..3u8,
+        probe in any::<OwnedKey>(),
+    ) {
+        let mut scoped = ScopedKeyMap::new();
+        let mut plain = HashMap::new();
+        for (scope, key, value) in &entries {
+            scoped.insert(*scope, key.clone(), *value);
+            plain.insert((*scope, key.clone()), *value);
+        }
+
+        // A view finds exactly the entries a map keyed by (scope, key) pairs would.
+        let view = scoped.scope(scope);
+        for (entry_scope, key, _) in &entries {
+            prop_assert_eq!(view.get(key), plain.get(&(scope, key.clone())));
+            prop_assert_eq!(scoped.get(*entry_scope, key), plain.get(&(*entry_scope, key.clone())));
+        }
+        prop_assert_eq!(view.get(&probe), plain.get(&(scope, probe.clone())));
+        let in_scope = plain.keys().filter(|(entry_scope, _)| *entry_scope == scope).count();
+        prop_assert_eq!(view.iter().count(), in_scope);
+    }
+}
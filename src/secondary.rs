@@ -0,0 +1,218 @@
+//! An index from one field of a key to every key that has it.
+
+use crate::collections::{HashMap, RandomState};
+use crate::map::KeyMap;
+use crate::{BorrowedKey, Key, OwnedKey};
+use alloc::borrow::ToOwned;
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::hash::BuildHasher;
+use core::iter::{self, FromIterator};
+#[cfg(test)]
+use proptest::prelude::*;
+
+// A KeyMap answers "what's stored under (s, bytes)?", but not "which keys have this s?". The only
+// way to answer that from the map is to scan every key. If the question comes up often, it's
+// cheaper to keep a second map on the side, from each s to the set of bytes stored with it.
+//
+// That's a multimap, so each s maps to a set rather than a single value. The set is a BTreeSet, so
+// the keys for one s come out in order of their bytes, the same order they'd have in a
+// BTreeSet<OwnedKey>. Lookups take a &str, since String implements Borrow<str>: a single field
+// doesn't need the trait object at all.
+//
+// The index is separate from the map, so it only knows about keys it's told about. Build one from
+// an existing map with KeyMap::index_by_s, then insert and remove keys in both as they change.
+/// A multimap from the string field of a key to every key that has it.
+#[derive(Clone, Debug, Default)]
+pub struct SecondaryIndex<S = RandomState> {
+    by_s: HashMap<String, BTreeSet<Vec<u8>>, S>,
+    len: usize,
+}
+
+impl SecondaryIndex {
+    /// Creates a new, empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S: BuildHasher> SecondaryIndex<S> {
+    /// Creates a new, empty index that uses `hasher` to hash strings.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            by_s: HashMap::with_hasher(hasher),
+            len: 0,
+        }
+    }
+
+    /// Adds `key` to the index. Returns true if it wasn't already present.
+    pub fn insert(&mut self, key: &dyn Key) -> bool {
+        let key = key.key();
+        let inserted = match self.by_s.get_mut(key.s) {
+            Some(bytes) if bytes.contains(key.bytes) => false,
+            Some(bytes) => bytes.insert(key.bytes.to_owned()),
+            None => {
+                let bytes = iter::once(key.bytes.to_owned()).collect();
+                self.by_s.insert(key.s.to_owned(), bytes);
+                true
+            }
+        };
+        self.len += usize::from(inserted);
+        inserted
+    }
+
+    /// Removes `key` from the index. Returns true if it was present.
+    pub fn remove(&mut self, key: &dyn Key) -> bool {
+        let key = key.key();
+        let bytes = match self.by_s.get_mut(key.s) {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+        let removed = bytes.remove(key.bytes);
+        if bytes.is_empty() {
+            self.by_s.remove(key.s);
+        }
+        self.len -= usize::from(removed);
+        removed
+    }
+
+    /// Returns true if the index has `key`.
+    pub fn contains(&self, key: &dyn Key) -> bool {
+        let key = key.key();
+        self.by_s
+            .get(key.s)
+            .is_some_and(|bytes| bytes.contains(key.bytes))
+    }
+
+    /// Iterates over every key whose string is `s`, in order of their bytes.
+    pub fn get(&self, s: &str) -> impl Iterator<Item = BorrowedKey<'_>> + '_ {
+        self.by_s.get_key_value(s).into_iter().flat_map(keys_for)
+    }
+
+    /// Iterates over every key in the index. Keys with the same string come out together, in
+    /// order of their bytes; the strings themselves come out in arbitrary order.
+    ///
+    /// The index doesn't store owned keys, so unlike the other containers, there's no separate
+    /// `iter_borrowed`.
+    pub fn iter(&self) -> impl Iterator<Item = BorrowedKey<'_>> + '_ {
+        self.by_s.iter().flat_map(keys_for)
+    }
+
+    /// Returns the number of keys whose string is `s`.
+    pub fn count(&self, s: &str) -> usize {
+        self.by_s.get(s).map_or(0, BTreeSet::len)
+    }
+
+    /// Returns the number of keys in the index.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+// Rebuilds the keys for one string from its entry in by_s.
+fn keys_for<'a>(
+    (s, bytes): (&'a String, &'a BTreeSet<Vec<u8>>),
+) -> impl Iterator<Item = BorrowedKey<'a>> + 'a {
+    bytes.iter().map(move |bytes| BorrowedKey { s, bytes })
+}
+
+impl<'k, S: BuildHasher + Default> FromIterator<&'k OwnedKey> for SecondaryIndex<S> {
+    fn from_iter<I: IntoIterator<Item = &'k OwnedKey>>(iter: I) -> Self {
+        let mut index = Self::with_hasher(S::default());
+        for key in iter {
+            index.insert(key);
+        }
+        index
+    }
+}
+
+impl<V, S: BuildHasher> KeyMap<V, S> {
+    /// Builds a [`SecondaryIndex`] over the keys in this map, to look keys up by their string.
+    ///
+    /// This scans the whole map once. The index isn't updated when the map changes: insert and
+    /// remove keys in both to keep them in sync.
+    pub fn index_by_s(&self) -> SecondaryIndex {
+        self.iter().map(|(key, _)| key).collect()
+    }
+}
+
+#[test]
+fn secondary_lookups() {
+    let mut map = KeyMap::new();
+    for (s, bytes) in [
+        ("foo", &b"b"[..]),
+        ("foo", b"a"),
+        ("bar", b"a"),
+        ("foo", b""),
+    ] {
+        map.insert(
+            OwnedKey {
+                s: s.to_owned(),
+                bytes: bytes.to_owned(),
+            },
+            (),
+        );
+    }
+
+    let mut index = map.index_by_s();
+    assert_eq!(index.len(), 4);
+    assert_eq!(index.count("foo"), 3);
+    let foos: Vec<_> = index.get("foo").map(|key| key.bytes).collect();
+    assert_eq!(foos, [&b""[..], b"a", b"b"]);
+    assert_eq!(index.get("baz").count(), 0);
+
+    // Each key that comes out can be used to look up the map directly.
+    for key in index.get("foo") {
+        assert!(map.contains_key(&key));
+    }
+    assert_eq!(index.iter().count(), 4);
+    assert!(index.iter().all(|key| map.contains_key(&key)));
+
+    // Removing the last key for a string removes the string.
+    let bar = BorrowedKey {
+        s: "bar",
+        bytes: b"a",
+    };
+    assert!(index.remove(&bar));
+    assert!(!index.remove(&bar));
+    assert_eq!(index.count("bar"), 0);
+    assert!(!index.contains(&bar));
+    assert!(index.insert(&bar));
+    assert!(!index.insert(&bar));
+    assert_eq!(index.len(), 4);
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn secondary_matches_scan(
+        inserts in prop::collection::vec(("[ab]{0,1}", prop::collection::vec(0..2u8, 0..2)), 0..16),
+        removes in prop::collection::vec(("[ab]{0,1}", prop::collection::vec(0..2u8, 0..2)), 0..16),
+        probe in "[ab]{0,1}",
+    ) {
+        // Keep a map and an index in sync by hand, then check the index against a scan of the map.
+        let mut map = KeyMap::new();
+        let mut index = SecondaryIndex::new();
+        for (s, bytes) in inserts {
+            let key = OwnedKey { s, bytes };
+            prop_assert_eq!(index.insert(&key), map.insert(key.clone(), ()).is_none());
+        }
+        for (s, bytes) in removes {
+            let key = BorrowedKey { s: &s, bytes: &bytes };
+            prop_assert_eq!(index.remove(&key), map.remove(&key).is_some());
+        }
+
+        let mut scanned: Vec<_> = map.iter().map(|(key, _)| key.key()).filter(|key| key.s == probe).collect();
+        scanned.sort();
+        prop_assert_eq!(index.get(&probe).collect::<Vec<_>>(), scanned);
+        prop_assert_eq!(index.len(), map.len());
+        let rebuilt = map.index_by_s();
+        prop_assert_eq!(rebuilt.get(&probe).collect::<Vec<_>>(), index.get(&probe).collect::<Vec<_>>());
+    }
+}
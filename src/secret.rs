@@ -0,0 +1,202 @@
+//! Keys made of secrets, such as API tokens, compared in constant time.
+//!
+//! Requires the `secret` feature.
+//!
+//! Secret keys deliberately don't implement Ord, so they can't be put in a `BTreeSet`:
+//!
+//! ```compile_fail
+//! use borrow_complex_key_example::secret::SecretOwnedKey;
+//! use std::collections::BTreeSet;
+//!
+//! let mut tokens = BTreeSet::new();
+//! tokens.insert(SecretOwnedKey { s: "alice".to_string(), bytes: b"hunter2".to_vec() });
+//! ```
+
+use crate::consistency::assert_consistent_hash;
+use proptest::prelude::*;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use subtle::ConstantTimeEq;
+
+// Looking up a token in a map compares it against the stored tokens. The derived Eq bails out at
+// the first byte that differs, so an attacker who can time lookups learns how many leading bytes
+// of their guess were right, and can recover a token a byte at a time. subtle's ct_eq looks at
+// every byte no matter what.
+//
+// What about the rest of the lookup? Here's what the timing of a HashMap lookup depends on:
+//
+// - Hashing: SipHash (the default hasher) does the same work for every input of a given length.
+// - Finding the bucket: this depends on the hash. With the default RandomState, the hash is keyed
+//   by a random per-map seed, so an attacker can't work out which guesses land near which stored
+//   tokens. A predictable hasher (like FxHash, or a fixed seed) throws this away.
+// - Comparing: constant time for inputs of the same length, via ct_eq below.
+//
+// Lengths aren't hidden at any step. If that matters, make all of your secrets the same length.
+//
+// Ord is where this runs out. A comparison that says which side is greater is telling you where
+// the first difference is, so there's no constant-time Ord to write, and no BTreeMap support.
+// That's also why this module has its own trait rather than using KeyFamily, which requires Ord.
+/// An owned key made of secrets. Both fields are compared in constant time.
+#[derive(Clone)]
+pub struct SecretOwnedKey {
+    pub s: String,
+    pub bytes: Vec<u8>,
+}
+
+/// The borrowed form of a [`SecretOwnedKey`].
+#[derive(Copy, Clone)]
+pub struct SecretBorrowedKey<'a> {
+    pub s: &'a str,
+    pub bytes: &'a [u8],
+}
+
+/// A key that can be viewed as a [`SecretBorrowedKey`]. This is the `Key` trait from the crate
+/// root, for secrets.
+pub trait SecretKey {
+    /// Returns the borrowed form of this key.
+    fn secret_key(&self) -> SecretBorrowedKey<'_>;
+}
+
+impl SecretKey for SecretOwnedKey {
+    fn secret_key(&self) -> SecretBorrowedKey<'_> {
+        SecretBorrowedKey {
+            s: &self.s,
+            bytes: &self.bytes,
+        }
+    }
+}
+
+impl<'a> SecretKey for SecretBorrowedKey<'a> {
+    fn secret_key(&self) -> SecretBorrowedKey<'_> {
+        *self
+    }
+}
+
+impl<'a> Borrow<dyn SecretKey + 'a> for SecretOwnedKey {
+    fn borrow(&self) -> &(dyn SecretKey + 'a) {
+        self
+    }
+}
+
+impl<'a> PartialEq for SecretBorrowedKey<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        // & rather than &&, so that a mismatch in s doesn't skip comparing the bytes.
+        (self.s.as_bytes().ct_eq(other.s.as_bytes()) & self.bytes.ct_eq(other.bytes)).into()
+    }
+}
+
+impl<'a> Eq for SecretBorrowedKey<'a> {}
+
+impl<'a> Hash for SecretBorrowedKey<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.s.hash(state);
+        self.bytes.hash(state);
+    }
+}
+
+// The owned key and the trait object forward to the borrowed key, which is the only place the
+// comparison is written.
+impl PartialEq for SecretOwnedKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.secret_key() == other.secret_key()
+    }
+}
+
+impl Eq for SecretOwnedKey {}
+
+impl Hash for SecretOwnedKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.secret_key().hash(state)
+    }
+}
+
+impl<'a> PartialEq for dyn SecretKey + 'a {
+    fn eq(&self, other: &Self) -> bool {
+        self.secret_key() == other.secret_key()
+    }
+}
+
+impl<'a> Eq for dyn SecretKey + 'a {}
+
+impl<'a> Hash for dyn SecretKey + 'a {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.secret_key().hash(state)
+    }
+}
+
+// Secrets shouldn't end up in logs, so Debug leaves them out.
+impl fmt::Debug for SecretOwnedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretOwnedKey").finish_non_exhaustive()
+    }
+}
+
+impl<'a> fmt::Debug for SecretBorrowedKey<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretBorrowedKey").finish_non_exhaustive()
+    }
+}
+
+impl Arbitrary for SecretOwnedKey {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        any::<(String, Vec<u8>)>()
+            .prop_map(|(s, bytes)| SecretOwnedKey { s, bytes })
+            .boxed()
+    }
+}
+
+#[test]
+fn secret_lookups() {
+    let mut sessions = HashMap::new();
+    let key = SecretOwnedKey {
+        s: "alice".to_string(),
+        bytes: b"hunter2".to_vec(),
+    };
+    assert_eq!(format!("{:?}", key), "SecretOwnedKey { .. }");
+    sessions.insert(key, "alice's session");
+
+    let probe = SecretBorrowedKey {
+        s: "alice",
+        bytes: b"hunter2",
+    };
+    assert_eq!(
+        sessions.get(&probe as &dyn SecretKey),
+        Some(&"alice's session")
+    );
+    let probe = SecretBorrowedKey {
+        s: "alice",
+        bytes: b"hunter3",
+    };
+    assert_eq!(sessions.get(&probe as &dyn SecretKey), None);
+}
+
+proptest! {
+    #[test]
+    fn secret_consistent_borrow(owned1 in any::<SecretOwnedKey>(), owned2 in any::<SecretOwnedKey>()) {
+        assert_consistent_hash::<_, dyn SecretKey>(&owned1, &owned2);
+    }
+
+    // Random keys are almost never equal, so also check keys that differ in a single byte, or not
+    // at all.
+    #[test]
+    fn secret_nearby_consistent_borrow(
+        owned in any::<SecretOwnedKey>(),
+        flip in any::<Option<(prop::sample::Index, u8)>>(),
+    ) {
+        let mut other = owned.clone();
+        if let Some((index, xor)) = flip {
+            if !other.bytes.is_empty() {
+                let index = index.index(other.bytes.len());
+                other.bytes[index] ^= xor;
+            }
+        }
+        let expected = owned.bytes == other.bytes;
+        prop_assert_eq!(owned == other, expected);
+        assert_consistent_hash::<_, dyn SecretKey>(&owned, &other);
+    }
+}
@@ -0,0 +1,115 @@
+//! An owned key that wipes its contents from memory when it's dropped.
+//!
+//! Requires the `zeroize` feature.
+
+use crate::consistency::assert_consistent;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::any::Any;
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::fmt;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+// When a String or Vec<u8> is dropped, its buffer goes back to the allocator as-is. If the key was
+// a session token, the token stays in memory until something else overwrites it, and can turn up
+// in a core dump or swap. Deriving ZeroizeOnDrop overwrites both buffers with zeroes first.
+//
+// The key is dropped, and so zeroized, whenever a collection lets go of it: on remove, on clear,
+// when an equal key is already present on insert, and when the collection itself is dropped.
+// Collections move keys around as they grow, but that only copies the String and Vec headers, never
+// the buffers they point to, so no stray copies of the contents are left behind.
+//
+// Growing a String or Vec in place can leave a copy behind, though: the old buffer is freed without
+// being zeroized. That's why the fields are private, and keys are built from a String and a Vec
+// that are moved in whole.
+//
+// Zeroizing doesn't change what the key compares as while it's alive, so the derives are still
+// consistent with BorrowedKey.
+/// An owned key whose contents are zeroized on drop.
+#[derive(Clone, Eq, Hash, Ord, PartialEq, PartialOrd, Zeroize, ZeroizeOnDrop)]
+pub struct SensitiveOwnedKey {
+    s: String,
+    bytes: Vec<u8>,
+}
+
+impl SensitiveOwnedKey {
+    /// Creates a new key, taking ownership of `s` and `bytes` without copying them.
+    pub fn new(s: String, bytes: Vec<u8>) -> Self {
+        Self { s, bytes }
+    }
+}
+
+impl Key for SensitiveOwnedKey {
+    fn key<'k>(&'k self) -> BorrowedKey<'k> {
+        BorrowedKey {
+            s: &self.s,
+            bytes: &self.bytes,
+        }
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+impl<'a> Borrow<dyn Key + 'a> for SensitiveOwnedKey {
+    fn borrow(&self) -> &(dyn Key + 'a) {
+        self
+    }
+}
+
+impl From<OwnedKey> for SensitiveOwnedKey {
+    fn from(key: OwnedKey) -> Self {
+        Self::new(key.s, key.bytes)
+    }
+}
+
+// Like with secret keys, the contents shouldn't end up in logs.
+impl fmt::Debug for SensitiveOwnedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SensitiveOwnedKey").finish_non_exhaustive()
+    }
+}
+
+impl Arbitrary for SensitiveOwnedKey {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        any::<OwnedKey>().prop_map(SensitiveOwnedKey::from).boxed()
+    }
+}
+
+#[test]
+fn sensitive_lookups() {
+    let s = "alice".to_string();
+    let s_ptr = s.as_ptr();
+    let key = SensitiveOwnedKey::new(s, b"hunter2".to_vec());
+    // The string was moved in, not copied.
+    assert_eq!(key.key().s.as_ptr(), s_ptr);
+    assert_eq!(format!("{:?}", key), "SensitiveOwnedKey { .. }");
+
+    let mut hash_set = HashSet::new();
+    hash_set.insert(key);
+    let borrowed_key = BorrowedKey {
+        s: "alice",
+        bytes: b"hunter2",
+    };
+    assert!(hash_set.contains(&borrowed_key as &dyn Key));
+
+    // take() hands the key back without dropping it. Zeroizing it is what drop would do.
+    let mut key = hash_set.take(&borrowed_key as &dyn Key).unwrap();
+    key.zeroize();
+    assert_eq!(key.key(), BorrowedKey { s: "", bytes: &[] });
+}
+
+proptest! {
+    #[test]
+    fn sensitive_consistent_borrow(
+        owned1 in any::<SensitiveOwnedKey>(),
+        owned2 in any::<SensitiveOwnedKey>(),
+    ) {
+        assert_consistent::<_, dyn Key>(&owned1, &owned2);
+    }
+}
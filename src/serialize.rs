@@ -0,0 +1,213 @@
+//! Serialize and Deserialize for keys, including zero-copy deserialization of borrowed keys, and
+//! for the crate's key containers.
+
+use crate::map::KeyMap;
+use crate::map_key;
+use crate::set::KeySet;
+use crate::tree_map::KeyBTreeMap;
+use crate::{BorrowedKey, Key, KeyStorage, OwnedKey};
+use proptest::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::hash::BuildHasher;
+
+// The same Borrow rules apply to serialization: an owned key and its borrowed form should serialize
+// to exactly the same thing, so that either can be deserialized as the other. So both serialize
+// through BorrowedKey, and there's a single definition of what a key looks like on the wire.
+//
+// serde serializes a &[u8] as a sequence of numbers unless told otherwise, which is both slow and
+// impossible to borrow from. serde_bytes serializes it as a byte string instead.
+//
+// A BorrowedKey<'de> can be deserialized by borrowing the string and bytes straight out of the
+// input, without copying them. That only works for formats that store them as-is, like postcard or
+// bincode. JSON can't do it: a string with escapes in it has to be unescaped into a new allocation,
+// and bytes are written as an array of numbers. Deserialize an OwnedKey for those formats.
+#[derive(Deserialize, Serialize)]
+#[serde(rename = "Key")]
+struct BorrowedRepr<'a> {
+    s: &'a str,
+    #[serde(borrow, with = "serde_bytes")]
+    bytes: &'a [u8],
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Key")]
+struct OwnedRepr {
+    s: String,
+    #[serde(with = "serde_bytes")]
+    bytes: Vec<u8>,
+}
+
+impl<'a> Serialize for BorrowedKey<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        BorrowedRepr {
+            s: self.s,
+            bytes: self.bytes,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<B: KeyStorage> Serialize for OwnedKey<B> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.key().serialize(serializer)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for BorrowedKey<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let BorrowedRepr { s, bytes } = BorrowedRepr::deserialize(deserializer)?;
+        Ok(BorrowedKey { s, bytes })
+    }
+}
+
+impl<'de, B: From<Vec<u8>>> Deserialize<'de> for OwnedKey<B> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let OwnedRepr { s, bytes } = OwnedRepr::deserialize(deserializer)?;
+        Ok(OwnedKey {
+            s,
+            bytes: bytes.into(),
+        })
+    }
+}
+
+// The containers serialize like the std collections they wrap: a KeySet as a sequence of keys, and
+// KeyMap and KeyBTreeMap as maps. Only the keys and values are written, not the hasher, which is
+// made anew with Default when a set or map is read back, so the reloaded container takes borrowed
+// keys just like the original.
+//
+// Map keys go through map_key.rs, so that the maps can be written as JSON objects, with each key
+// in its string form. Set elements aren't map keys, so they can use the plain key encoding above.
+// As with the std collections, a duplicate key in the input isn't an error: the last one wins.
+impl<S: BuildHasher> Serialize for KeySet<S> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        serializer.collect_seq(self)
+    }
+}
+
+impl<'de, S: BuildHasher + Default> Deserialize<'de> for KeySet<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let keys = Vec::<OwnedKey>::deserialize(deserializer)?;
+        Ok(keys.into_iter().collect())
+    }
+}
+
+impl<V: Serialize, S: BuildHasher> Serialize for KeyMap<V, S> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        map_key::serialize(self, serializer)
+    }
+}
+
+impl<'de, V: Deserialize<'de>, S: BuildHasher + Default> Deserialize<'de> for KeyMap<V, S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        map_key::deserialize(deserializer)
+    }
+}
+
+impl<V: Serialize> Serialize for KeyBTreeMap<V> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        map_key::serialize(self, serializer)
+    }
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for KeyBTreeMap<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        map_key::deserialize(deserializer)
+    }
+}
+
+#[test]
+fn serialize_round_trip() {
+    let key = OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+
+    let json = serde_json::to_string(&key).unwrap();
+    assert_eq!(json, r#"{"s":"foo","bytes":[97,98,99]}"#);
+    assert_eq!(serde_json::from_str::<OwnedKey>(&json).unwrap(), key);
+    // Strings and bytes can't be borrowed out of JSON.
+    assert!(serde_json::from_str::<BorrowedKey<'_>>(&json).is_err());
+
+    // They can be borrowed out of postcard, which stores them as-is.
+    let encoded = postcard::to_allocvec(&key).unwrap();
+    let borrowed: BorrowedKey<'_> = postcard::from_bytes(&encoded).unwrap();
+    assert_eq!(borrowed, key.key());
+    let range = encoded.as_ptr_range();
+    assert!(range.contains(&borrowed.s.as_ptr()) && range.contains(&borrowed.bytes.as_ptr()));
+}
+
+#[test]
+fn serialize_containers() {
+    let key = OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+
+    let mut set = KeySet::new();
+    set.insert(key.clone());
+    let json = serde_json::to_string(&set).unwrap();
+    assert_eq!(json, r#"[{"s":"foo","bytes":[97,98,99]}]"#);
+    let reloaded: KeySet = serde_json::from_str(&json).unwrap();
+    assert!(reloaded.contains(&borrowed_key));
+
+    let mut map = KeyMap::new();
+    map.insert(key.clone(), 1);
+    let json = serde_json::to_string(&map).unwrap();
+    assert_eq!(json, r#"{"foo:616263":1}"#);
+    let reloaded: KeyMap<u32> = serde_json::from_str(&json).unwrap();
+    assert_eq!(reloaded.get(&borrowed_key), Some(&1));
+
+    let tree_map: KeyBTreeMap<u32> = vec![(key, 1)].into_iter().collect();
+    let encoded = postcard::to_allocvec(&tree_map).unwrap();
+    let reloaded: KeyBTreeMap<u32> = postcard::from_bytes(&encoded).unwrap();
+    assert_eq!(reloaded, tree_map);
+    assert_eq!(reloaded.get(&borrowed_key), Some(&1));
+
+    // Duplicates in the input are folded together, the last one winning.
+    let reloaded: KeyMap<u32> = serde_json::from_str(r#"{"foo:":1,"foo:":2}"#).unwrap();
+    assert_eq!(reloaded.len(), 1);
+    assert_eq!(reloaded.get(&BorrowedKey::new("foo", b"")), Some(&2));
+}
+
+proptest! {
+    #[test]
+    fn serialize_containers_round_trip(
+        entries in prop::collection::vec((any::<OwnedKey>(), any::<u32>()), 0..8),
+    ) {
+        let set: KeySet = entries.iter().map(|(key, _)| key.clone()).collect();
+        let map: KeyMap<u32> = entries.iter().cloned().collect();
+        let tree_map: KeyBTreeMap<u32> = entries.iter().cloned().collect();
+
+        let json = serde_json::to_string(&set).unwrap();
+        prop_assert_eq!(&serde_json::from_str::<KeySet>(&json).unwrap(), &set);
+        let encoded = postcard::to_allocvec(&set).unwrap();
+        prop_assert_eq!(&postcard::from_bytes::<KeySet>(&encoded).unwrap(), &set);
+
+        let json = serde_json::to_string(&map).unwrap();
+        prop_assert_eq!(&serde_json::from_str::<KeyMap<u32>>(&json).unwrap(), &map);
+        let encoded = postcard::to_allocvec(&map).unwrap();
+        let reloaded = postcard::from_bytes::<KeyMap<u32>>(&encoded).unwrap();
+        for (key, _) in &entries {
+            prop_assert_eq!(reloaded.get(&key.key()), map.get(key));
+        }
+
+        let json = serde_json::to_string(&tree_map).unwrap();
+        prop_assert_eq!(&serde_json::from_str::<KeyBTreeMap<u32>>(&json).unwrap(), &tree_map);
+    }
+
+    #[test]
+    fn serialize_consistent(key in any::<OwnedKey>()) {
+        // Owned and borrowed keys serialize identically, and each deserializes as the other.
+        let encoded = postcard::to_allocvec(&key).unwrap();
+        prop_assert_eq!(&postcard::to_allocvec(&key.key()).unwrap(), &encoded);
+        prop_assert_eq!(postcard::from_bytes::<BorrowedKey<'_>>(&encoded).unwrap(), key.key());
+        prop_assert_eq!(postcard::from_bytes::<OwnedKey>(&encoded).unwrap(), key.clone());
+
+        let json = serde_json::to_string(&key.key()).unwrap();
+        prop_assert_eq!(serde_json::from_str::<OwnedKey>(&json).unwrap(), key);
+    }
+}
@@ -0,0 +1,380 @@
+//! A set of keys that takes borrowed keys directly.
+
+use crate::collections::{hash_set, HashSet, RandomState};
+#[cfg(test)]
+use crate::consistency::assert_consistent;
+use crate::{BorrowedKey, Key, OwnedKey};
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::hash::BuildHasher;
+use core::iter::FromIterator;
+#[cfg(test)]
+use proptest::prelude::*;
+
+// Everything in the crate root works with a plain HashSet<OwnedKey>, but each lookup has to spell
+// out the coercion: set.contains(&key as &dyn Key). KeySet takes &dyn Key in its signatures, so
+// the coercion happens on its own, and owned and borrowed keys can both be passed in as-is.
+//
+// Like HashSet, KeySet is generic over the hasher, with SipHash as the default. See the hashers
+// module for aliases that use faster ones. Without std, KeySet is backed by hashbrown instead, and
+// the default is hashbrown's.
+/// A set of owned keys, with lookups by owned or borrowed key.
+#[derive(Clone, Debug, Default)]
+pub struct KeySet<S = RandomState> {
+    keys: HashSet<OwnedKey, S>,
+}
+
+impl KeySet {
+    /// Creates a new, empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S: BuildHasher> KeySet<S> {
+    /// Creates a new, empty set that uses `hasher` to hash keys.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            keys: HashSet::with_hasher(hasher),
+        }
+    }
+
+    /// Returns the set's hasher.
+    pub fn hasher(&self) -> &S {
+        self.keys.hasher()
+    }
+
+    /// Adds a key to the set, returning true if it wasn't already present.
+    pub fn insert(&mut self, key: OwnedKey) -> bool {
+        self.keys.insert(key)
+    }
+
+    /// Returns true if the set contains `key`. This never allocates.
+    pub fn contains(&self, key: &dyn Key) -> bool {
+        self.keys.contains(key)
+    }
+
+    /// Returns the stored key equal to `key`, if any.
+    pub fn get(&self, key: &dyn Key) -> Option<&OwnedKey> {
+        self.keys.get(key)
+    }
+
+    /// Removes `key` from the set, returning true if it was present.
+    pub fn remove(&mut self, key: &dyn Key) -> bool {
+        self.keys.remove(key)
+    }
+
+    /// Returns the number of keys in the set.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Iterates over the keys in the set, in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = &OwnedKey> {
+        self.keys.iter()
+    }
+
+    /// Iterates over borrowed views of the keys in the set, in arbitrary order.
+    pub fn iter_borrowed(&self) -> impl Iterator<Item = BorrowedKey<'_>> {
+        self.keys.iter().map(|key| key.key())
+    }
+
+    // The set operations below are HashSet's, except that they yield borrowed views rather than
+    // &OwnedKey, and that the other set can use a different hasher: membership is checked with
+    // contains, which takes a &dyn Key, so the two sets never hash each other's keys with the wrong
+    // hasher. Nothing is copied unless the result is collected into a KeySet, which copies only
+    // the keys that end up in it.
+
+    /// Iterates over the keys in `self` or `other`, without duplicates.
+    pub fn union<'a, T: BuildHasher>(
+        &'a self,
+        other: &'a KeySet<T>,
+    ) -> impl Iterator<Item = BorrowedKey<'a>> + 'a {
+        self.iter_borrowed().chain(other.difference(self))
+    }
+
+    /// Iterates over the keys in both `self` and `other`.
+    pub fn intersection<'a, T: BuildHasher>(
+        &'a self,
+        other: &'a KeySet<T>,
+    ) -> impl Iterator<Item = BorrowedKey<'a>> + 'a {
+        // Walk the smaller set, and look each key up in the larger one. The two sets' iterators
+        // have different types, so rather than picking one, chain both, and cut the one that isn't
+        // walked off before it starts.
+        let walk_other = other.len() < self.len();
+        let (self_len, other_len) = if walk_other {
+            (0, other.len())
+        } else {
+            (self.len(), 0)
+        };
+        self.iter_borrowed()
+            .take(self_len)
+            .chain(other.iter_borrowed().take(other_len))
+            .filter(move |key| {
+                if walk_other {
+                    self.contains(key)
+                } else {
+                    other.contains(key)
+                }
+            })
+    }
+
+    /// Iterates over the keys in `self` but not in `other`.
+    pub fn difference<'a, T: BuildHasher>(
+        &'a self,
+        other: &'a KeySet<T>,
+    ) -> impl Iterator<Item = BorrowedKey<'a>> + 'a {
+        self.iter_borrowed().filter(move |key| !other.contains(key))
+    }
+
+    /// Iterates over the keys in exactly one of `self` and `other`.
+    pub fn symmetric_difference<'a, T: BuildHasher>(
+        &'a self,
+        other: &'a KeySet<T>,
+    ) -> impl Iterator<Item = BorrowedKey<'a>> + 'a {
+        self.difference(other).chain(other.difference(self))
+    }
+
+    /// Returns true if every key in `self` is also in `other`.
+    pub fn is_subset<T: BuildHasher>(&self, other: &KeySet<T>) -> bool {
+        self.len() <= other.len() && self.difference(other).next().is_none()
+    }
+
+    /// Returns true if every key in `other` is also in `self`.
+    pub fn is_superset<T: BuildHasher>(&self, other: &KeySet<T>) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Returns true if `self` and `other` have no keys in common.
+    pub fn is_disjoint<T: BuildHasher>(&self, other: &KeySet<T>) -> bool {
+        self.intersection(other).next().is_none()
+    }
+}
+
+impl<S: BuildHasher> PartialEq for KeySet<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.keys == other.keys
+    }
+}
+
+impl<S: BuildHasher> Eq for KeySet<S> {}
+
+impl<S: BuildHasher + Default> FromIterator<OwnedKey> for KeySet<S> {
+    fn from_iter<I: IntoIterator<Item = OwnedKey>>(iter: I) -> Self {
+        Self {
+            keys: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<S: BuildHasher> Extend<OwnedKey> for KeySet<S> {
+    fn extend<I: IntoIterator<Item = OwnedKey>>(&mut self, iter: I) {
+        self.keys.extend(iter)
+    }
+}
+
+// Keys often arrive as plain (string, bytes) pairs, out of a database row or a deserializer, before
+// anyone has made OwnedKeys out of them.
+impl<S: BuildHasher + Default> FromIterator<(String, Vec<u8>)> for KeySet<S> {
+    fn from_iter<I: IntoIterator<Item = (String, Vec<u8>)>>(iter: I) -> Self {
+        iter.into_iter()
+            .map(|(s, bytes)| OwnedKey { s, bytes })
+            .collect()
+    }
+}
+
+impl<S: BuildHasher> Extend<(String, Vec<u8>)> for KeySet<S> {
+    fn extend<I: IntoIterator<Item = (String, Vec<u8>)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(s, bytes)| OwnedKey { s, bytes }))
+    }
+}
+
+// Collecting borrowed keys, say from a set operation, copies each one once.
+impl<'a, S: BuildHasher + Default> FromIterator<BorrowedKey<'a>> for KeySet<S> {
+    fn from_iter<I: IntoIterator<Item = BorrowedKey<'a>>>(iter: I) -> Self {
+        let mut set = Self::with_hasher(S::default());
+        set.extend(iter);
+        set
+    }
+}
+
+// Extending from borrowed keys has to copy them, but only the ones that aren't there already.
+impl<'a, S: BuildHasher> Extend<BorrowedKey<'a>> for KeySet<S> {
+    fn extend<I: IntoIterator<Item = BorrowedKey<'a>>>(&mut self, iter: I) {
+        for key in iter {
+            if !self.keys.contains(&key as &dyn Key) {
+                self.keys.insert((&key as &dyn Key).to_owned());
+            }
+        }
+    }
+}
+
+impl<'a, S: BuildHasher> Extend<&'a OwnedKey> for KeySet<S> {
+    fn extend<I: IntoIterator<Item = &'a OwnedKey>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|key| key.key()))
+    }
+}
+
+impl<S> IntoIterator for KeySet<S> {
+    type Item = OwnedKey;
+    type IntoIter = hash_set::IntoIter<OwnedKey>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys.into_iter()
+    }
+}
+
+impl<'a, S> IntoIterator for &'a KeySet<S> {
+    type Item = &'a OwnedKey;
+    type IntoIter = hash_set::Iter<'a, OwnedKey>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys.iter()
+    }
+}
+
+#[test]
+fn set_lookups() {
+    let mut set = KeySet::new();
+    let key = OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+    assert!(set.insert(key.clone()));
+    assert!(!set.insert(key.clone()));
+
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert!(set.contains(&borrowed_key));
+    assert!(set.contains(&key));
+    assert_eq!(set.get(&borrowed_key), Some(&key));
+    assert!(set.remove(&borrowed_key));
+    assert!(set.is_empty());
+}
+
+#[test]
+fn set_conversions() {
+    let mut set: KeySet = vec![("foo".to_string(), b"abc".to_vec())]
+        .into_iter()
+        .collect();
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert!(set.contains(&borrowed_key));
+
+    let other = OwnedKey {
+        s: "bar".to_string(),
+        bytes: vec![],
+    };
+    set.extend([borrowed_key, other.key()]);
+    set.extend([&other]);
+    set.extend([("baz".to_string(), vec![1])]);
+    assert_eq!(set.len(), 3);
+
+    let mut borrowed: Vec<_> = set.iter_borrowed().collect();
+    borrowed.sort();
+    assert_eq!(borrowed[0], other.key());
+    let by_ref: Vec<&OwnedKey> = (&set).into_iter().collect();
+    assert_eq!(by_ref.len(), 3);
+    let mut owned: Vec<OwnedKey> = set.into_iter().collect();
+    owned.sort();
+    assert_eq!(owned[0], other);
+}
+
+#[test]
+fn set_operations() {
+    let a: KeySet = vec![("a", b"1"), ("b", b"2"), ("c", b"3")]
+        .into_iter()
+        .map(|(s, bytes)| (s.to_string(), bytes.to_vec()))
+        .collect();
+    let b: KeySet = vec![("b", b"2"), ("c", b"4")]
+        .into_iter()
+        .map(|(s, bytes)| (s.to_string(), bytes.to_vec()))
+        .collect();
+    let sorted = |iter: &mut dyn Iterator<Item = BorrowedKey<'_>>| {
+        let mut keys: Vec<(String, Vec<u8>)> = iter
+            .map(|key| (key.s.to_string(), key.bytes.to_vec()))
+            .collect();
+        keys.sort();
+        keys.into_iter()
+            .map(|(s, bytes)| format!("{}{}", s, String::from_utf8(bytes).unwrap()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(sorted(&mut a.union(&b)), ["a1", "b2", "c3", "c4"]);
+    assert_eq!(sorted(&mut a.intersection(&b)), ["b2"]);
+    assert_eq!(sorted(&mut b.intersection(&a)), ["b2"]);
+    assert_eq!(sorted(&mut a.difference(&b)), ["a1", "c3"]);
+    assert_eq!(sorted(&mut a.symmetric_difference(&b)), ["a1", "c3", "c4"]);
+
+    // The views borrow from the sets: nothing is copied until they're collected. b is the
+    // smaller set, so the intersection walks it.
+    let in_b = b.get(&BorrowedKey::new("b", b"2")).unwrap();
+    let common = a.intersection(&b).next().unwrap();
+    assert!(std::ptr::eq(common.s, in_b.s.as_str()));
+    let union: KeySet = a.union(&b).collect();
+    assert_eq!(union.len(), 4);
+
+    let c: KeySet = a.intersection(&b).collect();
+    assert!(c.is_subset(&a) && c.is_subset(&b));
+    assert!(a.is_superset(&c));
+    assert!(!a.is_subset(&b));
+    assert!(!a.is_disjoint(&b));
+    assert!(KeySet::new().is_disjoint(&a));
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn set_contains_inserted(keys in prop::collection::vec(any::<OwnedKey>(), 0..16)) {
+        let set: KeySet = keys.iter().cloned().collect();
+        for key in &keys {
+            prop_assert!(set.contains(&key.key()));
+        }
+
+        // However the set is built, it ends up with the same keys.
+        let mut from_borrowed = KeySet::new();
+        from_borrowed.extend(keys.iter().map(|key| key.key()));
+        prop_assert_eq!(&from_borrowed, &set);
+        let from_tuples: KeySet = keys.iter().map(|key| (key.s.clone(), key.bytes.clone())).collect();
+        prop_assert_eq!(&from_tuples, &set);
+        if let [owned1, owned2, ..] = &keys[..] {
+            assert_consistent::<_, dyn Key>(owned1, owned2);
+        }
+    }
+
+    #[test]
+    fn set_operations_match_hash_set(
+        a in prop::collection::hash_set(any::<OwnedKey>(), 0..8),
+        b in prop::collection::hash_set(any::<OwnedKey>(), 0..8),
+    ) {
+        // Share some keys, so the operations have something to do.
+        let b: std::collections::HashSet<OwnedKey> =
+            b.into_iter().chain(a.iter().take(2).cloned()).collect();
+        let key_a: KeySet = a.iter().cloned().collect();
+        let key_b: KeySet = b.iter().cloned().collect();
+        let check = |ours: KeySet, theirs: Vec<&OwnedKey>| {
+            let theirs: KeySet = theirs.into_iter().cloned().collect();
+            assert_eq!(ours, theirs);
+        };
+        check(key_a.union(&key_b).collect(), a.union(&b).collect());
+        check(key_a.intersection(&key_b).collect(), a.intersection(&b).collect());
+        check(key_a.difference(&key_b).collect(), a.difference(&b).collect());
+        check(
+            key_a.symmetric_difference(&key_b).collect(),
+            a.symmetric_difference(&b).collect(),
+        );
+        prop_assert_eq!(key_a.union(&key_b).count(), a.union(&b).count());
+        prop_assert_eq!(key_a.is_subset(&key_b), a.is_subset(&b));
+        prop_assert_eq!(key_a.is_disjoint(&key_b), a.is_disjoint(&b));
+    }
+}
@@ -0,0 +1,98 @@
+//! A cheaply-cloneable owned key, backed by reference-counted fields.
+
+use crate::consistency::assert_consistent;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::any::Any;
+use std::borrow::Borrow;
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
+
+// If the same key needs to live in several indexes at once, cloning an OwnedKey into each of them
+// copies the string and the bytes every time. Swapping the fields for Arc<str> and Arc<[u8]> makes a
+// clone just two reference count bumps.
+//
+// Nothing else about the pattern changes. Arc<T> forwards Eq, Ord and Hash to T, so the derived
+// impls below behave exactly like the ones on OwnedKey, and the key can borrow as the very same
+// BorrowedKey.
+/// An owned key whose fields are shared, so that clones don't copy the underlying data.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SharedOwnedKey {
+    pub s: Arc<str>,
+    pub bytes: Arc<[u8]>,
+}
+
+impl Key for SharedOwnedKey {
+    fn key<'k>(&'k self) -> BorrowedKey<'k> {
+        BorrowedKey {
+            s: &self.s,
+            bytes: &self.bytes,
+        }
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+impl<'a> Borrow<dyn Key + 'a> for SharedOwnedKey {
+    fn borrow(&self) -> &(dyn Key + 'a) {
+        self
+    }
+}
+
+impl<'a> From<BorrowedKey<'a>> for SharedOwnedKey {
+    fn from(key: BorrowedKey<'a>) -> Self {
+        Self {
+            s: key.s.into(),
+            bytes: key.bytes.into(),
+        }
+    }
+}
+
+impl From<OwnedKey> for SharedOwnedKey {
+    fn from(key: OwnedKey) -> Self {
+        Self {
+            s: key.s.into(),
+            bytes: key.bytes.into(),
+        }
+    }
+}
+
+impl Arbitrary for SharedOwnedKey {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        any::<OwnedKey>().prop_map(SharedOwnedKey::from).boxed()
+    }
+}
+
+#[test]
+fn shared_lookups() {
+    let key = SharedOwnedKey::from(BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    });
+
+    // The same key, in two indexes, sharing a single copy of its data.
+    let mut hash_set = HashSet::new();
+    let mut btree_set = BTreeSet::new();
+    hash_set.insert(key.clone());
+    btree_set.insert(key.clone());
+    assert!(Arc::ptr_eq(&key.s, &hash_set.iter().next().unwrap().s));
+
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert!(hash_set.contains(&borrowed_key as &dyn Key));
+    assert!(btree_set.contains(&borrowed_key as &dyn Key));
+}
+
+proptest! {
+    #[test]
+    fn shared_consistent_borrow(owned1 in any::<SharedOwnedKey>(), owned2 in any::<SharedOwnedKey>()) {
+        assert_consistent::<_, dyn Key>(&owned1, &owned2);
+    }
+}
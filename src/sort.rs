@@ -0,0 +1,172 @@
+//! Sorting and deduplicating keys through their borrowed views.
+
+#[cfg(test)]
+use crate::owned;
+#[cfg(test)]
+use crate::tree_map::KeyBTreeMap;
+use crate::Key;
+#[cfg(test)]
+use crate::{BorrowedKey, OwnedKey};
+use alloc::vec::Vec;
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(feature = "rayon")]
+use rayon::slice::ParallelSliceMut;
+#[cfg(test)]
+use std::collections::BTreeSet;
+
+// Bulk pipelines often end by handing a Vec of keys to something that wants them sorted and
+// distinct: KeyBTreeMap::from_sorted_iter, SortedKeyVec, a file that's read back with a binary
+// search. A Vec<OwnedKey> can already be sorted with sort(), since OwnedKey is Ord, but that only
+// works for key types that implement Ord themselves, and the order is only right if their Ord is
+// consistent with the borrowed one.
+//
+// The functions here compare keys by their borrowed views instead, the way every lookup in the
+// crate does, so they work on any key type, mixed key types behind &dyn Key included, and the
+// order they produce is the order the collections search in. The checks are the same comparisons
+// run over pairs of neighbours.
+//
+// With the rayon feature on, the par_ versions sort on every core, for batches of millions of keys.
+// Comparing borrowed views doesn't touch any shared state, so it parallelizes as well as sorting
+// plain integers does. They need the keys to be Send, which rules out &dyn Key; &(dyn Key + Sync)
+// works.
+/// Sorts `keys` by their borrowed views. The sort is stable, so equal keys keep their order.
+pub fn sort_keys<K: Key>(keys: &mut [K]) {
+    keys.sort_by(|a, b| a.key().cmp(&b.key()));
+}
+
+/// Sorts `keys` by their borrowed views, without keeping equal keys in order. Faster than
+/// [`sort_keys`] when that doesn't matter.
+pub fn sort_keys_unstable<K: Key>(keys: &mut [K]) {
+    keys.sort_unstable_by(|a, b| a.key().cmp(&b.key()));
+}
+
+/// Removes consecutive keys whose borrowed views are equal, keeping the first of each run.
+///
+/// On sorted keys, this leaves each key once.
+pub fn dedup_keys<K: Key>(keys: &mut Vec<K>) {
+    keys.dedup_by(|a, b| a.key() == b.key());
+}
+
+/// Sorts and deduplicates `keys`, leaving them in the form the sorted-build APIs expect.
+///
+/// Of each set of equal keys, the one that came first is kept.
+pub fn normalize_keys<K: Key>(keys: &mut Vec<K>) {
+    sort_keys(keys);
+    dedup_keys(keys);
+}
+
+/// Sorts `keys` by their borrowed views in parallel. The sort is stable, like [`sort_keys`].
+///
+/// Requires the `rayon` feature.
+#[cfg(feature = "rayon")]
+pub fn par_sort_keys<K: Key + Send>(keys: &mut [K]) {
+    keys.par_sort_by(|a, b| a.key().cmp(&b.key()));
+}
+
+/// Sorts `keys` by their borrowed views in parallel, without keeping equal keys in order.
+///
+/// Requires the `rayon` feature.
+#[cfg(feature = "rayon")]
+pub fn par_sort_keys_unstable<K: Key + Send>(keys: &mut [K]) {
+    keys.par_sort_unstable_by(|a, b| a.key().cmp(&b.key()));
+}
+
+/// Sorts `keys` in parallel and deduplicates them, like [`normalize_keys`]. Deduplicating is a
+/// single pass, and isn't parallel.
+///
+/// Requires the `rayon` feature.
+#[cfg(feature = "rayon")]
+pub fn par_normalize_keys<K: Key + Send>(keys: &mut Vec<K>) {
+    par_sort_keys(keys);
+    dedup_keys(keys);
+}
+
+/// Returns true if `keys` are sorted by their borrowed views, allowing equal neighbours.
+pub fn is_sorted_by_key<K: Key>(keys: &[K]) -> bool {
+    keys.windows(2).all(|pair| pair[0].key() <= pair[1].key())
+}
+
+/// Returns true if `keys` are sorted by their borrowed views, with no two equal. This is what
+/// [`KeyBTreeMap::from_sorted_iter`](crate::tree_map::KeyBTreeMap::from_sorted_iter) requires.
+pub fn is_strictly_sorted_by_key<K: Key>(keys: &[K]) -> bool {
+    keys.windows(2).all(|pair| pair[0].key() < pair[1].key())
+}
+
+#[test]
+fn sort_pipeline() {
+    let mut keys = vec![
+        owned("b", b""),
+        owned("a", b"2"),
+        owned("a", b"1"),
+        owned("b", b""),
+        owned("a", b"2"),
+    ];
+    assert!(!is_sorted_by_key(&keys));
+
+    sort_keys(&mut keys);
+    assert!(is_sorted_by_key(&keys));
+    assert!(!is_strictly_sorted_by_key(&keys));
+
+    dedup_keys(&mut keys);
+    assert!(is_strictly_sorted_by_key(&keys));
+    assert_eq!(keys, [owned("a", b"1"), owned("a", b"2"), owned("b", b"")]);
+
+    // Ready for the sorted-build APIs.
+    let map = KeyBTreeMap::from_sorted_iter(keys.into_iter().map(|key| (key, ()))).unwrap();
+    assert_eq!(map.len(), 3);
+
+    // Any key type works, including mixed ones behind trait objects.
+    let borrowed_key = BorrowedKey::new("a", b"1");
+    let owned_key = owned("a", b"1");
+    let other_key = owned("", b"");
+    let mut mixed: Vec<&dyn Key> = vec![&borrowed_key, &other_key, &owned_key];
+    normalize_keys(&mut mixed);
+    assert_eq!(mixed.len(), 2);
+    assert!(mixed[0] == &other_key as &dyn Key);
+    // The first of the equal keys is the one that's kept.
+    assert!(std::ptr::eq(
+        mixed[1] as *const dyn Key as *const u8,
+        &borrowed_key as *const BorrowedKey<'_> as *const u8
+    ));
+
+    #[cfg(feature = "rayon")]
+    {
+        let mut sync_keys: Vec<&(dyn Key + Sync)> = vec![&owned_key, &other_key, &borrowed_key];
+        par_sort_keys(&mut sync_keys);
+        assert_eq!(sync_keys[0].key(), other_key.key());
+        assert!(is_sorted_by_key(&sync_keys));
+    }
+
+    let empty: [OwnedKey; 0] = [];
+    assert!(is_strictly_sorted_by_key(&empty));
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn sort_matches_btree_set(keys in prop::collection::vec(any::<OwnedKey>(), 0..16)) {
+        let btree_set: BTreeSet<OwnedKey> = keys.iter().cloned().collect();
+        let mut normalized = keys.clone();
+        normalize_keys(&mut normalized);
+        prop_assert!(normalized.iter().eq(btree_set.iter()));
+        prop_assert!(is_strictly_sorted_by_key(&normalized));
+
+        let mut sorted = keys.clone();
+        sort_keys_unstable(&mut sorted);
+        prop_assert!(is_sorted_by_key(&sorted));
+        #[cfg(feature = "rayon")]
+        {
+            let mut par_normalized = keys.clone();
+            par_normalize_keys(&mut par_normalized);
+            prop_assert_eq!(&par_normalized, &normalized);
+            let mut par_sorted = keys.clone();
+            par_sort_keys_unstable(&mut par_sorted);
+            prop_assert_eq!(&par_sorted, &sorted);
+        }
+        prop_assert_eq!(
+            is_strictly_sorted_by_key(&keys),
+            KeyBTreeMap::from_sorted_iter(keys.into_iter().map(|key| (key, ()))).is_ok()
+        );
+    }
+}
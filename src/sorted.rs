@@ -0,0 +1,206 @@
+//! A sorted `Vec` of keys, for sets that are built once and then only read.
+
+#[cfg(test)]
+use crate::owned;
+use crate::sort::{dedup_keys, sort_keys_unstable};
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::collections::BTreeSet;
+use std::iter::FromIterator;
+use std::ops::{Bound, RangeBounds};
+use std::slice;
+
+// A BTreeSet<OwnedKey> keeps its keys sorted so that they can be inserted and removed at any time.
+// If the set is built once and then only read, such as a table loaded at startup, that flexibility
+// isn't needed. A sorted Vec holds the same keys in one allocation, with no per-node overhead, and
+// binary searching it touches far less memory than walking the tree does.
+//
+// SortedKeyVec doesn't need Borrow at all: slices already have binary_search_by and
+// partition_point, which take a closure instead of a key. The closures compare borrowed views, so
+// lookups and range queries take a &dyn Key, just as BTreeSet's do with the crate root's Borrow
+// impl.
+//
+// Building one sorts and then deduplicates, through the same borrowed views (see sort.rs). The
+// order is Ord on BorrowedKey, which is the order a BTreeSet<OwnedKey> would use, since the two are
+// consistent.
+/// A set of keys, stored as a sorted `Vec`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct SortedKeyVec {
+    keys: Vec<OwnedKey>,
+}
+
+impl SortedKeyVec {
+    /// Creates a new, empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Searches for `key`. Returns its index if it's present, or the index where it would be
+    /// inserted if it isn't, like [`slice::binary_search`].
+    pub fn binary_search_key(&self, key: &dyn Key) -> Result<usize, usize> {
+        let key = key.key();
+        self.keys.binary_search_by(|probe| probe.key().cmp(&key))
+    }
+
+    /// Returns true if the set has `key`.
+    pub fn contains(&self, key: &dyn Key) -> bool {
+        self.binary_search_key(key).is_ok()
+    }
+
+    /// Returns the stored key equal to `key`, if any.
+    pub fn get(&self, key: &dyn Key) -> Option<&OwnedKey> {
+        let index = self.binary_search_key(key).ok()?;
+        Some(&self.keys[index])
+    }
+
+    /// Returns the keys in `range`, in order, as a slice.
+    ///
+    /// A range whose start is after its end is empty.
+    pub fn range<'k, R: RangeBounds<&'k dyn Key>>(&self, range: R) -> &[OwnedKey] {
+        let start = match range.start_bound() {
+            Bound::Included(start) => self.partition_point(|key| key < start.key()),
+            Bound::Excluded(start) => self.partition_point(|key| key <= start.key()),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => self.partition_point(|key| key <= end.key()),
+            Bound::Excluded(end) => self.partition_point(|key| key < end.key()),
+            Bound::Unbounded => self.keys.len(),
+        };
+        &self.keys[start..end.max(start)]
+    }
+
+    /// Returns the number of keys in the set.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Iterates over the keys, in order.
+    pub fn iter(&self) -> slice::Iter<'_, OwnedKey> {
+        self.keys.iter()
+    }
+
+    /// Iterates over borrowed views of the keys, in order.
+    pub fn iter_borrowed(&self) -> impl Iterator<Item = BorrowedKey<'_>> {
+        self.keys.iter().map(|key| key.key())
+    }
+
+    /// Returns the keys as a sorted slice.
+    pub fn as_slice(&self) -> &[OwnedKey] {
+        &self.keys
+    }
+
+    /// Returns the keys as a sorted `Vec`.
+    pub fn into_vec(self) -> Vec<OwnedKey> {
+        self.keys
+    }
+
+    fn partition_point(&self, mut pred: impl FnMut(BorrowedKey<'_>) -> bool) -> usize {
+        self.keys.partition_point(|key| pred(key.key()))
+    }
+}
+
+impl FromIterator<OwnedKey> for SortedKeyVec {
+    fn from_iter<I: IntoIterator<Item = OwnedKey>>(iter: I) -> Self {
+        let mut keys: Vec<_> = iter.into_iter().collect();
+        sort_keys_unstable(&mut keys);
+        dedup_keys(&mut keys);
+        Self { keys }
+    }
+}
+
+impl<'a> IntoIterator for &'a SortedKeyVec {
+    type Item = &'a OwnedKey;
+    type IntoIter = slice::Iter<'a, OwnedKey>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[test]
+fn sorted_lookups() {
+    let sorted: SortedKeyVec = vec![
+        owned("b", b""),
+        owned("a", b"2"),
+        owned("a", b"1"),
+        owned("b", b""),
+        owned("c", b"x"),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(sorted.len(), 4);
+    assert_eq!(
+        sorted.as_slice(),
+        [
+            owned("a", b"1"),
+            owned("a", b"2"),
+            owned("b", b""),
+            owned("c", b"x")
+        ]
+    );
+    assert!(sorted
+        .iter_borrowed()
+        .eq(sorted.iter().map(|key| key.key())));
+
+    let borrowed_key = BorrowedKey {
+        s: "a",
+        bytes: b"2",
+    };
+    assert_eq!(sorted.binary_search_key(&borrowed_key), Ok(1));
+    assert_eq!(sorted.binary_search_key(&owned("a", b"3")), Err(2));
+    assert_eq!(sorted.get(&borrowed_key), Some(&owned("a", b"2")));
+    assert!(!sorted.contains(&owned("", b"")));
+
+    // Every key with the string "a": from ("a", b"") up to, but not including, ("a\0", b"").
+    let start = owned("a", b"");
+    let end = owned("a\0", b"");
+    let a_keys = sorted.range(&start as &dyn Key..&end);
+    assert_eq!(a_keys, [owned("a", b"1"), owned("a", b"2")]);
+
+    let b = owned("b", b"");
+    assert_eq!(sorted.range(&b as &dyn Key..).len(), 2);
+    assert_eq!(sorted.range(..=&b as &dyn Key).len(), 3);
+    assert_eq!(
+        sorted
+            .range((Bound::Excluded(&b as &dyn Key), Bound::Unbounded))
+            .len(),
+        1
+    );
+    // Backwards ranges are empty.
+    assert!(sorted.range(&end as &dyn Key..&start).is_empty());
+}
+
+proptest! {
+    #[test]
+    fn sorted_matches_btree_set(
+        keys in prop::collection::vec(any::<OwnedKey>(), 0..16),
+        probe in any::<OwnedKey>(),
+        bounds in (any::<OwnedKey>(), any::<OwnedKey>()),
+    ) {
+        let sorted: SortedKeyVec = keys.iter().cloned().collect();
+        let btree_set: BTreeSet<OwnedKey> = keys.into_iter().collect();
+        prop_assert!(sorted.iter().eq(btree_set.iter()));
+
+        // Probe with a stored key too, so that hits are tested as well as misses.
+        for probe in btree_set.iter().take(1).chain([&probe]) {
+            prop_assert_eq!(sorted.contains(probe), btree_set.contains(probe));
+            let position = btree_set.range(..probe.clone()).count();
+            let expected = if btree_set.contains(probe) { Ok(position) } else { Err(position) };
+            prop_assert_eq!(sorted.binary_search_key(probe), expected);
+        }
+
+        let (start, end) = bounds;
+        if start <= end {
+            let range = sorted.range(&start as &dyn Key..&end);
+            prop_assert!(range.iter().eq(btree_set.range(start.clone()..end.clone())));
+            let range = sorted.range(&start as &dyn Key..=&end);
+            prop_assert!(range.iter().eq(btree_set.range(start..=end)));
+        }
+    }
+}
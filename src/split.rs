@@ -0,0 +1,131 @@
+//! Borrowed keys whose string and bytes come from buffers with different lifetimes.
+
+#[cfg(test)]
+use crate::consistency::assert_consistent;
+use crate::key_fields::KeyFields;
+#[cfg(test)]
+use crate::OwnedKey;
+use crate::{BorrowedKey, Key};
+use core::borrow::Borrow;
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+use std::collections::{BTreeSet, HashSet};
+
+// BorrowedKey has a single lifetime for both of its fields. That's usually fine: a probe built from
+// a string and a byte string with different lifetimes gets the shorter of the two, since both
+// references are covariant and the compiler shortens the longer one to match.
+//
+// It stops being fine when the probe has to be kept around, or handed back to the caller, because
+// the single lifetime forgets which field was the long-lived one. A parser that keeps the string
+// from its input and the bytes from a scratch buffer can't return a BorrowedKey and later reuse
+// the buffer, even though the string is still good.
+//
+// SplitKey keeps the two lifetimes apart. It's still covariant in each of them, independently, and
+// Key::key shortens both to the lifetime of the borrow, so a &SplitKey<'s, 'b> coerces to
+// &(dyn Key + 'k) for any 'k that both 's and 'b outlive, and probes collections like any other
+// key. Changing BorrowedKey itself to have two lifetimes would have worked too, but would have
+// made every signature in the crate that mentions it longer, for the sake of the few callers that
+// care.
+/// A borrowed key with separate lifetimes for its string and its bytes.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, KeyFields)]
+pub struct SplitKey<'s, 'b> {
+    pub s: &'s str,
+    pub bytes: &'b [u8],
+}
+
+impl<'s, 'b> SplitKey<'s, 'b> {
+    /// Creates a key from a string and bytes with different lifetimes.
+    pub const fn new(s: &'s str, bytes: &'b [u8]) -> Self {
+        Self { s, bytes }
+    }
+
+    /// Shortens both lifetimes to `'a`, returning a [`BorrowedKey`].
+    pub fn shorten<'a>(self) -> BorrowedKey<'a>
+    where
+        's: 'a,
+        'b: 'a,
+    {
+        BorrowedKey {
+            s: self.s,
+            bytes: self.bytes,
+        }
+    }
+}
+
+impl<'a> From<BorrowedKey<'a>> for SplitKey<'a, 'a> {
+    fn from(key: BorrowedKey<'a>) -> Self {
+        Self::new(key.s, key.bytes)
+    }
+}
+
+impl<'s, 'b> Key for SplitKey<'s, 'b> {
+    fn key<'k>(&'k self) -> BorrowedKey<'k> {
+        // 's and 'b both outlive 'k, since &'k self is only valid while they are.
+        self.shorten()
+    }
+}
+
+// Like BorrowedKey, a set of SplitKeys can be probed with any other key, for any shorter trait
+// object lifetime 'a.
+impl<'a, 's: 'a, 'b: 'a> Borrow<dyn Key + 'a> for SplitKey<'s, 'b> {
+    fn borrow(&self) -> &(dyn Key + 'a) {
+        self
+    }
+}
+
+crate::assert_key_fields_match!(BorrowedKey, SplitKey);
+
+// SplitKey is covariant in 's and 'b separately: either one can be shortened while the other is
+// left alone. These would fail to compile if it weren't.
+#[cfg(test)]
+fn shorten_s<'long: 'short, 'short, 'b>(key: SplitKey<'long, 'b>) -> SplitKey<'short, 'b> {
+    key
+}
+
+#[cfg(test)]
+fn shorten_bytes<'s, 'long: 'short, 'short>(key: SplitKey<'s, 'long>) -> SplitKey<'s, 'short> {
+    key
+}
+
+#[test]
+fn split_lookups() {
+    let mut hash_set: HashSet<OwnedKey> = HashSet::new();
+    hash_set.insert(OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    });
+    let btree_set: BTreeSet<OwnedKey> = hash_set.iter().cloned().collect();
+
+    // The string lives for 'static, the bytes only for this block.
+    let s: &'static str = "foo";
+    let found = {
+        let bytes = b"abc".to_vec();
+        let key = SplitKey::new(s, &bytes);
+        assert!(hash_set.contains(&key as &dyn Key));
+        assert!(btree_set.contains(&key as &dyn Key));
+        assert_eq!(key.shorten(), BorrowedKey::new("foo", b"abc"));
+        key.s
+    };
+    // ... and the string is still usable after the bytes are gone.
+    assert_eq!(found, "foo");
+
+    let key = SplitKey::from(BorrowedKey::new("bar", b""));
+    let key = shorten_bytes(shorten_s(key));
+    assert!(!hash_set.contains(&key as &dyn Key));
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn split_consistent_borrow(owned1 in any::<OwnedKey>(), owned2 in any::<OwnedKey>()) {
+        // SplitKey's derives agree with dyn Key's comparisons, and so with every other key type.
+        let (split1, split2) = (
+            SplitKey::new(&owned1.s, &owned1.bytes),
+            SplitKey::new(&owned2.s, &owned2.bytes),
+        );
+        assert_consistent::<_, dyn Key>(&split1, &split2);
+        assert_consistent::<_, dyn Key>(&split1.shorten(), &split2.shorten());
+        prop_assert!(&split1 as &dyn Key == &owned1 as &dyn Key);
+    }
+}
@@ -0,0 +1,283 @@
+//! A key map stored in an SQLite table, with the key's fields as a compound primary key.
+//!
+//! Requires the `sqlite` feature.
+
+#[cfg(test)]
+use crate::owned;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use rusqlite::types::ToSql;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+
+// PersistentKeyMap has to squash a key into one byte string, because redb's keys are byte strings.
+// A relational database doesn't: a table can have a primary key made of several columns. So
+// SqliteKeyMap stores a key's string and bytes in columns of their own, and makes the pair the
+// primary key. Other queries, and people poking at the database with the sqlite3 shell, can read
+// the columns as they are.
+//
+// Queries bind the fields of a BorrowedKey directly as statement parameters. SQLite copies them in
+// while binding, so there's no need for an OwnedKey, or an encoded copy of the key, per query.
+// Statements are prepared once and cached by the connection, so a lookup doesn't parse any SQL
+// either.
+//
+// The primary key also keeps the rows in key order:
+//
+// - SQLite compares TEXT with the BINARY collation by default, which is memcmp on the UTF-8
+//   bytes. That's the same order as Ord on str.
+// - BLOBs are always compared with memcmp, which is the same order as Ord on [u8].
+// - A compound primary key compares its columns lexicographically, which is the same order as
+//   OwnedKey's derived Ord.
+//
+// So a range scan is an index scan with a row value comparison like (s, bytes) >= (?1, ?2), and
+// ORDER BY s, bytes comes straight out of the index.
+/// A map from keys to byte strings, stored in an SQLite table.
+pub struct SqliteKeyMap {
+    conn: Connection,
+}
+
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS keys (
+    s TEXT NOT NULL,
+    bytes BLOB NOT NULL,
+    value BLOB NOT NULL,
+    PRIMARY KEY (s, bytes)
+) WITHOUT ROWID";
+
+impl SqliteKeyMap {
+    /// Opens the map stored in the database file at `path`, creating the file if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Creates a new, empty map that's only stored in memory.
+    pub fn in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    /// Stores the map in a table called `keys` in an already open database, creating the table if
+    /// it doesn't exist.
+    pub fn from_connection(conn: Connection) -> Result<Self> {
+        conn.execute(CREATE_TABLE, [])?;
+        Ok(Self { conn })
+    }
+
+    /// Inserts a value for `key`, returning the old value if there was one.
+    pub fn insert(&mut self, key: BorrowedKey<'_>, value: &[u8]) -> Result<Option<Vec<u8>>> {
+        // Reading the old value and writing the new one in the same transaction means that nothing
+        // else can get in between.
+        let txn = self.conn.transaction()?;
+        let old = get(&txn, key)?;
+        txn.prepare_cached("INSERT OR REPLACE INTO keys (s, bytes, value) VALUES (?1, ?2, ?3)")?
+            .execute(params![key.s, key.bytes, value])?;
+        txn.commit()?;
+        Ok(old)
+    }
+
+    /// Removes the value for `key`, returning it if there was one.
+    pub fn remove(&mut self, key: BorrowedKey<'_>) -> Result<Option<Vec<u8>>> {
+        self.conn
+            .prepare_cached("DELETE FROM keys WHERE s = ?1 AND bytes = ?2 RETURNING value")?
+            .query_row(params![key.s, key.bytes], |row| row.get(0))
+            .optional()
+    }
+
+    /// Returns the value for `key`, if any.
+    pub fn get(&self, key: BorrowedKey<'_>) -> Result<Option<Vec<u8>>> {
+        get(&self.conn, key)
+    }
+
+    /// Returns true if the map has a value for `key`.
+    pub fn contains_key(&self, key: BorrowedKey<'_>) -> Result<bool> {
+        self.conn
+            .prepare_cached("SELECT EXISTS (SELECT 1 FROM keys WHERE s = ?1 AND bytes = ?2)")?
+            .query_row(params![key.s, key.bytes], |row| row.get(0))
+    }
+
+    /// Returns the entries with keys in `range`, in key order.
+    ///
+    /// A range whose start is after its end is empty.
+    pub fn range<'k, R: RangeBounds<BorrowedKey<'k>>>(
+        &self,
+        range: R,
+    ) -> Result<Vec<(OwnedKey, Vec<u8>)>> {
+        let mut conditions = Vec::new();
+        let mut params: Vec<&dyn ToSql> = Vec::new();
+        let bounds = [
+            (range.start_bound(), ">=", ">"),
+            (range.end_bound(), "<=", "<"),
+        ];
+        for (bound, included, excluded) in bounds {
+            let (op, key) = match bound {
+                Bound::Included(key) => (included, key),
+                Bound::Excluded(key) => (excluded, key),
+                Bound::Unbounded => continue,
+            };
+            conditions.push(format!(
+                "(s, bytes) {} (?{}, ?{})",
+                op,
+                params.len() + 1,
+                params.len() + 2
+            ));
+            params.push(&key.s);
+            params.push(&key.bytes);
+        }
+
+        // There are only nine shapes of query, so they all stay in the statement cache.
+        let mut sql = String::from("SELECT s, bytes, value FROM keys");
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY s, bytes");
+
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let rows = stmt.query_map(&params[..], |row| {
+            let key = OwnedKey {
+                s: row.get(0)?,
+                bytes: row.get(1)?,
+            };
+            Ok((key, row.get(2)?))
+        })?;
+        rows.collect()
+    }
+
+    /// Returns every entry, in key order.
+    pub fn iter(&self) -> Result<Vec<(OwnedKey, Vec<u8>)>> {
+        self.range::<(Bound<BorrowedKey<'_>>, Bound<BorrowedKey<'_>>)>((
+            Bound::Unbounded,
+            Bound::Unbounded,
+        ))
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> Result<u64> {
+        let count: i64 = self
+            .conn
+            .prepare_cached("SELECT COUNT(*) FROM keys")?
+            .query_row([], |row| row.get(0))?;
+        Ok(count as u64)
+    }
+
+    /// Returns true if the map is empty.
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Returns the connection the map is stored in, for running other queries against it.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Returns the connection the map is stored in.
+    pub fn into_connection(self) -> Connection {
+        self.conn
+    }
+}
+
+fn get(conn: &Connection, key: BorrowedKey<'_>) -> Result<Option<Vec<u8>>> {
+    conn.prepare_cached("SELECT value FROM keys WHERE s = ?1 AND bytes = ?2")?
+        .query_row(params![key.s, key.bytes], |row| row.get(0))
+        .optional()
+}
+
+impl fmt::Debug for SqliteKeyMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqliteKeyMap").finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn sqlite_lookups() {
+    let mut map = SqliteKeyMap::in_memory().unwrap();
+    let foo_abc = owned("foo", b"abc");
+    assert_eq!(map.insert(foo_abc.key(), b"1").unwrap(), None);
+    assert_eq!(
+        map.insert(foo_abc.key(), b"2").unwrap(),
+        Some(b"1".to_vec())
+    );
+    map.insert(owned("foo", b"").key(), b"3").unwrap();
+    map.insert(owned("foo\0bar", b"\0").key(), b"4").unwrap();
+    map.insert(owned("bar", b"x").key(), b"5").unwrap();
+    assert_eq!(map.len().unwrap(), 4);
+
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert_eq!(map.get(borrowed_key).unwrap(), Some(b"2".to_vec()));
+    assert!(!map.contains_key(owned("foo", b"ab").key()).unwrap());
+
+    // Every key with the string "foo", in order.
+    let start = owned("foo", b"");
+    let end = owned("foo\0", b"");
+    let foos = map.range(start.key()..end.key()).unwrap();
+    assert_eq!(
+        foos,
+        [
+            (owned("foo", b""), b"3".to_vec()),
+            (owned("foo", b"abc"), b"2".to_vec())
+        ]
+    );
+
+    // Strings with a 0x00 in them round trip, and sort after their prefix.
+    let (last, _) = map.iter().unwrap().pop().unwrap();
+    assert_eq!(last, owned("foo\0bar", b"\0"));
+
+    // The columns are there for other queries to use.
+    let bars: i64 = map
+        .connection()
+        .query_row("SELECT COUNT(*) FROM keys WHERE s = 'bar'", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(bars, 1);
+
+    assert_eq!(map.remove(borrowed_key).unwrap(), Some(b"2".to_vec()));
+    assert_eq!(map.remove(borrowed_key).unwrap(), None);
+    assert_eq!(map.len().unwrap(), 3);
+}
+
+proptest! {
+    #[test]
+    fn sqlite_matches_btree_map(
+        entries in prop::collection::vec((any::<OwnedKey>(), any::<Vec<u8>>()), 0..16),
+        removes in prop::collection::vec(any::<OwnedKey>(), 0..4),
+        bounds in (any::<OwnedKey>(), any::<OwnedKey>()),
+    ) {
+        let mut map = SqliteKeyMap::in_memory().unwrap();
+        let mut model = BTreeMap::new();
+        for (key, value) in entries {
+            prop_assert_eq!(map.insert(key.key(), &value).unwrap(), model.insert(key, value));
+        }
+        // Remove a stored key too, so that hits are tested as well as misses.
+        let stored = model.keys().next().cloned();
+        for key in stored.iter().chain(&removes) {
+            prop_assert_eq!(map.remove(key.key()).unwrap(), model.remove(key));
+        }
+        prop_assert_eq!(map.len().unwrap(), model.len() as u64);
+
+        // The rows come back in the same order as the BTreeMap's.
+        let all = map.iter().unwrap();
+        let expected: Vec<_> = model.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        prop_assert_eq!(all, expected);
+
+        let (start, end) = bounds;
+        if start <= end {
+            let range = map.range(start.key()..end.key()).unwrap();
+            let expected: Vec<_> = model
+                .range(start.clone()..end.clone())
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            prop_assert_eq!(range, expected);
+            let range = map.range((Bound::Excluded(start.key()), Bound::Included(end.key()))).unwrap();
+            let expected: Vec<_> = model
+                .range((Bound::Excluded(start), Bound::Included(end)))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            prop_assert_eq!(range, expected);
+        }
+    }
+}
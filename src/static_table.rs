@@ -0,0 +1,246 @@
+//! Lookup tables of borrowed keys, built and checked at compile time.
+
+use crate::{BorrowedKey, Key};
+use core::cmp::Ordering;
+use core::fmt;
+use core::slice;
+#[cfg(test)]
+use proptest::prelude::*;
+
+// Tables of well-known keys, like the built-in entries of a registry, are usually built at startup:
+// collect the keys into a map, then look them up. The keys are string and byte string literals,
+// though, so the whole table could be in the binary, already sorted, with nothing to build.
+//
+// BorrowedKey::new is a const fn, so a BorrowedKey can be written in a static. KeyTable is a static
+// slice of them, with values, sorted by key, and looked up by binary search through the same
+// borrowed views SortedKeyVec uses. Lookups take &dyn Key, like every other collection in the crate.
+//
+// The table has to be sorted for binary search to find anything, and a table of literals is easy
+// to get out of order when editing it by hand. KeyTable::new is also a const fn, and checks the
+// order, with no duplicates. Ord isn't usable in a const fn, so it compares the keys with a loop
+// over their bytes, which gives the same order as the derived Ord on BorrowedKey: strings first,
+// then byte strings, each lexicographically by byte. In a static or a const, the check runs while
+// compiling, so a table that's out of order is a compile error rather than lookups that quietly
+// miss. The key_table! macro is shorthand for writing the entries out.
+/// A sorted, static table of borrowed keys and values.
+///
+/// Build one in a `static` or `const` with [`key_table!`](crate::key_table), which checks at compile
+/// time that the keys are sorted and distinct.
+pub struct KeyTable<V: 'static> {
+    entries: &'static [(BorrowedKey<'static>, V)],
+}
+
+impl<V> KeyTable<V> {
+    /// Creates a table from entries sorted by key, with no duplicates.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the entries aren't sorted, or if two keys are equal. In a `static` or `const`, this
+    /// is a compile error.
+    pub const fn new(entries: &'static [(BorrowedKey<'static>, V)]) -> Self {
+        let mut i = 1;
+        while i < entries.len() {
+            if !matches!(cmp_keys(entries[i - 1].0, entries[i].0), Ordering::Less) {
+                panic!("key table entries must be sorted by key, with no duplicates");
+            }
+            i += 1;
+        }
+        Self { entries }
+    }
+
+    /// Returns the value for `key`, if any.
+    pub fn get(&self, key: &dyn Key) -> Option<&V> {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    /// Returns the stored key and value for `key`, if any.
+    pub fn get_key_value(&self, key: &dyn Key) -> Option<(BorrowedKey<'static>, &V)> {
+        let key = key.key();
+        let index = self
+            .entries
+            .binary_search_by(|(probe, _)| probe.cmp(&key))
+            .ok()?;
+        let (key, value) = &self.entries[index];
+        Some((*key, value))
+    }
+
+    /// Returns true if the table has a value for `key`.
+    pub fn contains_key(&self, key: &dyn Key) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the number of entries.
+    pub const fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the table is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns an iterator over the entries, in key order.
+    pub fn iter(&self) -> slice::Iter<'static, (BorrowedKey<'static>, V)> {
+        self.entries.iter()
+    }
+
+    /// Returns an iterator over the keys and references to their values, in key order.
+    pub fn iter_borrowed(&self) -> impl Iterator<Item = (BorrowedKey<'static>, &'static V)> {
+        self.entries.iter().map(|(key, value)| (*key, value))
+    }
+
+    /// Returns the entries, in key order.
+    pub const fn as_slice(&self) -> &'static [(BorrowedKey<'static>, V)] {
+        self.entries
+    }
+}
+
+impl<V: fmt::Debug> fmt::Debug for KeyTable<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.entries.iter().map(|(key, value)| (key, value)))
+            .finish()
+    }
+}
+
+impl<V> IntoIterator for &KeyTable<V> {
+    type Item = &'static (BorrowedKey<'static>, V);
+    type IntoIter = slice::Iter<'static, (BorrowedKey<'static>, V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub(crate) const fn cmp_keys(a: BorrowedKey<'_>, b: BorrowedKey<'_>) -> Ordering {
+    match cmp_bytes(a.s.as_bytes(), b.s.as_bytes()) {
+        Ordering::Equal => cmp_bytes(a.bytes, b.bytes),
+        ordering => ordering,
+    }
+}
+
+const fn cmp_bytes(a: &[u8], b: &[u8]) -> Ordering {
+    let mut i = 0;
+    while i < a.len() && i < b.len() {
+        if a[i] < b[i] {
+            return Ordering::Less;
+        }
+        if a[i] > b[i] {
+            return Ordering::Greater;
+        }
+        i += 1;
+    }
+    if a.len() < b.len() {
+        Ordering::Less
+    } else if a.len() > b.len() {
+        Ordering::Greater
+    } else {
+        Ordering::Equal
+    }
+}
+
+/// Builds a [`KeyTable`](crate::static_table::KeyTable) from `(s, bytes) => value` entries, which
+/// must be sorted by key, with no duplicates.
+///
+/// Used in a `static` or `const`, the order is checked at compile time:
+///
+/// ```
+/// use borrow_complex_key_example::key_table;
+/// use borrow_complex_key_example::static_table::KeyTable;
+/// use borrow_complex_key_example::BorrowedKey;
+///
+/// static PORTS: KeyTable<u16> = key_table! {
+///     ("http", b"tcp") => 80,
+///     ("https", b"tcp") => 443,
+///     ("https", b"udp") => 443,
+/// };
+///
+/// assert_eq!(PORTS.get(&BorrowedKey::new("https", b"udp")), Some(&443));
+/// ```
+///
+/// ```compile_fail
+/// use borrow_complex_key_example::key_table;
+/// use borrow_complex_key_example::static_table::KeyTable;
+///
+/// static PORTS: KeyTable<u16> = key_table! {
+///     ("https", b"tcp") => 443,
+///     ("http", b"tcp") => 80,
+/// };
+/// ```
+#[macro_export]
+macro_rules! key_table {
+    ($(($s:expr, $bytes:expr) => $value:expr),* $(,)?) => {
+        $crate::static_table::KeyTable::new(&[
+            $(($crate::BorrowedKey { s: $s, bytes: $bytes }, $value)),*
+        ])
+    };
+}
+
+#[cfg(test)]
+static TABLE: KeyTable<u32> = key_table! {
+    ("", b"") => 0,
+    ("bar", b"") => 1,
+    ("bar", b"\0") => 2,
+    ("foo", b"abc") => 3,
+    ("foo", b"abd") => 4,
+    ("foobar", b"") => 5,
+};
+
+#[test]
+fn static_table_lookups() {
+    let borrowed_key = BorrowedKey::new("foo", b"abc");
+    assert_eq!(TABLE.get(&borrowed_key), Some(&3));
+    let owned_key = crate::OwnedKey {
+        s: "bar".to_string(),
+        bytes: vec![0],
+    };
+    assert_eq!(TABLE.get_key_value(&owned_key), Some((owned_key.key(), &2)));
+    assert!(!TABLE.contains_key(&BorrowedKey::new("foo", b"ab")));
+    assert_eq!(TABLE.len(), 6);
+    let values: Vec<_> = TABLE.iter_borrowed().map(|(_, &value)| value).collect();
+    assert_eq!(values, [0, 1, 2, 3, 4, 5]);
+
+    const EMPTY: KeyTable<()> = key_table! {};
+    assert!(EMPTY.is_empty());
+    assert_eq!(EMPTY.get(&borrowed_key), None);
+}
+
+#[test]
+#[should_panic(expected = "key table entries must be sorted by key, with no duplicates")]
+fn static_table_duplicates() {
+    // Built at run time, the check panics instead.
+    let entries = vec![
+        (BorrowedKey::new("foo", b""), 1),
+        (BorrowedKey::new("foo", b""), 2),
+    ];
+    KeyTable::new(Vec::leak(entries));
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn static_table_order_matches_ord(
+        a in any::<crate::OwnedKey>(),
+        b in any::<crate::OwnedKey>(),
+    ) {
+        prop_assert_eq!(cmp_keys(a.key(), b.key()), a.key().cmp(&b.key()));
+    }
+
+    #[test]
+    fn static_table_matches_sorted(
+        keys in prop::collection::btree_set(any::<crate::OwnedKey>(), 0..16),
+        probe in any::<crate::OwnedKey>(),
+    ) {
+        let entries: Vec<_> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (BorrowedKey::new(key.s.clone().leak(), key.bytes.clone().leak()), i))
+            .collect();
+        let table = KeyTable::new(Vec::leak(entries));
+        for (i, key) in keys.iter().enumerate() {
+            prop_assert_eq!(table.get(key), Some(&i));
+        }
+        let expected = keys.iter().position(|key| *key == probe);
+        prop_assert_eq!(table.get(&probe).copied(), expected);
+    }
+}
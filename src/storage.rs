@@ -0,0 +1,101 @@
+//! Choosing where an `OwnedKey` keeps its bytes.
+//!
+//! `OwnedKey<B>` is a key for any `B: KeyStorage`. The Key and Borrow impls in the crate root are
+//! written once for all of them.
+
+use crate::consistency::assert_consistent;
+use crate::{BorrowedKey, Key, KeyStorage, OwnedKey};
+use proptest::prelude::*;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+
+// There's one thing the generic impls can't promise on their own. OwnedKey<B> derives Eq, Ord and
+// Hash, so for it to be consistent with BorrowedKey, B's impls of those traits must agree with
+// [u8]'s. Vec<u8>, Box<[u8]>, Arc<[u8]> and bytes::Bytes all forward to [u8], so they're fine.
+// String doesn't: it derefs to bytes, but hashes like a str, and a HashSet<OwnedKey<String>> would
+// miss keys that are in it. That's why KeyStorage is sealed, and only implemented for the four of
+// them. The tests below check each one.
+
+/// An `OwnedKey` that stores its bytes in a boxed slice, saving the capacity word of a `Vec`.
+pub type BoxedOwnedKey = OwnedKey<Box<[u8]>>;
+
+impl<B: AsRef<[u8]>> OwnedKey<B> {
+    /// Converts this key to one with a different byte storage.
+    pub fn map_storage<C: From<B>>(self) -> OwnedKey<C> {
+        OwnedKey {
+            s: self.s,
+            bytes: self.bytes.into(),
+        }
+    }
+}
+
+/// Returns a strategy that generates arbitrary owned keys with the given byte storage.
+pub fn owned_keys<B>() -> impl Strategy<Value = OwnedKey<B>>
+where
+    B: From<Vec<u8>> + std::fmt::Debug,
+{
+    any::<OwnedKey>().prop_map(OwnedKey::map_storage)
+}
+
+// Inserts a key with storage B into a HashSet and a BTreeSet, and finds it again by BorrowedKey.
+#[cfg(test)]
+pub(crate) fn check_storage<B>()
+where
+    B: KeyStorage + From<Vec<u8>> + Clone + Eq + Hash + Ord + fmt::Debug,
+{
+    let key: OwnedKey<B> = OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    }
+    .map_storage();
+    let hash_set: HashSet<_> = std::iter::once(key).collect();
+    let btree_set: BTreeSet<_> = hash_set.iter().cloned().collect();
+
+    let borrowed_key = BorrowedKey::new("foo", b"abc");
+    let missing = BorrowedKey::new("foo", b"ab");
+    assert!(hash_set.contains(&borrowed_key as &dyn Key));
+    assert!(btree_set.contains(&borrowed_key as &dyn Key));
+    assert!(!hash_set.contains(&missing as &dyn Key));
+    assert!(!btree_set.contains(&missing as &dyn Key));
+}
+
+#[test]
+fn storage_lookups() {
+    check_storage::<Vec<u8>>();
+    check_storage::<Box<[u8]>>();
+    check_storage::<Arc<[u8]>>();
+}
+
+#[test]
+fn boxed_lookups() {
+    let mut hash_set: HashSet<BoxedOwnedKey> = HashSet::new();
+    hash_set.insert(OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec().into_boxed_slice(),
+    });
+
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert!(hash_set.contains(&borrowed_key as &dyn Key));
+}
+
+proptest! {
+    #[test]
+    fn vec_consistent_borrow(owned1 in owned_keys::<Vec<u8>>(), owned2 in owned_keys::<Vec<u8>>()) {
+        assert_consistent::<_, dyn Key>(&owned1, &owned2);
+    }
+
+    #[test]
+    fn boxed_consistent_borrow(owned1 in owned_keys::<Box<[u8]>>(), owned2 in owned_keys::<Box<[u8]>>()) {
+        assert_consistent::<_, dyn Key>(&owned1, &owned2);
+    }
+
+    #[test]
+    fn arc_consistent_borrow(owned1 in owned_keys::<Arc<[u8]>>(), owned2 in owned_keys::<Arc<[u8]>>()) {
+        assert_consistent::<_, dyn Key>(&owned1, &owned2);
+    }
+}
@@ -0,0 +1,239 @@
+//! Proptest strategies for generating keys.
+
+use crate::columns::KeyColumns;
+use crate::map::KeyMap;
+use crate::set::KeySet;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::collection::SizeRange;
+use proptest::prelude::*;
+use proptest::test_runner::TestRunner;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::iter::FromIterator;
+
+// Deriving Arbitrary for OwnedKey would generate each field from any::<String>() and
+// any::<Vec<u8>>(). Those almost never produce the inputs that actually break a Borrow impl, or
+// an encoding built on top of one:
+//
+// - empty strings and empty bytes, where "a" + "" and "" + "a" are easy to mix up
+// - very long strings, which go past any inline or small-string storage
+// - strings with the characters other code treats as separators: the ':' in the text form, the
+//   NUL that the memcomparable encoding escapes, and the ',' and '"' that CSV quotes
+// - bytes that are valid UTF-8, so they could just as well have been the string
+//
+// So the strategy below picks one of those cases some of the time, and an ordinary value the rest
+// of the time.
+//
+// Each case is a plain proptest strategy, so each shrinks the way it normally would. The simplest
+// case comes first, because a union shrinks towards its earlier branches: a failing key with a
+// long string will try the empty string before it tries shortening the long one. Long strings are
+// a short string repeated, rather than hundreds of independent chars, so that shrinking one only
+// has to shrink a length and a few chars.
+/// Returns a strategy for key strings, weighted towards edge cases.
+pub fn key_strings() -> impl Strategy<Value = String> {
+    prop_oneof![
+        1 => Just(String::new()),
+        6 => any::<String>(),
+        2 => separated(vec![':', '\0', ',', '"', 'a']).prop_map(String::from_iter),
+        1 => long_strings(),
+    ]
+}
+
+/// Returns a strategy for key bytes, weighted towards edge cases.
+pub fn key_bytes() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        1 => Just(Vec::new()),
+        6 => any::<Vec<u8>>(),
+        2 => separated(vec![b':', 0x00, 0x01, 0xff, b'a']),
+        2 => any::<String>().prop_map(String::into_bytes),
+    ]
+}
+
+/// The lengths, in chars, of very long strings.
+const LONG: std::ops::Range<usize> = 256..1024;
+
+fn long_strings() -> impl Strategy<Value = String> {
+    ("\\PC{1,8}", LONG)
+        .prop_map(|(unit, len): (String, usize)| unit.chars().cycle().take(len).collect())
+}
+
+fn separated<T: Clone + fmt::Debug + 'static>(values: Vec<T>) -> impl Strategy<Value = Vec<T>> {
+    prop::collection::vec(prop::sample::select(values), 0..16)
+}
+
+impl<B: From<Vec<u8>> + fmt::Debug> Arbitrary for OwnedKey<B> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        (key_strings(), key_bytes())
+            .prop_map(|(s, bytes)| OwnedKey {
+                s,
+                bytes: bytes.into(),
+            })
+            .boxed()
+    }
+}
+
+// The containers generate their keys with the strategy above, and take a size range as their
+// parameter, like proptest's own impls for HashSet and HashMap: any::<KeySet>() has up to 100 keys,
+// and any_with::<KeySet>((0..8).into()) up to 8. The keys are distinct, so a set or map really has
+// as many entries as the size range asks for, rather than fewer after duplicates are folded
+// together. Shrinking removes keys first, then shrinks the ones that are left.
+impl<S> Arbitrary for KeySet<S>
+where
+    S: BuildHasher + Default + fmt::Debug + 'static,
+{
+    type Parameters = SizeRange;
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(size: SizeRange) -> Self::Strategy {
+        prop::collection::hash_set(any::<OwnedKey>(), size)
+            .prop_map(|keys| keys.into_iter().collect())
+            .boxed()
+    }
+}
+
+impl<V, S> Arbitrary for KeyMap<V, S>
+where
+    V: Arbitrary,
+    V::Strategy: 'static,
+    S: BuildHasher + Default + fmt::Debug + 'static,
+{
+    type Parameters = (SizeRange, V::Parameters);
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with((size, value_params): Self::Parameters) -> Self::Strategy {
+        prop::collection::hash_map(any::<OwnedKey>(), any_with::<V>(value_params), size)
+            .prop_map(|entries| entries.into_iter().collect())
+            .boxed()
+    }
+}
+
+// Properties about functions that take a BorrowedKey need a BorrowedKey, but a strategy can't
+// generate one on its own: its value has to own everything, and a BorrowedKey owns nothing. So
+// backed_keys generates the key together with the storage it borrows from, and hands out views
+// into that storage.
+//
+// Sometimes the storage is just the OwnedKey. Other times it's a KeyColumns with other keys
+// packed in before and after, so the view's slices start partway into a larger buffer, and code
+// that reads past either end of them picks up another key's data instead of crashing.
+/// Returns a strategy for keys along with the storage their borrowed forms point into.
+pub fn backed_keys() -> impl Strategy<Value = BackedKey> {
+    let neighbours = || prop::collection::vec(any::<OwnedKey>(), 0..4);
+    prop_oneof![
+        any::<OwnedKey>().prop_map(|owned| BackedKey {
+            owned,
+            columns: None,
+        }),
+        (neighbours(), any::<OwnedKey>(), neighbours()).prop_map(|(before, owned, after)| {
+            let mut columns = KeyColumns::new();
+            columns.extend(before.iter().map(OwnedKey::key));
+            let index = columns.push(&owned);
+            columns.extend(after.iter().map(OwnedKey::key));
+            BackedKey {
+                owned,
+                columns: Some((columns, index)),
+            }
+        }),
+    ]
+}
+
+/// A generated key, along with the storage its borrowed form points into. Generated by
+/// [`backed_keys`].
+#[derive(Clone, Debug)]
+pub struct BackedKey {
+    owned: OwnedKey,
+    columns: Option<(KeyColumns, usize)>,
+}
+
+impl BackedKey {
+    /// Returns the key in owned form.
+    pub fn owned(&self) -> &OwnedKey {
+        &self.owned
+    }
+
+    /// Returns a borrowed view of the key, pointing into its storage.
+    pub fn borrowed(&self) -> BorrowedKey<'_> {
+        match &self.columns {
+            Some((columns, index)) => columns.get(*index).expect("index is in bounds"),
+            None => self.owned.key(),
+        }
+    }
+
+    /// Calls `f` with a borrowed view of the key, and returns its result.
+    pub fn with<R>(&self, f: impl FnOnce(BorrowedKey<'_>) -> R) -> R {
+        f(self.borrowed())
+    }
+}
+
+#[test]
+fn strategy_edge_cases() {
+    let mut runner = TestRunner::deterministic();
+    let keys: Vec<OwnedKey> = (0..1000)
+        .map(|_| {
+            any::<OwnedKey>()
+                .new_tree(&mut runner)
+                .expect("generated a key")
+                .current()
+        })
+        .collect();
+
+    assert!(keys.iter().any(|key| key.s.is_empty()));
+    assert!(keys.iter().any(|key| key.s.chars().count() >= LONG.start));
+    assert!(keys.iter().any(|key| key.s.contains(':')));
+    assert!(keys.iter().any(|key| key.s.contains('\0')));
+    assert!(keys.iter().any(|key| key.bytes.is_empty()));
+    assert!(keys
+        .iter()
+        .any(|key| key.bytes.len() > 1 && std::str::from_utf8(&key.bytes).is_ok()));
+}
+
+#[test]
+fn strategy_shrinks() {
+    // A property that only fails for long strings should shrink to the shortest one that still
+    // fails, rather than getting stuck on whatever was generated first.
+    let mut runner = TestRunner::deterministic();
+    let result = runner.run(&any::<OwnedKey>(), |key| {
+        prop_assert!(key.s.len() < LONG.start);
+        Ok(())
+    });
+    match result {
+        Err(proptest::test_runner::TestError::Fail(_, key)) => {
+            assert_eq!(key.s.chars().count(), LONG.start, "shrunk to {:?}", key);
+            assert!(key.bytes.is_empty(), "shrunk to {:?}", key);
+        }
+        other => panic!("expected the property to fail, got {:?}", other),
+    }
+}
+
+proptest! {
+    #[test]
+    fn strategy_consistent(owned1 in any::<OwnedKey>(), owned2 in any::<OwnedKey>()) {
+        crate::consistency::assert_consistent::<_, dyn Key>(&owned1, &owned2);
+    }
+
+    #[test]
+    fn strategy_containers(
+        set in any_with::<KeySet>((1..8).into()),
+        map in any_with::<KeyMap<u8>>(((0..4).into(), ())),
+    ) {
+        prop_assert!((1..8).contains(&set.len()));
+        prop_assert!(map.len() < 4);
+        for key in set.iter() {
+            prop_assert!(set.contains(&key.key()));
+        }
+        for (key, value) in &map {
+            prop_assert_eq!(map.get(&key.key()), Some(value));
+        }
+    }
+
+    #[test]
+    fn strategy_backed_keys(backed1 in backed_keys(), backed2 in backed_keys()) {
+        prop_assert_eq!(backed1.borrowed(), backed1.owned().key());
+        let encoded = backed1.with(|key| crate::encoding::encode(&key));
+        prop_assert_eq!(encoded, crate::encoding::encode(backed1.owned()));
+        let ordering = backed1.with(|key1| backed2.with(|key2| key1.cmp(&key2)));
+        prop_assert_eq!(ordering, backed1.owned().cmp(backed2.owned()));
+    }
+}
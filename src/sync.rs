@@ -0,0 +1,37 @@
+//! The locks and threads that the concurrent containers are built on.
+
+// The concurrent containers use std's locks, and so do their tests, so that the tests exercise
+// what ships. Built with cfg(shuttle), the tests swap in shuttle's locks and threads instead, so
+// that shuttle can explore different interleavings of them:
+//
+//     RUSTFLAGS="--cfg shuttle" cargo test --lib
+//
+// shuttle's primitives panic when they're used outside a shuttle test, and shuttle doesn't build
+// for wasm, which is why this isn't done for every test build.
+#[cfg(all(test, shuttle))]
+pub(crate) use shuttle::sync::RwLock;
+#[cfg(not(all(test, shuttle)))]
+pub(crate) use std::sync::RwLock;
+
+#[cfg(all(test, shuttle))]
+pub(crate) use shuttle::thread;
+#[cfg(all(test, not(shuttle)))]
+pub(crate) use std::thread;
+
+/// Runs a test of concurrent code.
+///
+/// With cfg(shuttle), this runs `f` under `iterations` random schedules. Otherwise, it runs `f`
+/// once, on real threads.
+#[cfg(test)]
+pub(crate) fn check<F>(f: F, iterations: usize)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    #[cfg(shuttle)]
+    shuttle::check_random(f, iterations);
+    #[cfg(not(shuttle))]
+    {
+        let _ = iterations;
+        f();
+    }
+}
@@ -0,0 +1,293 @@
+//! A key map that can be shared between threads, with reads that take borrowed keys.
+
+use crate::collections::RandomState;
+use crate::map::KeyMap;
+use crate::sync::RwLock;
+use crate::{BorrowedKey, Key, OwnedKey};
+use std::fmt;
+use std::hash::BuildHasher;
+use std::ops::{Deref, DerefMut};
+use std::sync::PoisonError;
+
+// The first thing most multi-threaded users of KeyMap do is put it behind an RwLock, and then write
+// the same three helpers: look a key up and clone the value out, check whether a key is there, and
+// run a closure on an entry while the read lock is held. SyncKeyMap is those helpers.
+//
+// Keys that cross threads are often held as &(dyn Key + Send + Sync), or in a Box of one. That's a
+// different type from dyn Key, and it's tempting to think it needs its own Borrow, Eq and Hash
+// impls before it can look anything up. It doesn't: dropping auto traits from a trait object is an
+// ordinary coercion, so a &(dyn Key + Send + Sync) can be passed wherever a &dyn Key is expected,
+// and the crate root's impls take it from there. The read methods take &dyn Key, so every kind of
+// key works, including that one, and none of them allocate.
+//
+// Values come out either cloned, which is all that can escape the read lock, or by reference inside
+// a closure, which runs while the lock is held. A closure that panics under the write lock poisons
+// it, but the map carries on: std leaves deciding whether poisoned data is still good to whoever
+// holds the lock, and the map's own state is always good, so later calls take the lock regardless.
+/// A map from owned keys to values, behind a read-write lock, with lookups by owned or borrowed
+/// key.
+pub struct SyncKeyMap<V, S = RandomState> {
+    map: RwLock<KeyMap<V, S>>,
+}
+
+impl<V> SyncKeyMap<V> {
+    /// Creates a new, empty map.
+    pub fn new() -> Self {
+        Self::from_map(KeyMap::new())
+    }
+}
+
+impl<V> Default for SyncKeyMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V, S: BuildHasher> SyncKeyMap<V, S> {
+    /// Creates a new, empty map that uses `hasher` to hash keys.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::from_map(KeyMap::with_hasher(hasher))
+    }
+
+    /// Puts an existing map behind the lock.
+    pub fn from_map(map: KeyMap<V, S>) -> Self {
+        Self {
+            map: RwLock::new(map),
+        }
+    }
+
+    /// Inserts a value for `key`, returning the old value if there was one.
+    pub fn insert(&self, key: OwnedKey, value: V) -> Option<V> {
+        self.write().insert(key, value)
+    }
+
+    /// Removes the value for `key`, returning it if there was one.
+    pub fn remove(&self, key: &dyn Key) -> Option<V> {
+        self.write().remove(key)
+    }
+
+    /// Returns a clone of the value for `key`, if any.
+    pub fn get_cloned(&self, key: &dyn Key) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.read().get(key).cloned()
+    }
+
+    /// Returns true if the map has a value for `key`.
+    pub fn contains_key(&self, key: &dyn Key) -> bool {
+        self.read().contains_key(key)
+    }
+
+    /// Calls `f` with the stored key and value for `key`, or `None` if there isn't one, while
+    /// holding the read lock.
+    ///
+    /// `f` mustn't call back into the map to write to it, or it will deadlock.
+    pub fn with_entry<R, F>(&self, key: &dyn Key, f: F) -> R
+    where
+        F: FnOnce(Option<(BorrowedKey<'_>, &V)>) -> R,
+    {
+        let map = self.read();
+        f(map
+            .get_key_value(key)
+            .map(|(key, value)| (key.key(), value)))
+    }
+
+    /// Calls `f` with an iterator over borrowed views of the keys, with their values, in arbitrary
+    /// order, while holding the read lock.
+    ///
+    /// The views can't outlive the lock, so rather than returning an iterator, this lends one out.
+    /// `f` mustn't call back into the map to write to it, or it will deadlock.
+    pub fn with_iter_borrowed<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut dyn Iterator<Item = (BorrowedKey<'_>, &V)>) -> R,
+    {
+        let map = self.read();
+        let mut iter = map.iter_borrowed();
+        f(&mut iter)
+    }
+
+    /// Calls `f` with a mutable reference to the value for `key`, if there is one, while holding
+    /// the write lock. Returns what `f` returns, or `None` if there's no value.
+    ///
+    /// If `f` panics, the panic propagates, and the value keeps whatever changes `f` made to it
+    /// before panicking. The map stays usable.
+    pub fn with_value_mut<R, F>(&self, key: &dyn Key, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut V) -> R,
+    {
+        self.write().get_mut(key).map(f)
+    }
+
+    /// Calls `f` with a mutable reference to the value for `key`, inserting the result of
+    /// `default` first if there isn't one, while holding the write lock.
+    ///
+    /// The key is only copied into an `OwnedKey` if it isn't already present. As with
+    /// [`with_value_mut`](Self::with_value_mut), a panic in `default` or `f` leaves the map
+    /// usable.
+    pub fn with_value_or_insert_with<R, D, F>(&self, key: &dyn Key, default: D, f: F) -> R
+    where
+        D: FnOnce() -> V,
+        F: FnOnce(&mut V) -> R,
+    {
+        f(self.write().get_or_insert_with(key, default))
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    /// Returns true if the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    /// Takes the map out from behind the lock.
+    pub fn into_inner(self) -> KeyMap<V, S> {
+        self.map
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // A closure that panics while the write lock is held poisons it. The map itself is never left
+    // half-updated, since none of its code that runs under the lock can panic partway through a
+    // change, so the lock is taken anyway rather than making every later call panic too.
+    fn read(&self) -> impl Deref<Target = KeyMap<V, S>> + '_ {
+        self.map.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> impl DerefMut<Target = KeyMap<V, S>> + '_ {
+        self.map.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<V, S> fmt::Debug for SyncKeyMap<V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncKeyMap").finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn sync_map_lookups() {
+    crate::sync::check(
+        || {
+            let map = SyncKeyMap::new();
+            let owned_key = OwnedKey {
+                s: "foo".to_string(),
+                bytes: b"abc".to_vec(),
+            };
+            assert_eq!(map.insert(owned_key.clone(), 1), None);
+
+            // A key held as a Send + Sync trait object is looked up as it is.
+            let borrowed_key = BorrowedKey {
+                s: "foo",
+                bytes: b"abc",
+            };
+            let sync_key: &(dyn Key + Send + Sync) = &borrowed_key;
+            assert_eq!(map.get_cloned(sync_key), Some(1));
+            assert!(map.contains_key(sync_key));
+            assert_eq!(map.with_value_mut(sync_key, |value| *value += 1), Some(()));
+            let found = map.with_entry(sync_key, |entry| {
+                entry.map(|(key, &value)| (key.s.to_string(), value))
+            });
+            assert_eq!(found, Some(("foo".to_string(), 2)));
+            let entries = map.with_iter_borrowed(|iter| {
+                iter.map(|(key, &value)| (key.s.to_string(), value))
+                    .collect::<Vec<_>>()
+            });
+            assert_eq!(entries, [("foo".to_string(), 2)]);
+
+            let missing: Box<dyn Key + Send + Sync> = Box::new(OwnedKey {
+                s: "bar".to_string(),
+                bytes: vec![],
+            });
+            assert!(!map.contains_key(&*missing));
+            assert!(map.with_entry(&*missing, |entry| entry.is_none()));
+            assert_eq!(map.with_value_mut(&*missing, |value| *value += 1), None);
+
+            assert_eq!(map.remove(&owned_key), Some(2));
+            assert!(map.is_empty());
+        },
+        1,
+    );
+}
+
+#[test]
+// shuttle fails a test on any panic, even one that's caught.
+#[cfg_attr(shuttle, ignore)]
+fn sync_map_poisoned() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let map = SyncKeyMap::new();
+    let key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    map.insert((&key as &dyn Key).to_owned(), 1);
+
+    // A panic under the write lock poisons it. One under the read lock doesn't, but it mustn't
+    // break anything either.
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        map.with_value_mut(&key, |value| {
+            *value += 1;
+            panic!("closure panicked while holding the write lock");
+        })
+    }));
+    assert!(result.is_err());
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        map.with_entry(&key, |_| {
+            panic!("closure panicked while holding the read lock")
+        })
+    }));
+    assert!(result.is_err());
+
+    // Every method still works, and sees the change made before the panic.
+    assert_eq!(map.get_cloned(&key), Some(2));
+    assert!(map.contains_key(&key));
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.with_value_or_insert_with(&key, || 0, |value| *value), 2);
+    assert_eq!(map.remove(&key), Some(2));
+    assert!(map.into_inner().is_empty());
+}
+
+// Writers insert or bump a counter while a reader probes it, all through a key that's shared
+// between the threads as a Send + Sync trait object. Whatever order they run in, the reader must only ever see
+// values that some writer stored, and no update may be lost.
+#[test]
+fn sync_map_race() {
+    use std::sync::Arc;
+
+    crate::sync::check(
+        || {
+            let map = Arc::new(SyncKeyMap::new());
+            let key: Arc<dyn Key + Send + Sync> = Arc::new(OwnedKey {
+                s: "counter".to_string(),
+                bytes: b"abc".to_vec(),
+            });
+            let writers: Vec<_> = (0..2)
+                .map(|_| {
+                    let map = map.clone();
+                    let key = key.clone();
+                    crate::sync::thread::spawn(move || {
+                        map.with_value_or_insert_with(&*key, || 0, |value| *value += 1);
+                    })
+                })
+                .collect();
+            let reader = {
+                let map = map.clone();
+                let key = key.clone();
+                crate::sync::thread::spawn(move || {
+                    let value = map.get_cloned(&*key);
+                    assert!(matches!(value, None | Some(1) | Some(2)), "{:?}", value);
+                })
+            };
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            reader.join().unwrap();
+            assert_eq!(map.get_cloned(&*key), Some(2));
+        },
+        1000,
+    );
+}
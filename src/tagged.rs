@@ -0,0 +1,158 @@
+//! Keys with identical layouts that are nonetheless distinct types.
+//!
+//! ```compile_fail
+//! use borrow_complex_key_example::tagged::TaggedOwnedKey;
+//! use borrow_complex_key_example::{BorrowedKey, OwnedKey};
+//! use std::collections::HashSet;
+//!
+//! enum User {}
+//! enum Session {}
+//!
+//! let mut users: HashSet<TaggedOwnedKey<User>> = HashSet::new();
+//! users.insert(TaggedOwnedKey::new(OwnedKey { s: "alice".to_string(), bytes: vec![1] }));
+//!
+//! // A session key can't be used to look up users.
+//! let probe = TaggedOwnedKey::<Session>::probe(BorrowedKey { s: "alice", bytes: &[1] });
+//! users.contains(probe.as_lookup());
+//! ```
+
+use crate::consistency::assert_consistent;
+use crate::family::{KeyFamily, Lookup, Probe};
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+// Say your application has user keys and session keys, and both happen to be a string plus some
+// bytes. Using OwnedKey for both means nothing stops a session key from being looked up in the
+// user map. Adding a zero-sized tag type parameter turns that mistake into a compile error.
+//
+// Each tag is its own key family, all with BorrowedKey as the borrowed form. dyn Lookup<F> then
+// does all the work: dyn Lookup<TaggedOwnedKey<User>> and dyn Lookup<TaggedOwnedKey<Session>> are
+// different types, but they share every impl.
+/// An `OwnedKey` tagged with a marker type, so that keys with different tags can't be mixed up.
+pub struct TaggedOwnedKey<Tag> {
+    pub key: OwnedKey,
+    // fn() -> Tag keeps the key Send and Sync no matter what the tag is.
+    _tag: PhantomData<fn() -> Tag>,
+}
+
+/// The borrowed form of a [`TaggedOwnedKey`].
+pub type TaggedBorrowedKey<'a, Tag> = Probe<'a, TaggedOwnedKey<Tag>>;
+
+impl<Tag> TaggedOwnedKey<Tag> {
+    /// Tags an owned key.
+    pub fn new(key: OwnedKey) -> Self {
+        Self {
+            key,
+            _tag: PhantomData,
+        }
+    }
+
+    /// Removes the tag, returning the underlying key.
+    pub fn into_inner(self) -> OwnedKey {
+        self.key
+    }
+
+    /// Tags a borrowed key, for looking up keys with this tag.
+    pub fn probe(key: BorrowedKey<'_>) -> TaggedBorrowedKey<'_, Tag> {
+        Probe(key)
+    }
+}
+
+impl<Tag> KeyFamily for TaggedOwnedKey<Tag> {
+    type Borrowed<'a> = BorrowedKey<'a>;
+
+    fn shorten<'long: 'short, 'short>(borrowed: BorrowedKey<'long>) -> BorrowedKey<'short> {
+        borrowed
+    }
+}
+
+impl<Tag> Lookup<TaggedOwnedKey<Tag>> for TaggedOwnedKey<Tag> {
+    fn lookup_key(&self) -> BorrowedKey<'_> {
+        self.key.key()
+    }
+}
+
+impl<'a, Tag: 'a> Borrow<dyn Lookup<TaggedOwnedKey<Tag>> + 'a> for TaggedOwnedKey<Tag> {
+    fn borrow(&self) -> &(dyn Lookup<TaggedOwnedKey<Tag>> + 'a) {
+        self
+    }
+}
+
+// The derives would require the tag to implement each of these traits, which tags (usually empty
+// enums) don't. The tag carries no data, so each impl just forwards to the key.
+impl<Tag> Clone for TaggedOwnedKey<Tag> {
+    fn clone(&self) -> Self {
+        Self::new(self.key.clone())
+    }
+}
+
+impl<Tag> fmt::Debug for TaggedOwnedKey<Tag> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TaggedOwnedKey").field(&self.key).finish()
+    }
+}
+
+impl<Tag> PartialEq for TaggedOwnedKey<Tag> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<Tag> Eq for TaggedOwnedKey<Tag> {}
+
+impl<Tag> PartialOrd for TaggedOwnedKey<Tag> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<Tag> Ord for TaggedOwnedKey<Tag> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl<Tag> Hash for TaggedOwnedKey<Tag> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state)
+    }
+}
+
+#[test]
+fn tagged_lookups() {
+    enum User {}
+    enum Session {}
+
+    let mut users: HashSet<TaggedOwnedKey<User>> = HashSet::new();
+    let mut sessions: HashSet<TaggedOwnedKey<Session>> = HashSet::new();
+    let key = OwnedKey {
+        s: "alice".to_string(),
+        bytes: vec![1, 2, 3],
+    };
+    users.insert(TaggedOwnedKey::new(key.clone()));
+    sessions.insert(TaggedOwnedKey::new(key));
+
+    let borrowed_key = BorrowedKey {
+        s: "alice",
+        bytes: &[1, 2, 3],
+    };
+    assert!(users.contains(TaggedOwnedKey::<User>::probe(borrowed_key).as_lookup()));
+    assert!(sessions.contains(TaggedOwnedKey::<Session>::probe(borrowed_key).as_lookup()));
+}
+
+proptest! {
+    #[test]
+    fn tagged_consistent_borrow(owned1 in any::<OwnedKey>(), owned2 in any::<OwnedKey>()) {
+        enum User {}
+
+        let owned1 = TaggedOwnedKey::<User>::new(owned1);
+        let owned2 = TaggedOwnedKey::<User>::new(owned2);
+        assert_consistent::<_, dyn Lookup<TaggedOwnedKey<User>>>(&owned1, &owned2);
+    }
+}
@@ -0,0 +1,243 @@
+//! Rendering keys as strings, and parsing them back.
+
+use crate::{BorrowedKey, Key, KeyStorage, OwnedKey};
+use proptest::prelude::*;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// Some places only take strings: log lines, command-line arguments, JSON object keys, CSV cells.
+// The canonical string form of a key is its string, a colon, and then its bytes in lowercase hex:
+//
+//     ("foo", b"abc")  =>  foo:616263
+//
+// The string can contain colons of its own, but hex never does, so parsing splits at the *last*
+// colon and the result is never ambiguous. The string is written as-is, so nothing needs escaping.
+//
+// Hex doubles the length of the bytes, so KeyFormat can render them in base64 instead, with the
+// base64 feature on. Base64 (with the standard alphabet and padding) doesn't use colons either.
+// Display and FromStr always use the default, hex.
+//
+// As usual, an owned key renders exactly as its borrowed form does.
+/// Options for rendering keys as strings, and parsing them back.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct KeyFormat {
+    bytes: BytesEncoding,
+}
+
+/// How [`KeyFormat`] renders a key's bytes.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum BytesEncoding {
+    /// Lowercase hex. This is the default.
+    #[default]
+    Hex,
+    /// Base64, with the standard alphabet and padding.
+    #[cfg(feature = "base64")]
+    Base64,
+}
+
+impl KeyFormat {
+    /// Returns the default format, which renders bytes as hex.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how the bytes are rendered.
+    pub fn bytes_encoding(mut self, bytes: BytesEncoding) -> Self {
+        self.bytes = bytes;
+        self
+    }
+
+    /// Returns a value that displays `key` in this format.
+    pub fn display(self, key: &dyn Key) -> DisplayKey<'_> {
+        DisplayKey {
+            format: self,
+            key: key.key(),
+        }
+    }
+
+    /// Parses a key in this format.
+    pub fn parse<B: From<Vec<u8>>>(self, input: &str) -> Result<OwnedKey<B>, ParseKeyError> {
+        let (s, bytes) = input
+            .rsplit_once(':')
+            .ok_or(ParseKeyError::MissingSeparator)?;
+        let bytes = match self.bytes {
+            BytesEncoding::Hex => decode_hex(bytes)?,
+            #[cfg(feature = "base64")]
+            BytesEncoding::Base64 => {
+                use base64::Engine;
+                base64::engine::general_purpose::STANDARD
+                    .decode(bytes)
+                    .map_err(|_| ParseKeyError::InvalidBase64)?
+            }
+        };
+        Ok(OwnedKey {
+            s: s.to_string(),
+            bytes: bytes.into(),
+        })
+    }
+}
+
+/// Displays a key in a [`KeyFormat`]. Returned by [`KeyFormat::display`].
+#[derive(Copy, Clone, Debug)]
+pub struct DisplayKey<'a> {
+    format: KeyFormat,
+    key: BorrowedKey<'a>,
+}
+
+impl<'a> fmt::Display for DisplayKey<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.key.s)?;
+        match self.format.bytes {
+            BytesEncoding::Hex => Hex(self.key.bytes).fmt(f),
+            #[cfg(feature = "base64")]
+            BytesEncoding::Base64 => base64::display::Base64Display::new(
+                self.key.bytes,
+                &base64::engine::general_purpose::STANDARD,
+            )
+            .fmt(f),
+        }
+    }
+}
+
+impl<'a> fmt::Display for BorrowedKey<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        KeyFormat::default().display(self).fmt(f)
+    }
+}
+
+impl<B: KeyStorage> fmt::Display for OwnedKey<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.key().fmt(f)
+    }
+}
+
+impl<B: From<Vec<u8>>> FromStr for OwnedKey<B> {
+    type Err = ParseKeyError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        KeyFormat::default().parse(input)
+    }
+}
+
+/// Displays bytes as lowercase hex.
+pub(crate) struct Hex<'a>(pub(crate) &'a [u8]);
+
+impl<'a> fmt::Display for Hex<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn decode_hex(hex: &str) -> Result<Vec<u8>, ParseKeyError> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(ParseKeyError::InvalidHex);
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let high = hex_digit(pair[0])?;
+            let low = hex_digit(pair[1])?;
+            Ok(high << 4 | low)
+        })
+        .collect()
+}
+
+fn hex_digit(digit: u8) -> Result<u8, ParseKeyError> {
+    // Only lowercase is accepted, so that every key has exactly one string form.
+    match digit {
+        b'0'..=b'9' => Ok(digit - b'0'),
+        b'a'..=b'f' => Ok(digit - b'a' + 10),
+        _ => Err(ParseKeyError::InvalidHex),
+    }
+}
+
+/// An error returned when parsing a key from a string.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseKeyError {
+    /// There's no colon separating the string from the bytes.
+    MissingSeparator,
+    /// The bytes aren't an even number of lowercase hex digits.
+    InvalidHex,
+    /// The bytes aren't valid base64.
+    InvalidBase64,
+}
+
+impl fmt::Display for ParseKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseKeyError::MissingSeparator => write!(f, "key has no ':' separator"),
+            ParseKeyError::InvalidHex => write!(f, "key bytes aren't valid lowercase hex"),
+            ParseKeyError::InvalidBase64 => write!(f, "key bytes aren't valid base64"),
+        }
+    }
+}
+
+impl Error for ParseKeyError {}
+
+#[test]
+fn text_round_trip() {
+    let key = OwnedKey {
+        s: "a:b".to_string(),
+        bytes: vec![0x00, 0xab, 0xff],
+    };
+    assert_eq!(key.to_string(), "a:b:00abff");
+    assert_eq!(key.key().to_string(), key.to_string());
+    assert_eq!("a:b:00abff".parse::<OwnedKey>(), Ok(key));
+    assert_eq!(
+        ":".parse::<OwnedKey>(),
+        Ok(OwnedKey {
+            s: String::new(),
+            bytes: vec![]
+        })
+    );
+
+    assert_eq!(
+        "foo".parse::<OwnedKey>(),
+        Err(ParseKeyError::MissingSeparator)
+    );
+    assert_eq!(
+        "foo:abc".parse::<OwnedKey>(),
+        Err(ParseKeyError::InvalidHex)
+    );
+    assert_eq!("foo:AB".parse::<OwnedKey>(), Err(ParseKeyError::InvalidHex));
+}
+
+#[cfg(feature = "base64")]
+#[test]
+fn text_base64() {
+    let format = KeyFormat::new().bytes_encoding(BytesEncoding::Base64);
+    let key = OwnedKey {
+        s: "a:b".to_string(),
+        bytes: b"abcd".to_vec(),
+    };
+    assert_eq!(format.display(&key).to_string(), "a:b:YWJjZA==");
+    assert_eq!(format.parse("a:b:YWJjZA=="), Ok(key));
+    assert_eq!(
+        format.parse::<Vec<u8>>("a:b:YWJjZA"),
+        Err(ParseKeyError::InvalidBase64)
+    );
+}
+
+#[cfg(test)]
+fn key_formats() -> impl Strategy<Value = KeyFormat> {
+    let encodings = vec![
+        BytesEncoding::Hex,
+        #[cfg(feature = "base64")]
+        BytesEncoding::Base64,
+    ];
+    prop::sample::select(encodings).prop_map(|bytes| KeyFormat::new().bytes_encoding(bytes))
+}
+
+proptest! {
+    #[test]
+    fn text_round_trips(key in any::<OwnedKey>(), format in key_formats()) {
+        prop_assert_eq!(key.to_string().parse::<OwnedKey>(), Ok(key.clone()));
+        let rendered = format.display(&key).to_string();
+        prop_assert_eq!(format.parse::<Vec<u8>>(&rendered), Ok(key));
+    }
+}
@@ -0,0 +1,313 @@
+//! An ordered map keyed by owned keys that takes borrowed keys directly, with a fast path for
+//! loading keys that are already sorted.
+
+#[cfg(test)]
+use crate::owned;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::collections::btree_map::{self, BTreeMap};
+use std::error::Error;
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::RangeBounds;
+
+// KeyBTreeMap is to BTreeMap<OwnedKey, V> what KeyMap is to HashMap<OwnedKey, V>: lookups and
+// range queries take &dyn Key, so owned and borrowed keys can both be passed in.
+//
+// Loading a map with millions of keys one insert at a time walks down the tree from the root for
+// every key, and splits nodes as they fill up, leaving them half empty. If the keys come from
+// somewhere that's already sorted, like a database index or a file written by another KeyBTreeMap,
+// none of that is needed: the tree can be built bottom up, filling each leaf in turn and adding
+// parents as it goes.
+//
+// std's BTreeMap already does this in FromIterator. It sorts the entries first, which takes a
+// single pass when they're already in order, and then builds bottom up. What it doesn't do is tell
+// you that the input wasn't sorted, or that it had duplicates: it quietly sorts them and keeps the
+// last one. from_sorted_iter and SortedBuilder check the order as the entries come in, comparing
+// each key to the one before it through borrowed views, and return an error on the first key
+// that's out of order, before anything is built.
+/// An ordered map from owned keys to values, with lookups by owned or borrowed key.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct KeyBTreeMap<V> {
+    map: BTreeMap<OwnedKey, V>,
+}
+
+impl<V> KeyBTreeMap<V> {
+    /// Creates a new, empty map.
+    pub fn new() -> Self {
+        Self {
+            map: BTreeMap::new(),
+        }
+    }
+
+    /// Builds a map from entries that are sorted by key, with no duplicates, building the tree
+    /// bottom up.
+    ///
+    /// Returns an error for the first entry whose key isn't greater than the one before it.
+    pub fn from_sorted_iter<I: IntoIterator<Item = (OwnedKey, V)>>(
+        iter: I,
+    ) -> Result<Self, OutOfOrder<V>> {
+        let iter = iter.into_iter();
+        let mut builder = SortedBuilder::with_capacity(iter.size_hint().0);
+        for (key, value) in iter {
+            builder.push(key, value)?;
+        }
+        Ok(builder.build())
+    }
+
+    /// Returns a builder that takes entries one at a time, in sorted order, and then builds the map
+    /// bottom up.
+    pub fn sorted_builder() -> SortedBuilder<V> {
+        SortedBuilder::new()
+    }
+
+    /// Inserts a value for `key`, returning the old value if there was one.
+    pub fn insert(&mut self, key: OwnedKey, value: V) -> Option<V> {
+        self.map.insert(key, value)
+    }
+
+    /// Returns the value for `key`, if any.
+    pub fn get(&self, key: &dyn Key) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    /// Returns a mutable reference to the value for `key`, if any.
+    pub fn get_mut(&mut self, key: &dyn Key) -> Option<&mut V> {
+        self.map.get_mut(key)
+    }
+
+    /// Returns true if the map has a value for `key`.
+    pub fn contains_key(&self, key: &dyn Key) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Removes the value for `key`, returning it if there was one.
+    pub fn remove(&mut self, key: &dyn Key) -> Option<V> {
+        self.map.remove(key)
+    }
+
+    /// Iterates over the entries with keys in `range`, in key order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range's start is after its end, like [`BTreeMap::range`].
+    pub fn range<'k, R: RangeBounds<&'k dyn Key>>(
+        &self,
+        range: R,
+    ) -> btree_map::Range<'_, OwnedKey, V> {
+        let start = range.start_bound().map(|key| *key);
+        let end = range.end_bound().map(|key| *key);
+        self.map.range::<dyn Key, _>((start, end))
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Iterates over the entries in the map, in key order.
+    pub fn iter(&self) -> btree_map::Iter<'_, OwnedKey, V> {
+        self.map.iter()
+    }
+
+    /// Iterates over borrowed views of the keys, with their values, in key order.
+    pub fn iter_borrowed(&self) -> impl Iterator<Item = (BorrowedKey<'_>, &V)> {
+        self.map.iter().map(|(key, value)| (key.key(), value))
+    }
+}
+
+impl<V> FromIterator<(OwnedKey, V)> for KeyBTreeMap<V> {
+    fn from_iter<I: IntoIterator<Item = (OwnedKey, V)>>(iter: I) -> Self {
+        Self {
+            map: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<V> Extend<(OwnedKey, V)> for KeyBTreeMap<V> {
+    fn extend<I: IntoIterator<Item = (OwnedKey, V)>>(&mut self, iter: I) {
+        self.map.extend(iter)
+    }
+}
+
+impl<'a, V> IntoIterator for &'a KeyBTreeMap<V> {
+    type Item = (&'a OwnedKey, &'a V);
+    type IntoIter = btree_map::Iter<'a, OwnedKey, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Builds a [`KeyBTreeMap`] from entries that arrive in sorted order.
+///
+/// Returned by [`KeyBTreeMap::sorted_builder`].
+#[derive(Clone, Debug)]
+pub struct SortedBuilder<V> {
+    entries: Vec<(OwnedKey, V)>,
+}
+
+impl<V> SortedBuilder<V> {
+    fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Adds an entry, whose key must be greater than every key added so far.
+    ///
+    /// If it isn't, the entry is handed back in the error, and the builder is left as it was.
+    pub fn push(&mut self, key: OwnedKey, value: V) -> Result<(), OutOfOrder<V>> {
+        if let Some((last, _)) = self.entries.last() {
+            if key.key() <= last.key() {
+                return Err(OutOfOrder {
+                    index: self.entries.len(),
+                    key,
+                    value,
+                });
+            }
+        }
+        self.entries.push((key, value));
+        Ok(())
+    }
+
+    /// Returns the last key added, if any. The next key must be greater than it.
+    pub fn last_key(&self) -> Option<BorrowedKey<'_>> {
+        self.entries.last().map(|(key, _)| key.key())
+    }
+
+    /// Returns the number of entries added so far.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no entries have been added.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Builds the map.
+    pub fn build(self) -> KeyBTreeMap<V> {
+        // The entries are already sorted, so FromIterator's sort is a single pass over them, and
+        // then it builds the tree bottom up.
+        self.entries.into_iter().collect()
+    }
+}
+
+/// An entry whose key was out of order, passed to [`KeyBTreeMap::from_sorted_iter`] or
+/// [`SortedBuilder::push`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OutOfOrder<V> {
+    /// The entry's position in the input.
+    pub index: usize,
+    /// The entry's key, which isn't greater than the key before it.
+    pub key: OwnedKey,
+    /// The entry's value.
+    pub value: V,
+}
+
+impl<V> fmt::Display for OutOfOrder<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "key at index {} isn't greater than the key before it",
+            self.index
+        )
+    }
+}
+
+impl<V: fmt::Debug> Error for OutOfOrder<V> {}
+
+#[test]
+fn tree_map_lookups() {
+    let map = KeyBTreeMap::from_sorted_iter(vec![
+        (owned("a", b"1"), 1),
+        (owned("a", b"2"), 2),
+        (owned("b", b""), 3),
+        (owned("c", b"x"), 4),
+    ])
+    .unwrap();
+    assert_eq!(map.len(), 4);
+
+    let borrowed_key = BorrowedKey {
+        s: "a",
+        bytes: b"2",
+    };
+    assert_eq!(map.get(&borrowed_key), Some(&2));
+    assert!(!map.contains_key(&owned("a", b"3")));
+
+    // Every key with the string "a": from ("a", b"") up to, but not including, ("a\0", b"").
+    let start = owned("a", b"");
+    let end = owned("a\0", b"");
+    let a_values: Vec<_> = map
+        .range(&start as &dyn Key..&end)
+        .map(|(_, &value)| value)
+        .collect();
+    assert_eq!(a_values, [1, 2]);
+    assert_eq!(map.range(&borrowed_key as &dyn Key..).count(), 3);
+
+    let entries: Vec<_> = map.iter_borrowed().collect();
+    assert_eq!(entries[1], (borrowed_key, &2));
+    assert_eq!(entries.len(), 4);
+
+    // Out of order and duplicate keys are rejected, and handed back.
+    let err = KeyBTreeMap::from_sorted_iter(vec![(owned("b", b""), 1), (owned("a", b""), 2)])
+        .unwrap_err();
+    assert_eq!(
+        err,
+        OutOfOrder {
+            index: 1,
+            key: owned("a", b""),
+            value: 2
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "key at index 1 isn't greater than the key before it"
+    );
+
+    let mut builder = KeyBTreeMap::sorted_builder();
+    builder.push(owned("a", b""), 1).unwrap();
+    assert_eq!(builder.push(owned("a", b""), 2).unwrap_err().index, 1);
+    builder.push(owned("a", b"\0"), 3).unwrap();
+    assert_eq!(builder.last_key(), Some(owned("a", b"\0").key()));
+    let map = builder.build();
+    assert_eq!(map.iter().map(|(_, &v)| v).collect::<Vec<_>>(), [1, 3]);
+}
+
+proptest! {
+    #[test]
+    fn tree_map_sorted_build_matches_btree_map(
+        entries in prop::collection::vec((any::<OwnedKey>(), any::<u8>()), 0..32),
+    ) {
+        // Built from unsorted input, from_sorted_iter must report the first key that's out of
+        // order. Built from sorted input, it must match BTreeMap.
+        let first_out_of_order = entries
+            .windows(2)
+            .position(|pair| pair[1].0 <= pair[0].0)
+            .map(|index| index + 1);
+        match KeyBTreeMap::from_sorted_iter(entries.clone()) {
+            Ok(map) => {
+                prop_assert_eq!(first_out_of_order, None);
+                prop_assert!(map.iter().eq(entries.iter().map(|(k, v)| (k, v))));
+            }
+            Err(err) => prop_assert_eq!(Some(err.index), first_out_of_order),
+        }
+
+        let model: BTreeMap<OwnedKey, u8> = entries.into_iter().collect();
+        let map = KeyBTreeMap::from_sorted_iter(model.clone()).unwrap();
+        prop_assert!(map.iter().eq(model.iter()));
+        for key in model.keys() {
+            prop_assert_eq!(map.get(&key.key()), model.get(key));
+        }
+    }
+}
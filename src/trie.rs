@@ -0,0 +1,319 @@
+//! A radix tree over encoded keys, with prefix search.
+
+use crate::encoding;
+#[cfg(test)]
+use crate::owned;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::collections::btree_map::{self, BTreeMap};
+use std::mem;
+
+// Routing tables look keys up by prefix: every route under "/api/", say. A BTreeSet<OwnedKey> can
+// do that with a range scan, but a trie shares the storage and the comparisons for common prefixes,
+// which is most of a routing table.
+//
+// A trie needs its keys to be a single sequence, though, and a key has two fields. The encoding in
+// encoding.rs turns a key into one byte string that sorts the same way, so KeyTrie is a trie over
+// encoded keys:
+//
+// - Each edge is labelled with a run of bytes, and nodes with a single child are merged into their
+//   parent's edge. (This is what makes it a radix tree rather than a plain trie.)
+// - Children are kept in a BTreeMap by the first byte of their label, so walking the tree depth
+//   first visits keys in byte order, which is key order.
+// - A key whose string starts with a prefix has an encoding that starts with the escaped prefix,
+//   so iter_prefix walks down to the prefix and then iterates over everything below it.
+//
+// The tree only routes: the bytes along the edges are the encoded form, which can't be borrowed as
+// a BorrowedKey without decoding it. So each node with a value also keeps its key, and iteration
+// hands out BorrowedKeys that point into it.
+/// A map from keys to values, stored in a radix tree, that can find every key whose string starts
+/// with a given prefix.
+#[derive(Clone, Debug)]
+pub struct KeyTrie<V> {
+    root: Node<V>,
+    len: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Node<V> {
+    entry: Option<(OwnedKey, V)>,
+    children: BTreeMap<u8, Edge<V>>,
+}
+
+#[derive(Clone, Debug)]
+struct Edge<V> {
+    label: Vec<u8>,
+    node: Node<V>,
+}
+
+impl<V> Node<V> {
+    fn new(entry: Option<(OwnedKey, V)>) -> Self {
+        Self {
+            entry,
+            children: BTreeMap::new(),
+        }
+    }
+
+    fn insert(&mut self, rest: &[u8], key: OwnedKey, value: V) -> Option<V> {
+        let first = match rest.first() {
+            Some(&first) => first,
+            None => {
+                return match &mut self.entry {
+                    Some((_, old)) => Some(mem::replace(old, value)),
+                    None => {
+                        self.entry = Some((key, value));
+                        None
+                    }
+                }
+            }
+        };
+        let edge = match self.children.entry(first) {
+            btree_map::Entry::Vacant(vacant) => {
+                vacant.insert(Edge {
+                    label: rest.to_vec(),
+                    node: Node::new(Some((key, value))),
+                });
+                return None;
+            }
+            btree_map::Entry::Occupied(occupied) => occupied.into_mut(),
+        };
+
+        let common = edge
+            .label
+            .iter()
+            .zip(rest)
+            .take_while(|(a, b)| a == b)
+            .count();
+        if common < edge.label.len() {
+            // The key leaves this edge partway along, so split the edge in two at that point.
+            let tail = edge.label.split_off(common);
+            let below = mem::replace(&mut edge.node, Node::new(None));
+            edge.node.children.insert(
+                tail[0],
+                Edge {
+                    label: tail,
+                    node: below,
+                },
+            );
+        }
+        edge.node.insert(&rest[common..], key, value)
+    }
+
+    // Returns the node for exactly this encoding, if there is one.
+    fn find(&self, mut rest: &[u8]) -> Option<&Self> {
+        let mut node = self;
+        while let Some(first) = rest.first() {
+            let edge = node.children.get(first)?;
+            rest = rest.strip_prefix(&edge.label[..])?;
+            node = &edge.node;
+        }
+        Some(node)
+    }
+
+    fn find_mut(&mut self, mut rest: &[u8]) -> Option<&mut Self> {
+        let mut node = self;
+        while let Some(first) = rest.first() {
+            let edge = node.children.get_mut(first)?;
+            rest = rest.strip_prefix(&edge.label[..])?;
+            node = &mut edge.node;
+        }
+        Some(node)
+    }
+
+    // Returns the highest node under which every encoding starts with the prefix, if there is one.
+    fn find_prefix(&self, mut rest: &[u8]) -> Option<&Self> {
+        let mut node = self;
+        while let Some(first) = rest.first() {
+            let edge = node.children.get(first)?;
+            if edge.label.starts_with(rest) {
+                return Some(&edge.node);
+            }
+            rest = rest.strip_prefix(&edge.label[..])?;
+            node = &edge.node;
+        }
+        Some(node)
+    }
+}
+
+impl<V> Default for KeyTrie<V> {
+    fn default() -> Self {
+        Self {
+            root: Node::new(None),
+            len: 0,
+        }
+    }
+}
+
+impl<V> KeyTrie<V> {
+    /// Creates a new, empty trie.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a value for `key`, returning the old value if there was one.
+    pub fn insert(&mut self, key: OwnedKey, value: V) -> Option<V> {
+        let encoded = encoding::encode(&key);
+        let old = self.root.insert(&encoded, key, value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Returns the value for `key`, if any.
+    pub fn get(&self, key: &dyn Key) -> Option<&V> {
+        let node = self.root.find(&encoding::encode(key))?;
+        node.entry.as_ref().map(|(_, value)| value)
+    }
+
+    /// Returns a mutable reference to the value for `key`, if any.
+    pub fn get_mut(&mut self, key: &dyn Key) -> Option<&mut V> {
+        let node = self.root.find_mut(&encoding::encode(key))?;
+        node.entry.as_mut().map(|(_, value)| value)
+    }
+
+    /// Returns true if the trie has a value for `key`.
+    pub fn contains_key(&self, key: &dyn Key) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Iterates over every key whose string starts with `prefix`, and its value, in key order.
+    pub fn iter_prefix(&self, prefix: &str) -> Iter<'_, V> {
+        let encoded = encoding::string_starts_with_prefix(prefix);
+        Iter {
+            stack: self.root.find_prefix(&encoded).into_iter().collect(),
+        }
+    }
+
+    /// Iterates over every key and value, in key order.
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter {
+            stack: vec![&self.root],
+        }
+    }
+
+    /// Returns the number of entries in the trie.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the trie is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// An iterator over the entries of a [`KeyTrie`], in key order.
+///
+/// Returned by [`KeyTrie::iter`] and [`KeyTrie::iter_prefix`].
+#[derive(Clone, Debug)]
+pub struct Iter<'a, V> {
+    // Nodes still to visit, with the next one on top.
+    stack: Vec<&'a Node<V>>,
+}
+
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = (BorrowedKey<'a>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // A node's own key is a prefix of every key below it, so it comes first. Then its
+        // children, in byte order.
+        while let Some(node) = self.stack.pop() {
+            self.stack
+                .extend(node.children.values().rev().map(|edge| &edge.node));
+            if let Some((key, value)) = &node.entry {
+                return Some((key.key(), value));
+            }
+        }
+        None
+    }
+}
+
+#[test]
+fn trie_lookups() {
+    let mut trie = KeyTrie::new();
+    let routes = [
+        ("/api/users", &b"GET"[..], 1),
+        ("/api/users", b"POST", 2),
+        ("/api/posts", b"GET", 3),
+        ("/about", b"GET", 4),
+        ("/api", b"GET", 5),
+        ("/api\0", b"GET", 6),
+    ];
+    for &(s, bytes, value) in &routes {
+        assert_eq!(trie.insert(owned(s, bytes), value), None);
+    }
+    assert_eq!(trie.len(), routes.len());
+    assert_eq!(trie.insert(owned("/about", b"GET"), 40), Some(4));
+    *trie.get_mut(&owned("/about", b"GET")).unwrap() += 1;
+
+    let borrowed_key = BorrowedKey {
+        s: "/api/users",
+        bytes: b"POST",
+    };
+    assert_eq!(trie.get(&borrowed_key), Some(&2));
+    assert_eq!(trie.get(&owned("/about", b"GET")), Some(&41));
+    // A prefix of a stored key isn't a key itself.
+    assert!(!trie.contains_key(&owned("/api/users", b"")));
+    assert!(!trie.contains_key(&owned("/api/user", b"GET")));
+
+    // Everything under /api/, in key order.
+    let api: Vec<_> = trie
+        .iter_prefix("/api/")
+        .map(|(key, &value)| (key.s, key.bytes, value))
+        .collect();
+    assert_eq!(
+        api,
+        [
+            ("/api/posts", &b"GET"[..], 3),
+            ("/api/users", b"GET", 1),
+            ("/api/users", b"POST", 2),
+        ]
+    );
+
+    // /api matches itself and everything that starts with it, including "/api\0".
+    assert_eq!(trie.iter_prefix("/api").count(), 5);
+    assert_eq!(trie.iter_prefix("/api\0").count(), 1);
+    assert_eq!(trie.iter_prefix("/x").count(), 0);
+    assert_eq!(trie.iter_prefix("").count(), trie.len());
+}
+
+#[cfg(test)]
+fn trie_keys() -> impl Strategy<Value = OwnedKey> {
+    // A small alphabet, including the bytes that the encoding escapes, so that keys share long
+    // prefixes and edges get split often.
+    (
+        "[\0ab]{0,3}",
+        prop::collection::vec(prop_oneof![Just(0u8), Just(1), Just(0xff)], 0..3),
+    )
+        .prop_map(|(s, bytes)| OwnedKey { s, bytes })
+}
+
+proptest! {
+    #[test]
+    fn trie_matches_btree_map(
+        entries in prop::collection::vec((trie_keys(), any::<u8>()), 0..32),
+        probe in trie_keys(),
+        prefix in "[\0ab]{0,2}",
+    ) {
+        let mut trie = KeyTrie::new();
+        let mut model = std::collections::BTreeMap::new();
+        for (key, value) in entries {
+            prop_assert_eq!(trie.insert(key.clone(), value), model.insert(key, value));
+        }
+        prop_assert_eq!(trie.len(), model.len());
+        prop_assert_eq!(trie.get(&probe), model.get(&probe));
+
+        let all: Vec<_> = trie.iter().collect();
+        let expected: Vec<_> = model.iter().map(|(key, value)| (key.key(), value)).collect();
+        prop_assert_eq!(all, expected);
+
+        let matching: Vec<_> = trie.iter_prefix(&prefix).collect();
+        let expected: Vec<_> = model
+            .iter()
+            .filter(|(key, _)| key.s.starts_with(&prefix))
+            .map(|(key, value)| (key.key(), value))
+            .collect();
+        prop_assert_eq!(matching, expected);
+    }
+}
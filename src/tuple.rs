@@ -0,0 +1,90 @@
+//! Tuples of key components as composite keys.
+
+use crate::component::KeyComponent;
+use crate::consistency::assert_consistent;
+use crate::family::{KeyFamily, Lookup, Probe};
+use proptest::prelude::*;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+
+// Sometimes you don't want to define a key type at all: a HashMap<(String, Vec<u8>, u32), V> is
+// right there. Tuples of key components form key families of their own, with the tuple of borrowed
+// components as the borrowed form, so maps keyed by tuples can be probed with borrowed tuples:
+//
+//   map.get(Probe::<(String, Vec<u8>, u32)>(("foo", b"abc", 42)).as_lookup())
+//
+// The orphan rules allow the Borrow impl below because the trait object type is local to this
+// crate, and the tuple's type parameters are "covered" by the tuple.
+macro_rules! tuple_keys {
+    ($(($($ty:ident $var:ident),+))+) => {
+        $(
+            impl<$($ty: KeyComponent),+> KeyFamily for ($($ty,)+) {
+                type Borrowed<'a> = ($($ty::Borrowed<'a>,)+);
+
+                fn shorten<'long: 'short, 'short>(
+                    ($($var,)+): Self::Borrowed<'long>,
+                ) -> Self::Borrowed<'short> {
+                    ($($ty::shorten($var),)+)
+                }
+            }
+
+            impl<$($ty: KeyComponent),+> Lookup<($($ty,)+)> for ($($ty,)+) {
+                fn lookup_key(&self) -> ($($ty::Borrowed<'_>,)+) {
+                    let ($($var,)+) = self;
+                    ($($var.as_borrowed(),)+)
+                }
+            }
+
+            impl<'a, $($ty: KeyComponent + 'a),+> Borrow<dyn Lookup<($($ty,)+)> + 'a>
+                for ($($ty,)+)
+            {
+                fn borrow(&self) -> &(dyn Lookup<($($ty,)+)> + 'a) {
+                    self
+                }
+            }
+        )+
+    };
+}
+
+tuple_keys! {
+    (A a)
+    (A a, B b)
+    (A a, B b, C c)
+    (A a, B b, C c, D d)
+    (A a, B b, C c, D d, E e)
+    (A a, B b, C c, D d, E e, F f)
+    (A a, B b, C c, D d, E e, F f, G g)
+    (A a, B b, C c, D d, E e, F f, G g, H h)
+}
+
+#[test]
+fn tuple_lookups() {
+    let mut map: HashMap<(String, Vec<u8>, u32), &str> = HashMap::new();
+    map.insert(("foo".to_string(), b"abc".to_vec(), 42), "value");
+
+    let probe = Probe::<(String, Vec<u8>, u32)>(("foo", b"abc", 42));
+    assert_eq!(map.get(probe.as_lookup()), Some(&"value"));
+    let probe = Probe::<(String, Vec<u8>, u32)>(("foo", b"abc", 43));
+    assert_eq!(map.get(probe.as_lookup()), None);
+
+    // Both ends of the supported range.
+    type One = (String,);
+    type Eight = (String, u8, u16, u32, u64, i8, Vec<u8>, bool);
+    let one: One = ("a".to_string(),);
+    let eight: Eight = ("a".to_string(), 1, 2, 3, 4, 5, vec![6], true);
+    assert!(Borrow::<dyn Lookup<One>>::borrow(&one) == Probe::<One>(("a",)).as_lookup());
+    assert!(
+        Borrow::<dyn Lookup<Eight>>::borrow(&eight)
+            == Probe::<Eight>(("a", 1, 2, 3, 4, 5, &[6], true)).as_lookup()
+    );
+}
+
+proptest! {
+    #[test]
+    fn tuple_consistent_borrow(
+        owned1 in any::<(String, Vec<u8>, u32)>(),
+        owned2 in any::<(String, Vec<u8>, u32)>(),
+    ) {
+        assert_consistent::<_, dyn Lookup<(String, Vec<u8>, u32)>>(&owned1, &owned2);
+    }
+}
@@ -0,0 +1,210 @@
+//! A map that holds values of many types, each under its own composite keys.
+
+use crate::consistency::assert_consistent_hash;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use std::any::{Any, TypeId};
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher};
+
+// Plugin and extension systems often keep a registry of values of different types, looked up by
+// name: a plugin registers a Config and a Handler under the same key, and the host asks for "the
+// Handler registered as ("auth", b"v2")". That's a map keyed by the value's TypeId and a composite
+// key together.
+//
+// The owned key is a struct with both, TypedOwnedKey. Lookups shouldn't have to build one, so this
+// is the crate root pattern all over again, with the TypeId added: a TypedKey trait whose borrowed
+// form is a (TypeId, BorrowedKey) tuple, Eq and Hash for dyn TypedKey that go through it, and
+// Borrow<dyn TypedKey> for the owned key. The derived Hash on TypedOwnedKey hashes the TypeId and
+// then the key's fields, in order, which is exactly what hashing the tuple does. (There's no Ord,
+// since TypedKeyMap is a hash map and TypeIds don't have a meaningful order.)
+/// A map from composite keys to values of any number of types.
+///
+/// Each type has its own namespace: the same key can hold one value of each type.
+#[derive(Debug, Default)]
+pub struct TypedKeyMap<S = RandomState> {
+    map: HashMap<TypedOwnedKey, Box<dyn Any + Send + Sync>, S>,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct TypedOwnedKey {
+    type_id: TypeId,
+    key: OwnedKey,
+}
+
+trait TypedKey {
+    fn typed_key(&self) -> (TypeId, BorrowedKey<'_>);
+}
+
+impl TypedKey for TypedOwnedKey {
+    fn typed_key(&self) -> (TypeId, BorrowedKey<'_>) {
+        (self.type_id, self.key.key())
+    }
+}
+
+impl<'a> TypedKey for (TypeId, BorrowedKey<'a>) {
+    fn typed_key(&self) -> (TypeId, BorrowedKey<'_>) {
+        *self
+    }
+}
+
+impl<'a> Borrow<dyn TypedKey + 'a> for TypedOwnedKey {
+    fn borrow(&self) -> &(dyn TypedKey + 'a) {
+        self
+    }
+}
+
+impl<'a> PartialEq for dyn TypedKey + 'a {
+    fn eq(&self, other: &Self) -> bool {
+        self.typed_key() == other.typed_key()
+    }
+}
+
+impl<'a> Eq for dyn TypedKey + 'a {}
+
+impl<'a> Hash for dyn TypedKey + 'a {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.typed_key().hash(state)
+    }
+}
+
+fn probe<T: Any>(key: &dyn Key) -> (TypeId, BorrowedKey<'_>) {
+    (TypeId::of::<T>(), key.key())
+}
+
+impl TypedKeyMap {
+    /// Creates a new, empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S: BuildHasher> TypedKeyMap<S> {
+    /// Creates a new, empty map that uses `hasher` to hash keys.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            map: HashMap::with_hasher(hasher),
+        }
+    }
+
+    /// Inserts a value of type `T` for `key`, returning the old value of that type if there was
+    /// one. Values of other types under the same key are left alone.
+    pub fn insert<T: Any + Send + Sync>(&mut self, key: OwnedKey, value: T) -> Option<T> {
+        let key = TypedOwnedKey {
+            type_id: TypeId::of::<T>(),
+            key,
+        };
+        self.map.insert(key, Box::new(value)).map(|old| {
+            *old.downcast()
+                .expect("values are stored under their own TypeId")
+        })
+    }
+
+    /// Returns the value of type `T` for `key`, if any.
+    pub fn get<T: Any>(&self, key: &dyn Key) -> Option<&T> {
+        let value = self.map.get(&probe::<T>(key) as &dyn TypedKey)?;
+        value.downcast_ref()
+    }
+
+    /// Returns a mutable reference to the value of type `T` for `key`, if any.
+    pub fn get_mut<T: Any>(&mut self, key: &dyn Key) -> Option<&mut T> {
+        let value = self.map.get_mut(&probe::<T>(key) as &dyn TypedKey)?;
+        value.downcast_mut()
+    }
+
+    /// Returns true if the map has a value of type `T` for `key`.
+    pub fn contains_key<T: Any>(&self, key: &dyn Key) -> bool {
+        self.map.contains_key(&probe::<T>(key) as &dyn TypedKey)
+    }
+
+    /// Removes the value of type `T` for `key`, returning it if there was one.
+    pub fn remove<T: Any>(&mut self, key: &dyn Key) -> Option<T> {
+        let value = self.map.remove(&probe::<T>(key) as &dyn TypedKey)?;
+        Some(
+            *value
+                .downcast()
+                .expect("values are stored under their own TypeId"),
+        )
+    }
+
+    /// Iterates over the keys and values of type `T`, in arbitrary order.
+    pub fn iter<T: Any>(&self) -> impl Iterator<Item = (&OwnedKey, &T)> {
+        self.map
+            .iter()
+            .filter(|(key, _)| key.type_id == TypeId::of::<T>())
+            .filter_map(|(key, value)| Some((&key.key, value.downcast_ref()?)))
+    }
+
+    /// Iterates over borrowed views of the keys with values of type `T`, with the values, in
+    /// arbitrary order.
+    pub fn iter_borrowed<T: Any>(&self) -> impl Iterator<Item = (BorrowedKey<'_>, &T)> {
+        self.iter().map(|(key, value)| (key.key(), value))
+    }
+
+    /// Returns the number of values in the map, of all types.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+#[test]
+fn typed_map_lookups() {
+    struct Config(u32);
+    struct Handler(&'static str);
+
+    let key = OwnedKey {
+        s: "auth".to_string(),
+        bytes: b"v2".to_vec(),
+    };
+    let borrowed_key = BorrowedKey {
+        s: "auth",
+        bytes: b"v2",
+    };
+
+    let mut map = TypedKeyMap::new();
+    assert!(map.insert(key.clone(), Config(1)).is_none());
+    assert!(map.insert(key.clone(), Handler("login")).is_none());
+    assert_eq!(map.len(), 2);
+
+    // Each type has its own entry under the same key.
+    assert_eq!(map.get::<Config>(&borrowed_key).map(|c| c.0), Some(1));
+    assert_eq!(
+        map.get::<Handler>(&borrowed_key).map(|h| h.0),
+        Some("login")
+    );
+    assert!(!map.contains_key::<u32>(&borrowed_key));
+
+    map.get_mut::<Config>(&borrowed_key).unwrap().0 = 2;
+    assert_eq!(map.insert(key.clone(), Config(3)).map(|c| c.0), Some(2));
+    assert_eq!(map.iter::<Config>().count(), 1);
+    let configs: Vec<_> = map
+        .iter_borrowed::<Config>()
+        .map(|(key, config)| (key, config.0))
+        .collect();
+    assert_eq!(configs, [(borrowed_key, 3)]);
+
+    assert_eq!(map.remove::<Handler>(&key).map(|h| h.0), Some("login"));
+    assert!(map.get::<Handler>(&borrowed_key).is_none());
+    assert!(map.get::<Config>(&borrowed_key).is_some());
+}
+
+proptest! {
+    #[test]
+    fn typed_map_consistent_hash(
+        owned1 in any::<OwnedKey>(),
+        owned2 in any::<OwnedKey>(),
+        same_type in any::<bool>(),
+    ) {
+        let key1 = TypedOwnedKey { type_id: TypeId::of::<u8>(), key: owned1 };
+        let type_id = if same_type { TypeId::of::<u8>() } else { TypeId::of::<u16>() };
+        let key2 = TypedOwnedKey { type_id, key: owned2 };
+        assert_consistent_hash::<_, dyn TypedKey>(&key1, &key2);
+    }
+}
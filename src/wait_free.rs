@@ -0,0 +1,293 @@
+//! A key map with wait-free readers and a single writer, built on
+//! [left-right](https://docs.rs/left-right).
+//!
+//! Requires the `left-right` feature.
+
+use crate::map::KeyMap;
+#[cfg(test)]
+use crate::numbered_key;
+#[cfg(test)]
+use crate::owned;
+use crate::{BorrowedKey, Key, OwnedKey};
+use left_right::{Absorb, ReadGuard, ReadHandle, WriteHandle};
+use proptest::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+
+// SyncKeyMap's readers take a read lock, and AtomicKeySet's copy the whole set on every write.
+// left-right sits between the two: it keeps two copies of the map, and readers read one while the
+// writer writes to the other. When the writer publishes, the copies swap, the writer waits for the
+// last readers of the old copy to leave, and then replays the same writes on it. Readers never wait
+// for anything, and a write costs a second application rather than a copy of the whole map.
+//
+// The writes are kept in a log until they've been applied to both copies, so they have to own
+// their keys: removing by a borrowed key copies it into an OwnedKey, once, on the writer's side.
+// Reads are where the borrowed keys pay off. A reader enters its copy, which is just a counter
+// bump, and does an ordinary KeyMap lookup by &dyn Key in it, with no locking and no allocation.
+//
+// The two copies must stay identical, including how they hash. The second copy is made by cloning
+// the first, so they share a RandomState, rather than each getting a random one of its own.
+/// The writing half of a wait-free key map.
+///
+/// Writes are batched: they aren't visible to readers until [`publish`](Self::publish) is called.
+pub struct KeyMapWriter<V: Clone> {
+    inner: WriteHandle<KeyMap<V>, Op<V>>,
+}
+
+/// A reading half of a wait-free key map.
+///
+/// Each thread needs a reader of its own: clone this one to get another.
+pub struct KeyMapReader<V> {
+    inner: ReadHandle<KeyMap<V>>,
+}
+
+enum Op<V> {
+    Insert(OwnedKey, V),
+    Remove(OwnedKey),
+    Clear,
+}
+
+impl<V: Clone> Absorb<Op<V>> for KeyMap<V> {
+    fn absorb_first(&mut self, operation: &mut Op<V>, _: &Self) {
+        match operation {
+            Op::Insert(key, value) => {
+                self.insert(key.clone(), value.clone());
+            }
+            Op::Remove(key) => {
+                self.remove(key);
+            }
+            Op::Clear => self.clear(),
+        }
+    }
+
+    fn absorb_second(&mut self, operation: Op<V>, _: &Self) {
+        // This is the last time the operation is used, so it can be moved in.
+        match operation {
+            Op::Insert(key, value) => {
+                self.insert(key, value);
+            }
+            Op::Remove(key) => {
+                self.remove(&key);
+            }
+            Op::Clear => self.clear(),
+        }
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        *self = first.clone();
+    }
+}
+
+/// Creates a new, empty map, returning its writer and a first reader.
+pub fn key_map<V: Clone>() -> (KeyMapWriter<V>, KeyMapReader<V>) {
+    let (mut writer, reader) = left_right::new_from_empty(KeyMap::new());
+    // Until the first publish, left-right applies writes to the writer's copy straight away, rather
+    // than logging them, so has_pending wouldn't see them. Publishing the empty map starts the log.
+    writer.publish();
+    (
+        KeyMapWriter { inner: writer },
+        KeyMapReader { inner: reader },
+    )
+}
+
+impl<V: Clone> KeyMapWriter<V> {
+    /// Queues an insert of `value` for `key`.
+    pub fn insert(&mut self, key: OwnedKey, value: V) -> &mut Self {
+        self.inner.append(Op::Insert(key, value));
+        self
+    }
+
+    /// Queues a removal of `key`. This copies the key into an `OwnedKey`.
+    pub fn remove(&mut self, key: &dyn Key) -> &mut Self {
+        let key = key.key();
+        self.inner.append(Op::Remove(OwnedKey {
+            s: key.s.to_owned(),
+            bytes: key.bytes.to_owned(),
+        }));
+        self
+    }
+
+    /// Queues a removal of every key.
+    pub fn clear(&mut self) -> &mut Self {
+        self.inner.append(Op::Clear);
+        self
+    }
+
+    /// Makes every queued write visible to readers, waiting for readers of the old copy to leave
+    /// it first.
+    pub fn publish(&mut self) -> &mut Self {
+        self.inner.publish();
+        self
+    }
+
+    /// Returns true if there are writes that haven't been published yet.
+    pub fn has_pending(&self) -> bool {
+        self.inner.has_pending_operations()
+    }
+}
+
+impl<V> KeyMapReader<V> {
+    /// Returns the value for `key` as of the last publish, if any.
+    ///
+    /// The writer can't apply its next publish to this copy of the map until the guard is
+    /// dropped, so don't hold on to it.
+    pub fn get(&self, key: &dyn Key) -> Option<ReadGuard<'_, V>> {
+        ReadGuard::try_map(self.inner.enter()?, |map| map.get(key))
+    }
+
+    /// Returns a clone of the value for `key` as of the last publish, if any.
+    pub fn get_cloned(&self, key: &dyn Key) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.get(key).map(|value| value.clone())
+    }
+
+    /// Returns true if the map had a value for `key` as of the last publish.
+    pub fn contains_key(&self, key: &dyn Key) -> bool {
+        self.with_map(|map| map.contains_key(key)).unwrap_or(false)
+    }
+
+    /// Calls `f` with the map as of the last publish, and returns what it returns. Returns `None`
+    /// if the writer has been dropped.
+    pub fn with_map<R, F: FnOnce(&KeyMap<V>) -> R>(&self, f: F) -> Option<R> {
+        let map = self.inner.enter()?;
+        Some(f(&map))
+    }
+
+    /// Returns the number of entries as of the last publish.
+    pub fn len(&self) -> usize {
+        self.with_map(|map| map.len()).unwrap_or(0)
+    }
+
+    /// Returns true if the map was empty as of the last publish.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if the writer has been dropped. Once it has, every read acts as if the map is
+    /// empty.
+    pub fn writer_dropped(&self) -> bool {
+        self.inner.was_dropped()
+    }
+}
+
+impl<V> Clone for KeyMapReader<V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<V: Clone> fmt::Debug for KeyMapWriter<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyMapWriter").finish_non_exhaustive()
+    }
+}
+
+impl<V> fmt::Debug for KeyMapReader<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyMapReader").finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn wait_free_lookups() {
+    let (mut writer, reader) = key_map();
+    writer.insert(owned("foo", b"abc"), 1);
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+
+    // Nothing is visible until it's published.
+    assert!(writer.has_pending());
+    assert!(!reader.contains_key(&borrowed_key));
+    writer.publish();
+    assert!(!writer.has_pending());
+    assert_eq!(reader.get(&borrowed_key).as_deref(), Some(&1));
+
+    writer
+        .insert(owned("foo", b"abc"), 2)
+        .insert(owned("bar", b""), 3)
+        .publish();
+    let other_reader = reader.clone();
+    assert_eq!(other_reader.get_cloned(&borrowed_key), Some(2));
+    assert_eq!(reader.len(), 2);
+
+    writer.remove(&borrowed_key).publish();
+    assert_eq!(reader.get_cloned(&borrowed_key), None);
+    writer.clear().publish();
+    assert!(reader.is_empty());
+
+    drop(writer);
+    assert!(reader.writer_dropped());
+    assert_eq!(reader.with_map(|map| map.len()), None);
+}
+
+// Reader threads probe while the writer publishes batches. Each batch adds one more key from the
+// same list, so every reader must see a prefix of it, and never one that shrinks.
+#[test]
+fn wait_free_readers_see_published_batches() {
+    let (mut writer, reader) = key_map();
+    let keys: Vec<_> = (0..8).map(numbered_key).collect();
+
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            let reader = reader.clone();
+            let keys = &keys;
+            scope.spawn(move || {
+                let mut last = 0;
+                for _ in 0..1000 {
+                    let present = reader
+                        .with_map(|map| {
+                            let present = keys.iter().take_while(|key| map.contains_key(*key));
+                            let present = present.count();
+                            assert_eq!(present, map.len());
+                            present
+                        })
+                        .unwrap();
+                    assert!(present >= last);
+                    last = present;
+                }
+            });
+        }
+        for (i, key) in keys.iter().enumerate() {
+            writer.insert(key.clone(), i).publish();
+        }
+    });
+    assert_eq!(reader.len(), keys.len());
+}
+
+proptest! {
+    #[test]
+    fn wait_free_matches_model(
+        ops in prop::collection::vec((0..3u8, any::<OwnedKey>(), any::<u8>()), 0..32),
+    ) {
+        // Readers only ever see the model as of the last publish.
+        let (mut writer, reader) = key_map();
+        let mut pending = HashMap::new();
+        let mut published = HashMap::new();
+        for (op, key, value) in ops {
+            match op {
+                0 => {
+                    writer.insert(key.clone(), value);
+                    pending.insert(key, value);
+                }
+                1 => {
+                    writer.remove(&key.key());
+                    pending.remove(&key);
+                }
+                _ => {
+                    writer.publish();
+                    published = pending.clone();
+                }
+            }
+            prop_assert_eq!(reader.len(), published.len());
+            for (key, value) in &published {
+                prop_assert_eq!(reader.get_cloned(&key.key()), Some(*value));
+            }
+        }
+    }
+}
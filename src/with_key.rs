@@ -0,0 +1,138 @@
+//! Helpers that lend out a key's borrowed view to a closure.
+
+use crate::{BorrowedKey, Key, KeyStorage, OwnedKey};
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+use std::collections::{BTreeMap, HashSet};
+
+// Code that's generic over keys often wants to hand a key's borrowed view to a callback: to probe
+// a collection, to hash it, to log it. The callback has to accept a BorrowedKey of *any* lifetime,
+// since the view only lives as long as the borrow of the key it came from, and no caller-chosen
+// lifetime will do. That's a higher-ranked trait bound, for<'a> FnOnce(BorrowedKey<'a>) -> R.
+//
+// Writing the signature is easy to get subtly wrong. A lifetime parameter on the function, as in
+// fn with<'a, R>(key: &'a OwnedKey, f: impl FnOnce(BorrowedKey<'a>) -> R), compiles, but lets the
+// closure keep the view around, and ties the caller's closure to one specific lifetime, which then
+// breaks as soon as the helper is called from a generic context with a shorter-lived key. Going the
+// other way, closures stored in a variable before being passed in don't pick up the higher-ranked
+// signature on their own, and the errors ("implementation of FnOnce is not general enough") don't
+// point at the fix.
+//
+// The helpers here have the bounds spelled out. The result type R is chosen outside the for<'a>,
+// so it can't borrow from the view: the view doesn't escape, and the closure can return anything
+// else it likes.
+/// Calls `f` with the borrowed view of `owned`.
+///
+/// The closure can't hold on to the view, since its lifetime ends when `f` returns:
+///
+/// ```compile_fail
+/// use borrow_complex_key_example::with_key::with_borrowed_key;
+/// use borrow_complex_key_example::OwnedKey;
+///
+/// let owned = OwnedKey { s: "foo".to_string(), bytes: vec![] };
+/// let escaped = with_borrowed_key(&owned, |key| key);
+/// ```
+pub fn with_borrowed_key<B, R>(
+    owned: &OwnedKey<B>,
+    f: impl for<'a> FnOnce(BorrowedKey<'a>) -> R,
+) -> R
+where
+    B: KeyStorage,
+{
+    f(owned.key())
+}
+
+/// Calls `f` with the borrowed view of any key.
+pub fn with_key<R>(key: &dyn Key, f: impl for<'a> FnOnce(BorrowedKey<'a>) -> R) -> R {
+    f(key.key())
+}
+
+/// Builds a probe out of `s` and `bytes`, and calls `f` with it as a `&dyn Key`, ready to look up
+/// in a collection of owned keys.
+pub fn with_probe<R>(s: &str, bytes: &[u8], f: impl for<'a> FnOnce(&'a (dyn Key + 'a)) -> R) -> R {
+    f(&BorrowedKey { s, bytes })
+}
+
+// A closure that's stored in a variable before it's passed in gets its signature from its own
+// body, not from the helper's bound, and won't be higher-ranked unless its argument type is written
+// out: |key: BorrowedKey<'_>| ... works, |key| ... may not. These functions do the annotating: pass
+// a closure through them to make it higher-ranked, then store it anywhere.
+/// Returns `f` unchanged, with its signature fixed as taking a [`BorrowedKey`] of any lifetime.
+pub fn borrowed_key_fn<R, F>(f: F) -> F
+where
+    F: for<'a> FnOnce(BorrowedKey<'a>) -> R,
+{
+    f
+}
+
+/// Returns `f` unchanged, with its signature fixed as taking a `&dyn Key` of any lifetime.
+pub fn dyn_key_fn<R, F>(f: F) -> F
+where
+    F: for<'a> FnOnce(&'a (dyn Key + 'a)) -> R,
+{
+    f
+}
+
+// The call patterns below are the ones that tend to trip up hand-written helpers. Each one only
+// needs to compile.
+
+// Called from a function generic over the key type, with a key whose lifetime is local to it.
+#[cfg(test)]
+fn generic_len<K: Key + ?Sized>(key: &K) -> usize {
+    let key: &dyn Key = &key.key();
+    with_key(key, |key| key.s.len() + key.bytes.len())
+}
+
+// Passing a generic function rather than a closure.
+#[cfg(test)]
+fn s_of(key: BorrowedKey<'_>) -> String {
+    key.s.to_string()
+}
+
+#[test]
+fn with_key_call_patterns() {
+    let owned = OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    };
+
+    // Returning something that doesn't borrow from the view.
+    assert_eq!(with_borrowed_key(&owned, |key| key.s.len()), 3);
+    assert_eq!(with_borrowed_key(&owned, s_of), "foo");
+    assert_eq!(generic_len(&owned), 6);
+    assert_eq!(generic_len(&BorrowedKey::new("ab", b"")), 2);
+
+    // Returning something that borrows from elsewhere, but not from the view.
+    let mut by_s: BTreeMap<String, u32> = BTreeMap::new();
+    by_s.insert("foo".to_string(), 1);
+    let value: Option<&u32> = with_borrowed_key(&owned, |key| by_s.get(key.s));
+    assert_eq!(value, Some(&1));
+
+    // Stored closures, made higher-ranked up front.
+    let describe = borrowed_key_fn(|key| format!("{}:{:?}", key.s, key.bytes));
+    assert_eq!(with_key(&owned, describe), "foo:[97, 98, 99]");
+
+    let mut hash_set: HashSet<OwnedKey> = HashSet::new();
+    hash_set.insert(owned.clone());
+    let lookup = dyn_key_fn(|key| hash_set.contains(key));
+    assert!(with_probe("foo", b"abc", lookup));
+    assert!(!with_probe("foo", b"", |key| hash_set.contains(key)));
+
+    // FnOnce, so the closure can move out of its captures.
+    let mut log = Vec::new();
+    with_key(&owned, |key| log.push(key.s.to_string()));
+    let taken = with_key(&owned, move |_| log);
+    assert_eq!(taken, ["foo"]);
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn with_key_sees_key(owned in any::<OwnedKey>()) {
+        let expected = owned.key();
+        prop_assert!(with_borrowed_key(&owned, |key| key == expected));
+        prop_assert!(with_key(&owned, |key| key == expected));
+        prop_assert!(with_probe(&owned.s, &owned.bytes, |key| key == &owned as &dyn Key));
+    }
+}
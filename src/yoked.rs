@@ -0,0 +1,209 @@
+//! An owned key that borrows its fields from a buffer it owns.
+//!
+//! Requires the `yoke` feature.
+
+use crate::consistency::assert_consistent;
+use crate::{BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use stable_deref_trait::StableDeref;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+use yoke::{CloneableCart, Yoke};
+
+// BytesOwnedKey (see bytes_key.rs) avoids copying the bytes out of a frame, but not the string:
+// its s is still a String. When the whole key is in the frame, both fields could point into it,
+// and the key would be the frame plus a BorrowedKey. That's a self-referential struct, which safe
+// Rust doesn't allow on its own, since nothing stops the frame from being moved or freed while the
+// BorrowedKey still points into it.
+//
+// yoke solves this: a Yoke holds a "cart", which is a pointer to data that stays put when the
+// pointer moves (an Arc<[u8]>, a Box<[u8]>, and so on), together with a value borrowed from the
+// cart. BorrowedKey derives Yokeable with the yoke feature on, which is what lets yoke swap its
+// lifetime out while it's stored, and back in to a borrow of the YokedKey when it's read.
+//
+// Key::key only needs to return a BorrowedKey that lives as long as &self, which is exactly what
+// Yoke::get gives out. So YokedKey implements Key and Borrow<dyn Key> like OwnedKey, and a set of
+// YokedKeys can be probed with any other key. Eq, Ord and Hash go through the BorrowedKey too, so
+// they're consistent by construction. The frame itself plays no part in them: two keys parsed out
+// of different frames are equal if their fields are.
+/// An owned key whose string and bytes are borrowed from a buffer it owns, such as a network frame.
+pub struct YokedKey<C = Arc<[u8]>> {
+    yoke: Yoke<BorrowedKey<'static>, C>,
+}
+
+impl<C> YokedKey<C>
+where
+    C: StableDeref,
+    C::Target: 'static,
+{
+    /// Creates a key by finding its fields in `cart`.
+    ///
+    /// `f` gets the data `cart` points to, and returns the key's fields, borrowed from it.
+    pub fn attach(cart: C, f: impl for<'a> FnOnce(&'a C::Target) -> BorrowedKey<'a>) -> Self {
+        Self {
+            yoke: Yoke::<BorrowedKey<'static>, C>::attach_to_cart(cart, f),
+        }
+    }
+
+    /// Creates a key by finding its fields in `cart`, with a parser that can fail.
+    pub fn try_attach<E>(
+        cart: C,
+        f: impl for<'a> FnOnce(&'a C::Target) -> Result<BorrowedKey<'a>, E>,
+    ) -> Result<Self, E> {
+        Ok(Self {
+            yoke: Yoke::<BorrowedKey<'static>, C>::try_attach_to_cart(cart, f)?,
+        })
+    }
+}
+
+impl<C> YokedKey<C> {
+    /// Returns the buffer the key borrows from.
+    pub fn backing_cart(&self) -> &C {
+        self.yoke.backing_cart()
+    }
+
+    /// Copies the key's fields out into an [`OwnedKey`], so the buffer can be freed.
+    pub fn to_owned_key(&self) -> OwnedKey {
+        let BorrowedKey { s, bytes } = self.key();
+        OwnedKey {
+            s: s.to_string(),
+            bytes: bytes.to_vec(),
+        }
+    }
+}
+
+impl<C> Key for YokedKey<C> {
+    fn key<'k>(&'k self) -> BorrowedKey<'k> {
+        *self.yoke.get()
+    }
+}
+
+impl<'a, C: 'a> Borrow<dyn Key + 'a> for YokedKey<C> {
+    fn borrow(&self) -> &(dyn Key + 'a) {
+        self
+    }
+}
+
+impl<C: CloneableCart> Clone for YokedKey<C> {
+    fn clone(&self) -> Self {
+        Self {
+            yoke: self.yoke.clone(),
+        }
+    }
+}
+
+impl<C> fmt::Debug for YokedKey<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("YokedKey").field(&self.key()).finish()
+    }
+}
+
+impl<C> PartialEq for YokedKey<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl<C> Eq for YokedKey<C> {}
+
+impl<C> PartialOrd for YokedKey<C> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<C> Ord for YokedKey<C> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl<C> Hash for YokedKey<C> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+// A made-up wire format for the tests: the string's length as a little-endian u32, the string,
+// then the bytes.
+#[cfg(test)]
+fn encode_frame(key: &OwnedKey) -> Arc<[u8]> {
+    let mut frame = (key.s.len() as u32).to_le_bytes().to_vec();
+    frame.extend_from_slice(key.s.as_bytes());
+    frame.extend_from_slice(&key.bytes);
+    frame.into()
+}
+
+#[cfg(test)]
+fn parse_frame(frame: &[u8]) -> Result<BorrowedKey<'_>, &'static str> {
+    let (len, rest) = frame.split_first_chunk::<4>().ok_or("frame too short")?;
+    let len = u32::from_le_bytes(*len) as usize;
+    if rest.len() < len {
+        return Err("frame too short");
+    }
+    let (s, bytes) = rest.split_at(len);
+    let s = std::str::from_utf8(s).map_err(|_| "string isn't UTF-8")?;
+    Ok(BorrowedKey { s, bytes })
+}
+
+#[cfg(test)]
+fn yoked_keys() -> impl Strategy<Value = YokedKey> {
+    any::<OwnedKey>().prop_map(|key| YokedKey::try_attach(encode_frame(&key), parse_frame).unwrap())
+}
+
+#[test]
+fn yoked_lookups() {
+    let frame = encode_frame(&OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    });
+    let key = YokedKey::try_attach(frame.clone(), parse_frame).unwrap();
+    // No copies were made: both fields still live inside the frame.
+    assert_eq!(key.key().s.as_ptr(), frame[4..].as_ptr());
+    assert_eq!(key.key().bytes.as_ptr(), frame[7..].as_ptr());
+    assert!(Arc::ptr_eq(key.backing_cart(), &frame));
+
+    let mut hash_set = HashSet::new();
+    hash_set.insert(key.clone());
+    let mut btree_set = BTreeSet::new();
+    btree_set.insert(key);
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    assert!(hash_set.contains(&borrowed_key as &dyn Key));
+    assert!(btree_set.contains(&borrowed_key as &dyn Key));
+
+    // Any cart works, as long as its data doesn't move with it.
+    let boxed: Box<[u8]> = b"barxyz".to_vec().into_boxed_slice();
+    let key = YokedKey::attach(boxed, |frame| BorrowedKey {
+        s: std::str::from_utf8(&frame[..3]).unwrap(),
+        bytes: &frame[3..],
+    });
+    assert_eq!(
+        key.to_owned_key(),
+        OwnedKey {
+            s: "bar".to_string(),
+            bytes: b"xyz".to_vec(),
+        }
+    );
+
+    assert_eq!(
+        YokedKey::try_attach(Arc::from(&b"\x05\0\0\0ab"[..]), parse_frame).unwrap_err(),
+        "frame too short"
+    );
+}
+
+proptest! {
+    #[test]
+    fn yoked_consistent_borrow(yoked1 in yoked_keys(), yoked2 in yoked_keys()) {
+        assert_consistent::<_, dyn Key>(&yoked1, &yoked2);
+        let owned1 = yoked1.to_owned_key();
+        prop_assert_eq!(owned1.key(), yoked1.key());
+    }
+}
@@ -0,0 +1,138 @@
+// Checks that borrowed lookups really don't allocate.
+//
+// This is its own test binary because it replaces the global allocator. Tests run on several
+// threads at once, so allocations are counted per thread.
+
+use borrow_complex_key_example::family::Probe;
+use borrow_complex_key_example::map::KeyMap;
+use borrow_complex_key_example::pair::KeyPair;
+use borrow_complex_key_example::set::KeySet;
+use borrow_complex_key_example::{BorrowedKey, Key, OwnedKey};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashSet};
+use std::hint::black_box;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    // try_with, because the allocator is also called while thread-locals are being torn down.
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns how many times `f` allocated on this thread.
+fn allocations<R>(f: impl FnOnce() -> R) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    black_box(f());
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+fn owned_key() -> OwnedKey {
+    OwnedKey {
+        s: "foo".to_string(),
+        bytes: b"abc".to_vec(),
+    }
+}
+
+const BORROWED_KEY: BorrowedKey<'static> = BorrowedKey {
+    s: "foo",
+    bytes: b"abc",
+};
+
+#[test]
+fn key_set_contains_does_not_allocate() {
+    let set: KeySet = std::iter::once(owned_key()).collect();
+    let borrowed_key = black_box(BORROWED_KEY);
+    assert_eq!(allocations(|| set.contains(&borrowed_key)), 0);
+    assert_eq!(allocations(|| set.get(&borrowed_key).is_some()), 0);
+}
+
+#[test]
+fn owned_key_per_lookup_allocates() {
+    // The baseline the crate exists to avoid: one allocation for the string, one for the bytes.
+    let set: HashSet<OwnedKey> = std::iter::once(owned_key()).collect();
+    let borrowed_key = black_box(BORROWED_KEY);
+    let count = allocations(|| {
+        set.contains(&OwnedKey {
+            s: borrowed_key.s.to_owned(),
+            bytes: borrowed_key.bytes.to_owned(),
+        })
+    });
+    assert!(count >= 2, "expected at least 2 allocations, got {}", count);
+}
+
+#[test]
+fn std_collections_do_not_allocate() {
+    let hash_set: HashSet<OwnedKey> = std::iter::once(owned_key()).collect();
+    let btree_set: BTreeSet<OwnedKey> = std::iter::once(owned_key()).collect();
+    let borrowed_key = black_box(BORROWED_KEY);
+    assert_eq!(
+        allocations(|| hash_set.contains(&borrowed_key as &dyn Key)),
+        0
+    );
+    assert_eq!(
+        allocations(|| btree_set.contains(&borrowed_key as &dyn Key)),
+        0
+    );
+}
+
+#[test]
+fn key_family_probe_does_not_allocate() {
+    let set: HashSet<KeyPair<String, Vec<u8>>> = std::iter::once(KeyPair {
+        a: "foo".to_string(),
+        b: b"abc".to_vec(),
+    })
+    .collect();
+    let probe = black_box(("foo", &b"abc"[..]));
+    assert_eq!(
+        allocations(|| set.contains(Probe::<KeyPair<String, Vec<u8>>>(probe).as_lookup())),
+        0
+    );
+}
+
+#[test]
+fn key_map_entry_by_key_only_allocates_when_vacant() {
+    let mut map: KeyMap<u32> = std::iter::once((owned_key(), 0)).collect();
+    let borrowed_key = black_box(BORROWED_KEY);
+    assert_eq!(
+        allocations(|| *map.entry_by_key(&borrowed_key).or_insert(0) += 1),
+        0
+    );
+    assert_eq!(map.get(&borrowed_key), Some(&1));
+
+    // A vacant entry copies the key when it's filled in.
+    let missing = black_box(BorrowedKey {
+        s: "bar",
+        bytes: b"abc",
+    });
+    let count = allocations(|| *map.entry_by_key(&missing).or_insert(0) += 1);
+    assert!(count >= 2, "expected at least 2 allocations, got {}", count);
+}
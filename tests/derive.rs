@@ -0,0 +1,758 @@
+// Tests for #[derive(BorrowKey)].
+//
+// Each derive generates a trait called Key, so every key type lives in its own module, unless the
+// trait is renamed.
+
+mod plain {
+    use borrow_complex_key_example::consistency::assert_consistent;
+    use borrow_complex_key_example::BorrowKey;
+    use proptest::prelude::*;
+    use proptest_derive::Arbitrary;
+    use std::collections::{BTreeSet, HashSet};
+
+    #[derive(BorrowKey, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Arbitrary)]
+    pub struct OwnedKey {
+        pub s: String,
+        pub bytes: Vec<u8>,
+        pub id: u32,
+    }
+
+    #[test]
+    fn lookups() {
+        let mut hash_set = HashSet::new();
+        let mut btree_set = BTreeSet::new();
+        let key = OwnedKey {
+            s: "foo".to_string(),
+            bytes: b"abc".to_vec(),
+            id: 7,
+        };
+        hash_set.insert(key.clone());
+        btree_set.insert(key);
+
+        let borrowed_key = BorrowedKey {
+            s: "foo",
+            bytes: b"abc",
+            id: 7,
+        };
+        assert!(hash_set.contains(&borrowed_key as &dyn Key));
+        assert!(btree_set.contains(&borrowed_key as &dyn Key));
+    }
+
+    proptest! {
+        #[test]
+        fn consistent_borrow(owned1 in any::<OwnedKey>(), owned2 in any::<OwnedKey>()) {
+            assert_consistent::<_, dyn Key>(&owned1, &owned2);
+        }
+    }
+}
+
+mod generic {
+    use borrow_complex_key_example::consistency::assert_consistent;
+    use borrow_complex_key_example::BorrowKey;
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+    use std::fmt;
+
+    // The example from the derive's documentation, plus a where clause for good measure.
+    // The proptest option, with a strategy, also generates Keyed_consistent_borrow. Fields that
+    // borrow through AsRef get comparisons generated in terms of the key, so none are derived.
+    #[derive(BorrowKey, Clone, Debug)]
+    #[borrow_key(borrowed = KeyedRef, proptest(strategy = keyed))]
+    pub struct Keyed<T: AsRef<str>, B>
+    where
+        B: AsRef<[u8]> + fmt::Debug,
+    {
+        pub name: T,
+        pub payload: B,
+        pub id: u64,
+    }
+
+    #[test]
+    fn generic_lookups() {
+        let mut strings: HashSet<Keyed<String, Vec<u8>>> = HashSet::new();
+        strings.insert(Keyed {
+            name: "foo".to_string(),
+            payload: b"abc".to_vec(),
+            id: 1,
+        });
+        let mut boxes: HashSet<Keyed<Box<str>, Box<[u8]>>> = HashSet::new();
+        boxes.insert(Keyed {
+            name: "foo".into(),
+            payload: b"abc".to_vec().into(),
+            id: 1,
+        });
+
+        let probe = KeyedRef {
+            name: "foo",
+            payload: b"abc",
+            id: 1,
+        };
+        assert!(strings.contains(&probe as &dyn Key));
+        assert!(boxes.contains(&probe as &dyn Key));
+    }
+
+    fn keyed() -> impl Strategy<Value = Keyed<Box<str>, Vec<u8>>> {
+        (any::<String>(), any::<Vec<u8>>(), any::<u64>()).prop_map(|(name, payload, id)| Keyed {
+            name: name.into(),
+            payload,
+            id,
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn generic_consistent_borrow(owned1 in keyed(), owned2 in keyed()) {
+            assert_consistent::<_, dyn Key>(&owned1, &owned2);
+        }
+    }
+}
+
+// A type parameter borrows through AsRef, and can be instantiated with a type whose comparisons
+// disagree with the AsRef target's. The owned type's comparisons are generated in terms of the key,
+// so lookups still work.
+mod as_ref_comparisons {
+    use borrow_complex_key_example::consistency::assert_consistent;
+    use borrow_complex_key_example::BorrowKey;
+    use proptest::prelude::*;
+    use std::collections::{BTreeSet, HashSet};
+    use std::hash::{Hash, Hasher};
+
+    #[derive(Clone, Debug)]
+    pub struct CaseInsensitive(String);
+
+    impl AsRef<str> for CaseInsensitive {
+        fn as_ref(&self) -> &str {
+            &self.0
+        }
+    }
+
+    impl PartialEq for CaseInsensitive {
+        fn eq(&self, other: &Self) -> bool {
+            self.0.eq_ignore_ascii_case(&other.0)
+        }
+    }
+
+    impl Eq for CaseInsensitive {}
+
+    impl PartialOrd for CaseInsensitive {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for CaseInsensitive {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            self.0
+                .to_ascii_lowercase()
+                .cmp(&other.0.to_ascii_lowercase())
+        }
+    }
+
+    impl Hash for CaseInsensitive {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.0.to_ascii_lowercase().hash(state);
+        }
+    }
+
+    #[derive(BorrowKey, Clone, Debug)]
+    pub struct OwnedName<T: AsRef<str>> {
+        pub name: T,
+    }
+
+    fn name(s: &str) -> OwnedName<CaseInsensitive> {
+        OwnedName {
+            name: CaseInsensitive(s.to_string()),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn as_ref_comparisons_lookups(s in "[a-z]{1,4}") {
+            let lower = name(&s);
+            let upper = name(&s.to_ascii_uppercase());
+
+            // The owned keys compare like their borrowed forms, not like CaseInsensitive.
+            prop_assert_ne!(&lower, &upper);
+            prop_assert_ne!(lower.key(), upper.key());
+
+            // So each one is found by its own borrowed form, and not by the other's.
+            let hash_set: HashSet<_> = vec![lower.clone(), upper.clone()].into_iter().collect();
+            let btree_set: BTreeSet<_> = vec![lower.clone(), upper.clone()].into_iter().collect();
+            prop_assert_eq!(hash_set.len(), 2);
+            for key in [&lower, &upper] {
+                prop_assert!(hash_set.contains(&key.key() as &dyn Key));
+                prop_assert!(btree_set.contains(&key.key() as &dyn Key));
+            }
+            let btree_set: BTreeSet<_> = std::iter::once(upper.clone()).collect();
+            prop_assert!(!btree_set.contains(&lower.key() as &dyn Key));
+        }
+
+        #[test]
+        fn as_ref_comparisons_consistent_borrow(s1 in "[a-zA-Z]{0,3}", s2 in "[a-zA-Z]{0,3}") {
+            assert_consistent::<_, dyn Key>(&name(&s1), &name(&s2));
+        }
+    }
+}
+
+mod reverse {
+    use borrow_complex_key_example::consistency::assert_consistent;
+    use borrow_complex_key_example::BorrowKey;
+    use proptest::prelude::*;
+    use proptest_derive::Arbitrary;
+    use std::cmp::Reverse;
+    use std::collections::BTreeSet;
+
+    // Highest score first, then by name.
+    #[derive(BorrowKey, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Arbitrary)]
+    pub struct OwnedScore {
+        pub score: Reverse<u64>,
+        pub name: Reverse<String>,
+    }
+
+    #[test]
+    fn reverse_lookups() {
+        let mut btree_set = BTreeSet::new();
+        for (score, name) in [(10, "a"), (20, "a"), (20, "b")] {
+            btree_set.insert(OwnedScore {
+                score: Reverse(score),
+                name: Reverse(name.to_string()),
+            });
+        }
+
+        let order: Vec<_> = btree_set
+            .iter()
+            .map(|key| (key.score.0, key.name.0.as_str()))
+            .collect();
+        assert_eq!(order, [(20, "b"), (20, "a"), (10, "a")]);
+
+        let borrowed_key = BorrowedScore {
+            score: Reverse(20),
+            name: Reverse("a"),
+        };
+        assert!(btree_set.contains(&borrowed_key as &dyn Key));
+    }
+
+    proptest! {
+        #[test]
+        fn reverse_consistent_borrow(owned1 in any::<OwnedScore>(), owned2 in any::<OwnedScore>()) {
+            assert_consistent::<_, dyn Key>(&owned1, &owned2);
+        }
+    }
+}
+
+mod skip {
+    use borrow_complex_key_example::consistency::assert_consistent;
+    use borrow_complex_key_example::BorrowKey;
+    use proptest::prelude::*;
+    use std::collections::{BTreeSet, HashSet};
+    use std::time::Duration;
+
+    // Eq, Ord and Hash come from the derive, since fields are skipped. Skipped fields don't need to
+    // be of a type the derive knows how to borrow.
+    #[derive(BorrowKey, Clone, Debug)]
+    pub struct OwnedEntry {
+        pub name: String,
+        #[borrow_key(skip)]
+        pub hits: u64,
+        pub bytes: Vec<u8>,
+        #[borrow_key(skip)]
+        pub age: Duration,
+    }
+
+    fn entry(name: &str, bytes: &[u8], hits: u64) -> OwnedEntry {
+        OwnedEntry {
+            name: name.to_string(),
+            hits,
+            bytes: bytes.to_vec(),
+            age: Duration::from_secs(hits),
+        }
+    }
+
+    #[test]
+    fn skip_lookups() {
+        let mut hash_set = HashSet::new();
+        let mut btree_set = BTreeSet::new();
+        assert!(hash_set.insert(entry("foo", b"abc", 1)));
+        assert!(btree_set.insert(entry("foo", b"abc", 1)));
+
+        // Entries that only differ in skipped fields are equal, and the first one stays.
+        assert_eq!(entry("foo", b"abc", 1), entry("foo", b"abc", 2));
+        assert!(!hash_set.insert(entry("foo", b"abc", 2)));
+        assert!(!btree_set.insert(entry("foo", b"abc", 2)));
+
+        let borrowed_key = BorrowedEntry {
+            name: "foo",
+            bytes: b"abc",
+        };
+        assert_eq!(hash_set.get(&borrowed_key as &dyn Key).unwrap().hits, 1);
+        let found = btree_set.get(&borrowed_key as &dyn Key).unwrap();
+        assert_eq!((found.hits, found.age), (1, Duration::from_secs(1)));
+    }
+
+    fn entries() -> impl Strategy<Value = OwnedEntry> {
+        // Small domains for the key, so that entries with equal keys and different metadata are
+        // common.
+        (
+            "[ab]{0,2}",
+            prop::collection::vec(0..2u8, 0..2),
+            any::<u64>(),
+        )
+            .prop_map(|(name, bytes, hits)| entry(&name, &bytes, hits))
+    }
+
+    proptest! {
+        #[test]
+        fn skip_consistent_borrow(owned1 in entries(), owned2 in entries()) {
+            assert_consistent::<_, dyn Key>(&owned1, &owned2);
+        }
+    }
+}
+
+mod borrow_with {
+    use borrow_complex_key_example::consistency::assert_consistent;
+    use borrow_complex_key_example::BorrowKey;
+    use proptest::prelude::*;
+    use proptest_derive::Arbitrary;
+    use std::collections::{BTreeSet, HashSet};
+
+    // A type the derive doesn't know, that borrows as a str through a method of its own.
+    #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Arbitrary)]
+    pub struct Url(String);
+
+    impl Url {
+        pub fn as_str(&self) -> &str {
+            &self.0
+        }
+    }
+
+    #[derive(BorrowKey, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Arbitrary)]
+    pub struct OwnedLink {
+        #[borrow_key(borrow_with = Url::as_str, ty = &'a str)]
+        pub url: Url,
+        #[borrow_key(borrow_with = Option::as_deref, ty = Option<&'a str>)]
+        pub title: Option<String>,
+        pub bytes: Vec<u8>,
+    }
+
+    #[test]
+    fn borrow_with_lookups() {
+        let mut hash_set = HashSet::new();
+        let mut btree_set = BTreeSet::new();
+        let link = OwnedLink {
+            url: Url("https://example.com".to_string()),
+            title: Some("Example".to_string()),
+            bytes: b"abc".to_vec(),
+        };
+        hash_set.insert(link.clone());
+        btree_set.insert(link);
+
+        let borrowed_key = BorrowedLink {
+            url: "https://example.com",
+            title: Some("Example"),
+            bytes: b"abc",
+        };
+        assert!(hash_set.contains(&borrowed_key as &dyn Key));
+        assert!(btree_set.contains(&borrowed_key as &dyn Key));
+        let untitled = BorrowedLink {
+            title: None,
+            ..borrowed_key
+        };
+        assert!(!hash_set.contains(&untitled as &dyn Key));
+    }
+
+    proptest! {
+        #[test]
+        fn borrow_with_consistent_borrow(owned1 in any::<OwnedLink>(), owned2 in any::<OwnedLink>()) {
+            assert_consistent::<_, dyn Key>(&owned1, &owned2);
+        }
+    }
+}
+
+mod enums {
+    use borrow_complex_key_example::BorrowKey;
+    use proptest_derive::Arbitrary;
+    use std::collections::{BTreeSet, HashSet};
+
+    // The proptest option generates a test, OwnedName_consistent_borrow, that runs the consistency
+    // checks on random pairs of names.
+    #[derive(BorrowKey, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Arbitrary)]
+    #[borrow_key(proptest)]
+    pub enum OwnedName {
+        Anonymous,
+        Id(u64, String),
+        Qualified { namespace: String, name: Vec<u8> },
+    }
+
+    #[test]
+    fn enum_lookups() {
+        let names = [
+            OwnedName::Anonymous,
+            OwnedName::Id(7, "seven".to_string()),
+            OwnedName::Qualified {
+                namespace: "foo".to_string(),
+                name: b"abc".to_vec(),
+            },
+        ];
+        let hash_set: HashSet<_> = names.iter().cloned().collect();
+        let btree_set: BTreeSet<_> = names.iter().cloned().collect();
+
+        let borrowed_names = [
+            BorrowedName::Anonymous,
+            BorrowedName::Id(7, "seven"),
+            BorrowedName::Qualified {
+                namespace: "foo",
+                name: b"abc",
+            },
+        ];
+        for name in &borrowed_names {
+            assert!(hash_set.contains(name as &dyn Key));
+            assert!(btree_set.contains(name as &dyn Key));
+        }
+        assert!(!hash_set.contains(&BorrowedName::Id(7, "") as &dyn Key));
+
+        // Variants are in declaration order, in both forms.
+        let order: Vec<_> = btree_set.iter().map(|name| name.key()).collect();
+        assert_eq!(order, borrowed_names);
+    }
+}
+
+mod enum_skip {
+    use borrow_complex_key_example::BorrowKey;
+    use proptest_derive::Arbitrary;
+    use std::collections::HashSet;
+
+    // Skipped fields work in variants too, and bring generated comparisons with them.
+    #[derive(BorrowKey, Clone, Debug, Arbitrary)]
+    #[borrow_key(proptest)]
+    pub enum OwnedEvent {
+        Started(String, #[borrow_key(skip)] u64),
+        Stopped {
+            name: String,
+            #[borrow_key(skip)]
+            reason: Option<String>,
+        },
+    }
+
+    #[test]
+    fn enum_skip_lookups() {
+        let mut hash_set = HashSet::new();
+        assert!(hash_set.insert(OwnedEvent::Started("foo".to_string(), 1)));
+        assert!(!hash_set.insert(OwnedEvent::Started("foo".to_string(), 2)));
+        assert!(hash_set.insert(OwnedEvent::Stopped {
+            name: "foo".to_string(),
+            reason: Some("done".to_string()),
+        }));
+        assert!(!hash_set.insert(OwnedEvent::Stopped {
+            name: "foo".to_string(),
+            reason: None,
+        }));
+        match hash_set.get(&BorrowedEvent::Stopped { name: "foo" } as &dyn Key) {
+            Some(OwnedEvent::Stopped { reason, .. }) => assert_eq!(reason.as_deref(), Some("done")),
+            other => panic!("unexpected event {:?}", other),
+        }
+        match hash_set.get(&BorrowedEvent::Started("foo") as &dyn Key) {
+            Some(OwnedEvent::Started(_, at)) => assert_eq!(*at, 1),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+}
+
+mod trait_names {
+    use borrow_complex_key_example::consistency::assert_consistent;
+    use borrow_complex_key_example::BorrowKey;
+    use proptest::prelude::*;
+    use proptest_derive::Arbitrary;
+    use std::collections::HashMap;
+
+    // Two key types in one module, each with a trait of its own.
+    #[derive(BorrowKey, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Arbitrary)]
+    #[borrow_key(trait = "UserKeyLookup", proptest)]
+    pub struct OwnedUserKey {
+        pub tenant: String,
+        pub name: String,
+    }
+
+    #[derive(BorrowKey, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Arbitrary)]
+    #[borrow_key(trait = GroupKeyLookup, borrowed = "GroupRef")]
+    pub struct OwnedGroupKey {
+        pub tenant: String,
+        pub id: u32,
+    }
+
+    #[test]
+    fn trait_names_lookups() {
+        let mut users = HashMap::new();
+        users.insert(
+            OwnedUserKey {
+                tenant: "acme".to_string(),
+                name: "ada".to_string(),
+            },
+            1,
+        );
+        let mut groups = HashMap::new();
+        groups.insert(
+            OwnedGroupKey {
+                tenant: "acme".to_string(),
+                id: 7,
+            },
+            2,
+        );
+
+        let user = BorrowedUserKey {
+            tenant: "acme",
+            name: "ada",
+        };
+        let group = GroupRef {
+            tenant: "acme",
+            id: 7,
+        };
+        assert_eq!(users.get(&user as &dyn UserKeyLookup), Some(&1));
+        assert_eq!(groups.get(&group as &dyn GroupKeyLookup), Some(&2));
+    }
+
+    proptest! {
+        #[test]
+        fn trait_names_consistent_borrow(owned1 in any::<OwnedGroupKey>(), owned2 in any::<OwnedGroupKey>()) {
+            assert_consistent::<_, dyn GroupKeyLookup>(&owned1, &owned2);
+        }
+    }
+}
+
+mod portable_hash {
+    use borrow_complex_key_example::BorrowKey;
+    use proptest::prelude::*;
+    use proptest_derive::Arbitrary;
+    use std::collections::HashSet;
+    use std::hash::{Hash, Hasher};
+
+    // The proptest option checks that the generated Hash impls are consistent with each other.
+    #[derive(BorrowKey, Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Arbitrary)]
+    #[borrow_key(portable_hash, proptest)]
+    pub struct OwnedSpan {
+        pub file: String,
+        pub start: usize,
+        pub offset: isize,
+    }
+
+    #[derive(BorrowKey, Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Arbitrary)]
+    #[borrow_key(trait = "PlaceKey", portable_hash, proptest)]
+    pub enum OwnedPlace {
+        Nowhere,
+        Line(usize),
+        Span { file: String, start: usize },
+    }
+
+    // Records each write to the hasher, along with the method used to make it.
+    #[derive(Default)]
+    struct RecordingHasher {
+        writes: Vec<(&'static str, Vec<u8>)>,
+    }
+
+    impl Hasher for RecordingHasher {
+        fn finish(&self) -> u64 {
+            0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            self.writes.push(("write", bytes.to_vec()));
+        }
+
+        fn write_u64(&mut self, i: u64) {
+            self.writes.push(("write_u64", i.to_le_bytes().to_vec()));
+        }
+
+        fn write_usize(&mut self, i: usize) {
+            self.writes.push(("write_usize", i.to_le_bytes().to_vec()));
+        }
+    }
+
+    fn writes<T: Hash + ?Sized>(value: &T) -> Vec<(&'static str, Vec<u8>)> {
+        let mut hasher = RecordingHasher::default();
+        value.hash(&mut hasher);
+        hasher.writes
+    }
+
+    #[test]
+    fn portable_hash_lookups() {
+        let mut spans = HashSet::new();
+        spans.insert(OwnedSpan {
+            file: "lib.rs".to_string(),
+            start: 10,
+            offset: -1,
+        });
+        let span = BorrowedSpan {
+            file: "lib.rs",
+            start: 10,
+            offset: -1,
+        };
+        assert!(spans.contains(&span as &dyn Key));
+
+        let places: HashSet<_> = vec![OwnedPlace::Nowhere, OwnedPlace::Line(3)]
+            .into_iter()
+            .collect();
+        assert!(places.contains(&BorrowedPlace::Line(3) as &dyn PlaceKey));
+        assert!(!places.contains(&BorrowedPlace::Line(4) as &dyn PlaceKey));
+    }
+
+    #[test]
+    fn portable_hash_writes() {
+        // The variant's index goes first, as a u32, and then the fields, with the usize widened to
+        // 64 bits. Everything arrives through write, as little-endian bytes.
+        let place = BorrowedPlace::Span {
+            file: "a",
+            start: 2,
+        };
+        let expected: Vec<(&str, Vec<u8>)> = vec![
+            ("write", 2u32.to_le_bytes().to_vec()),
+            ("write", b"a".to_vec()),
+            ("write", vec![0xff]),
+            ("write", 2u64.to_le_bytes().to_vec()),
+        ];
+        assert_eq!(writes(&place), expected);
+    }
+
+    proptest! {
+        // Any usize or isize a 32-bit target could hold hashes exactly like the same value as a
+        // u64 or i64, which is what a 64-bit target hashes too.
+        #[test]
+        fn portable_hash_cross_width(file in ".*", start in any::<u32>(), offset in any::<i32>()) {
+            let owned = OwnedSpan {
+                file: file.clone(),
+                start: start as usize,
+                offset: offset as isize,
+            };
+            let span = BorrowedSpan { file: &file, start: start as usize, offset: offset as isize };
+            let mut expected = vec![("write", file.as_bytes().to_vec()), ("write", vec![0xff])];
+            expected.push(("write", u64::from(start).to_le_bytes().to_vec()));
+            expected.push(("write", i64::from(offset).to_le_bytes().to_vec()));
+            prop_assert_eq!(writes(&span), expected);
+
+            prop_assert_eq!(writes(&owned), writes(&span));
+            prop_assert_eq!(writes(&owned as &dyn Key), writes(&span));
+        }
+    }
+}
+
+mod multi_index {
+    use borrow_complex_key_example::MultiIndex;
+    use proptest::prelude::*;
+
+    #[derive(Clone, Debug, MultiIndex, PartialEq)]
+    #[multi_index(container = Records, by_key(s, bytes), by_id(id))]
+    pub struct Record {
+        pub s: String,
+        pub bytes: Vec<u8>,
+        pub id: u32,
+        pub payload: &'static str,
+    }
+
+    fn record(s: &str, bytes: &[u8], id: u32, payload: &'static str) -> Record {
+        Record {
+            s: s.to_string(),
+            bytes: bytes.to_vec(),
+            id,
+            payload,
+        }
+    }
+
+    #[test]
+    fn multi_index_lookups() {
+        let mut records = Records::new();
+        records.insert(record("foo", b"abc", 1, "first")).unwrap();
+        records.insert(record("foo", b"", 2, "second")).unwrap();
+        records.insert(record("bar", b"abc", 3, "third")).unwrap();
+        assert_eq!(records.len(), 3);
+
+        // Each index finds the same record.
+        assert_eq!(
+            records.get_by_key("foo", b"").map(|r| r.payload),
+            Some("second")
+        );
+        assert_eq!(records.get_by_id(2).map(|r| r.payload), Some("second"));
+        assert!(records.get_by_key("bar", b"").is_none());
+
+        // A clash in any index rejects the whole record, leaving the others alone.
+        let err = records.insert(record("baz", b"", 3, "fourth")).unwrap_err();
+        assert_eq!(err.index, "by_id");
+        assert_eq!(err.record.payload, "fourth");
+        assert!(records.get_by_key("baz", b"").is_none());
+        let err = records
+            .insert(record("foo", b"abc", 4, "fourth"))
+            .unwrap_err();
+        assert_eq!(err.index, "by_key");
+        assert!(records.get_by_id(4).is_none());
+
+        // Removing through one index removes from all of them. The last record moves into the
+        // removed one's place, and stays reachable through every index.
+        let removed = records.remove_by_key("foo", b"abc").unwrap();
+        assert_eq!(removed.id, 1);
+        assert!(records.get_by_id(1).is_none());
+        assert_eq!(records.get_by_id(3).map(|r| r.payload), Some("third"));
+        assert_eq!(records.get_by_key("bar", b"abc").map(|r| r.id), Some(3));
+        assert_eq!(records.remove_by_id(2).map(|r| r.payload), Some("second"));
+        assert_eq!(records.iter().map(|r| r.id).collect::<Vec<_>>(), [3]);
+    }
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Insert(Record),
+        RemoveByKey(String, Vec<u8>),
+        RemoveById(u32),
+    }
+
+    fn ops() -> impl Strategy<Value = Vec<Op>> {
+        // Small domains, so that keys collide often.
+        let s = "[ab]{0,2}";
+        let bytes = prop::collection::vec(0..2u8, 0..2);
+        let id = 0..4u32;
+        let op = prop_oneof![
+            (s, bytes.clone(), id.clone()).prop_map(|(s, bytes, id)| Op::Insert(Record {
+                s,
+                bytes,
+                id,
+                payload: ""
+            })),
+            (s, bytes).prop_map(|(s, bytes)| Op::RemoveByKey(s, bytes)),
+            id.prop_map(Op::RemoveById),
+        ];
+        prop::collection::vec(op, 0..32)
+    }
+
+    proptest! {
+        #[test]
+        fn multi_index_matches_model(ops in ops()) {
+            // The model is a plain list of records, searched linearly.
+            let mut records = Records::new();
+            let mut model: Vec<Record> = Vec::new();
+            for op in ops {
+                match op {
+                    Op::Insert(record) => {
+                        let taken = model
+                            .iter()
+                            .any(|r| (&r.s, &r.bytes) == (&record.s, &record.bytes) || r.id == record.id);
+                        prop_assert_eq!(records.insert(record.clone()).is_err(), taken);
+                        if !taken {
+                            model.push(record);
+                        }
+                    }
+                    Op::RemoveByKey(s, bytes) => {
+                        let position = model.iter().position(|r| r.s == s && r.bytes == bytes);
+                        let expected = position.map(|position| model.remove(position));
+                        prop_assert_eq!(records.remove_by_key(&s, &bytes), expected);
+                    }
+                    Op::RemoveById(id) => {
+                        let position = model.iter().position(|r| r.id == id);
+                        let expected = position.map(|position| model.remove(position));
+                        prop_assert_eq!(records.remove_by_id(id), expected);
+                    }
+                }
+
+                prop_assert_eq!(records.len(), model.len());
+                for r in &model {
+                    prop_assert_eq!(records.get_by_key(&r.s, &r.bytes), Some(r));
+                    prop_assert_eq!(records.get_by_id(r.id), Some(r));
+                }
+            }
+        }
+    }
+}
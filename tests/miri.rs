@@ -0,0 +1,152 @@
+// Exercises every key type that's built on unsafe code, with small, fixed inputs, so that the whole
+// file runs under Miri in a reasonable amount of time:
+//
+//     cargo +nightly miri test --test miri --all-features
+//
+// Property tests are far too slow under Miri, so each case here is picked by hand to hit a boundary
+// the unsafe code has to get right: empty fields, a split point right after a multi-byte char,
+// buffers that aren't aligned, and views that outlive the value they were cloned from. Any new
+// unsafe key type should get its cases added here.
+
+use borrow_complex_key_example::packed::CompactKey;
+use borrow_complex_key_example::{BorrowedKey, Key};
+use std::collections::{BTreeSet, HashSet};
+
+const KEYS: &[(&str, &[u8])] = &[
+    ("", b""),
+    ("", b"\x00"),
+    ("a", b""),
+    ("ab", b""),
+    ("a", b"b"),
+    ("\u{e9}", b"\xff"),
+    ("\u{1f600}", b"\x00\x01"),
+];
+
+fn borrowed_keys() -> impl Iterator<Item = BorrowedKey<'static>> {
+    KEYS.iter().map(|&(s, bytes)| BorrowedKey { s, bytes })
+}
+
+#[test]
+fn miri_compact_key() {
+    for key in borrowed_keys() {
+        let compact = CompactKey::new(key.s, key.bytes);
+        assert_eq!(compact.s(), key.s);
+        assert_eq!(compact.bytes(), key.bytes);
+        assert_eq!(compact.key(), key);
+
+        // The string and the bytes are adjacent slices of the same allocation.
+        let (s, bytes) = (compact.s().as_bytes(), compact.bytes());
+        assert_eq!(s.as_ptr_range().end, bytes.as_ptr_range().start);
+    }
+}
+
+#[test]
+fn miri_compact_key_clone() {
+    // A clone has its own buffer: reading it after the original is gone must not touch freed
+    // memory.
+    let clones: Vec<CompactKey> = borrowed_keys()
+        .map(|key| {
+            let original = CompactKey::new(key.s, key.bytes);
+            original.clone()
+        })
+        .collect();
+    for (clone, key) in clones.iter().zip(borrowed_keys()) {
+        assert_eq!(clone.key(), key);
+    }
+}
+
+#[test]
+fn miri_compact_key_lookups() {
+    let hash_set: HashSet<CompactKey> = borrowed_keys()
+        .map(|key| CompactKey::new(key.s, key.bytes))
+        .collect();
+    let btree_set: BTreeSet<CompactKey> = hash_set.iter().cloned().collect();
+    assert_eq!(hash_set.len(), KEYS.len());
+    for key in borrowed_keys() {
+        assert!(hash_set.contains(&key as &dyn Key));
+        assert!(btree_set.contains(&key as &dyn Key));
+    }
+    assert!(!hash_set.contains(&BorrowedKey { s: "b", bytes: b"" } as &dyn Key));
+}
+
+#[test]
+fn miri_key_bytes() {
+    use borrow_complex_key_example::key_bytes::{EncodedOwnedKey, KeyBytes};
+
+    let mut buf = Vec::new();
+    for key in borrowed_keys() {
+        let owned = EncodedOwnedKey::new(&key);
+        let probe = KeyBytes::encode(&key, &mut buf);
+        assert_eq!(probe, owned.as_key_bytes());
+        assert_eq!(probe.as_bytes().as_ptr_range(), buf.as_ptr_range());
+        assert_eq!(owned.key_bytes(), key.bytes);
+
+        // A KeyBytes made from a slice of a larger buffer stays within that slice.
+        let mut padded = vec![0xaa];
+        padded.extend_from_slice(owned.as_bytes());
+        padded.push(0xaa);
+        let inner = &padded[1..padded.len() - 1];
+        let from_encoded = KeyBytes::from_encoded(inner).expect("valid encoding");
+        assert_eq!(from_encoded.as_bytes().as_ptr_range(), inner.as_ptr_range());
+        assert_eq!(from_encoded.to_owned(), owned);
+    }
+}
+
+#[cfg(feature = "flatbuffers")]
+#[test]
+fn miri_key_message() {
+    use borrow_complex_key_example::message::{KeyMessage, KeyReader};
+
+    // Checks that `view` lies entirely within `buf`, by comparing addresses.
+    fn assert_within(buf: &[u8], view: &[u8]) {
+        let buf = buf.as_ptr_range();
+        let view = view.as_ptr_range();
+        assert!(buf.start <= view.start && view.end <= buf.end);
+    }
+
+    for key in borrowed_keys() {
+        let mut builder = flatbuffers::FlatBufferBuilder::new();
+        let message = KeyMessage::create(&mut builder, &key);
+        builder.finish(message, None);
+        let data = builder.finished_data();
+
+        // Read the message at its natural alignment, and then shifted by one byte, since a buffer
+        // that comes off the network has no alignment guarantees.
+        let mut shifted = vec![0u8; data.len() + 1];
+        shifted[1..].copy_from_slice(data);
+        for buf in [data, &shifted[1..]] {
+            let message = KeyMessage::root(buf).expect("valid message");
+            assert_eq!(message.read_key(), Ok(key));
+            assert_within(buf, message.s().as_bytes());
+            assert_within(buf, message.bytes());
+        }
+
+        // A truncated message is either rejected by the verifier, or only lost trailing padding.
+        // Either way, nothing is read out of bounds.
+        for len in 0..data.len() {
+            if let Ok(message) = KeyMessage::root(&data[..len]) {
+                assert_eq!(message.read_key(), Ok(key));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "ref-cast")]
+#[test]
+fn miri_name_ref() {
+    use borrow_complex_key_example::name::{Name, NameRef};
+
+    for &(s, _) in KEYS {
+        // The cast keeps the original pointer and length, so the NameRef is the same memory.
+        let name_ref = NameRef::new(s);
+        assert_eq!(
+            name_ref.as_str().as_bytes().as_ptr_range(),
+            s.as_bytes().as_ptr_range()
+        );
+
+        let name = Name::new(s);
+        let borrowed: &NameRef = std::borrow::Borrow::borrow(&name);
+        assert_eq!(borrowed, name_ref);
+        assert_eq!(borrowed.to_owned(), name);
+    }
+}
@@ -0,0 +1,102 @@
+// Checks that every enabled format gives back a key that's indistinguishable from the original: equal
+// to it, ordered the same way against other keys, and hashed the same way. A map that's persisted
+// in one of these formats and loaded back relies on all three.
+
+use borrow_complex_key_example::{Key, OwnedKey};
+use proptest::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+fn hash_of(key: &dyn Key) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn check(format: &str, original: &OwnedKey, other: &OwnedKey, decoded: &dyn Key) {
+    let original: &dyn Key = original;
+    let other: &dyn Key = other;
+    assert!(original == decoded, "{}: decoded key is equal", format);
+    assert_eq!(
+        original.cmp(other),
+        decoded.cmp(other),
+        "{}: decoded key orders the same",
+        format
+    );
+    assert_eq!(
+        hash_of(original),
+        hash_of(decoded),
+        "{}: decoded key hashes the same",
+        format
+    );
+}
+
+proptest! {
+    #[test]
+    fn round_trips_preserve_consistency(key in any::<OwnedKey>(), other in any::<OwnedKey>()) {
+        {
+            use borrow_complex_key_example::encoding;
+            let decoded = encoding::decode(&encoding::encode(&key)).unwrap();
+            check("memcomparable", &key, &other, &decoded);
+        }
+        {
+            let decoded: OwnedKey = key.to_string().parse().unwrap();
+            check("Display/FromStr", &key, &other, &decoded);
+        }
+        #[cfg(feature = "base64")]
+        {
+            use borrow_complex_key_example::text::{BytesEncoding, KeyFormat};
+            let format = KeyFormat::new().bytes_encoding(BytesEncoding::Base64);
+            let decoded: OwnedKey = format.parse(&format.display(&key).to_string()).unwrap();
+            check("KeyFormat base64", &key, &other, &decoded);
+        }
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&key).unwrap();
+            let decoded: OwnedKey = serde_json::from_str(&json).unwrap();
+            check("serde JSON", &key, &other, &decoded);
+        }
+        #[cfg(feature = "bincode")]
+        {
+            use borrow_complex_key_example::binary;
+            let encoded = binary::to_bincode(&key);
+            check("bincode", &key, &other, &binary::from_bincode(&encoded).unwrap());
+        }
+        #[cfg(feature = "postcard")]
+        {
+            use borrow_complex_key_example::binary;
+            let encoded = binary::to_postcard(&key);
+            check("postcard", &key, &other, &binary::from_postcard(&encoded).unwrap());
+        }
+        #[cfg(feature = "rkyv")]
+        {
+            use rkyv::rancor;
+            let bytes = rkyv::to_bytes::<rancor::Error>(&key).unwrap();
+            let archived =
+                rkyv::access::<rkyv::Archived<OwnedKey>, rancor::Error>(&bytes).unwrap();
+            check("rkyv", &key, &other, archived);
+        }
+        #[cfg(feature = "flatbuffers")]
+        {
+            use borrow_complex_key_example::message::{KeyMessage, KeyReader};
+            let mut builder = flatbuffers::FlatBufferBuilder::new();
+            let message = KeyMessage::create(&mut builder, &key);
+            builder.finish(message, None);
+            let decoded = KeyMessage::root(builder.finished_data())
+                .unwrap()
+                .read_key()
+                .unwrap();
+            check("flatbuffers", &key, &other, &decoded);
+        }
+        #[cfg(feature = "csv")]
+        {
+            use borrow_complex_key_example::set::KeySet;
+            let set: KeySet = std::iter::once(key.clone()).collect();
+            let mut output = Vec::new();
+            set.to_csv_writer(&mut output).unwrap();
+            let read: KeySet = KeySet::from_csv_reader(&output[..]).unwrap();
+            let decoded = read.iter().next().unwrap();
+            check("CSV", &key, &other, decoded);
+        }
+    }
+}
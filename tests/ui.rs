@@ -0,0 +1,11 @@
+// Compile-fail tests for the mistakes the crate root walks through, and for the derive's
+// diagnostics, in tests/ui/derive. Each case has its error message pinned in a .stderr file next to
+// it. If a compiler upgrade changes one, rerun with TRYBUILD=overwrite and check that the new
+// message still shows the same failure.
+
+#[test]
+fn ui() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+    cases.compile_fail("tests/ui/derive/*.rs");
+}
@@ -0,0 +1,11 @@
+// borrow_with can't work out the borrowed type on its own.
+
+use borrow_complex_key_example::BorrowKey;
+
+#[derive(BorrowKey)]
+pub struct OwnedKey {
+    #[borrow_key(borrow_with = String::as_str)]
+    pub s: String,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+// A borrow_with function has to take a reference to the field and return the borrowed type. The
+// errors point at the function in the attribute.
+
+use borrow_complex_key_example::BorrowKey;
+
+#[derive(BorrowKey)]
+pub struct OwnedKey {
+    #[borrow_key(borrow_with = String::len, ty = &'a str)]
+    pub s: String,
+    #[borrow_key(borrow_with = Vec::as_slice, ty = &'a [u8])]
+    pub t: String,
+}
+
+fn main() {}
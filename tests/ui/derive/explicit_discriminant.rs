@@ -0,0 +1,12 @@
+// Explicit discriminants can reorder an enum's variants, which the borrowed enum wouldn't follow.
+
+use borrow_complex_key_example::BorrowKey;
+
+#[derive(BorrowKey)]
+#[repr(u8)]
+pub enum OwnedKey {
+    First(String),
+    Second = 5,
+}
+
+fn main() {}
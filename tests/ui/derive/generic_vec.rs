@@ -0,0 +1,12 @@
+// A Vec<T> would borrow as a &[T], but the borrowed type can't be generic, so the derive points at
+// the field instead of producing code that doesn't compile.
+
+use borrow_complex_key_example::BorrowKey;
+
+#[derive(BorrowKey)]
+pub struct OwnedKey<T> {
+    pub s: String,
+    pub items: Vec<T>,
+}
+
+fn main() {}
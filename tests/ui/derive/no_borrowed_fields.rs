@@ -0,0 +1,12 @@
+// A key made of Copy fields alone has nothing to borrow. Skipped fields don't count.
+
+use borrow_complex_key_example::BorrowKey;
+
+#[derive(BorrowKey)]
+pub struct OwnedKey {
+    pub id: u32,
+    #[borrow_key(skip)]
+    pub name: String,
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+// The derive doesn't know how to borrow an Instant, and says how to either borrow it with a function
+// or leave it out of the key.
+
+use borrow_complex_key_example::BorrowKey;
+use std::time::Instant;
+
+#[derive(BorrowKey)]
+pub struct OwnedKey {
+    pub s: String,
+    pub created: Instant,
+}
+
+fn main() {}
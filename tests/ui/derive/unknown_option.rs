@@ -0,0 +1,17 @@
+// Misspelled options are errors, rather than being silently ignored.
+
+use borrow_complex_key_example::BorrowKey;
+
+#[derive(BorrowKey)]
+#[borrow_key(borowed = KeyRef)]
+pub struct OwnedKey {
+    pub s: String,
+}
+
+#[derive(BorrowKey)]
+pub struct OwnedOtherKey {
+    #[borrow_key(skipped)]
+    pub s: String,
+}
+
+fn main() {}
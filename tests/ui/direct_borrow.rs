@@ -0,0 +1,26 @@
+// The first thing the crate root tries: implementing Borrow<BorrowedKey> for OwnedKey directly.
+// borrow() has to return a reference, but there's no BorrowedKey stored inside an OwnedKey to point
+// to. Building one on the spot means returning a reference to a temporary.
+
+use std::borrow::Borrow;
+
+pub struct OwnedKey {
+    pub s: String,
+    pub bytes: Vec<u8>,
+}
+
+pub struct BorrowedKey<'a> {
+    pub s: &'a str,
+    pub bytes: &'a [u8],
+}
+
+impl<'a> Borrow<BorrowedKey<'a>> for OwnedKey {
+    fn borrow(&self) -> &BorrowedKey<'a> {
+        &BorrowedKey {
+            s: &self.s,
+            bytes: &self.bytes,
+        }
+    }
+}
+
+fn main() {}
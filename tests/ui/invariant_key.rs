@@ -0,0 +1,36 @@
+// BorrowedKey is covariant in 'a, so a set of BorrowedKey<'static> can stand in for a set of
+// shorter-lived keys, and be probed with one. A borrowed key that's invariant in its lifetime (here
+// because of the Cell) loses that: the set and the probe have to agree on the lifetime exactly, so
+// probing a set of 'static keys requires a 'static probe.
+
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::marker::PhantomData;
+
+#[derive(Eq, Hash, PartialEq)]
+pub struct InvariantKey<'a> {
+    pub s: &'a str,
+    pub bytes: &'a [u8],
+    marker: PhantomData<Cell<&'a ()>>,
+}
+
+impl<'a> InvariantKey<'a> {
+    pub fn new(s: &'a str, bytes: &'a [u8]) -> Self {
+        InvariantKey {
+            s,
+            bytes,
+            marker: PhantomData,
+        }
+    }
+}
+
+fn contains<'a>(set: &HashSet<InvariantKey<'a>>, key: &InvariantKey<'a>) -> bool {
+    set.contains(key)
+}
+
+fn main() {
+    let set: HashSet<InvariantKey<'static>> = HashSet::new();
+    let s = String::from("foo");
+    let key = InvariantKey::new(&s, b"abc");
+    contains(&set, &key);
+}
@@ -0,0 +1,24 @@
+// Storing the BorrowedKey in a local first doesn't help: it still goes away when the function
+// returns. This is why Key::key returns a BorrowedKey by value, and Borrow goes through dyn Key.
+
+pub struct OwnedKey {
+    pub s: String,
+    pub bytes: Vec<u8>,
+}
+
+pub struct BorrowedKey<'a> {
+    pub s: &'a str,
+    pub bytes: &'a [u8],
+}
+
+impl OwnedKey {
+    pub fn key_ref(&self) -> &BorrowedKey<'_> {
+        let key = BorrowedKey {
+            s: &self.s,
+            bytes: &self.bytes,
+        };
+        &key
+    }
+}
+
+fn main() {}
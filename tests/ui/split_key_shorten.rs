@@ -0,0 +1,17 @@
+// SplitKey::shorten gives a BorrowedKey that lives only as long as the shorter of the two
+// lifetimes. It can't be used to stretch the bytes out to the string's lifetime: once the two are
+// joined into one BorrowedKey, the longer lifetime is gone.
+
+use borrow_complex_key_example::split::SplitKey;
+use borrow_complex_key_example::BorrowedKey;
+
+fn stretch<'s, 'b>(key: SplitKey<'s, 'b>) -> BorrowedKey<'s> {
+    key.shorten()
+}
+
+fn main() {
+    let bytes = vec![1, 2, 3];
+    let key = stretch(SplitKey::new("foo", &bytes));
+    drop(bytes);
+    println!("{:?}", key);
+}
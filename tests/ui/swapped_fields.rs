@@ -0,0 +1,18 @@
+use borrow_complex_key_example::assert_key_fields_match;
+use borrow_complex_key_example::key_fields::KeyFields;
+
+#[derive(KeyFields)]
+struct OwnedKey {
+    bytes: Vec<u8>,
+    s: String,
+}
+
+#[derive(KeyFields)]
+struct BorrowedKey<'a> {
+    s: &'a str,
+    bytes: &'a [u8],
+}
+
+assert_key_fields_match!(OwnedKey, BorrowedKey);
+
+fn main() {}
@@ -0,0 +1,118 @@
+// Runs the pattern on wasm32-unknown-unknown, where it's used to key plugins by composite
+// identifiers. With wasm-bindgen-cli installed, this runs under Node.js:
+//
+//     cargo test --target wasm32-unknown-unknown --test wasm --all-features
+//
+// The ordinary #[test]s don't run on wasm, so the cases here cover each part of the crate a plugin
+// host would lean on: lookups in every kind of container, hashing, the text and memcomparable
+// encodings, and the property checks. wasm is much slower than native code, so the property checks
+// run fewer cases than they do elsewhere.
+
+#![cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+
+use borrow_complex_key_example::consistency::{assert_consistent, assert_consistent_hash};
+use borrow_complex_key_example::map::KeyMap;
+use borrow_complex_key_example::portable::PortableHasher;
+use borrow_complex_key_example::set::KeySet;
+use borrow_complex_key_example::{encoding, BorrowedKey, Key, OwnedKey};
+use proptest::prelude::*;
+use proptest::test_runner::{Config, TestRunner};
+use std::collections::{BTreeSet, HashSet};
+use std::hash::{Hash, Hasher};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const CASES: u32 = 32;
+
+fn owned(s: &str, bytes: &[u8]) -> OwnedKey {
+    OwnedKey {
+        s: s.to_string(),
+        bytes: bytes.to_vec(),
+    }
+}
+
+fn runner() -> TestRunner {
+    TestRunner::new(Config {
+        cases: CASES,
+        // There's no file system to persist failures to.
+        failure_persistence: None,
+        ..Config::default()
+    })
+}
+
+#[wasm_bindgen_test]
+fn wasm_lookups() {
+    let keys = [owned("foo", b"abc"), owned("", b""), owned("foo", b"")];
+    let borrowed_key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+
+    let hash_set: HashSet<OwnedKey> = keys.iter().cloned().collect();
+    assert!(hash_set.contains(&borrowed_key as &dyn Key));
+    let btree_set: BTreeSet<OwnedKey> = keys.iter().cloned().collect();
+    assert!(btree_set.contains(&borrowed_key as &dyn Key));
+    let key_set: KeySet = keys.iter().cloned().collect();
+    assert!(key_set.contains(&borrowed_key));
+
+    let mut key_map = KeyMap::new();
+    *key_map.get_or_insert_with(&borrowed_key, || 0) += 1;
+    assert_eq!(key_map.get(&keys[0]), Some(&1));
+}
+
+#[wasm_bindgen_test]
+fn wasm_hashing() {
+    assert_consistent_hash::<_, dyn Key>(&owned("foo", b"abc"), &owned("foo", b"abc"));
+    assert_consistent_hash::<_, dyn Key>(&owned("ab", b""), &owned("a", b"b"));
+}
+
+// usize is 32 bits here, so this is where portable hashing has something to prove. The bytes are
+// the same ones the native tests expect.
+#[wasm_bindgen_test]
+fn wasm_portable_hash() {
+    #[derive(Default)]
+    struct Concat(Vec<u8>);
+
+    impl Hasher for Concat {
+        fn finish(&self) -> u64 {
+            0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            self.0.extend_from_slice(bytes);
+        }
+    }
+
+    let key = BorrowedKey {
+        s: "foo",
+        bytes: b"abc",
+    };
+    let mut hasher = PortableHasher::new(Concat::default());
+    key.hash(&mut hasher);
+    assert_eq!(hasher.into_inner().0, b"foo\xff\x03\0\0\0\0\0\0\0abc");
+}
+
+#[wasm_bindgen_test]
+fn wasm_encodings() {
+    let key = owned("a:b\0c", b"\x00\xff");
+    assert_eq!(encoding::decode(&encoding::encode(&key)), Ok(key.clone()));
+    assert_eq!(key.to_string().parse::<OwnedKey>(), Ok(key));
+}
+
+#[wasm_bindgen_test]
+fn wasm_consistent_borrow() {
+    runner()
+        .run(
+            &(any::<OwnedKey>(), any::<OwnedKey>()),
+            |(owned1, owned2)| {
+                assert_consistent::<_, dyn Key>(&owned1, &owned2);
+                Ok(())
+            },
+        )
+        .unwrap();
+}
+
+#[cfg(feature = "quickcheck")]
+#[wasm_bindgen_test]
+fn wasm_quickcheck() {
+    borrow_complex_key_example::consistency::quickcheck_consistent::<OwnedKey, dyn Key>();
+}